@@ -1,22 +1,51 @@
-use std::env;
+use std::{env, sync::Arc, time::Duration};
 
 use askama::Template;
+use auth_middleware::{auth_token, AuthLayer, AuthenticatedUser, JwksVerifier, RemoteVerifier, Verifier};
 use axum::{
-    http::StatusCode,
-    response::{Html, IntoResponse},
-    routing::get,
+    extract::State,
+    http::{header, HeaderMap, HeaderValue, StatusCode},
+    response::{Html, IntoResponse, Response},
+    routing::{get, post},
     Json, Router,
 };
-use axum_extra::extract::CookieJar;
 use serde::Serialize;
 use tower_http::services::ServeDir;
 
+// Tokens are not checked with auth-service again for this long, which is also
+// how long a token banned elsewhere keeps working here
+const VERIFY_TOKEN_CACHE_TTL: Duration = Duration::from_secs(5);
+// Number of tokens to remember at most
+const VERIFY_TOKEN_CACHE_CAPACITY: usize = 10_000;
+
+#[derive(Clone)]
+struct AppState {
+    // One connection pool to auth-service for all requests
+    http_client: reqwest::Client,
+    verifier: Arc<Verifier>,
+    auth_service_url: String,
+}
+
 #[tokio::main]
 async fn main() {
+    let auth_hostname = env::var("AUTH_SERVICE_HOST_NAME").unwrap_or("0.0.0.0".to_owned());
+    let auth_service_url = format!("http://{}:3000", auth_hostname);
+    let http_client = reqwest::Client::new();
+    let verifier = Arc::new(auth_verifier(&auth_service_url, http_client.clone()));
+
+    let state = AppState {
+        http_client,
+        verifier: verifier.clone(),
+        auth_service_url,
+    };
+
     let app = Router::new()
+        .route("/protected", get(protected))
+        .route_layer(AuthLayer::new(verifier))
         .nest_service("/assets", ServeDir::new("assets"))
         .route("/", get(root))
-        .route("/protected", get(protected));
+        .route("/logout", post(logout))
+        .with_state(state);
 
     let listener = tokio::net::TcpListener::bind("0.0.0.0:8000").await.unwrap();
 
@@ -37,7 +66,8 @@ async fn root() -> impl IntoResponse {
         address = "localhost".to_owned();
     }
     let login_link = format!("http://{}:3000", address);
-    let logout_link = format!("http://{}:3000/logout", address);
+    // Logging out goes through app-service, so that it forgets the token too
+    let logout_link = "/logout".to_owned();
 
     let template = IndexTemplate {
         login_link,
@@ -46,40 +76,73 @@ async fn root() -> impl IntoResponse {
     Html(template.render().unwrap())
 }
 
-async fn protected(jar: CookieJar) -> impl IntoResponse {
-    let jwt_cookie = match jar.get("jwt") {
-        Some(cookie) => cookie,
-        None => {
-            return StatusCode::UNAUTHORIZED.into_response();
+// Tokens are checked by auth-service's /verify-token, with results cached
+// briefly, unless AUTH_VERIFICATION is `jwks`, which checks them locally with
+// auth-service's public keys. That requires auth-service to sign with RS256 or
+// EdDSA, and JWT_ISSUER and JWT_AUDIENCES to match its settings.
+fn auth_verifier(auth_service_url: &str, http_client: reqwest::Client) -> Verifier {
+    match env::var("AUTH_VERIFICATION").as_deref() {
+        Ok("jwks") => {
+            let issuer = env::var("JWT_ISSUER").unwrap_or("auth-service".to_owned());
+            let audiences = env::var("JWT_AUDIENCES")
+                .unwrap_or("auth-service".to_owned())
+                .split(',')
+                .map(|audience| audience.trim().to_owned())
+                .collect();
+            let url = format!("{}/.well-known/jwks.json", auth_service_url);
+            Verifier::Jwks(JwksVerifier::new(url, issuer, audiences).with_client(http_client))
         }
-    };
-
-    let api_client = reqwest::Client::builder().build().unwrap();
+        _ => {
+            let url = format!("{}/verify-token", auth_service_url);
+            Verifier::Remote(
+                RemoteVerifier::new(url)
+                    .with_client(http_client)
+                    .with_cache(VERIFY_TOKEN_CACHE_TTL, VERIFY_TOKEN_CACHE_CAPACITY),
+            )
+        }
+    }
+}
 
-    let verify_token_body = serde_json::json!({
-        "token": &jwt_cookie.value(),
-    });
+// Pass the logout on to auth-service, together with the cookies it sets to
+// remove the session, and drop the token from the verification cache so it
+// stops working here right away
+async fn logout(State(state): State<AppState>, headers: HeaderMap) -> Response {
+    let mut request = state.http_client.post(format!("{}/logout", state.auth_service_url));
+    for name in [header::COOKIE, header::AUTHORIZATION] {
+        if let Some(value) = headers.get(&name) {
+            request = request.header(name.as_str(), value.as_bytes());
+        }
+    }
 
-    let auth_hostname = env::var("AUTH_SERVICE_HOST_NAME").unwrap_or("0.0.0.0".to_owned());
-    let url = format!("http://{}:3000/verify-token", auth_hostname);
+    let result = request.send().await;
+    if let Some(token) = auth_token(&headers) {
+        state.verifier.forget(&token);
+    }
 
-    let response = match api_client.post(&url).json(&verify_token_body).send().await {
+    let response = match result {
         Ok(response) => response,
-        Err(_) => {
-            return StatusCode::INTERNAL_SERVER_ERROR.into_response();
-        }
+        Err(_) => return StatusCode::INTERNAL_SERVER_ERROR.into_response(),
     };
 
-    match response.status() {
-        reqwest::StatusCode::UNAUTHORIZED | reqwest::StatusCode::BAD_REQUEST => {
-            StatusCode::UNAUTHORIZED.into_response()
-        }
-        reqwest::StatusCode::OK => Json(ProtectedRouteResponse {
-            img_url: "https://i.ibb.co/YP90j68/Light-Live-Bootcamp-Certificate.png".to_owned(),
-        })
-        .into_response(),
-        _ => StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+    let status = StatusCode::from_u16(response.status().as_u16()).unwrap_or(StatusCode::INTERNAL_SERVER_ERROR);
+    let set_cookies: Vec<HeaderValue> = response
+        .headers()
+        .get_all(reqwest::header::SET_COOKIE)
+        .iter()
+        .filter_map(|value| HeaderValue::from_bytes(value.as_bytes()).ok())
+        .collect();
+
+    let mut response = status.into_response();
+    for value in set_cookies {
+        response.headers_mut().append(header::SET_COOKIE, value);
     }
+    response
+}
+
+async fn protected(AuthenticatedUser(_claims): AuthenticatedUser) -> impl IntoResponse {
+    Json(ProtectedRouteResponse {
+        img_url: "https://i.ibb.co/YP90j68/Light-Live-Bootcamp-Certificate.png".to_owned(),
+    })
 }
 
 #[derive(Serialize)]
@@ -0,0 +1,174 @@
+use std::{
+    collections::HashMap,
+    sync::Mutex,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use sha2::{Digest, Sha256};
+
+use crate::Claims;
+
+// Longest time a verification result is trusted. A token banned at
+// auth-service is still accepted until its entry expires, so this is the
+// longest a logged out token keeps working elsewhere.
+pub(crate) const MAX_TTL: Duration = Duration::from_secs(5);
+
+// Results of recently verified tokens, so that a page load doesn't cost a
+// round trip to auth-service per request. Entries are keyed by the token's
+// hash and live for at most `ttl`, capped at `MAX_TTL`. Accepted tokens are
+// never kept past their own expiry, and rejected ones are kept too, since a
+// rejected token never becomes valid again.
+pub(crate) struct VerificationCache {
+    ttl: Duration,
+    capacity: usize,
+    entries: Mutex<HashMap<[u8; 32], CacheEntry>>,
+}
+
+struct CacheEntry {
+    // `None` if auth-service rejected the token
+    claims: Option<Claims>,
+    expires_at: Instant,
+}
+
+impl VerificationCache {
+    pub(crate) fn new(ttl: Duration, capacity: usize) -> Self {
+        Self {
+            ttl: ttl.min(MAX_TTL),
+            capacity,
+            entries: Mutex::new(HashMap::new()),
+        }
+    }
+
+    // `Some(None)` for a token that was rejected
+    pub(crate) fn get(&self, token: &str) -> Option<Option<Claims>> {
+        let entries = self.entries.lock().unwrap_or_else(|e| e.into_inner());
+        entries
+            .get(&hash(token))
+            .filter(|entry| entry.expires_at > Instant::now())
+            .map(|entry| entry.claims.clone())
+    }
+
+    pub(crate) fn insert(&self, token: &str, claims: Claims) {
+        let remaining_lifetime = remaining_lifetime(claims.exp);
+        if remaining_lifetime.is_zero() {
+            return;
+        }
+        self.insert_entry(token, Some(claims), self.ttl.min(remaining_lifetime));
+    }
+
+    pub(crate) fn insert_rejected(&self, token: &str) {
+        self.insert_entry(token, None, self.ttl);
+    }
+
+    fn insert_entry(&self, token: &str, claims: Option<Claims>, ttl: Duration) {
+        if self.capacity == 0 {
+            return;
+        }
+        let now = Instant::now();
+        let expires_at = now + ttl;
+
+        let mut entries = self.entries.lock().unwrap_or_else(|e| e.into_inner());
+        let key = hash(token);
+        if !entries.contains_key(&key) && entries.len() >= self.capacity {
+            entries.retain(|_, entry| entry.expires_at > now);
+
+            // Still full, make room by dropping the entry closest to expiring
+            if entries.len() >= self.capacity {
+                let soonest = entries
+                    .iter()
+                    .min_by_key(|(_, entry)| entry.expires_at)
+                    .map(|(key, _)| *key);
+                if let Some(soonest) = soonest {
+                    entries.remove(&soonest);
+                }
+            }
+        }
+        entries.insert(key, CacheEntry { claims, expires_at });
+    }
+
+    pub(crate) fn remove(&self, token: &str) {
+        let mut entries = self.entries.lock().unwrap_or_else(|e| e.into_inner());
+        entries.remove(&hash(token));
+    }
+}
+
+fn hash(token: &str) -> [u8; 32] {
+    Sha256::digest(token.as_bytes()).into()
+}
+
+// Time until `exp`, a Unix timestamp
+fn remaining_lifetime(exp: usize) -> Duration {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default();
+    Duration::from_secs(exp as u64).saturating_sub(now)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::verifier::tests::claims;
+
+    fn unix_now() -> usize {
+        SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs() as usize
+    }
+
+    #[test]
+    fn test_get_inserted_claims() {
+        let cache = VerificationCache::new(Duration::from_secs(30), 10);
+        cache.insert("token", claims());
+
+        assert_eq!(cache.get("token"), Some(Some(claims())));
+        assert_eq!(cache.get("other-token"), None);
+
+        cache.remove("token");
+        assert_eq!(cache.get("token"), None);
+    }
+
+    #[test]
+    fn test_entries_expire_with_the_token() {
+        let cache = VerificationCache::new(Duration::from_secs(30), 10);
+
+        cache.insert("expired", Claims { exp: unix_now() - 1, ..claims() });
+        assert_eq!(cache.get("expired"), None);
+
+        cache.insert("expiring", Claims { exp: unix_now() + 5, ..claims() });
+        let entries = cache.entries.lock().unwrap();
+        let entry = entries.get(&hash("expiring")).unwrap();
+        assert!(entry.expires_at <= Instant::now() + Duration::from_secs(5));
+    }
+
+    #[test]
+    fn test_capacity_is_bounded() {
+        let cache = VerificationCache::new(Duration::from_secs(30), 2);
+        cache.insert("first", Claims { exp: unix_now() + 10, ..claims() });
+        cache.insert("second", claims());
+        cache.insert("third", claims());
+
+        // The entry closest to expiring made room
+        assert_eq!(cache.entries.lock().unwrap().len(), 2);
+        assert_eq!(cache.get("first"), None);
+        assert!(cache.get("second").is_some());
+        assert!(cache.get("third").is_some());
+    }
+
+    #[test]
+    fn test_rejected_tokens_are_remembered() {
+        let cache = VerificationCache::new(Duration::from_secs(30), 10);
+        cache.insert_rejected("banned");
+
+        assert_eq!(cache.get("banned"), Some(None));
+    }
+
+    #[test]
+    fn test_ttl_is_capped() {
+        let cache = VerificationCache::new(Duration::from_secs(30), 10);
+        cache.insert("token", claims());
+        cache.insert_rejected("banned");
+
+        let entries = cache.entries.lock().unwrap();
+        for entry in entries.values() {
+            assert!(entry.expires_at <= Instant::now() + MAX_TTL);
+        }
+    }
+}
@@ -0,0 +1,17 @@
+use serde::{Deserialize, Serialize};
+
+// Claims of an auth token issued by auth-service
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Claims {
+    // ID of the user the token was issued to
+    pub sub: String,
+    // Their email address, only included if auth-service is configured to
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub email: Option<String>,
+    pub exp: usize,
+    pub iss: String,
+    pub aud: Vec<String>,
+    pub iat: usize,
+    pub nbf: usize,
+    pub jti: String,
+}
@@ -0,0 +1,174 @@
+use std::{
+    convert::Infallible,
+    future::Future,
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll},
+};
+
+use axum::{
+    body::Body,
+    http::{Request, StatusCode},
+    response::{IntoResponse, Response},
+};
+use tower_layer::Layer;
+use tower_service::Service;
+
+use crate::{auth_token, AuthenticatedUser, Verifier, VerifyError};
+
+// Verifies the auth token of every request and makes its claims available
+// through `AuthenticatedUser`. Requests without a valid token are answered
+// with 401 right away, unless the layer was made with `optional`.
+#[derive(Clone)]
+pub struct AuthLayer {
+    verifier: Arc<Verifier>,
+    required: bool,
+}
+
+impl AuthLayer {
+    // Takes an `Arc<Verifier>` too, to keep using the verifier elsewhere
+    pub fn new(verifier: impl Into<Arc<Verifier>>) -> Self {
+        Self { verifier: verifier.into(), required: true }
+    }
+
+    // Let requests without a valid token through, e.g. for pages which only
+    // look different for logged in users
+    pub fn optional(verifier: impl Into<Arc<Verifier>>) -> Self {
+        Self { verifier: verifier.into(), required: false }
+    }
+}
+
+impl<S> Layer<S> for AuthLayer {
+    type Service = AuthService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        AuthService {
+            inner,
+            verifier: self.verifier.clone(),
+            required: self.required,
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct AuthService<S> {
+    inner: S,
+    verifier: Arc<Verifier>,
+    required: bool,
+}
+
+impl<S> Service<Request<Body>> for AuthService<S>
+where
+    S: Service<Request<Body>, Response = Response, Error = Infallible> + Clone + Send + 'static,
+    S::Future: Send + 'static,
+{
+    type Response = Response;
+    type Error = Infallible;
+    type Future = Pin<Box<dyn Future<Output = Result<Response, Infallible>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, mut request: Request<Body>) -> Self::Future {
+        // The clone may not be ready yet, so keep the one which is and leave the clone behind
+        let clone = self.inner.clone();
+        let mut inner = std::mem::replace(&mut self.inner, clone);
+        let verifier = self.verifier.clone();
+        let required = self.required;
+
+        Box::pin(async move {
+            let result = match auth_token(request.headers()) {
+                Some(token) => verifier.verify(&token).await,
+                None => Err(VerifyError::InvalidToken),
+            };
+
+            match result {
+                Ok(claims) => {
+                    request.extensions_mut().insert(AuthenticatedUser(claims));
+                }
+                Err(_) if !required => {}
+                Err(VerifyError::InvalidToken) => return Ok(StatusCode::UNAUTHORIZED.into_response()),
+                Err(VerifyError::Unavailable) => {
+                    return Ok(StatusCode::INTERNAL_SERVER_ERROR.into_response())
+                }
+            }
+
+            inner.call(request).await
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::{routing::get, Router};
+    use tower::ServiceExt;
+
+    use super::*;
+    use crate::verifier::tests::{claims, jwks_verifier, sign};
+
+    async fn whoami(AuthenticatedUser(claims): AuthenticatedUser) -> String {
+        claims.sub
+    }
+
+    async fn greeting(user: Option<AuthenticatedUser>) -> String {
+        match user {
+            Some(AuthenticatedUser(claims)) => format!("Hello {}", claims.sub),
+            None => "Hello".to_owned(),
+        }
+    }
+
+    fn app() -> Router {
+        Router::new()
+            .route("/whoami", get(whoami))
+            .route_layer(AuthLayer::new(Verifier::Jwks(jwks_verifier())))
+            .merge(
+                Router::new()
+                    .route("/greeting", get(greeting))
+                    .route_layer(AuthLayer::optional(Verifier::Jwks(jwks_verifier()))),
+            )
+    }
+
+    async fn get_body(uri: &str, headers: &[(&str, String)]) -> (StatusCode, String) {
+        let mut request = Request::builder().uri(uri);
+        for (name, value) in headers {
+            request = request.header(*name, value);
+        }
+        let response = app().oneshot(request.body(Body::empty()).unwrap()).await.unwrap();
+
+        let status = response.status();
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        (status, String::from_utf8(body.to_vec()).unwrap())
+    }
+
+    #[tokio::test]
+    async fn test_required_layer() {
+        let token = sign(&claims(), "ed25519");
+
+        let (status, body) = get_body("/whoami", &[("authorization", format!("Bearer {}", token))]).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body, claims().sub);
+
+        let (status, _) = get_body("/whoami", &[("cookie", format!("jwt={}", token))]).await;
+        assert_eq!(status, StatusCode::OK);
+
+        let (status, _) = get_body("/whoami", &[]).await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+
+        let (status, _) = get_body("/whoami", &[("cookie", "jwt=invalid".to_owned())]).await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+    }
+
+    #[tokio::test]
+    async fn test_optional_layer() {
+        let token = sign(&claims(), "ed25519");
+
+        let (status, body) = get_body("/greeting", &[("cookie", format!("jwt={}", token))]).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body, format!("Hello {}", claims().sub));
+
+        let (status, body) = get_body("/greeting", &[]).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body, "Hello");
+    }
+}
@@ -0,0 +1,33 @@
+// Authentication for services sitting behind auth-service.
+//
+// `AuthLayer` checks the auth token of every request, taken from an
+// `Authorization: Bearer` header or the `jwt` cookie, and hands the token's
+// claims to handlers through the `AuthenticatedUser` extractor:
+//
+//     let verifier = Verifier::Remote(RemoteVerifier::new("http://auth-service:3000/verify-token"));
+//     let app = Router::new()
+//         .route("/protected", get(protected))
+//         .route_layer(AuthLayer::new(verifier));
+//
+//     async fn protected(AuthenticatedUser(claims): AuthenticatedUser) -> String {
+//         claims.sub
+//     }
+//
+// Tokens are verified either remotely by auth-service's `/verify-token`, with
+// an optional short-lived cache, or locally with the public keys auth-service
+// publishes as a JWKS, which only works when it signs with RS256 or EdDSA.
+
+mod cache;
+mod claims;
+mod extractor;
+mod layer;
+mod verifier;
+
+// re-export items from sub-modules
+pub use claims::*;
+pub use extractor::*;
+pub use layer::*;
+pub use verifier::*;
+
+// Name of the cookie auth-service sets the auth token in
+pub const JWT_COOKIE_NAME: &str = "jwt";
@@ -0,0 +1,312 @@
+use std::{
+    str::FromStr,
+    time::{Duration, Instant},
+};
+
+use jsonwebtoken::{
+    decode, decode_header,
+    jwk::{Jwk, JwkSet},
+    Algorithm, DecodingKey, Validation,
+};
+use tokio::sync::{Mutex, RwLock};
+
+use crate::{cache::VerificationCache, Claims};
+
+// An unknown `kid` makes the JWKS verifier fetch the keys again, but not more
+// often than this, so that made up tokens can't flood auth-service
+const MIN_JWKS_REFETCH_INTERVAL: Duration = Duration::from_secs(30);
+
+// How auth tokens are checked
+pub enum Verifier {
+    Jwks(JwksVerifier),
+    Remote(RemoteVerifier),
+}
+
+#[derive(Debug, PartialEq)]
+pub enum VerifyError {
+    // The token is malformed, expired, banned or was not issued by auth-service
+    InvalidToken,
+    // auth-service could not be asked
+    Unavailable,
+}
+
+impl Verifier {
+    pub async fn verify(&self, token: &str) -> Result<Claims, VerifyError> {
+        match self {
+            Verifier::Jwks(verifier) => verifier.verify(token).await,
+            Verifier::Remote(verifier) => verifier.verify(token).await,
+        }
+    }
+
+    // Stop accepting a token on the strength of an earlier verification, e.g.
+    // because it is being logged out
+    pub fn forget(&self, token: &str) {
+        if let Verifier::Remote(verifier) = self {
+            verifier.forget(token);
+        }
+    }
+}
+
+// Verifies tokens locally with the public keys auth-service publishes at
+// `/.well-known/jwks.json`. Saves a request per token, but can't tell whether
+// a token was banned by a logout before it expired.
+pub struct JwksVerifier {
+    jwks_url: String,
+    issuer: String,
+    audiences: Vec<String>,
+    client: reqwest::Client,
+    keys: RwLock<JwkSet>,
+    // Also makes concurrent requests with a new `kid` wait for a single fetch
+    last_fetch: Mutex<Option<Instant>>,
+}
+
+impl JwksVerifier {
+    // Only tokens from `issuer` for at least one of `audiences` are accepted,
+    // matching auth-service's JWT_ISSUER and JWT_AUDIENCES. The keys are
+    // fetched on the first token.
+    pub fn new(jwks_url: impl Into<String>, issuer: impl Into<String>, audiences: Vec<String>) -> Self {
+        Self {
+            jwks_url: jwks_url.into(),
+            issuer: issuer.into(),
+            audiences,
+            client: reqwest::Client::new(),
+            keys: RwLock::new(JwkSet { keys: Vec::new() }),
+            last_fetch: Mutex::new(None),
+        }
+    }
+
+    // Share a client with the rest of the service
+    pub fn with_client(mut self, client: reqwest::Client) -> Self {
+        self.client = client;
+        self
+    }
+
+    // Start out with already known keys instead of fetching them
+    pub fn with_keys(mut self, keys: JwkSet) -> Self {
+        self.keys = RwLock::new(keys);
+        self
+    }
+
+    pub async fn verify(&self, token: &str) -> Result<Claims, VerifyError> {
+        let header = decode_header(token).map_err(|_| VerifyError::InvalidToken)?;
+        let kid = header.kid.ok_or(VerifyError::InvalidToken)?;
+        let jwk = self.find_key(&kid).await?;
+
+        // The token must be signed with the algorithm of its key, anything else
+        // could e.g. be an HS256 token using the public key as the secret
+        let algorithm = jwk
+            .common
+            .key_algorithm
+            .and_then(|algorithm| Algorithm::from_str(&algorithm.to_string()).ok())
+            .ok_or(VerifyError::InvalidToken)?;
+        if header.alg != algorithm {
+            return Err(VerifyError::InvalidToken);
+        }
+
+        let key = DecodingKey::from_jwk(&jwk).map_err(|_| VerifyError::InvalidToken)?;
+
+        let mut validation = Validation::new(algorithm);
+        validation.set_issuer(&[&self.issuer]);
+        validation.set_audience(&self.audiences);
+        validation.set_required_spec_claims(&["exp", "nbf", "iss", "aud", "sub"]);
+        validation.validate_nbf = true;
+
+        decode::<Claims>(token, &key, &validation)
+            .map(|data| data.claims)
+            .map_err(|_| VerifyError::InvalidToken)
+    }
+
+    async fn find_key(&self, kid: &str) -> Result<Jwk, VerifyError> {
+        if let Some(jwk) = self.keys.read().await.find(kid) {
+            return Ok(jwk.clone());
+        }
+
+        // The key may have been added to auth-service since the last fetch
+        let mut last_fetch = self.last_fetch.lock().await;
+        if let Some(jwk) = self.keys.read().await.find(kid) {
+            return Ok(jwk.clone());
+        }
+        if last_fetch.is_some_and(|fetched_at| fetched_at.elapsed() < MIN_JWKS_REFETCH_INTERVAL) {
+            return Err(VerifyError::InvalidToken);
+        }
+        *last_fetch = Some(Instant::now());
+
+        let keys = self.fetch_keys().await?;
+        let jwk = keys.find(kid).cloned();
+        *self.keys.write().await = keys;
+
+        jwk.ok_or(VerifyError::InvalidToken)
+    }
+
+    async fn fetch_keys(&self) -> Result<JwkSet, VerifyError> {
+        self.client
+            .get(&self.jwks_url)
+            .send()
+            .await
+            .and_then(|response| response.error_for_status())
+            .map_err(|_| VerifyError::Unavailable)?
+            .json::<JwkSet>()
+            .await
+            .map_err(|_| VerifyError::Unavailable)
+    }
+}
+
+// Asks auth-service's `/verify-token` about every token. Works with any
+// signing algorithm and rejects banned tokens right away.
+pub struct RemoteVerifier {
+    verify_token_url: String,
+    client: reqwest::Client,
+    cache: Option<VerificationCache>,
+}
+
+impl RemoteVerifier {
+    pub fn new(verify_token_url: impl Into<String>) -> Self {
+        Self {
+            verify_token_url: verify_token_url.into(),
+            client: reqwest::Client::new(),
+            cache: None,
+        }
+    }
+
+    // Share a client with the rest of the service
+    pub fn with_client(mut self, client: reqwest::Client) -> Self {
+        self.client = client;
+        self
+    }
+
+    // Remember the answers for up to `capacity` tokens for `ttl`, instead of
+    // asking auth-service on every request. `ttl` is capped at 5 seconds:
+    // tokens logged out elsewhere are accepted until their entry expires, see
+    // `Verifier::forget`.
+    pub fn with_cache(mut self, ttl: Duration, capacity: usize) -> Self {
+        self.cache = Some(VerificationCache::new(ttl, capacity));
+        self
+    }
+
+    pub async fn verify(&self, token: &str) -> Result<Claims, VerifyError> {
+        if let Some(cached) = self.cache.as_ref().and_then(|cache| cache.get(token)) {
+            return cached.ok_or(VerifyError::InvalidToken);
+        }
+
+        let result = self.verify_remotely(token).await;
+        if let Some(cache) = &self.cache {
+            match &result {
+                Ok(claims) => cache.insert(token, claims.clone()),
+                // Not knowing is not a rejection, ask again next time
+                Err(VerifyError::InvalidToken) => cache.insert_rejected(token),
+                Err(VerifyError::Unavailable) => {}
+            }
+        }
+        result
+    }
+
+    pub fn forget(&self, token: &str) {
+        if let Some(cache) = &self.cache {
+            cache.remove(token);
+        }
+    }
+
+    async fn verify_remotely(&self, token: &str) -> Result<Claims, VerifyError> {
+        let response = self
+            .client
+            .post(&self.verify_token_url)
+            .json(&VerifyTokenRequest { token })
+            .send()
+            .await
+            .map_err(|_| VerifyError::Unavailable)?;
+
+        match response.status() {
+            reqwest::StatusCode::OK => response
+                .json::<Claims>()
+                .await
+                .map_err(|_| VerifyError::Unavailable),
+            reqwest::StatusCode::UNAUTHORIZED | reqwest::StatusCode::BAD_REQUEST => {
+                Err(VerifyError::InvalidToken)
+            }
+            _ => Err(VerifyError::Unavailable),
+        }
+    }
+}
+
+#[derive(serde::Serialize)]
+struct VerifyTokenRequest<'a> {
+    token: &'a str,
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use jsonwebtoken::{encode, EncodingKey, Header};
+
+    use super::*;
+
+    const ED25519_PEM: &str = include_str!("../tests/fixtures/ed25519.pem");
+    const JWKS: &str = include_str!("../tests/fixtures/jwks.json");
+
+    pub(crate) fn claims() -> Claims {
+        Claims {
+            sub: "8d7c6b5a-4f3e-4d2c-9b1a-0e9f8d7c6b5a".to_owned(),
+            email: None,
+            exp: 4_102_444_800,
+            iss: "auth-service".to_owned(),
+            aud: vec!["auth-service".to_owned()],
+            iat: 1_700_000_000,
+            nbf: 1_700_000_000,
+            jti: "3f0b1b1e-6f4e-4d7a-9c1a-5b7e2f0c9d11".to_owned(),
+        }
+    }
+
+    pub(crate) fn sign(claims: &Claims, kid: &str) -> String {
+        let mut header = Header::new(Algorithm::EdDSA);
+        header.kid = Some(kid.to_owned());
+        let key = EncodingKey::from_ed_pem(ED25519_PEM.as_bytes()).unwrap();
+        encode(&header, claims, &key).unwrap()
+    }
+
+    // Never fetches, the URL points nowhere
+    pub(crate) fn jwks_verifier() -> JwksVerifier {
+        let keys = serde_json::from_str(JWKS).unwrap();
+        JwksVerifier::new("http://127.0.0.1:9/.well-known/jwks.json", "auth-service", vec!["auth-service".to_owned()])
+            .with_keys(keys)
+    }
+
+    #[tokio::test]
+    async fn test_jwks_verifier_accepts_valid_token() {
+        let token = sign(&claims(), "ed25519");
+        assert_eq!(jwks_verifier().verify(&token).await, Ok(claims()));
+    }
+
+    #[tokio::test]
+    async fn test_jwks_verifier_rejects_foreign_issuer_or_audience() {
+        let verifier = jwks_verifier();
+
+        let token = sign(&Claims { iss: "other-service".to_owned(), ..claims() }, "ed25519");
+        assert_eq!(verifier.verify(&token).await, Err(VerifyError::InvalidToken));
+
+        let token = sign(&Claims { aud: vec!["other-service".to_owned()], ..claims() }, "ed25519");
+        assert_eq!(verifier.verify(&token).await, Err(VerifyError::InvalidToken));
+    }
+
+    #[tokio::test]
+    async fn test_jwks_verifier_rejects_expired_token() {
+        let token = sign(&Claims { exp: 1_700_000_600, ..claims() }, "ed25519");
+        assert_eq!(jwks_verifier().verify(&token).await, Err(VerifyError::InvalidToken));
+    }
+
+    #[tokio::test]
+    async fn test_jwks_verifier_rejects_token_with_other_algorithm() {
+        let mut header = Header::new(Algorithm::HS256);
+        header.kid = Some("ed25519".to_owned());
+        let token = encode(&header, &claims(), &EncodingKey::from_secret(b"secret")).unwrap();
+        assert_eq!(jwks_verifier().verify(&token).await, Err(VerifyError::InvalidToken));
+    }
+
+    #[tokio::test]
+    async fn test_jwks_verifier_refetches_keys_for_unknown_kid() {
+        let verifier = jwks_verifier();
+        let token = sign(&claims(), "unknown");
+
+        // The fetch fails, and is not retried right away
+        assert_eq!(verifier.verify(&token).await, Err(VerifyError::Unavailable));
+        assert_eq!(verifier.verify(&token).await, Err(VerifyError::InvalidToken));
+    }
+}
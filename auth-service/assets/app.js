@@ -104,7 +104,15 @@ signupButton.addEventListener("click", (e) => {
             signupForm.password.value = "";
             signupForm.twoFA.checked = false;
             signupErrAlter.style.display = "none";
-            alert("You have successfully created a user.");
+            response.json().then(data => {
+                if (data.recoveryCodes) {
+                    alert("You have successfully created a user.\n\n" +
+                        "Store these recovery codes somewhere safe. Each of them can be used once " +
+                        "instead of a 2FA code:\n\n" + data.recoveryCodes.join("\n"));
+                } else {
+                    alert("You have successfully created a user.");
+                }
+            });
             loginSection.style.display = "block";
             twoFASection.style.display = "none";
             signupSection.style.display = "none";
@@ -149,6 +157,19 @@ TwoFAButton.addEventListener("click", (e) => {
             loginSection.style.display = "block";
             twoFASection.style.display = "none";
             signupSection.style.display = "none";
+        } else if (response.status === 410) {
+            // The login attempt expired or too many wrong codes were entered
+            TwoFAForm.email.value = "";
+            TwoFAForm.email_code.value = "";
+            TwoFAForm.login_attempt_id.value = "";
+            TwoFAErrAlter.style.display = "none";
+            response.json().then(data => {
+                loginErrAlter.innerHTML = `<span><strong>Error: </strong>${data.error}</span>`;
+                loginErrAlter.style.display = "block";
+            });
+            loginSection.style.display = "block";
+            twoFASection.style.display = "none";
+            signupSection.style.display = "none";
         } else {
             response.json().then(data => {
                 let error_msg = data.error;
@@ -0,0 +1,6 @@
+CREATE TABLE IF NOT EXISTS users (
+    email TEXT NOT NULL,
+    password_hash TEXT NOT NULL,
+    requires_2fa BOOLEAN NOT NULL DEFAULT FALSE,
+    CONSTRAINT users_email_key PRIMARY KEY (email)
+);
@@ -0,0 +1,3 @@
+ALTER TABLE users
+    ADD COLUMN two_fa_method TEXT NOT NULL DEFAULT 'email',
+    ADD COLUMN totp_secret TEXT;
@@ -0,0 +1,17 @@
+-- Users are keyed by a stable ID instead of their email address, which can change
+ALTER TABLE users ADD COLUMN id TEXT NOT NULL DEFAULT gen_random_uuid()::TEXT;
+ALTER TABLE users ALTER COLUMN id DROP DEFAULT;
+
+ALTER TABLE recovery_codes ADD COLUMN user_id TEXT;
+UPDATE recovery_codes SET user_id = users.id FROM users WHERE users.email = recovery_codes.email;
+-- Also drops the primary key and the foreign key on the email
+ALTER TABLE recovery_codes DROP COLUMN email;
+ALTER TABLE recovery_codes ALTER COLUMN user_id SET NOT NULL;
+
+ALTER TABLE users DROP CONSTRAINT users_email_key;
+ALTER TABLE users ADD CONSTRAINT users_pkey PRIMARY KEY (id);
+ALTER TABLE users ADD CONSTRAINT users_email_key UNIQUE (email);
+
+ALTER TABLE recovery_codes ADD PRIMARY KEY (user_id, code_hash);
+ALTER TABLE recovery_codes
+    ADD CONSTRAINT recovery_codes_user_id_fkey FOREIGN KEY (user_id) REFERENCES users (id) ON DELETE CASCADE;
@@ -0,0 +1,5 @@
+-- Set while a user's account waits out the grace period before it is deleted
+ALTER TABLE users
+    ADD COLUMN deletion_due_at BIGINT;
+
+CREATE INDEX users_deletion_due_at_idx ON users (deletion_due_at) WHERE deletion_due_at IS NOT NULL;
@@ -0,0 +1,3 @@
+-- The time step of the last accepted authenticator app code, codes from it
+-- or an earlier step are rejected so that each code works only once
+ALTER TABLE users ADD COLUMN totp_last_step BIGINT;
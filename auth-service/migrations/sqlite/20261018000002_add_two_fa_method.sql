@@ -0,0 +1,2 @@
+ALTER TABLE users ADD COLUMN two_fa_method TEXT NOT NULL DEFAULT 'email';
+ALTER TABLE users ADD COLUMN totp_secret TEXT;
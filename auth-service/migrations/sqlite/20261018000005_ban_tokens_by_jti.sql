@@ -0,0 +1,4 @@
+-- Tokens are banned by their `jti` claim. Tokens issued before carry no `jti`
+-- and fail validation anyway, so their bans can go.
+DELETE FROM banned_tokens;
+ALTER TABLE banned_tokens RENAME COLUMN token TO jti;
@@ -0,0 +1,2 @@
+-- Users who signed up before email verification existed keep logging in as before
+ALTER TABLE users ADD COLUMN email_verified BOOLEAN NOT NULL DEFAULT TRUE;
@@ -0,0 +1,8 @@
+-- All tokens of a subject issued before `issued_before` are banned until `exp`
+CREATE TABLE IF NOT EXISTS banned_subjects (
+    sub TEXT NOT NULL PRIMARY KEY,
+    issued_before INTEGER NOT NULL,
+    exp INTEGER NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS refresh_tokens_email_idx ON refresh_tokens (email);
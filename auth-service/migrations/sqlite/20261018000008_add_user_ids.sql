@@ -0,0 +1,50 @@
+-- Users are keyed by a stable ID instead of their email address, which can
+-- change. SQLite cannot change a primary key in place, so the tables are
+-- rebuilt. Recovery codes are set aside first, dropping `users` would
+-- otherwise delete them through the foreign key.
+CREATE TABLE recovery_codes_by_email AS SELECT email, code_hash FROM recovery_codes;
+DROP TABLE recovery_codes;
+
+CREATE TABLE users_by_id (
+    id TEXT NOT NULL PRIMARY KEY,
+    email TEXT NOT NULL UNIQUE,
+    password_hash TEXT NOT NULL,
+    requires_2fa BOOLEAN NOT NULL DEFAULT FALSE,
+    two_fa_method TEXT NOT NULL DEFAULT 'email',
+    totp_secret TEXT,
+    email_verified BOOLEAN NOT NULL DEFAULT TRUE
+);
+
+-- A random version 4 UUID for every existing user
+INSERT INTO users_by_id (id, email, password_hash, requires_2fa, two_fa_method, totp_secret, email_verified)
+SELECT
+    lower(
+        hex(randomblob(4)) || '-' || hex(randomblob(2)) || '-4' || substr(hex(randomblob(2)), 2) || '-'
+        || substr('89ab', 1 + (abs(random()) % 4), 1) || substr(hex(randomblob(2)), 2) || '-'
+        || hex(randomblob(6))
+    ),
+    email, password_hash, requires_2fa, two_fa_method, totp_secret, email_verified
+FROM users;
+
+DROP TABLE users;
+ALTER TABLE users_by_id RENAME TO users;
+
+CREATE TABLE recovery_codes (
+    user_id TEXT NOT NULL REFERENCES users (id) ON DELETE CASCADE,
+    code_hash TEXT NOT NULL,
+    PRIMARY KEY (user_id, code_hash)
+);
+INSERT INTO recovery_codes (user_id, code_hash)
+SELECT users.id, recovery_codes_by_email.code_hash
+FROM recovery_codes_by_email JOIN users ON users.email = recovery_codes_by_email.email;
+DROP TABLE recovery_codes_by_email;
+
+-- Refresh tokens keep working, pending 2FA logins only last minutes and start over
+DELETE FROM refresh_tokens WHERE email NOT IN (SELECT email FROM users);
+UPDATE refresh_tokens SET email = (SELECT id FROM users WHERE users.email = refresh_tokens.email);
+DROP INDEX refresh_tokens_email_idx;
+ALTER TABLE refresh_tokens RENAME COLUMN email TO user_id;
+CREATE INDEX refresh_tokens_user_id_idx ON refresh_tokens (user_id);
+
+DELETE FROM two_fa_codes;
+ALTER TABLE two_fa_codes RENAME COLUMN email TO user_id;
@@ -0,0 +1,9 @@
+-- Failed logins per email address or client IP, forgotten once none was added
+-- for the store's TTL
+CREATE TABLE IF NOT EXISTS failed_logins (
+    key TEXT NOT NULL PRIMARY KEY,
+    count INTEGER NOT NULL,
+    last_failed_at INTEGER NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS failed_logins_last_failed_at_idx ON failed_logins (last_failed_at);
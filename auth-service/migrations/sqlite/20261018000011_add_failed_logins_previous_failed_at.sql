@@ -0,0 +1,3 @@
+-- The latest failure before `last_failed_at`, returned when counting a new
+-- attempt in a single statement
+ALTER TABLE failed_logins ADD COLUMN previous_failed_at INTEGER;
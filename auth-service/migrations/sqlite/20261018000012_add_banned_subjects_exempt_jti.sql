@@ -0,0 +1,3 @@
+-- The token issued together with a ban, e.g. to the user changing their
+-- password, which the ban does not cover
+ALTER TABLE banned_subjects ADD COLUMN exempt_jti TEXT;
@@ -0,0 +1,6 @@
+-- Revoked token families are remembered until their tokens would have
+-- expired, so that a token being refreshed cannot add a new one to them
+CREATE TABLE IF NOT EXISTS revoked_refresh_token_families (
+    family_id TEXT NOT NULL PRIMARY KEY,
+    expires_at INTEGER NOT NULL
+);
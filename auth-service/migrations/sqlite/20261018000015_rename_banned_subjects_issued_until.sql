@@ -0,0 +1,3 @@
+-- All tokens of a subject issued at or before `issued_until`, the last second
+-- covered by the ban, are banned until `exp`
+ALTER TABLE banned_subjects RENAME COLUMN issued_before TO issued_until;
@@ -1,12 +1,15 @@
 use std::sync::Arc;
 use tokio::sync::RwLock;
 
-use crate::domain::{data_stores::{BannedTokenStore, TwoFACodeStore, UserStore}};
+use crate::domain::{data_stores::{BannedTokenStore, FailedLoginStore, RefreshTokenStore, TwoFACodeStore, UserStore}};
+use crate::utils::{constants::ALLOW_UNVERIFIED_LOGIN, login_throttle::LoginThrottle};
 
 // Using a type alias to improve readability!
 pub type UserStoreType = Arc<RwLock<dyn UserStore + Send + Sync>>;
 pub type BannedTokenStoreType = Arc<RwLock<dyn BannedTokenStore + Send + Sync>>;
 pub type TwoFACodeStoreType = Arc<RwLock<dyn TwoFACodeStore + Send + Sync>>;
+pub type RefreshTokenStoreType = Arc<RwLock<dyn RefreshTokenStore + Send + Sync>>;
+pub type FailedLoginStoreType = Arc<RwLock<dyn FailedLoginStore + Send + Sync>>;
 pub type EmailClientType = Arc<RwLock<dyn crate::domain::EmailClient + Send + Sync>>;
 
 #[derive(Clone)]
@@ -14,11 +17,38 @@ pub struct AppState {
     pub user_store: UserStoreType,
     pub banned_token_store: BannedTokenStoreType,
     pub two_fa_code_store: TwoFACodeStoreType,
+    pub refresh_token_store: RefreshTokenStoreType,
+    pub failed_login_store: FailedLoginStoreType,
     pub email_client: EmailClientType,
+    // Whether users can log in before verifying their email address
+    pub allow_unverified_login: bool,
+    // When failed logins slow down and lock out further attempts
+    pub login_throttle: LoginThrottle,
 }
 
 impl AppState {
-    pub fn new(user_store: UserStoreType, banned_token_store: BannedTokenStoreType, two_fa_code_store: TwoFACodeStoreType, email_client: EmailClientType) -> Self {
-        Self { user_store, banned_token_store, two_fa_code_store, email_client }
+    pub fn new(user_store: UserStoreType, banned_token_store: BannedTokenStoreType, two_fa_code_store: TwoFACodeStoreType, refresh_token_store: RefreshTokenStoreType, failed_login_store: FailedLoginStoreType, email_client: EmailClientType) -> Self {
+        Self {
+            user_store,
+            banned_token_store,
+            two_fa_code_store,
+            refresh_token_store,
+            failed_login_store,
+            email_client,
+            allow_unverified_login: *ALLOW_UNVERIFIED_LOGIN,
+            login_throttle: LoginThrottle::default(),
+        }
+    }
+
+    // Override ALLOW_UNVERIFIED_LOGIN
+    pub fn with_allow_unverified_login(mut self, allow_unverified_login: bool) -> Self {
+        self.allow_unverified_login = allow_unverified_login;
+        self
+    }
+
+    // Override the LOGIN_* throttling settings
+    pub fn with_login_throttle(mut self, login_throttle: LoginThrottle) -> Self {
+        self.login_throttle = login_throttle;
+        self
     }
 }
\ No newline at end of file
@@ -1,14 +1,57 @@
+use std::net::IpAddr;
+
 use uuid::Uuid;
 use rand::Rng;
 
-use super::{User, Email, Password};
+use super::{
+    User, UserId, Email, Password, PasswordHash, RecoveryCodeHash, RefreshSession, RefreshTokenFamilyId,
+    RefreshTokenHash, TotpSecret,
+};
 use crate::services::{HashmapUserStore, HashsetBannedTokenStore};
 
+// Users are keyed by their ID, their email address is a unique secondary key
 #[async_trait::async_trait]
 pub trait UserStore {
     async fn add_user(&mut self, user: User) -> Result<(), UserStoreError>;
-    async fn get_user(&self, email: &Email) -> Result<User, UserStoreError>;
-    async fn validate_user(&self, email: &Email, password: &Password) -> Result<(), UserStoreError>;
+    async fn get_user(&self, id: &UserId) -> Result<User, UserStoreError>;
+    // Look a user up by their current email address, e.g. on login
+    async fn get_user_by_email(&self, email: &Email) -> Result<User, UserStoreError>;
+    async fn validate_user(&self, id: &UserId, password: &Password) -> Result<(), UserStoreError>;
+    async fn update_password_hash(&mut self, id: &UserId, password_hash: PasswordHash) -> Result<(), UserStoreError>;
+    // Replace the hash only if it is still `current_password_hash`, failing
+    // with `InvalidCredentials` if the password was changed in the meantime
+    async fn replace_password_hash(
+        &mut self,
+        id: &UserId,
+        current_password_hash: &PasswordHash,
+        new_password_hash: PasswordHash,
+    ) -> Result<(), UserStoreError>;
+    // Store the secret of an authenticator app enrollment without activating it
+    async fn set_totp_secret(&mut self, id: &UserId, totp_secret: TotpSecret) -> Result<(), UserStoreError>;
+    // Require 2FA through the stored authenticator app secret from now on
+    async fn enable_totp(&mut self, id: &UserId) -> Result<(), UserStoreError>;
+    // Record that an authenticator app code of time step `step` was used,
+    // failing with `InvalidCredentials` if a code of that step or a later one
+    // was used before, so that every code works only once
+    async fn accept_totp_step(&mut self, id: &UserId, step: u64) -> Result<(), UserStoreError>;
+    // Replace the user's recovery codes with a new set
+    async fn set_recovery_codes(&mut self, id: &UserId, recovery_codes: Vec<RecoveryCodeHash>) -> Result<(), UserStoreError>;
+    // Remove a recovery code so it cannot be used again, failing with
+    // `InvalidCredentials` if the user has no such code
+    async fn consume_recovery_code(&mut self, id: &UserId, recovery_code: &RecoveryCodeHash) -> Result<(), UserStoreError>;
+    // Mark the user's email address as verified
+    async fn verify_email(&mut self, id: &UserId) -> Result<(), UserStoreError>;
+    // Move the user to `new_email`. The new address counts as verified, since
+    // the user confirmed it. Fails with `UserAlreadyExists` if it is taken.
+    async fn change_email(&mut self, id: &UserId, new_email: Email) -> Result<(), UserStoreError>;
+    // Mark the user for deletion once `due_at`, a Unix timestamp, has passed
+    async fn schedule_deletion(&mut self, id: &UserId, due_at: i64) -> Result<(), UserStoreError>;
+    // Keep the user after all, e.g. because they logged in again
+    async fn cancel_deletion(&mut self, id: &UserId) -> Result<(), UserStoreError>;
+    // IDs of the users whose deletion was due at `now`
+    async fn users_due_for_deletion(&self, now: i64) -> Result<Vec<UserId>, UserStoreError>;
+    // Remove the user together with their recovery codes
+    async fn delete_user(&mut self, id: &UserId) -> Result<(), UserStoreError>;
 }
 
 #[async_trait::async_trait]
@@ -17,12 +60,73 @@ impl UserStore for HashmapUserStore {
         self.add_user(user)
     }
 
-    async fn get_user(&self, email: &Email) -> Result<User, UserStoreError> {
-        self.get_user(email)
+    async fn get_user(&self, id: &UserId) -> Result<User, UserStoreError> {
+        self.get_user(id)
+    }
+
+    async fn get_user_by_email(&self, email: &Email) -> Result<User, UserStoreError> {
+        self.get_user_by_email(email)
+    }
+
+    async fn validate_user(&self, id: &UserId, password: &Password) -> Result<(), UserStoreError> {
+        self.validate_user(id, password).await
+    }
+
+    async fn update_password_hash(&mut self, id: &UserId, password_hash: PasswordHash) -> Result<(), UserStoreError> {
+        self.update_password_hash(id, password_hash)
+    }
+
+    async fn replace_password_hash(
+        &mut self,
+        id: &UserId,
+        current_password_hash: &PasswordHash,
+        new_password_hash: PasswordHash,
+    ) -> Result<(), UserStoreError> {
+        self.replace_password_hash(id, current_password_hash, new_password_hash)
+    }
+
+    async fn set_totp_secret(&mut self, id: &UserId, totp_secret: TotpSecret) -> Result<(), UserStoreError> {
+        self.set_totp_secret(id, totp_secret)
+    }
+
+    async fn enable_totp(&mut self, id: &UserId) -> Result<(), UserStoreError> {
+        self.enable_totp(id)
+    }
+
+    async fn accept_totp_step(&mut self, id: &UserId, step: u64) -> Result<(), UserStoreError> {
+        self.accept_totp_step(id, step)
     }
 
-    async fn validate_user(&self, email: &Email, password: &Password) -> Result<(), UserStoreError> {
-        self.validate_user(email, password)
+    async fn set_recovery_codes(&mut self, id: &UserId, recovery_codes: Vec<RecoveryCodeHash>) -> Result<(), UserStoreError> {
+        self.set_recovery_codes(id, recovery_codes)
+    }
+
+    async fn consume_recovery_code(&mut self, id: &UserId, recovery_code: &RecoveryCodeHash) -> Result<(), UserStoreError> {
+        self.consume_recovery_code(id, recovery_code)
+    }
+
+    async fn verify_email(&mut self, id: &UserId) -> Result<(), UserStoreError> {
+        self.verify_email(id)
+    }
+
+    async fn change_email(&mut self, id: &UserId, new_email: Email) -> Result<(), UserStoreError> {
+        self.change_email(id, new_email)
+    }
+
+    async fn schedule_deletion(&mut self, id: &UserId, due_at: i64) -> Result<(), UserStoreError> {
+        self.schedule_deletion(id, due_at)
+    }
+
+    async fn cancel_deletion(&mut self, id: &UserId) -> Result<(), UserStoreError> {
+        self.cancel_deletion(id)
+    }
+
+    async fn users_due_for_deletion(&self, now: i64) -> Result<Vec<UserId>, UserStoreError> {
+        Ok(self.users_due_for_deletion(now))
+    }
+
+    async fn delete_user(&mut self, id: &UserId) -> Result<(), UserStoreError> {
+        self.delete_user(id)
     }
 }
 
@@ -34,20 +138,59 @@ pub enum UserStoreError {
     UnexpectedError,
 }
 
+// Tokens are banned by their `jti` claim until their `exp`
 #[async_trait::async_trait]
 pub trait BannedTokenStore {
-    async fn store_tokens(&mut self, token: String, exp: usize) -> Result<(), BannedTokenStoreError>;
-    async fn is_token_exists(&self, token: &str) -> Result<bool, BannedTokenStoreError>;
+    async fn store_tokens(&mut self, jti: String, exp: usize) -> Result<(), BannedTokenStoreError>;
+    async fn is_token_exists(&self, jti: &str) -> Result<bool, BannedTokenStoreError>;
+    // Ban every token of `sub` issued up to and including the second
+    // `issued_until`, e.g. after a password reset, except the one with
+    // `exempt_jti`. The ban is kept until `exp`, when all of them expired.
+    async fn ban_subject(
+        &mut self,
+        sub: String,
+        issued_until: usize,
+        exempt_jti: Option<String>,
+        exp: usize,
+    ) -> Result<(), BannedTokenStoreError>;
+    // Whether the token `jti` of `sub` issued at `iat` falls under a ban of its subject
+    async fn is_subject_banned(&self, sub: &str, iat: usize, jti: &str) -> Result<bool, BannedTokenStoreError>;
+    // Drop tokens whose `exp` has passed, returning how many were removed
+    async fn prune_expired(&mut self) -> Result<usize, BannedTokenStoreError>;
+    // Number of banned tokens currently held
+    async fn size(&self) -> Result<usize, BannedTokenStoreError>;
 }
 
 #[async_trait::async_trait]
 impl BannedTokenStore for HashsetBannedTokenStore {
-    async fn store_tokens(&mut self, token: String, exp: usize) -> Result<(), BannedTokenStoreError> {
-        self.store_tokens(token, exp).await
+    async fn store_tokens(&mut self, jti: String, exp: usize) -> Result<(), BannedTokenStoreError> {
+        self.store_tokens(jti, exp).await
+    }
+
+    async fn is_token_exists(&self, jti: &str) -> Result<bool, BannedTokenStoreError> {
+        self.is_token_exists(jti).await
     }
 
-    async fn is_token_exists(&self, token: &str) -> Result<bool, BannedTokenStoreError> {
-        self.is_token_exists(token).await
+    async fn ban_subject(
+        &mut self,
+        sub: String,
+        issued_until: usize,
+        exempt_jti: Option<String>,
+        exp: usize,
+    ) -> Result<(), BannedTokenStoreError> {
+        self.ban_subject(sub, issued_until, exempt_jti, exp).await
+    }
+
+    async fn is_subject_banned(&self, sub: &str, iat: usize, jti: &str) -> Result<bool, BannedTokenStoreError> {
+        self.is_subject_banned(sub, iat, jti).await
+    }
+
+    async fn prune_expired(&mut self) -> Result<usize, BannedTokenStoreError> {
+        self.prune_expired().await
+    }
+
+    async fn size(&self) -> Result<usize, BannedTokenStoreError> {
+        self.size().await
     }
 }
 
@@ -60,23 +203,115 @@ pub enum BannedTokenStoreError {
 pub trait TwoFACodeStore {
     async fn add_code(
         &mut self,
-        email: Email,
+        user_id: UserId,
         login_attempt_id: LoginAttemptId,
         code: TwoFACode,
     ) -> Result<(), TwoFACodeStoreError>;
-    async fn remove_code(&mut self, email: &Email) -> Result<(), TwoFACodeStoreError>;
+    async fn remove_code(&mut self, user_id: &UserId) -> Result<(), TwoFACodeStoreError>;
+    // Codes older than the store's TTL are rejected with `CodeExpired`
     async fn get_code(
         &self,
-        email: &Email,
+        user_id: &UserId,
     ) -> Result<(LoginAttemptId, TwoFACode), TwoFACodeStoreError>;
+    // Use up the pending login attempt if it is `login_attempt_id` and, when
+    // given, its code is `code`, all in one step so that it can only be used
+    // once. Otherwise the wrong attempt is counted in the same step and fails
+    // with `IncorrectCode`, see `record_failed_attempt`. Expired attempts are
+    // removed and fail with `CodeExpired`.
+    async fn take_code(
+        &mut self,
+        user_id: &UserId,
+        login_attempt_id: &LoginAttemptId,
+        code: Option<&TwoFACode>,
+        max_attempts: u32,
+    ) -> Result<(), TwoFACodeStoreError>;
+    // Count a wrong code for the pending login attempt, returning the number
+    // of failed attempts so far. The attempt is removed once `max_attempts`
+    // were counted, so that the code cannot be brute forced. Like in
+    // `take_code`, an expired attempt is removed and fails with `CodeExpired`.
+    async fn record_failed_attempt(&mut self, user_id: &UserId, max_attempts: u32) -> Result<u32, TwoFACodeStoreError>;
 }
 
 #[derive(Debug, PartialEq)]
 pub enum TwoFACodeStoreError {
     LoginAttemptIdNotFound,
+    CodeExpired,
+    // The number of failed attempts including this one
+    IncorrectCode(u32),
+    UnexpectedError,
+}
+
+#[async_trait::async_trait]
+pub trait RefreshTokenStore {
+    // Store a newly issued token, which can be used once until it expires.
+    // Fails with `FamilyRevoked` if its family was revoked in the meantime,
+    // e.g. while the token it replaces was being refreshed.
+    async fn add_token(&mut self, token: RefreshTokenHash, session: RefreshSession) -> Result<(), RefreshTokenStoreError>;
+    // Mark the token as used and return what it stands for. A token which was
+    // used before fails with `TokenReused`, so that its family can be revoked.
+    async fn use_token(&mut self, token: &RefreshTokenHash) -> Result<RefreshSession, RefreshTokenStoreError>;
+    // Revoke all tokens descending from the same login. The family is
+    // remembered as revoked until its tokens would have expired, so that no
+    // new token can be added to it.
+    async fn revoke_family(&mut self, family_id: &RefreshTokenFamilyId) -> Result<(), RefreshTokenStoreError>;
+    // Revoke the families of all of the user's sessions
+    async fn revoke_user(&mut self, user_id: &UserId) -> Result<(), RefreshTokenStoreError>;
+}
+
+#[derive(Debug, PartialEq)]
+pub enum RefreshTokenStoreError {
+    TokenNotFound,
+    TokenReused(RefreshTokenFamilyId),
+    FamilyRevoked,
     UnexpectedError,
 }
 
+// Failed logins are counted per email address and per client IP. The count
+// of a key is forgotten once no failure was added to it for the store's TTL.
+#[async_trait::async_trait]
+pub trait FailedLoginStore {
+    // The failures of the key, without counting an attempt
+    async fn get_failures(&self, key: &FailedLoginKey) -> Result<Option<FailedLogins>, FailedLoginStoreError>;
+    // Count a login attempt as failed before its password is checked,
+    // returning the failures of the key before it. Counting and reading are
+    // one step, so that concurrent attempts cannot all see the same count.
+    async fn record_attempt(&mut self, key: &FailedLoginKey) -> Result<Option<FailedLogins>, FailedLoginStoreError>;
+    // Take back an attempt whose password turned out to be right
+    async fn forgive_attempt(&mut self, key: &FailedLoginKey) -> Result<(), FailedLoginStoreError>;
+    // Forget the failures of the key, e.g. after a successful login
+    async fn clear_failures(&mut self, key: &FailedLoginKey) -> Result<(), FailedLoginStoreError>;
+    // Drop failures that expired, returning how many keys were removed
+    async fn prune_expired(&mut self) -> Result<usize, FailedLoginStoreError>;
+}
+
+#[derive(Debug, PartialEq)]
+pub enum FailedLoginStoreError {
+    UnexpectedError,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum FailedLoginKey {
+    Email(Email),
+    Ip(IpAddr),
+}
+
+impl FailedLoginKey {
+    // How the key is stored, unique across both kinds
+    pub fn as_key(&self) -> String {
+        match self {
+            FailedLoginKey::Email(email) => format!("email:{}", email.as_ref()),
+            FailedLoginKey::Ip(ip) => format!("ip:{}", ip),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FailedLogins {
+    pub count: u32,
+    // Unix timestamp of the latest failure
+    pub last_failed_at: i64,
+}
+
 #[derive(Debug, Clone, PartialEq)]
 pub struct LoginAttemptId(String);
 
@@ -1,3 +1,4 @@
+#[derive(Debug)]
 pub enum AuthAPIError {
     UserAlreadyExists,
     InvalidCredentials,
@@ -5,4 +6,16 @@ pub enum AuthAPIError {
     UnexpectedError,
     MissingToken,
     InvalidToken,
+    LoginAttemptExpired,
+    TotpAlreadyEnabled,
+    TotpNotEnrolled,
+    TwoFANotEnabled,
+    InvalidClient,
+    EmailNotVerified,
+    // Too many failed logins from the client or for the account, retry after
+    // the given number of seconds
+    TooManyLoginAttempts(u64),
+    // The account is locked after too many failed logins, for the given
+    // number of seconds
+    AccountLocked(u64),
 }
\ No newline at end of file
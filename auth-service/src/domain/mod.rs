@@ -1,15 +1,26 @@
 pub mod user;
+pub mod user_id;
 pub mod error;
 pub mod data_stores;
 pub mod email;
 pub mod password;
+pub mod password_hash;
+pub mod recovery_code;
+pub mod refresh_token;
+pub mod totp;
 pub mod email_client;
 pub use email_client::*;
 
 pub use error::AuthAPIError;
-pub use user::User;
+pub use user::{User, TwoFAMethod};
+pub use user_id::UserId;
 pub use data_stores::{UserStore, UserStoreError, BannedTokenStore, 
     BannedTokenStoreError, TwoFACodeStore, TwoFACodeStoreError, LoginAttemptId, 
-    TwoFACode};
+    TwoFACode, RefreshTokenStore, RefreshTokenStoreError, FailedLoginStore, FailedLoginStoreError,
+    FailedLoginKey, FailedLogins};
 pub use email::{Email, EmailParseError};
-pub use password::{Password, PasswordParseError};
\ No newline at end of file
+pub use password::{Password, PasswordParseError};
+pub use password_hash::{PasswordHash, PasswordHashError};
+pub use recovery_code::{RecoveryCode, RecoveryCodeHash};
+pub use refresh_token::{RefreshToken, RefreshTokenHash, RefreshTokenFamilyId, RefreshSession};
+pub use totp::{TotpSecret, TotpError};
\ No newline at end of file
@@ -0,0 +1,307 @@
+use argon2::{
+    password_hash::{self, rand_core::OsRng, PasswordHasher, PasswordVerifier, SaltString},
+    Algorithm, Argon2, Params, Version,
+};
+use tokio::sync::OnceCell;
+
+use super::Password;
+use crate::utils::constants::{ARGON2_ITERATIONS, ARGON2_MEMORY_KIB, ARGON2_PARALLELISM};
+
+// A password hash in PHC string format, e.g. `$argon2id$v=19$m=19456,t=2,p=1$<salt>$<hash>`.
+// Hashes in the bcrypt modular crypt format (`$2b$<cost>$...`), e.g. imported from
+// another system, are accepted as well and upgraded to Argon2id on the next login.
+// The plaintext password is never stored, only this hash.
+#[derive(Clone, Debug, PartialEq)]
+pub struct PasswordHash(String);
+
+#[derive(Debug, PartialEq)]
+pub enum PasswordHashError {
+    InvalidFormat,
+    InvalidParams,
+    PasswordMismatch,
+    UnexpectedError,
+}
+
+// The hashing schemes we are able to verify
+#[derive(Debug, PartialEq)]
+enum HashScheme {
+    Argon2,
+    Bcrypt,
+}
+
+impl PasswordHash {
+    // Parse an already computed hash, e.g. one loaded from a database
+    pub fn parse(hash: String) -> Result<PasswordHash, PasswordHashError> {
+        match scheme(&hash)? {
+            HashScheme::Argon2 => {
+                let parsed_hash = password_hash::PasswordHash::new(&hash)
+                    .map_err(|_| PasswordHashError::InvalidFormat)?;
+                Params::try_from(&parsed_hash).map_err(|_| PasswordHashError::InvalidFormat)?;
+            }
+            HashScheme::Bcrypt => {
+                hash.parse::<bcrypt::HashParts>()
+                    .map_err(|_| PasswordHashError::InvalidFormat)?;
+            }
+        }
+
+        Ok(PasswordHash(hash))
+    }
+
+    // Hash the password with Argon2id on a blocking thread, since hashing is
+    // deliberately CPU and memory intensive.
+    pub async fn from_password(password: Password) -> Result<PasswordHash, PasswordHashError> {
+        tokio::task::spawn_blocking(move || {
+            let salt = SaltString::generate(&mut OsRng);
+            let hash = argon2()?
+                .hash_password(password.as_ref().as_bytes(), &salt)
+                .map_err(|_| PasswordHashError::UnexpectedError)?
+                .to_string();
+
+            Ok(PasswordHash(hash))
+        })
+        .await
+        .map_err(|_| PasswordHashError::UnexpectedError)?
+    }
+
+    // A hash of a random password made with the configured parameters, computed
+    // once. Verifying against it when there is no user takes as long as
+    // verifying against a real hash, so response times do not tell which
+    // accounts exist.
+    pub async fn dummy() -> Result<&'static PasswordHash, PasswordHashError> {
+        static DUMMY: OnceCell<PasswordHash> = OnceCell::const_new();
+
+        DUMMY
+            .get_or_try_init(|| async {
+                // Prefixed to meet the password rules whatever the random part is
+                let random = SaltString::generate(&mut OsRng);
+                let password = Password::parse(format!("Aa1!{}", random.as_str()))
+                    .map_err(|_| PasswordHashError::UnexpectedError)?;
+                PasswordHash::from_password(password).await
+            })
+            .await
+    }
+
+    // Verify the password against this hash on a blocking thread, using the
+    // algorithm the hash was created with. The hash comparison itself is done
+    // in constant time by the argon2 and bcrypt crates.
+    pub async fn verify(&self, password: &Password) -> Result<(), PasswordHashError> {
+        let hash = self.0.clone();
+        let password = password.clone();
+
+        tokio::task::spawn_blocking(move || match scheme(&hash)? {
+            HashScheme::Argon2 => verify_argon2(&hash, &password),
+            HashScheme::Bcrypt => verify_bcrypt(&hash, &password),
+        })
+        .await
+        .map_err(|_| PasswordHashError::UnexpectedError)?
+    }
+
+    // Whether the hash was made with another algorithm or with other Argon2
+    // parameters than the ones currently configured, and should be replaced
+    // by a fresh hash once the plaintext password is known.
+    pub fn needs_rehash(&self) -> bool {
+        if scheme(&self.0) != Ok(HashScheme::Argon2) {
+            return true;
+        }
+
+        let Ok(parsed_hash) = password_hash::PasswordHash::new(&self.0) else {
+            return true;
+        };
+
+        if parsed_hash.algorithm != Algorithm::Argon2id.ident()
+            || parsed_hash.version != Some(Version::V0x13.into())
+        {
+            return true;
+        }
+
+        match Params::try_from(&parsed_hash) {
+            Ok(params) => {
+                params.m_cost() != *ARGON2_MEMORY_KIB
+                    || params.t_cost() != *ARGON2_ITERATIONS
+                    || params.p_cost() != *ARGON2_PARALLELISM
+            }
+            Err(_) => true,
+        }
+    }
+}
+
+impl AsRef<str> for PasswordHash {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+fn scheme(hash: &str) -> Result<HashScheme, PasswordHashError> {
+    if hash.starts_with("$argon2") {
+        Ok(HashScheme::Argon2)
+    } else if ["$2a$", "$2b$", "$2x$", "$2y$"].iter().any(|prefix| hash.starts_with(prefix)) {
+        Ok(HashScheme::Bcrypt)
+    } else {
+        Err(PasswordHashError::InvalidFormat)
+    }
+}
+
+// Argon2 verification reads the variant (argon2i, argon2d or argon2id), version
+// and parameters from the PHC string itself.
+fn verify_argon2(hash: &str, password: &Password) -> Result<(), PasswordHashError> {
+    let parsed_hash =
+        password_hash::PasswordHash::new(hash).map_err(|_| PasswordHashError::InvalidFormat)?;
+
+    Argon2::default()
+        .verify_password(password.as_ref().as_bytes(), &parsed_hash)
+        .map_err(|e| match e {
+            password_hash::Error::Password => PasswordHashError::PasswordMismatch,
+            _ => PasswordHashError::UnexpectedError,
+        })
+}
+
+fn verify_bcrypt(hash: &str, password: &Password) -> Result<(), PasswordHashError> {
+    match bcrypt::verify(password.as_ref(), hash) {
+        Ok(true) => Ok(()),
+        Ok(false) => Err(PasswordHashError::PasswordMismatch),
+        Err(_) => Err(PasswordHashError::InvalidFormat),
+    }
+}
+
+// Argon2id hasher using the configured memory, time and parallelism costs
+fn argon2() -> Result<Argon2<'static>, PasswordHashError> {
+    let params = Params::new(*ARGON2_MEMORY_KIB, *ARGON2_ITERATIONS, *ARGON2_PARALLELISM, None)
+        .map_err(|_| PasswordHashError::InvalidParams)?;
+
+    Ok(Argon2::new(Algorithm::Argon2id, Version::V0x13, params))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn password() -> Password {
+        Password::parse("Password123!".to_string()).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_hash_is_argon2id_phc_string() {
+        let hash = PasswordHash::from_password(password()).await.unwrap();
+        assert!(hash.as_ref().starts_with("$argon2id$v=19$"));
+        assert!(!hash.as_ref().contains("Password123!"));
+    }
+
+    #[tokio::test]
+    async fn test_hash_uses_configured_params() {
+        let hash = PasswordHash::from_password(password()).await.unwrap();
+        let expected = format!(
+            "m={},t={},p={}",
+            *ARGON2_MEMORY_KIB, *ARGON2_ITERATIONS, *ARGON2_PARALLELISM
+        );
+        assert!(hash.as_ref().contains(&expected));
+    }
+
+    #[tokio::test]
+    async fn test_same_password_hashes_differently() {
+        let first = PasswordHash::from_password(password()).await.unwrap();
+        let second = PasswordHash::from_password(password()).await.unwrap();
+        assert_ne!(first, second);
+    }
+
+    #[tokio::test]
+    async fn test_dummy_hash_uses_configured_params() {
+        let dummy = PasswordHash::dummy().await.unwrap();
+        assert!(!dummy.needs_rehash());
+        assert_eq!(PasswordHash::dummy().await.unwrap(), dummy);
+        assert_eq!(
+            dummy.verify(&password()).await,
+            Err(PasswordHashError::PasswordMismatch)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_verify_correct_password() {
+        let hash = PasswordHash::from_password(password()).await.unwrap();
+        assert!(hash.verify(&password()).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_verify_wrong_password() {
+        let hash = PasswordHash::from_password(password()).await.unwrap();
+        let wrong_password = Password::parse("WrongPassword123!".to_string()).unwrap();
+        assert_eq!(
+            hash.verify(&wrong_password).await,
+            Err(PasswordHashError::PasswordMismatch)
+        );
+    }
+
+    #[test]
+    fn test_parse_valid_hash() {
+        let hash = "$argon2id$v=19$m=19456,t=2,p=1$c2FsdHNhbHRzYWx0$6Wk2WpTJ4CknSCSEQNUmo1BPZPHYM3m3dL5M4kjcW8E";
+        assert!(PasswordHash::parse(hash.to_string()).is_ok());
+    }
+
+    #[test]
+    fn test_parse_invalid_hash() {
+        let result = PasswordHash::parse("Password123!".to_string());
+        assert_eq!(result, Err(PasswordHashError::InvalidFormat));
+    }
+
+    #[test]
+    fn test_parse_bcrypt_hash() {
+        let hash = bcrypt::hash("Password123!", 4).unwrap();
+        assert!(PasswordHash::parse(hash).is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_verify_bcrypt_hash() {
+        let hash = PasswordHash::parse(bcrypt::hash("Password123!", 4).unwrap()).unwrap();
+        assert!(hash.verify(&password()).await.is_ok());
+
+        let wrong_password = Password::parse("WrongPassword123!".to_string()).unwrap();
+        assert_eq!(
+            hash.verify(&wrong_password).await,
+            Err(PasswordHashError::PasswordMismatch)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_verify_argon2i_hash() {
+        let salt = SaltString::generate(&mut OsRng);
+        let hash = Argon2::new(Algorithm::Argon2i, Version::V0x13, Params::default())
+            .hash_password(b"Password123!", &salt)
+            .unwrap()
+            .to_string();
+        let hash = PasswordHash::parse(hash).unwrap();
+        assert!(hash.verify(&password()).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_fresh_hash_does_not_need_rehash() {
+        let hash = PasswordHash::from_password(password()).await.unwrap();
+        assert!(!hash.needs_rehash());
+    }
+
+    #[test]
+    fn test_bcrypt_hash_needs_rehash() {
+        let hash = PasswordHash::parse(bcrypt::hash("Password123!", 4).unwrap()).unwrap();
+        assert!(hash.needs_rehash());
+    }
+
+    #[test]
+    fn test_other_argon2_variant_needs_rehash() {
+        let salt = SaltString::generate(&mut OsRng);
+        let params = Params::new(*ARGON2_MEMORY_KIB, *ARGON2_ITERATIONS, *ARGON2_PARALLELISM, None).unwrap();
+        let hash = Argon2::new(Algorithm::Argon2i, Version::V0x13, params)
+            .hash_password(b"Password123!", &salt)
+            .unwrap()
+            .to_string();
+        assert!(PasswordHash::parse(hash).unwrap().needs_rehash());
+    }
+
+    #[test]
+    fn test_weaker_argon2_params_need_rehash() {
+        let salt = SaltString::generate(&mut OsRng);
+        let params = Params::new(8, 1, 1, None).unwrap();
+        let hash = Argon2::new(Algorithm::Argon2id, Version::V0x13, params)
+            .hash_password(b"Password123!", &salt)
+            .unwrap()
+            .to_string();
+        assert!(PasswordHash::parse(hash).unwrap().needs_rehash());
+    }
+}
@@ -0,0 +1,143 @@
+use rand::Rng;
+use sha2::{Digest, Sha256};
+
+// Number of codes in a set, each can be used once in place of a 2FA code
+pub const RECOVERY_CODE_COUNT: usize = 10;
+
+// Codes are 16 characters in groups of 4, e.g. `k7qm-2xhd-w9ta-c4ne`. The
+// alphabet leaves out characters that are easily confused when written down.
+const ALPHABET: &[u8] = b"abcdefghjkmnpqrstuvwxyz23456789";
+const GROUP_COUNT: usize = 4;
+const GROUP_LENGTH: usize = 4;
+
+// A recovery code as shown to the user once, only its hash is stored
+#[derive(Clone, Debug, PartialEq)]
+pub struct RecoveryCode(String);
+
+// SHA-256 of a recovery code, hex encoded. Unlike passwords, recovery codes
+// are random with about 80 bits of entropy, so a fast hash cannot be brute
+// forced and allows looking codes up by their hash.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct RecoveryCodeHash(String);
+
+impl RecoveryCode {
+    // Parse a code as typed by the user, ignoring case and surrounding whitespace
+    pub fn parse(code: String) -> Result<Self, String> {
+        let code = code.trim().to_lowercase();
+        let groups: Vec<&str> = code.split('-').collect();
+
+        let is_valid = groups.len() == GROUP_COUNT
+            && groups.iter().all(|group| {
+                group.len() == GROUP_LENGTH && group.bytes().all(|c| ALPHABET.contains(&c))
+            });
+
+        if !is_valid {
+            return Err("Invalid recovery code format".to_string());
+        }
+
+        Ok(Self(code))
+    }
+
+    // A fresh set of `RECOVERY_CODE_COUNT` codes
+    pub fn generate_set() -> Vec<Self> {
+        (0..RECOVERY_CODE_COUNT).map(|_| Self::generate()).collect()
+    }
+
+    fn generate() -> Self {
+        let mut rng = rand::thread_rng();
+        let groups: Vec<String> = (0..GROUP_COUNT)
+            .map(|_| {
+                (0..GROUP_LENGTH)
+                    .map(|_| ALPHABET[rng.gen_range(0..ALPHABET.len())] as char)
+                    .collect()
+            })
+            .collect();
+
+        Self(groups.join("-"))
+    }
+
+    pub fn hash(&self) -> RecoveryCodeHash {
+        RecoveryCodeHash(hex::encode(Sha256::digest(self.0.as_bytes())))
+    }
+}
+
+impl AsRef<str> for RecoveryCode {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+impl RecoveryCodeHash {
+    // Parse an already computed hash, e.g. one loaded from a database
+    pub fn parse(hash: String) -> Result<Self, String> {
+        if hash.len() != 64 || !hash.bytes().all(|c| c.is_ascii_hexdigit()) {
+            return Err("Invalid recovery code hash".to_string());
+        }
+
+        Ok(Self(hash.to_lowercase()))
+    }
+}
+
+impl AsRef<str> for RecoveryCodeHash {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_generated_codes_can_be_parsed() {
+        for code in RecoveryCode::generate_set() {
+            assert_eq!(RecoveryCode::parse(code.as_ref().to_owned()), Ok(code));
+        }
+    }
+
+    #[test]
+    fn test_generated_codes_are_unique() {
+        let codes = RecoveryCode::generate_set();
+        assert_eq!(codes.len(), RECOVERY_CODE_COUNT);
+        for (i, code) in codes.iter().enumerate() {
+            assert!(!codes[i + 1..].contains(code));
+        }
+    }
+
+    #[test]
+    fn test_parse_normalizes_input() {
+        let code = RecoveryCode::parse(" K7QM-2XHD-W9TA-C4NE\n".to_string()).unwrap();
+        assert_eq!(code.as_ref(), "k7qm-2xhd-w9ta-c4ne");
+    }
+
+    #[test]
+    fn test_parse_invalid_codes() {
+        let invalid_codes = [
+            "",
+            "123456",
+            "k7qm2xhdw9tac4ne",
+            "k7qm-2xhd-w9ta",
+            "k7qm-2xhd-w9ta-c4ne-abcd",
+            "k7qm-2xhd-w9ta-c4n",
+            // 0, 1, i, l and o are not part of the alphabet
+            "k7qm-2xhd-w9ta-c4n0",
+        ];
+        for code in invalid_codes {
+            assert!(RecoveryCode::parse(code.to_string()).is_err(), "{}", code);
+        }
+    }
+
+    #[test]
+    fn test_hash() {
+        let code = RecoveryCode::parse("k7qm-2xhd-w9ta-c4ne".to_string()).unwrap();
+        let hash = code.hash();
+        assert_eq!(hash, RecoveryCode::parse("K7QM-2XHD-W9TA-C4NE".to_string()).unwrap().hash());
+        assert_ne!(hash.as_ref(), code.as_ref());
+        assert_eq!(RecoveryCodeHash::parse(hash.as_ref().to_owned()), Ok(hash));
+    }
+
+    #[test]
+    fn test_parse_invalid_hash() {
+        assert!(RecoveryCodeHash::parse("k7qm-2xhd-w9ta-c4ne".to_string()).is_err());
+    }
+}
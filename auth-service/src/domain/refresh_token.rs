@@ -0,0 +1,134 @@
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use rand::RngCore;
+use sha2::{Digest, Sha256};
+use uuid::Uuid;
+
+use super::UserId;
+
+const TOKEN_LENGTH_BYTES: usize = 32;
+
+// An opaque, long-lived token which can be exchanged for a new access token
+// at `/refresh`. Only its hash is stored server-side.
+#[derive(Clone, Debug, PartialEq)]
+pub struct RefreshToken(String);
+
+// SHA-256 of a refresh token, hex encoded. Refresh tokens are random, so a
+// fast hash is enough and allows looking tokens up by their hash.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct RefreshTokenHash(String);
+
+// Every refresh token descends from one login. Rotating a token keeps the
+// family, so that all tokens of a session can be revoked together.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct RefreshTokenFamilyId(String);
+
+// What a refresh token stands for
+#[derive(Clone, Debug, PartialEq)]
+pub struct RefreshSession {
+    pub user_id: UserId,
+    pub family_id: RefreshTokenFamilyId,
+    // Unix timestamp after which the token is no longer accepted
+    pub expires_at: i64,
+}
+
+impl RefreshToken {
+    pub fn parse(token: String) -> Result<Self, String> {
+        match URL_SAFE_NO_PAD.decode(&token) {
+            Ok(bytes) if bytes.len() == TOKEN_LENGTH_BYTES => Ok(Self(token)),
+            _ => Err("Invalid refresh token".to_string()),
+        }
+    }
+
+    pub fn generate() -> Self {
+        let mut bytes = [0u8; TOKEN_LENGTH_BYTES];
+        rand::thread_rng().fill_bytes(&mut bytes);
+        Self(URL_SAFE_NO_PAD.encode(bytes))
+    }
+
+    pub fn hash(&self) -> RefreshTokenHash {
+        RefreshTokenHash(hex::encode(Sha256::digest(self.0.as_bytes())))
+    }
+}
+
+impl AsRef<str> for RefreshToken {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+impl RefreshTokenHash {
+    // Parse an already computed hash, e.g. one loaded from a database
+    pub fn parse(hash: String) -> Result<Self, String> {
+        if hash.len() != 64 || !hash.bytes().all(|c| c.is_ascii_hexdigit()) {
+            return Err("Invalid refresh token hash".to_string());
+        }
+
+        Ok(Self(hash.to_lowercase()))
+    }
+}
+
+impl AsRef<str> for RefreshTokenHash {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+impl RefreshTokenFamilyId {
+    pub fn parse(id: String) -> Result<Self, String> {
+        Uuid::parse_str(&id)
+            .map(|_| Self(id))
+            .map_err(|_| "Invalid refresh token family ID format".to_string())
+    }
+}
+
+impl Default for RefreshTokenFamilyId {
+    fn default() -> Self {
+        RefreshTokenFamilyId(Uuid::new_v4().to_string())
+    }
+}
+
+impl AsRef<str> for RefreshTokenFamilyId {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_generated_token_can_be_parsed() {
+        let token = RefreshToken::generate();
+        assert_eq!(RefreshToken::parse(token.as_ref().to_owned()), Ok(token));
+    }
+
+    #[test]
+    fn test_generated_tokens_differ() {
+        assert_ne!(RefreshToken::generate(), RefreshToken::generate());
+    }
+
+    #[test]
+    fn test_parse_invalid_token() {
+        assert!(RefreshToken::parse("".to_string()).is_err());
+        assert!(RefreshToken::parse("not a token!".to_string()).is_err());
+        // Valid base64, but too short
+        assert!(RefreshToken::parse(URL_SAFE_NO_PAD.encode([0u8; 16])).is_err());
+    }
+
+    #[test]
+    fn test_hash() {
+        let token = RefreshToken::generate();
+        let hash = token.hash();
+        assert_eq!(hash, token.hash());
+        assert_ne!(hash, RefreshToken::generate().hash());
+        assert_eq!(RefreshTokenHash::parse(hash.as_ref().to_owned()), Ok(hash));
+    }
+
+    #[test]
+    fn test_parse_family_id() {
+        let family_id = RefreshTokenFamilyId::default();
+        assert_eq!(RefreshTokenFamilyId::parse(family_id.as_ref().to_owned()), Ok(family_id));
+        assert!(RefreshTokenFamilyId::parse("family".to_string()).is_err());
+    }
+}
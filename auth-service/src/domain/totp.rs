@@ -0,0 +1,221 @@
+use std::{
+    io::Cursor,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use base64::{engine::general_purpose::STANDARD, Engine};
+use image::{ImageFormat, Luma};
+use qrcode::{render::svg, QrCode};
+use rand::RngCore;
+use totp_rs::{Algorithm, Secret, TOTP};
+
+use super::{data_stores::TwoFACode, Email};
+use crate::utils::constants::{TOTP_DRIFT_STEPS, TOTP_ISSUER};
+
+// Length of generated secrets, 160 bits as recommended by RFC 4226
+const SECRET_LENGTH_BYTES: usize = 20;
+// Codes are 6 digits long and change every 30 seconds, the defaults every
+// authenticator app supports
+const CODE_DIGITS: usize = 6;
+const STEP_SECONDS: u64 = 30;
+// Minimum size of the rendered QR codes, in pixels
+const QR_CODE_SIZE: u32 = 256;
+
+// The secret shared with a user's authenticator app for RFC 6238 time-based
+// one-time passwords, base32 encoded as it appears in otpauth:// URIs
+#[derive(Clone, Debug, PartialEq)]
+pub struct TotpSecret(String);
+
+#[derive(Debug, PartialEq)]
+pub enum TotpError {
+    InvalidSecret,
+    InvalidAccountName,
+    UnexpectedError,
+}
+
+impl TotpSecret {
+    // Parse an already generated secret, e.g. one loaded from a database
+    pub fn parse(secret: String) -> Result<Self, TotpError> {
+        let bytes = Secret::Encoded(secret.clone())
+            .to_bytes()
+            .map_err(|_| TotpError::InvalidSecret)?;
+
+        // RFC 4226 requires at least 128 bits
+        if bytes.len() < 16 {
+            return Err(TotpError::InvalidSecret);
+        }
+
+        Ok(Self(secret))
+    }
+
+    pub fn generate() -> Self {
+        let mut bytes = [0u8; SECRET_LENGTH_BYTES];
+        rand::thread_rng().fill_bytes(&mut bytes);
+        Self(Secret::Raw(bytes.to_vec()).to_encoded().to_string())
+    }
+
+    // Check the code against the current time, also accepting codes from
+    // `TOTP_DRIFT_STEPS` steps before and after to allow for clock drift.
+    // Returns the time step the code belongs to, so that the caller can make
+    // sure it is only used once.
+    pub fn verify(&self, code: &TwoFACode, account: &Email) -> Result<Option<u64>, TotpError> {
+        let totp = self.totp(account)?;
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_err(|_| TotpError::UnexpectedError)?
+            .as_secs();
+        let current_step = now / STEP_SECONDS;
+        let drift_steps = u64::from(*TOTP_DRIFT_STEPS);
+
+        // The latest step wins should the code happen to match several
+        let step = (current_step.saturating_sub(drift_steps)..=current_step.saturating_add(drift_steps))
+            .rev()
+            .find(|step| totp.check(code.as_ref(), step * STEP_SECONDS));
+        Ok(step)
+    }
+
+    // The otpauth:// URI authenticator apps are provisioned with
+    pub fn provisioning_uri(&self, account: &Email) -> Result<String, TotpError> {
+        Ok(self.totp(account)?.get_url())
+    }
+
+    // The provisioning URI as a base64 encoded PNG QR code
+    pub fn qr_code_png(&self, account: &Email) -> Result<String, TotpError> {
+        let image = qr_code(&self.provisioning_uri(account)?)?
+            .render::<Luma<u8>>()
+            .min_dimensions(QR_CODE_SIZE, QR_CODE_SIZE)
+            .build();
+
+        let mut png = Vec::new();
+        image
+            .write_to(&mut Cursor::new(&mut png), ImageFormat::Png)
+            .map_err(|_| TotpError::UnexpectedError)?;
+
+        Ok(STANDARD.encode(png))
+    }
+
+    // The provisioning URI as an SVG QR code
+    pub fn qr_code_svg(&self, account: &Email) -> Result<String, TotpError> {
+        let svg = qr_code(&self.provisioning_uri(account)?)?
+            .render::<svg::Color>()
+            .min_dimensions(QR_CODE_SIZE, QR_CODE_SIZE)
+            .build();
+
+        Ok(svg)
+    }
+
+    fn totp(&self, account: &Email) -> Result<TOTP, TotpError> {
+        let secret = Secret::Encoded(self.0.clone())
+            .to_bytes()
+            .map_err(|_| TotpError::InvalidSecret)?;
+        // Drift is handled by `verify`, one step at a time
+        TOTP::new(
+            Algorithm::SHA1,
+            CODE_DIGITS,
+            0,
+            STEP_SECONDS,
+            secret,
+            Some(TOTP_ISSUER.clone()),
+            account.as_ref().to_owned(),
+        )
+        .map_err(|e| match e {
+            totp_rs::TotpUrlError::AccountName(_) => TotpError::InvalidAccountName,
+            totp_rs::TotpUrlError::SecretSize(_) => TotpError::InvalidSecret,
+            _ => TotpError::UnexpectedError,
+        })
+    }
+}
+
+impl AsRef<str> for TotpSecret {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+fn qr_code(uri: &str) -> Result<QrCode, TotpError> {
+    QrCode::new(uri.as_bytes()).map_err(|_| TotpError::UnexpectedError)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn account() -> Email {
+        Email::parse("test@example.com".to_string()).unwrap()
+    }
+
+    fn code_at(secret: &TotpSecret, time: u64) -> TwoFACode {
+        let code = secret.totp(&account()).unwrap().generate(time);
+        TwoFACode::parse(code).unwrap()
+    }
+
+    fn now() -> u64 {
+        chrono::Utc::now().timestamp() as u64
+    }
+
+    #[test]
+    fn test_generated_secret_can_be_parsed() {
+        let secret = TotpSecret::generate();
+        assert_eq!(TotpSecret::parse(secret.as_ref().to_owned()), Ok(secret));
+    }
+
+    #[test]
+    fn test_generated_secrets_differ() {
+        assert_ne!(TotpSecret::generate(), TotpSecret::generate());
+    }
+
+    #[test]
+    fn test_parse_invalid_secret() {
+        assert_eq!(TotpSecret::parse("not base32!".to_string()), Err(TotpError::InvalidSecret));
+        // Valid base32, but only 80 bits
+        assert_eq!(TotpSecret::parse("JBSWY3DPEHPK3PXP".to_string()), Err(TotpError::InvalidSecret));
+    }
+
+    #[test]
+    fn test_verify_current_code() {
+        let secret = TotpSecret::generate();
+        let code = code_at(&secret, now());
+        assert_eq!(secret.verify(&code, &account()), Ok(Some(now() / STEP_SECONDS)));
+    }
+
+    #[test]
+    fn test_verify_code_within_drift_window() {
+        let secret = TotpSecret::generate();
+        let code = code_at(&secret, now() - STEP_SECONDS);
+        // Another step in the window could produce the same code by chance
+        if code != code_at(&secret, now()) && code != code_at(&secret, now() + STEP_SECONDS) {
+            assert_eq!(secret.verify(&code, &account()), Ok(Some(now() / STEP_SECONDS - 1)));
+        }
+    }
+
+    #[test]
+    fn test_verify_code_outside_drift_window() {
+        let secret = TotpSecret::generate();
+        let code = code_at(&secret, now() - 10 * STEP_SECONDS);
+        // Another window could produce the same code by chance
+        if code != code_at(&secret, now()) {
+            assert_eq!(secret.verify(&code, &account()), Ok(None));
+        }
+    }
+
+    #[test]
+    fn test_provisioning_uri() {
+        let secret = TotpSecret::generate();
+        let uri = secret.provisioning_uri(&account()).unwrap();
+        assert!(uri.starts_with("otpauth://totp/"));
+        assert!(uri.contains("test%40example.com"));
+        assert!(uri.contains(&format!("secret={}", secret.as_ref())));
+        assert!(uri.contains(&format!("issuer={}", *TOTP_ISSUER)));
+    }
+
+    #[test]
+    fn test_qr_codes() {
+        let secret = TotpSecret::generate();
+
+        let png = STANDARD.decode(secret.qr_code_png(&account()).unwrap()).unwrap();
+        assert!(png.starts_with(b"\x89PNG"));
+
+        let svg = secret.qr_code_svg(&account()).unwrap();
+        assert!(svg.contains("<svg"));
+    }
+}
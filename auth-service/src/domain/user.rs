@@ -1,26 +1,108 @@
-use crate::domain::{Email, Password};
+use crate::domain::{Email, PasswordHash, TotpSecret, UserId};
 
 #[derive(Clone, Debug, PartialEq)]
 pub struct User {
+    pub id: UserId,
     pub email: Email,
-    password: Password,
-    pub requires_2fa: bool
+    password_hash: PasswordHash,
+    pub requires_2fa: bool,
+    two_fa_method: TwoFAMethod,
+    // Set once the user starts enrolling an authenticator app, the method only
+    // switches to `Totp` after the first code has been confirmed
+    totp_secret: Option<TotpSecret>,
+    // New users have to prove they own their email address, by following the
+    // link emailed to them at signup
+    email_verified: bool,
+    // Unix timestamp after which the account is purged, set while the user
+    // asked for it to be deleted and has not logged in since
+    deletion_due_at: Option<i64>,
+}
+
+// How the second factor is delivered to users with `requires_2fa`
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum TwoFAMethod {
+    // A code sent through the `EmailClient`
+    #[default]
+    Email,
+    // A code from an authenticator app
+    Totp,
+}
+
+impl TwoFAMethod {
+    pub fn parse(method: &str) -> Result<Self, String> {
+        match method {
+            "email" => Ok(TwoFAMethod::Email),
+            "totp" => Ok(TwoFAMethod::Totp),
+            _ => Err(format!("Unknown 2FA method: {}", method)),
+        }
+    }
+}
+
+impl AsRef<str> for TwoFAMethod {
+    fn as_ref(&self) -> &str {
+        match self {
+            TwoFAMethod::Email => "email",
+            TwoFAMethod::Totp => "totp",
+        }
+    }
 }
 
 impl User {
-    pub fn new(email: Email, password: Password, requires_2fa: bool) -> Self {
+    // A new user with a new ID, stores set the ID of users they load
+    pub fn new(email: Email, password_hash: PasswordHash, requires_2fa: bool) -> Self {
         Self {
+            id: UserId::default(),
             email,
-            password,
-            requires_2fa
+            password_hash,
+            requires_2fa,
+            two_fa_method: TwoFAMethod::default(),
+            totp_secret: None,
+            email_verified: false,
+            deletion_due_at: None,
         }
     }
 
-    pub fn password(&self) -> &Password {
-        &self.password
+    pub fn password_hash(&self) -> &PasswordHash {
+        &self.password_hash
+    }
+
+    pub fn set_password_hash(&mut self, password_hash: PasswordHash) {
+        self.password_hash = password_hash;
     }
 
     pub fn requires_2fa(&self) -> bool {
         self.requires_2fa
     }
+
+    pub fn two_fa_method(&self) -> TwoFAMethod {
+        self.two_fa_method
+    }
+
+    pub fn set_two_fa_method(&mut self, two_fa_method: TwoFAMethod) {
+        self.two_fa_method = two_fa_method;
+    }
+
+    pub fn totp_secret(&self) -> Option<&TotpSecret> {
+        self.totp_secret.as_ref()
+    }
+
+    pub fn set_totp_secret(&mut self, totp_secret: Option<TotpSecret>) {
+        self.totp_secret = totp_secret;
+    }
+
+    pub fn email_verified(&self) -> bool {
+        self.email_verified
+    }
+
+    pub fn set_email_verified(&mut self, email_verified: bool) {
+        self.email_verified = email_verified;
+    }
+
+    pub fn deletion_due_at(&self) -> Option<i64> {
+        self.deletion_due_at
+    }
+
+    pub fn set_deletion_due_at(&mut self, deletion_due_at: Option<i64>) {
+        self.deletion_due_at = deletion_due_at;
+    }
 }
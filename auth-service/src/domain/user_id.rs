@@ -0,0 +1,44 @@
+use uuid::Uuid;
+
+// Stable identifier of a user, which unlike their email address never changes.
+// It is the `sub` of their tokens, so tokens do not carry the address itself.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct UserId(String);
+
+impl UserId {
+    pub fn parse(id: String) -> Result<Self, String> {
+        Uuid::parse_str(&id)
+            .map(|uuid| Self(uuid.hyphenated().to_string()))
+            .map_err(|_| "Invalid user ID format".to_string())
+    }
+}
+
+impl Default for UserId {
+    fn default() -> Self {
+        UserId(Uuid::new_v4().to_string())
+    }
+}
+
+impl AsRef<str> for UserId {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_user_id() {
+        let id = UserId::default();
+        assert_eq!(UserId::parse(id.as_ref().to_owned()), Ok(id.clone()));
+        assert_eq!(UserId::parse(id.as_ref().to_uppercase()), Ok(id));
+        assert!(UserId::parse("test@example.com".to_string()).is_err());
+    }
+
+    #[test]
+    fn test_generated_ids_differ() {
+        assert_ne!(UserId::default(), UserId::default());
+    }
+}
@@ -1,11 +1,14 @@
-use std::error::Error;
+use std::{error::Error, net::SocketAddr};
 use axum::{
-    http::{Method, StatusCode},
+    extract::{connect_info::IntoMakeServiceWithConnectInfo, ConnectInfo},
+    http::{header, HeaderValue, Method, StatusCode},
+    middleware::AddExtension,
     response::{IntoResponse, Response},
-    routing::post,
+    routing::{delete, get, post},
     serve::Serve,
     Json, Router,
 };
+use sqlx::{migrate::Migrator, postgres::PgPoolOptions, PgPool};
 use tower_http::{cors::CorsLayer, services::ServeDir};
 use app_state::AppState;
 use domain::AuthAPIError;
@@ -17,9 +20,14 @@ pub mod services;
 pub mod app_state;
 pub mod utils;
 
+// Migrations are embedded into the binary and run on startup
+pub static POSTGRES_MIGRATOR: Migrator = sqlx::migrate!("./migrations/postgres");
+#[cfg(feature = "sqlite")]
+pub static SQLITE_MIGRATOR: Migrator = sqlx::migrate!("./migrations/sqlite");
+
 // This struct encapsulates our application-related logic.
 pub struct Application {
-    server: Serve<Router, Router>,
+    server: Serve<IntoMakeServiceWithConnectInfo<Router, SocketAddr>, AddExtension<Router, ConnectInfo<SocketAddr>>>,
     pub address: String,
 }
 
@@ -31,7 +39,7 @@ impl Application {
         ];
 
         let cors = CorsLayer::new()
-            .allow_methods([Method::GET, Method::POST])
+            .allow_methods([Method::GET, Method::POST, Method::DELETE])
             // Allow cookies to be included in requests
             .allow_credentials(true)
             .allow_origin(allowed_origins);
@@ -42,13 +50,37 @@ impl Application {
             .route("/login", post(routes::login))
             .route("/logout", post(routes::logout))
             .route("/verify-2fa", post(routes::verify_2fa))
+            .route("/verify-email", get(routes::verify_email))
+            .route("/verify-email/resend", post(routes::resend_verification_email))
+            .route("/password-reset/request", post(routes::request_password_reset))
+            .route("/password-reset/confirm", post(routes::confirm_password_reset))
+            .route("/change-password", post(routes::change_password))
+            .route("/change-email", post(routes::change_email))
+            .route(
+                "/change-email/confirm",
+                get(routes::confirm_email_change_page).post(routes::confirm_email_change),
+            )
+            .route(
+                "/change-email/cancel",
+                get(routes::cancel_email_change_page).post(routes::cancel_email_change),
+            )
+            .route("/account", delete(routes::delete_account))
+            .route("/refresh", post(routes::refresh))
             .route("/verify-token", post(routes::verify_token))
+            .route("/introspect", post(routes::introspect))
+            .route("/revoke", post(routes::revoke))
+            .route("/2fa/totp/enroll", post(routes::enroll_totp))
+            .route("/2fa/totp/confirm", post(routes::confirm_totp))
+            .route("/2fa/recovery-codes", post(routes::regenerate_recovery_codes))
+            .route("/metrics", get(routes::metrics))
+            .route("/.well-known/jwks.json", get(routes::jwks))
             .with_state(app_state)
             .layer(cors);
 
         let listener = tokio::net::TcpListener::bind(address).await?;
         let address = listener.local_addr()?.to_string();
-        let server = axum::serve(listener, router);
+        // Login throttling needs the client's address, see `ClientIp`
+        let server = axum::serve(listener, router.into_make_service_with_connect_info::<SocketAddr>());
 
         // Create a new Application instance and return it
         Ok(Self { server, address })
@@ -68,17 +100,60 @@ pub struct ErrorResponse {
 
 impl IntoResponse for AuthAPIError {
     fn into_response(self) -> Response {
+        // RFC 6749 asks for a challenge when client authentication fails
+        let challenge = matches!(self, AuthAPIError::InvalidClient);
+        let retry_after = match self {
+            AuthAPIError::TooManyLoginAttempts(seconds) | AuthAPIError::AccountLocked(seconds) => Some(seconds),
+            _ => None,
+        };
         let (status, error_message) = match self {
             AuthAPIError::UserAlreadyExists => (StatusCode::CONFLICT, "User already exists"),
             AuthAPIError::InvalidCredentials => (StatusCode::BAD_REQUEST, "Invalid credentials"),
             AuthAPIError::IncorrectCredentials => (StatusCode::UNAUTHORIZED, "Incorrect credentials"),
             AuthAPIError::UnexpectedError =>  (StatusCode::INTERNAL_SERVER_ERROR, "Unexpected error"),
             AuthAPIError::MissingToken => (StatusCode::BAD_REQUEST, "Token needed"),
-            AuthAPIError::InvalidToken => (StatusCode::UNAUTHORIZED, "User unauthorized")
+            AuthAPIError::InvalidToken => (StatusCode::UNAUTHORIZED, "User unauthorized"),
+            AuthAPIError::LoginAttemptExpired => (StatusCode::GONE, "Login attempt expired, please log in again"),
+            AuthAPIError::TotpAlreadyEnabled => (StatusCode::CONFLICT, "Authenticator app already enabled"),
+            AuthAPIError::TotpNotEnrolled => (StatusCode::BAD_REQUEST, "Authenticator app enrollment not started"),
+            AuthAPIError::TwoFANotEnabled => (StatusCode::BAD_REQUEST, "2FA is not enabled"),
+            AuthAPIError::InvalidClient => (StatusCode::UNAUTHORIZED, "Invalid client credentials"),
+            AuthAPIError::EmailNotVerified => (StatusCode::FORBIDDEN, "Email address not verified"),
+            AuthAPIError::TooManyLoginAttempts(_) => (StatusCode::TOO_MANY_REQUESTS, "Too many failed logins, please retry later"),
+            AuthAPIError::AccountLocked(_) => (StatusCode::LOCKED, "Account locked after too many failed logins"),
         };
         let body = Json(ErrorResponse {
             error: error_message.to_string(),
         });
-        (status, body).into_response()
+        let mut response = (status, body).into_response();
+        if challenge {
+            response.headers_mut().insert(header::WWW_AUTHENTICATE, HeaderValue::from_static("Basic"));
+        }
+        if let Some(seconds) = retry_after {
+            response.headers_mut().insert(header::RETRY_AFTER, HeaderValue::from(seconds));
+        }
+        response
     }
-}
\ No newline at end of file
+}
+
+// Create a Postgres connection pool
+pub async fn get_postgres_pool(url: &str) -> Result<PgPool, sqlx::Error> {
+    PgPoolOptions::new().max_connections(5).connect(url).await
+}
+
+// Connect to Redis. The connection manager reconnects on its own after failures
+// and can be cloned cheaply to share one multiplexed connection.
+pub async fn get_redis_connection(url: &str) -> redis::RedisResult<redis::aio::ConnectionManager> {
+    let client = redis::Client::open(url)?;
+    redis::aio::ConnectionManager::new(client).await
+}
+
+// Create a SQLite connection pool, creating the database file if it does not exist yet
+#[cfg(feature = "sqlite")]
+pub async fn get_sqlite_pool(url: &str) -> Result<sqlx::SqlitePool, sqlx::Error> {
+    use sqlx::sqlite::{SqliteConnectOptions, SqlitePoolOptions};
+    use std::str::FromStr;
+
+    let options = SqliteConnectOptions::from_str(url)?.create_if_missing(true);
+    SqlitePoolOptions::new().max_connections(5).connect_with(options).await
+}
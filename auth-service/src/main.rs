@@ -1,19 +1,71 @@
 use auth_service::{
-    app_state::AppState, services::{hashmap_user_store::HashmapUserStore, HashmapTwoFACodeStore, HashsetBannedTokenStore, MockEmailClient}, utils::constants::prod,
-    Application,
+    app_state::{
+        AppState, BannedTokenStoreType, FailedLoginStoreType, RefreshTokenStoreType, TwoFACodeStoreType, UserStoreType,
+    },
+    get_postgres_pool, get_redis_connection,
+    services::{
+        hashmap_user_store::HashmapUserStore, HashmapFailedLoginStore, HashmapRefreshTokenStore, HashmapTwoFACodeStore,
+        HashsetBannedTokenStore, MockEmailClient, PostgresUserStore, RedisBannedTokenStore, RedisFailedLoginStore,
+        RedisRefreshTokenStore, RedisTwoFACodeStore,
+    },
+    utils::{
+        account_deletion_sweeper::spawn_account_deletion_sweeper,
+        banned_token_sweeper::spawn_banned_token_sweeper,
+        failed_login_sweeper::spawn_failed_login_sweeper,
+        keyring_reloader::spawn_keyring_reloader,
+        keys::keyring,
+        constants::{
+            prod, ACCOUNT_DELETION_SWEEP_INTERVAL_SECONDS, BANNED_TOKEN_SWEEP_INTERVAL_SECONDS, DATABASE_URL,
+            FAILED_LOGIN_SWEEP_INTERVAL_SECONDS, LOGIN_LOCKOUT_SECONDS, REDIS_URL, TWO_FA_CODE_TTL_SECONDS,
+        },
+    },
+    Application, POSTGRES_MIGRATOR,
 };
-use std::sync::Arc;
+use sqlx::PgPool;
+use std::{sync::Arc, time::Duration};
 use tokio::sync::RwLock;
 
 
 #[tokio::main]
 async fn main() {
-    let user_store: Arc<RwLock<dyn auth_service::domain::data_stores::UserStore + Send + Sync>> = Arc::new(RwLock::new(HashmapUserStore::default()));
-    let banned_token_store: Arc<RwLock<dyn auth_service::domain::data_stores::BannedTokenStore + Send + Sync>> = Arc::new(RwLock::new(HashsetBannedTokenStore::default()));
-    let two_fa_code_store: Arc<RwLock<dyn auth_service::domain::data_stores::TwoFACodeStore + Send + Sync>> = Arc::new(RwLock::new(HashmapTwoFACodeStore::default()));
+    // Fail on startup rather than on the first login if the signing keys are misconfigured
+    keyring();
+    spawn_keyring_reloader();
+
+    let (user_store, mut banned_token_store, mut two_fa_code_store, mut refresh_token_store, mut failed_login_store) =
+        configure_stores().await;
+
+    // Share banned tokens, 2FA codes, refresh tokens and failed logins between
+    // replicas when Redis is configured
+    if let Some(url) = REDIS_URL.as_deref() {
+        (banned_token_store, two_fa_code_store, refresh_token_store, failed_login_store) = configure_redis_stores(url).await;
+    }
+
+    spawn_banned_token_sweeper(
+        banned_token_store.clone(),
+        Duration::from_secs((*BANNED_TOKEN_SWEEP_INTERVAL_SECONDS).into()),
+    );
+
+    spawn_failed_login_sweeper(
+        failed_login_store.clone(),
+        Duration::from_secs((*FAILED_LOGIN_SWEEP_INTERVAL_SECONDS).into()),
+    );
+
     let email_client: Arc<RwLock<dyn auth_service::domain::EmailClient + Send + Sync>> = Arc::new(RwLock::new(MockEmailClient));
-    
-    let app_state = AppState::new(user_store, banned_token_store, two_fa_code_store, email_client);
+
+    let app_state = AppState::new(
+        user_store,
+        banned_token_store,
+        two_fa_code_store,
+        refresh_token_store,
+        failed_login_store,
+        email_client,
+    );
+
+    spawn_account_deletion_sweeper(
+        app_state.clone(),
+        Duration::from_secs((*ACCOUNT_DELETION_SWEEP_INTERVAL_SECONDS).into()),
+    );
 
     let app = Application::build(app_state, prod::APP_ADDRESS)
         .await
@@ -21,3 +73,99 @@ async fn main() {
 
     app.run().await.expect("Failed to run app");
 }
+
+// The DATABASE_URL scheme selects the storage backend:
+// - postgres://... keeps users in Postgres
+// - sqlite://... keeps users, banned tokens, 2FA codes, refresh tokens and
+//   failed logins in a single SQLite file (requires the `sqlite` cargo feature)
+// - unset keeps everything in memory
+// Everything but users moves to Redis when REDIS_URL is set, see main.
+async fn configure_stores() -> (UserStoreType, BannedTokenStoreType, TwoFACodeStoreType, RefreshTokenStoreType, FailedLoginStoreType) {
+    let in_memory_banned_token_store = || Arc::new(RwLock::new(HashsetBannedTokenStore::default()));
+    let in_memory_two_fa_code_store = || Arc::new(RwLock::new(HashmapTwoFACodeStore::new((*TWO_FA_CODE_TTL_SECONDS).into())));
+    let in_memory_refresh_token_store = || Arc::new(RwLock::new(HashmapRefreshTokenStore::default()));
+    let in_memory_failed_login_store = || Arc::new(RwLock::new(HashmapFailedLoginStore::new((*LOGIN_LOCKOUT_SECONDS).into())));
+
+    match DATABASE_URL.as_deref() {
+        Some(url) if url.starts_with("sqlite:") => configure_sqlite_stores(url).await,
+        Some(url) => {
+            let pool = configure_postgresql(url).await;
+            (
+                Arc::new(RwLock::new(PostgresUserStore::new(pool))),
+                in_memory_banned_token_store(),
+                in_memory_two_fa_code_store(),
+                in_memory_refresh_token_store(),
+                in_memory_failed_login_store(),
+            )
+        }
+        None => {
+            println!("DATABASE_URL is not set, users are stored in memory");
+            (
+                Arc::new(RwLock::new(HashmapUserStore::default())),
+                in_memory_banned_token_store(),
+                in_memory_two_fa_code_store(),
+                in_memory_refresh_token_store(),
+                in_memory_failed_login_store(),
+            )
+        }
+    }
+}
+
+async fn configure_postgresql(url: &str) -> PgPool {
+    let pg_pool = get_postgres_pool(url)
+        .await
+        .expect("Failed to create Postgres connection pool!");
+
+    POSTGRES_MIGRATOR
+        .run(&pg_pool)
+        .await
+        .expect("Failed to run migrations");
+
+    pg_pool
+}
+
+async fn configure_redis_stores(url: &str) -> (BannedTokenStoreType, TwoFACodeStoreType, RefreshTokenStoreType, FailedLoginStoreType) {
+    let redis_connection = get_redis_connection(url)
+        .await
+        .expect("Failed to connect to Redis");
+
+    (
+        Arc::new(RwLock::new(RedisBannedTokenStore::new(redis_connection.clone()))),
+        Arc::new(RwLock::new(RedisTwoFACodeStore::new(redis_connection.clone(), (*TWO_FA_CODE_TTL_SECONDS).into()))),
+        Arc::new(RwLock::new(RedisRefreshTokenStore::new(redis_connection.clone()))),
+        Arc::new(RwLock::new(RedisFailedLoginStore::new(redis_connection, (*LOGIN_LOCKOUT_SECONDS).into()))),
+    )
+}
+
+#[cfg(feature = "sqlite")]
+async fn configure_sqlite_stores(url: &str) -> (UserStoreType, BannedTokenStoreType, TwoFACodeStoreType, RefreshTokenStoreType, FailedLoginStoreType) {
+    use auth_service::{
+        get_sqlite_pool,
+        services::{
+            SqliteBannedTokenStore, SqliteFailedLoginStore, SqliteRefreshTokenStore, SqliteTwoFACodeStore, SqliteUserStore,
+        },
+        SQLITE_MIGRATOR,
+    };
+
+    let sqlite_pool = get_sqlite_pool(url)
+        .await
+        .expect("Failed to create SQLite connection pool!");
+
+    SQLITE_MIGRATOR
+        .run(&sqlite_pool)
+        .await
+        .expect("Failed to run migrations");
+
+    (
+        Arc::new(RwLock::new(SqliteUserStore::new(sqlite_pool.clone()))),
+        Arc::new(RwLock::new(SqliteBannedTokenStore::new(sqlite_pool.clone()))),
+        Arc::new(RwLock::new(SqliteTwoFACodeStore::new(sqlite_pool.clone(), (*TWO_FA_CODE_TTL_SECONDS).into()))),
+        Arc::new(RwLock::new(SqliteRefreshTokenStore::new(sqlite_pool.clone()))),
+        Arc::new(RwLock::new(SqliteFailedLoginStore::new(sqlite_pool, (*LOGIN_LOCKOUT_SECONDS).into()))),
+    )
+}
+
+#[cfg(not(feature = "sqlite"))]
+async fn configure_sqlite_stores(_url: &str) -> (UserStoreType, BannedTokenStoreType, TwoFACodeStoreType, RefreshTokenStoreType, FailedLoginStoreType) {
+    panic!("DATABASE_URL points to SQLite, but auth-service was built without the `sqlite` feature");
+}
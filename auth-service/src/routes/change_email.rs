@@ -0,0 +1,243 @@
+use axum::{
+    extract::{Query, State},
+    http::StatusCode,
+    response::{Html, IntoResponse},
+    Form, Json,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    app_state::AppState,
+    domain::{AuthAPIError, Email, Password, User, UserStore, UserStoreError},
+    utils::{
+        auth::{self, authenticated_user, AuthToken, EmailChange},
+        client_ip::ClientIp,
+        constants::{EMAIL_VERIFICATION_TTL_SECONDS, PUBLIC_URL},
+        login_throttle,
+    },
+};
+
+// Start moving the logged in user to a new email address. The change is only
+// made once it is confirmed through the link sent to the new address, and the
+// old address is told about it with a link to cancel it.
+pub async fn change_email(
+    State(state): State<AppState>,
+    ClientIp(ip): ClientIp,
+    token: AuthToken,
+    Json(request): Json<ChangeEmailRequest>,
+) -> Result<impl IntoResponse, AuthAPIError> {
+    let user = authenticated_user(&token, &state).await?;
+
+    let new_email = Email::parse(request.new_email).map_err(|_| AuthAPIError::InvalidCredentials)?;
+    let password = Password::parse(request.password).map_err(|_| AuthAPIError::InvalidCredentials)?;
+    if new_email == user.email {
+        return Err(AuthAPIError::InvalidCredentials);
+    }
+
+    login_throttle::verify_password(&user, &password, ip, &state).await?;
+
+    match state.user_store.read().await.get_user_by_email(&new_email).await {
+        Ok(_) => return Err(AuthAPIError::UserAlreadyExists),
+        Err(UserStoreError::UserNotFound) => {}
+        Err(_) => return Err(AuthAPIError::UnexpectedError),
+    }
+
+    send_email_change_emails(&user, &new_email, &state).await?;
+
+    Ok((
+        StatusCode::OK,
+        Json(ChangeEmailResponse {
+            message: "A confirmation link was sent to the new email address".to_owned(),
+        }),
+    ))
+}
+
+// Target of the link sent to the new address. Opening it changes nothing,
+// since mail scanners and link previews fetch links on their own; the page
+// asks the user to submit the token to `confirm_email_change`.
+pub async fn confirm_email_change_page(Query(request): Query<EmailChangeTokenRequest>) -> Html<String> {
+    token_form_page(
+        "Confirm your new email address",
+        "/change-email/confirm",
+        &request.token,
+        "Confirm email change",
+    )
+}
+
+// Move the user over to the new address. Every session ends, since whoever
+// controlled the old address may be logged in.
+pub async fn confirm_email_change(
+    State(state): State<AppState>,
+    Form(request): Form<EmailChangeTokenRequest>,
+) -> Result<impl IntoResponse, AuthAPIError> {
+    let change = auth::validate_email_change_token(&request.token).map_err(|_| AuthAPIError::InvalidToken)?;
+
+    {
+        // Holding the lock until the token is banned keeps a concurrent cancel
+        // or a second confirmation from slipping in between
+        let mut user_store = state.user_store.write().await;
+        ensure_pending(&change, &*user_store, &state).await?;
+
+        user_store
+            .change_email(&change.user_id, change.new_email.clone())
+            .await
+            .map_err(|e| match e {
+                // Someone signed up with the new address in the meantime
+                UserStoreError::UserAlreadyExists => AuthAPIError::UserAlreadyExists,
+                _ => AuthAPIError::UnexpectedError,
+            })?;
+
+        ban_email_change(&change, &state).await?;
+    }
+
+    auth::revoke_all_sessions(&change.user_id, None, &state).await?;
+
+    Ok((
+        StatusCode::OK,
+        Json(ChangeEmailResponse {
+            message: "Email address changed, please log in again".to_owned(),
+        }),
+    ))
+}
+
+// Target of the link sent to the old address, a page submitting the token to
+// `cancel_email_change`
+pub async fn cancel_email_change_page(Query(request): Query<EmailChangeTokenRequest>) -> Html<String> {
+    token_form_page(
+        "Cancel the change of your email address",
+        "/change-email/cancel",
+        &request.token,
+        "Cancel email change",
+    )
+}
+
+// Stop a change that was not confirmed yet
+pub async fn cancel_email_change(
+    State(state): State<AppState>,
+    Form(request): Form<EmailChangeTokenRequest>,
+) -> Result<impl IntoResponse, AuthAPIError> {
+    let change = auth::validate_email_change_cancel_token(&request.token)
+        .map_err(|_| AuthAPIError::InvalidToken)?;
+
+    {
+        let user_store = state.user_store.write().await;
+        ensure_pending(&change, &*user_store, &state).await?;
+
+        ban_email_change(&change, &state).await?;
+    }
+
+    Ok((
+        StatusCode::OK,
+        Json(ChangeEmailResponse {
+            message: "Email change cancelled".to_owned(),
+        }),
+    ))
+}
+
+// Fail if the change was confirmed or cancelled before, or the user is gone
+// or no longer has the address the change was requested from
+async fn ensure_pending(
+    change: &EmailChange,
+    user_store: &(dyn UserStore + Send + Sync),
+    state: &AppState,
+) -> Result<(), AuthAPIError> {
+    match user_store.get_user(&change.user_id).await {
+        Ok(user) if user.email == change.email => {}
+        Ok(_) | Err(UserStoreError::UserNotFound) => return Err(AuthAPIError::InvalidToken),
+        Err(_) => return Err(AuthAPIError::UnexpectedError),
+    }
+
+    let banned_token_store = state.banned_token_store.read().await;
+    match banned_token_store.is_token_exists(&change.jti).await {
+        Ok(false) => Ok(()),
+        Ok(true) => Err(AuthAPIError::InvalidToken),
+        Err(_) => Err(AuthAPIError::UnexpectedError),
+    }
+}
+
+// Use up both links of the change
+async fn ban_email_change(change: &EmailChange, state: &AppState) -> Result<(), AuthAPIError> {
+    let mut banned_token_store = state.banned_token_store.write().await;
+    banned_token_store
+        .store_tokens(change.jti.clone(), change.exp)
+        .await
+        .map_err(|_| AuthAPIError::UnexpectedError)
+}
+
+fn token_form_page(title: &str, action: &str, token: &str, button: &str) -> Html<String> {
+    // The token comes from the query string, so it must not break out of the
+    // attribute
+    let token = token
+        .replace('&', "&amp;")
+        .replace('"', "&quot;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;");
+    Html(format!(
+        r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta charset="utf-8">
+    <title>{title}</title>
+</head>
+<body>
+    <h1>{title}</h1>
+    <form method="post" action="{action}">
+        <input type="hidden" name="token" value="{token}">
+        <button type="submit">{button}</button>
+    </form>
+</body>
+</html>
+"#
+    ))
+}
+
+async fn send_email_change_emails(user: &User, new_email: &Email, state: &AppState) -> Result<(), AuthAPIError> {
+    let (confirm_token, cancel_token) =
+        auth::generate_email_change_tokens(user, new_email).map_err(|_| AuthAPIError::UnexpectedError)?;
+    let public_url = PUBLIC_URL.trim_end_matches('/');
+    let hours = *EMAIL_VERIFICATION_TTL_SECONDS / 3600;
+
+    let email_client = state.email_client.read().await;
+    email_client
+        .send_email(
+            new_email,
+            "Confirm your new email address",
+            &format!(
+                "Please confirm the change of your account's email address to this one within {} hours by opening this link: {}/change-email/confirm?token={}",
+                hours, public_url, confirm_token
+            ),
+        )
+        .await
+        .map_err(|_| AuthAPIError::UnexpectedError)?;
+
+    email_client
+        .send_email(
+            &user.email,
+            "Your email address is being changed",
+            &format!(
+                "Someone asked to change your account's email address to {}. If it was not you, cancel the change by opening this link and change your password: {}/change-email/cancel?token={}",
+                new_email.as_ref(),
+                public_url,
+                cancel_token
+            ),
+        )
+        .await
+        .map_err(|_| AuthAPIError::UnexpectedError)
+}
+
+#[derive(Deserialize)]
+pub struct ChangeEmailRequest {
+    #[serde(rename = "newEmail")]
+    pub new_email: String,
+    pub password: String,
+}
+
+#[derive(Deserialize)]
+pub struct EmailChangeTokenRequest {
+    pub token: String,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ChangeEmailResponse {
+    pub message: String,
+}
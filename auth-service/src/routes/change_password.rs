@@ -0,0 +1,112 @@
+use axum::{extract::State, http::StatusCode, response::IntoResponse, Json};
+use axum_extra::extract::CookieJar;
+use serde::Deserialize;
+use uuid::Uuid;
+
+use super::login::{AuthTokenResponse, LoginResponse};
+use crate::{
+    app_state::AppState,
+    domain::{AuthAPIError, Password, PasswordHash, RefreshTokenFamilyId, UserStoreError},
+    utils::{
+        auth::{self, authenticated_user, AuthToken},
+        client_ip::ClientIp,
+        login_throttle,
+    },
+};
+
+// Change the password of the logged in user, who has to know the current one.
+// Every other session is ended, since it may belong to whoever knew the old
+// password; the caller gets a new session in place of the current one.
+pub async fn change_password(
+    State(state): State<AppState>,
+    ClientIp(ip): ClientIp,
+    jar: CookieJar,
+    token: AuthToken,
+    Json(request): Json<ChangePasswordRequest>,
+) -> (CookieJar, Result<impl IntoResponse, AuthAPIError>) {
+    let user = match authenticated_user(&token, &state).await {
+        Ok(user) => user,
+        Err(e) => return (jar, Err(e)),
+    };
+
+    let (current_password, new_password) = match (
+        Password::parse(request.current_password),
+        Password::parse(request.new_password),
+    ) {
+        (Ok(current_password), Ok(new_password)) => (current_password, new_password),
+        _ => return (jar, Err(AuthAPIError::InvalidCredentials)),
+    };
+
+    // Hash before taking the store lock, hashing is intentionally slow
+    let new_password_hash = match PasswordHash::from_password(new_password).await {
+        Ok(password_hash) => password_hash,
+        Err(_) => return (jar, Err(AuthAPIError::UnexpectedError)),
+    };
+
+    if let Err(e) = login_throttle::verify_password(&user, &current_password, ip, &state).await {
+        return (jar, Err(e));
+    }
+
+    // The swap fails if the password changed since the user was loaded
+    let result = state
+        .user_store
+        .write()
+        .await
+        .replace_password_hash(&user.id, user.password_hash(), new_password_hash)
+        .await;
+    if let Err(e) = result {
+        return (
+            jar,
+            Err(match e {
+                UserStoreError::InvalidCredentials => AuthAPIError::IncorrectCredentials,
+                // The token may outlive the user it was issued to
+                UserStoreError::UserNotFound => AuthAPIError::InvalidToken,
+                _ => AuthAPIError::UnexpectedError,
+            }),
+        );
+    }
+
+    // The replacement token may be issued in the same second as the ban
+    let jti = Uuid::new_v4().to_string();
+    if let Err(e) = auth::revoke_all_sessions(&user.id, Some(jti.clone()), &state).await {
+        return (jar, Err(e));
+    }
+
+    let auth_cookie = match auth::generate_auth_cookie_with_jti(&user, jti) {
+        Ok(cookie) => cookie,
+        Err(_) => return (jar, Err(AuthAPIError::UnexpectedError)),
+    };
+
+    let refresh_cookie = match auth::generate_refresh_cookie(
+        &user.id,
+        RefreshTokenFamilyId::default(),
+        &state.refresh_token_store,
+    )
+    .await
+    {
+        Ok(cookie) => cookie,
+        Err(_) => return (jar, Err(AuthAPIError::UnexpectedError)),
+    };
+
+    let response = if request.return_token {
+        LoginResponse::AuthToken(AuthTokenResponse::new(&auth_cookie))
+    } else {
+        LoginResponse::RegularAuth
+    };
+
+    (
+        jar.add(auth_cookie).add(refresh_cookie),
+        Ok((StatusCode::OK, Json(response))),
+    )
+}
+
+#[derive(Deserialize)]
+pub struct ChangePasswordRequest {
+    #[serde(rename = "currentPassword")]
+    pub current_password: String,
+    #[serde(rename = "newPassword")]
+    pub new_password: String,
+    // Clients sending the auth token as a bearer token need the new one
+    #[serde(rename = "returnToken", default)]
+    pub return_token: bool,
+}
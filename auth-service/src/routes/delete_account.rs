@@ -0,0 +1,153 @@
+use axum::{extract::State, http::StatusCode, Json};
+use axum_extra::extract::CookieJar;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use super::{
+    login::TwoFactorAuthResponse,
+    verify_2fa::{reauthenticate, Reauthentication},
+};
+use crate::{
+    app_state::AppState,
+    domain::{AuthAPIError, User, UserStoreError},
+    utils::{
+        auth::{self, authenticated_user, AuthToken},
+        client_ip::ClientIp,
+        constants::{ACCOUNT_DELETION_GRACE_PERIOD_SECONDS, JWT_COOKIE_NAME, REFRESH_COOKIE_NAME},
+    },
+};
+
+// Delete the logged in user's account once the grace period has passed. The
+// user has to confirm with their password, and with a second factor if they
+// use 2FA: the first request answers with a login attempt ID like `/login`,
+// the second one sends it together with the code. Every session ends right
+// away, logging in again before the account is purged keeps it.
+pub async fn delete_account(
+    State(state): State<AppState>,
+    ClientIp(ip): ClientIp,
+    jar: CookieJar,
+    token: AuthToken,
+    Json(request): Json<DeleteAccountRequest>,
+) -> (CookieJar, Result<(StatusCode, Json<DeleteAccountResponse>), AuthAPIError>) {
+    let user = match authenticated_user(&token, &state).await {
+        Ok(user) => user,
+        Err(e) => return (jar, Err(e)),
+    };
+
+    let reauthentication = reauthenticate(
+        &user,
+        request.password,
+        request.login_attempt_id,
+        request.two_fa_code,
+        ip,
+        &state,
+    )
+    .await;
+    match reauthentication {
+        Ok(Reauthentication::Confirmed) => {}
+        Ok(Reauthentication::SecondFactorRequired(login_attempt_id)) => {
+            return (
+                jar,
+                Ok((
+                    StatusCode::PARTIAL_CONTENT,
+                    Json(DeleteAccountResponse::TwoFactorAuth(TwoFactorAuthResponse {
+                        message: "2FA required".to_owned(),
+                        login_attempt_id: login_attempt_id.as_ref().to_owned(),
+                    })),
+                )),
+            );
+        }
+        Err(e) => return (jar, Err(e)),
+    }
+
+    let deletion_due_at = Utc::now().timestamp() + i64::from(*ACCOUNT_DELETION_GRACE_PERIOD_SECONDS);
+    {
+        let mut user_store = state.user_store.write().await;
+        if user_store.schedule_deletion(&user.id, deletion_due_at).await.is_err() {
+            return (jar, Err(AuthAPIError::UnexpectedError));
+        }
+    }
+
+    if let Err(e) = auth::revoke_all_sessions(&user.id, None, &state).await {
+        return (jar, Err(e));
+    }
+
+    // The deletion is scheduled either way, the email only tells the owner
+    if let Err(e) = send_deletion_email(&user, deletion_due_at, &state).await {
+        println!("Failed to send account deletion email to {}: {:?}", user.email.as_ref(), e);
+    }
+
+    (
+        jar.remove(JWT_COOKIE_NAME).remove(REFRESH_COOKIE_NAME),
+        Ok((
+            StatusCode::OK,
+            Json(DeleteAccountResponse::Scheduled(AccountDeletionScheduledResponse {
+                message: "Account scheduled for deletion, log in again to keep it".to_owned(),
+                deletion_due_at,
+            })),
+        )),
+    )
+}
+
+// Keep the account of a user who logged in while it waited to be deleted.
+// Once the grace period has passed the account counts as gone, even if it
+// was not purged yet.
+pub(crate) async fn restore_account(user: &User, state: &AppState) -> Result<(), AuthAPIError> {
+    let deletion_due_at = match user.deletion_due_at() {
+        Some(deletion_due_at) => deletion_due_at,
+        None => return Ok(()),
+    };
+
+    if deletion_due_at <= Utc::now().timestamp() {
+        return Err(AuthAPIError::IncorrectCredentials);
+    }
+
+    let mut user_store = state.user_store.write().await;
+    user_store.cancel_deletion(&user.id).await.map_err(|e| match e {
+        // Purged in the meantime
+        UserStoreError::UserNotFound => AuthAPIError::IncorrectCredentials,
+        _ => AuthAPIError::UnexpectedError,
+    })
+}
+
+async fn send_deletion_email(user: &User, deletion_due_at: i64, state: &AppState) -> Result<(), AuthAPIError> {
+    let due = DateTime::from_timestamp(deletion_due_at, 0).ok_or(AuthAPIError::UnexpectedError)?;
+
+    let email_client = state.email_client.read().await;
+    email_client
+        .send_email(
+            &user.email,
+            "Your account will be deleted",
+            &format!(
+                "Your account is scheduled for deletion on {}. If you want to keep it, log in before then.",
+                due.format("%Y-%m-%d %H:%M UTC")
+            ),
+        )
+        .await
+        .map_err(|_| AuthAPIError::UnexpectedError)
+}
+
+#[derive(Deserialize)]
+pub struct DeleteAccountRequest {
+    pub password: String,
+    // Only for users with 2FA, see `delete_account`
+    #[serde(rename = "loginAttemptId")]
+    pub login_attempt_id: Option<String>,
+    #[serde(rename = "2FACode")]
+    pub two_fa_code: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(untagged)]
+pub enum DeleteAccountResponse {
+    Scheduled(AccountDeletionScheduledResponse),
+    TwoFactorAuth(TwoFactorAuthResponse),
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AccountDeletionScheduledResponse {
+    pub message: String,
+    // Unix timestamp after which the account is purged
+    #[serde(rename = "deletionDueAt")]
+    pub deletion_due_at: i64,
+}
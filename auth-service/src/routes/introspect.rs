@@ -0,0 +1,79 @@
+use axum::{extract::State, http::HeaderMap, Form, Json};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    app_state::AppState,
+    domain::AuthAPIError,
+    utils::{auth::validate_token, client_auth::authenticate_client},
+};
+
+// Auth tokens are only issued by /login, to our own web frontend, for a full session
+const AUTH_TOKEN_CLIENT_ID: &str = "auth-service";
+const AUTH_TOKEN_SCOPE: &str = "session";
+
+// OAuth 2.0 token introspection (RFC 7662) for API gateways and proxies.
+// Invalid, expired and banned tokens are all reported as inactive, without
+// telling the caller why.
+pub async fn introspect(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Form(request): Form<IntrospectRequest>,
+) -> Result<Json<IntrospectResponse>, AuthAPIError> {
+    authenticate_client(&headers)?;
+
+    // Only JWT auth tokens can be introspected, so `token_type_hint` is ignored
+    let response = match validate_token(&request.token, Some(&state.banned_token_store)).await {
+        Ok(claims) => IntrospectResponse {
+            active: true,
+            sub: Some(claims.sub),
+            // RFC 7662's human-readable identifier, present if the token carries it
+            username: claims.email,
+            exp: Some(claims.exp),
+            iat: Some(claims.iat),
+            nbf: Some(claims.nbf),
+            iss: Some(claims.iss),
+            aud: Some(claims.aud),
+            jti: Some(claims.jti),
+            scope: Some(AUTH_TOKEN_SCOPE.to_owned()),
+            client_id: Some(AUTH_TOKEN_CLIENT_ID.to_owned()),
+            token_type: Some("Bearer".to_owned()),
+        },
+        Err(_) => IntrospectResponse::default(),
+    };
+
+    Ok(Json(response))
+}
+
+#[derive(Deserialize)]
+pub struct IntrospectRequest {
+    pub token: String,
+    pub token_type_hint: Option<String>,
+}
+
+// Inactive tokens are reported as `{"active": false}` only
+#[derive(Debug, Default, Serialize, Deserialize, PartialEq)]
+pub struct IntrospectResponse {
+    pub active: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sub: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub username: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub exp: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub iat: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub nbf: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub iss: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub aud: Option<Vec<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub jti: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub scope: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub client_id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub token_type: Option<String>,
+}
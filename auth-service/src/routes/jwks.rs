@@ -0,0 +1,12 @@
+use axum::{http::header, response::IntoResponse, Json};
+
+use crate::utils::keys;
+
+// Public keys for verifying auth tokens locally. Clients may cache them
+// for a while, but should refetch on seeing an unknown `kid`.
+pub async fn jwks() -> impl IntoResponse {
+    (
+        [(header::CACHE_CONTROL, "public, max-age=300")],
+        Json(keys::keyring().jwks()),
+    )
+}
@@ -1,11 +1,14 @@
 use axum::{extract::State, http::StatusCode, response::IntoResponse, Json};
 use serde::{Serialize, Deserialize};
-use axum_extra::extract::CookieJar;
-use crate::{app_state::AppState, domain::{AuthAPIError, Email, Password, UserStoreError, data_stores::{TwoFACode, LoginAttemptId}}};
-use crate::utils::auth;
+use axum_extra::extract::{cookie::Cookie, CookieJar};
+use crate::{app_state::AppState, domain::{AuthAPIError, Email, Password, PasswordHash, RefreshTokenFamilyId, TwoFAMethod, User, UserStoreError, data_stores::{TwoFACode, LoginAttemptId}}};
+use crate::utils::{auth, client_ip::ClientIp, login_throttle};
+
+use super::delete_account::restore_account;
 
 pub async fn login(
     State(state): State<AppState>,
+    ClientIp(ip): ClientIp,
     jar: CookieJar,
     Json(request): Json<LoginRequest>
 ) -> (CookieJar, Result<impl IntoResponse, AuthAPIError>) {
@@ -19,26 +22,85 @@ pub async fn login(
         Err(_) => return (jar, Err(AuthAPIError::InvalidCredentials)),
     };
 
+    let attempt = match login_throttle::count_login_attempt(&email, ip, &state).await {
+        Ok(attempt) => attempt,
+        Err(e) => return (jar, Err(e)),
+    };
+
+    let user = match authenticate(&email, &password, &state).await {
+        Ok(user) => user,
+        Err(e) => {
+            attempt.failed(&state).await;
+            return (jar, Err(e));
+        }
+    };
+
+    if let Err(e) = attempt.succeeded(&state).await {
+        return (jar, Err(e));
+    }
+
+    // Only checked once the password is known to be correct, so that it does
+    // not tell anyone else whether an account exists
+    if !user.email_verified() && !state.allow_unverified_login {
+        return (jar, Err(AuthAPIError::EmailNotVerified));
+    }
+
+    if user.password_hash().needs_rehash() {
+        rehash_password(&user, password, &state).await;
+    }
+
+    if user.requires_2fa() {
+        handle_2fa(&user, &state, jar).await
+    } else {
+        handle_no_2fa(&user, request.return_token, &state, jar).await
+    }
+}
+
+// The user with the email address, if the password is theirs
+async fn authenticate(email: &Email, password: &Password, state: &AppState) -> Result<User, AuthAPIError> {
     let user_store = state.user_store.read().await;
-    
-    match user_store.validate_user(&email, &password).await {
-        Ok(_) => {
-            // Get user to check 2FA requirement
-            match user_store.get_user(&email).await {
-                Ok(user) => {
-                    if user.requires_2fa() {
-                        handle_2fa(&email, &state, jar).await
-                    } else {
-                        handle_no_2fa(&email, jar).await
-                    }
-                }
-                Err(_) => (jar, Err(AuthAPIError::UnexpectedError)),
-            }
+
+    let user = match user_store.get_user_by_email(email).await {
+        Ok(user) => user,
+        Err(UserStoreError::UserNotFound) => {
+            drop(user_store);
+            // Take as long as a wrong password would, so that response times
+            // do not tell which email addresses have an account
+            let dummy = PasswordHash::dummy().await.map_err(|_| AuthAPIError::UnexpectedError)?;
+            let _ = dummy.verify(password).await;
+            return Err(AuthAPIError::IncorrectCredentials);
         }
+        Err(_) => return Err(AuthAPIError::UnexpectedError),
+    };
+
+    match user_store.validate_user(&user.id, password).await {
+        Ok(_) => Ok(user),
         Err(UserStoreError::UserNotFound) | Err(UserStoreError::InvalidCredentials) => {
-            (jar, Err(AuthAPIError::IncorrectCredentials))
+            Err(AuthAPIError::IncorrectCredentials)
         }
-        Err(_) => (jar, Err(AuthAPIError::UnexpectedError)),
+        Err(_) => Err(AuthAPIError::UnexpectedError),
+    }
+}
+
+// Replace a hash made with an outdated algorithm or outdated parameters now that
+// the plaintext password is known to be correct. Only the verified hash is
+// replaced, so that a password change committed in the meantime is not undone
+// with the old password. A failure here must not fail the login, the old hash
+// keeps working and the upgrade is retried next time.
+async fn rehash_password(user: &User, password: Password, state: &AppState) {
+    let password_hash = match PasswordHash::from_password(password).await {
+        Ok(password_hash) => password_hash,
+        Err(e) => {
+            println!("Failed to rehash password for {}: {:?}", user.email.as_ref(), e);
+            return;
+        }
+    };
+
+    let mut user_store = state.user_store.write().await;
+    match user_store.replace_password_hash(&user.id, user.password_hash(), password_hash).await {
+        // The password was changed in the meantime, the new one is hashed anew anyway
+        Ok(()) | Err(UserStoreError::InvalidCredentials) => {}
+        Err(e) => println!("Failed to store rehashed password for {}: {:?}", user.email.as_ref(), e),
     }
 }
 
@@ -46,63 +108,102 @@ pub async fn login(
 pub struct LoginRequest {
     pub email: String,
     pub password: String,
+    // Non-browser clients ask for the auth token in the response body, to send
+    // it as a bearer token
+    #[serde(rename = "returnToken", default)]
+    pub return_token: bool,
 }
 
 async fn handle_2fa(
-    email: &Email,
+    user: &User,
     state: &AppState,
     jar: CookieJar,
 ) -> (
     CookieJar,
     Result<(StatusCode, Json<LoginResponse>), AuthAPIError>,
 ) {
+    match start_2fa(user, state).await {
+        Ok(login_attempt_id) => (
+            jar,
+            Ok((
+                StatusCode::PARTIAL_CONTENT,
+                Json(LoginResponse::TwoFactorAuth(TwoFactorAuthResponse {
+                    message: "2FA required".to_owned(),
+                    login_attempt_id: login_attempt_id.as_ref().to_owned(),
+                }))
+            ))
+        ),
+        Err(e) => (jar, Err(e)),
+    }
+}
+
+// Record a new 2FA attempt for the user, replacing any pending one, and email
+// them the code unless they use an authenticator app. The attempt expires and
+// counts wrong codes either way.
+pub(crate) async fn start_2fa(user: &User, state: &AppState) -> Result<LoginAttemptId, AuthAPIError> {
     let login_attempt_id = LoginAttemptId::default();
     let two_fa_code = TwoFACode::default();
 
-    let email_client = state.email_client.read().await;
-    if let Err(_) = email_client.send_email(
-        email,
-        "2FA Authentication Code",
-        &format!("Your 2FA code is: {}", two_fa_code.as_ref())
-    ).await {
-        return (jar, Err(AuthAPIError::UnexpectedError));
-    }
-    
-    let mut two_fa_store = state.two_fa_code_store.write().await;
-    match two_fa_store.add_code(email.clone(), login_attempt_id.clone(), two_fa_code).await {
-        Ok(_) => {
-            (
-                jar,
-                Ok((
-                    StatusCode::PARTIAL_CONTENT,
-                    Json(LoginResponse::TwoFactorAuth(TwoFactorAuthResponse {
-                        message: "2FA required".to_owned(),
-                        login_attempt_id: login_attempt_id.as_ref().to_owned(),
-                    }))
-                ))
-            )
+    if user.two_fa_method() == TwoFAMethod::Email {
+        let email_client = state.email_client.read().await;
+        if email_client.send_email(
+            &user.email,
+            "2FA Authentication Code",
+            &format!("Your 2FA code is: {}", two_fa_code.as_ref())
+        ).await.is_err() {
+            return Err(AuthAPIError::UnexpectedError);
         }
-        Err(_) => (jar, Err(AuthAPIError::UnexpectedError)),
     }
+
+    let mut two_fa_store = state.two_fa_code_store.write().await;
+    two_fa_store
+        .add_code(user.id.clone(), login_attempt_id.clone(), two_fa_code)
+        .await
+        .map_err(|_| AuthAPIError::UnexpectedError)?;
+
+    Ok(login_attempt_id)
 }
 
 async fn handle_no_2fa(
-    email: &Email,
+    user: &User,
+    return_token: bool,
+    state: &AppState,
     jar: CookieJar,
 ) -> (
     CookieJar,
     Result<(StatusCode, Json<LoginResponse>), AuthAPIError>,
 ) {
-    let auth_cookie = match auth::generate_auth_cookie(email) {
+    // Restore an account that was about to be deleted
+    if let Err(e) = restore_account(user, state).await {
+        return (jar, Err(e));
+    }
+
+    let auth_cookie = match auth::generate_auth_cookie(user) {
         Ok(cookie) => cookie,
         Err(_) => return (jar, Err(AuthAPIError::UnexpectedError)),
     };
     
-    let updated_jar = jar.add(auth_cookie);
+    // A new login starts a new refresh token family
+    let refresh_cookie = match auth::generate_refresh_cookie(
+        &user.id,
+        RefreshTokenFamilyId::default(),
+        &state.refresh_token_store,
+    ).await {
+        Ok(cookie) => cookie,
+        Err(_) => return (jar, Err(AuthAPIError::UnexpectedError)),
+    };
+
+    let response = if return_token {
+        LoginResponse::AuthToken(AuthTokenResponse::new(&auth_cookie))
+    } else {
+        LoginResponse::RegularAuth
+    };
+
+    let updated_jar = jar.add(auth_cookie).add(refresh_cookie);
     
     (
         updated_jar,
-        Ok((StatusCode::OK, Json(LoginResponse::RegularAuth)))
+        Ok((StatusCode::OK, Json(response)))
     )
 }
 
@@ -110,9 +211,30 @@ async fn handle_no_2fa(
 #[serde(untagged)]
 pub enum LoginResponse {
     RegularAuth,
+    AuthToken(AuthTokenResponse),
     TwoFactorAuth(TwoFactorAuthResponse),
 }
 
+// The auth token for clients which asked for it with `returnToken`
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AuthTokenResponse {
+    pub token: String,
+    #[serde(rename = "tokenType")]
+    pub token_type: String,
+    #[serde(rename = "expiresIn")]
+    pub expires_in: i64,
+}
+
+impl AuthTokenResponse {
+    pub(crate) fn new(auth_cookie: &Cookie<'_>) -> Self {
+        Self {
+            token: auth_cookie.value().to_owned(),
+            token_type: "Bearer".to_owned(),
+            expires_in: auth::TOKEN_TTL_SECONDS,
+        }
+    }
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct TwoFactorAuthResponse {
     pub message: String,
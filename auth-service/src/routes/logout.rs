@@ -3,26 +3,35 @@ use axum_extra::extract::CookieJar;
 
 use crate::{
     app_state::AppState,
-    domain::AuthAPIError,
-    utils::{auth::validate_token, constants::JWT_COOKIE_NAME},
+    domain::{AuthAPIError, RefreshToken},
+    utils::{
+        auth::{revoke_refresh_token, validate_token, AuthToken},
+        constants::{JWT_COOKIE_NAME, REFRESH_COOKIE_NAME},
+    },
 };
 
 pub async fn logout(
     State(state): State<AppState>,
     jar: CookieJar,
+    token: Option<AuthToken>,
 ) -> (CookieJar, Result<impl IntoResponse, AuthAPIError>) {
-    let cookie = match jar.get(JWT_COOKIE_NAME) {
-        Some(cookie) => cookie,
-        None => return (jar, Err(AuthAPIError::MissingToken)),
+    // The refresh token goes first, it must not survive a failed logout
+    // because of an expired auth token
+    let jar = match revoke_refresh_cookie(jar, &state).await {
+        Ok(jar) => jar,
+        Err((jar, e)) => return (jar, Err(e)),
     };
 
-    let token = cookie.value().to_owned();
+    let token = match token {
+        Some(token) => token,
+        None => return (jar, Err(AuthAPIError::MissingToken)),
+    };
 
-    match validate_token(&token, None).await {
+    match validate_token(token.as_ref(), None).await {
         Ok(claims) => {
             // Add token to banned store
             let mut banned_store = state.banned_token_store.write().await;
-            if let Err(_) = banned_store.store_tokens(token, claims.exp).await {
+            if banned_store.store_tokens(claims.jti, claims.exp).await.is_err() {
                 return (jar, Err(AuthAPIError::UnexpectedError));
             }
 
@@ -31,4 +40,23 @@ pub async fn logout(
         },
         Err(_) => (jar, Err(AuthAPIError::InvalidToken)),
     }
-}
\ No newline at end of file
+}
+
+// Revoke the session of the refresh cookie, if there is one
+async fn revoke_refresh_cookie(
+    jar: CookieJar,
+    state: &AppState,
+) -> Result<CookieJar, (CookieJar, AuthAPIError)> {
+    let token = match jar.get(REFRESH_COOKIE_NAME) {
+        Some(cookie) => RefreshToken::parse(cookie.value().to_owned()),
+        None => return Ok(jar),
+    };
+
+    if let Ok(token) = token {
+        if revoke_refresh_token(&token, &state.refresh_token_store).await.is_err() {
+            return Err((jar, AuthAPIError::UnexpectedError));
+        }
+    }
+
+    Ok(jar.remove(REFRESH_COOKIE_NAME))
+}
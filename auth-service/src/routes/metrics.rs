@@ -0,0 +1,10 @@
+use axum::{http::header, response::IntoResponse};
+
+use crate::utils::metrics;
+
+pub async fn metrics() -> impl IntoResponse {
+    (
+        [(header::CONTENT_TYPE, "text/plain; version=0.0.4")],
+        metrics::render(),
+    )
+}
@@ -1,12 +1,36 @@
+mod change_email;
+mod change_password;
+mod delete_account;
+mod introspect;
+mod jwks;
 mod login;
 mod logout;
+mod metrics;
+mod password_reset;
+mod recovery_codes;
+mod refresh;
+mod revoke;
 mod signup;
+mod totp;
 mod verify_2fa;
+mod verify_email;
 mod verify_token;
 
 // re-export items from sub-modules
+pub use change_email::*;
+pub use change_password::*;
+pub use delete_account::*;
+pub use introspect::*;
+pub use jwks::*;
 pub use login::*;
 pub use logout::*;
+pub use metrics::*;
+pub use password_reset::*;
+pub use recovery_codes::*;
+pub use refresh::*;
+pub use revoke::*;
 pub use signup::*;
+pub use totp::*;
 pub use verify_2fa::*;
+pub use verify_email::*;
 pub use verify_token::*;
\ No newline at end of file
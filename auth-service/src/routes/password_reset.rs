@@ -0,0 +1,121 @@
+use axum::{extract::State, http::StatusCode, response::IntoResponse, Json};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    app_state::AppState,
+    domain::{AuthAPIError, Email, Password, PasswordHash, User, UserStoreError},
+    utils::{auth, constants::PASSWORD_RESET_TTL_SECONDS},
+};
+
+// Email a password reset token to the user. The answer is the same whether or
+// not the account exists, so that it does not reveal who signed up.
+pub async fn request_password_reset(
+    State(state): State<AppState>,
+    Json(request): Json<PasswordResetRequest>,
+) -> Result<impl IntoResponse, AuthAPIError> {
+    let email = Email::parse(request.email).map_err(|_| AuthAPIError::InvalidCredentials)?;
+
+    let user = {
+        let user_store = state.user_store.read().await;
+        match user_store.get_user_by_email(&email).await {
+            Ok(user) => Some(user),
+            Err(UserStoreError::UserNotFound) => None,
+            Err(_) => return Err(AuthAPIError::UnexpectedError),
+        }
+    };
+
+    // Sending happens after answering, the time it takes would otherwise tell
+    // whether the account exists
+    if let Some(user) = user {
+        tokio::spawn(async move {
+            if let Err(e) = send_password_reset_email(&user, &state).await {
+                println!("Failed to send password reset email to {}: {:?}", email.as_ref(), e);
+            }
+        });
+    }
+
+    Ok((
+        StatusCode::OK,
+        Json(PasswordResetResponse {
+            message: "If the account exists, a password reset token was sent to it".to_owned(),
+        }),
+    ))
+}
+
+// Set a new password with the emailed token and end all sessions, which may
+// belong to whoever knew the old password
+pub async fn confirm_password_reset(
+    State(state): State<AppState>,
+    Json(request): Json<ConfirmPasswordResetRequest>,
+) -> Result<impl IntoResponse, AuthAPIError> {
+    let reset = auth::validate_password_reset_token(&request.token).map_err(|_| AuthAPIError::InvalidToken)?;
+    let password = Password::parse(request.password).map_err(|_| AuthAPIError::InvalidCredentials)?;
+
+    // Hash before taking the store lock, hashing is intentionally slow
+    let password_hash = PasswordHash::from_password(password)
+        .await
+        .map_err(|_| AuthAPIError::UnexpectedError)?;
+
+    {
+        // Checking and replacing the password under one lock keeps two requests
+        // with the same token from both succeeding
+        let mut user_store = state.user_store.write().await;
+        let user = user_store.get_user(&reset.user_id).await.map_err(|e| match e {
+            UserStoreError::UserNotFound => AuthAPIError::InvalidToken,
+            _ => AuthAPIError::UnexpectedError,
+        })?;
+
+        if !reset.is_for(user.password_hash()) {
+            return Err(AuthAPIError::InvalidToken);
+        }
+
+        user_store
+            .update_password_hash(&reset.user_id, password_hash)
+            .await
+            .map_err(|_| AuthAPIError::UnexpectedError)?;
+    }
+
+    auth::revoke_all_sessions(&reset.user_id, None, &state).await?;
+
+    Ok((
+        StatusCode::OK,
+        Json(PasswordResetResponse {
+            message: "Password reset, please log in again".to_owned(),
+        }),
+    ))
+}
+
+async fn send_password_reset_email(user: &User, state: &AppState) -> Result<(), AuthAPIError> {
+    let token = auth::generate_password_reset_token(&user.id, user.password_hash())
+        .map_err(|_| AuthAPIError::UnexpectedError)?;
+
+    let email_client = state.email_client.read().await;
+    email_client
+        .send_email(
+            &user.email,
+            "Reset your password",
+            &format!(
+                "Someone asked to reset your password. If it was you, use this token within {} minutes: {}",
+                *PASSWORD_RESET_TTL_SECONDS / 60,
+                token
+            ),
+        )
+        .await
+        .map_err(|_| AuthAPIError::UnexpectedError)
+}
+
+#[derive(Deserialize)]
+pub struct PasswordResetRequest {
+    pub email: String,
+}
+
+#[derive(Deserialize)]
+pub struct ConfirmPasswordResetRequest {
+    pub token: String,
+    pub password: String,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct PasswordResetResponse {
+    pub message: String,
+}
@@ -0,0 +1,93 @@
+use axum::{
+    extract::State,
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    Json,
+};
+use serde::{Deserialize, Serialize};
+
+use super::{
+    login::TwoFactorAuthResponse,
+    verify_2fa::{reauthenticate, Reauthentication},
+};
+use crate::{
+    app_state::AppState,
+    domain::{AuthAPIError, RecoveryCode, UserId, UserStore},
+    utils::{
+        auth::{authenticated_user, AuthToken},
+        client_ip::ClientIp,
+    },
+};
+
+// Replace the logged in user's recovery codes with a new set, e.g. after
+// using some of them up. The old codes stop working. The user confirms with
+// their password and a second factor, see `reauthenticate`, since a new set
+// of codes is a way around 2FA.
+pub async fn regenerate_recovery_codes(
+    State(state): State<AppState>,
+    ClientIp(ip): ClientIp,
+    token: AuthToken,
+    Json(request): Json<RegenerateRecoveryCodesRequest>,
+) -> Result<Response, AuthAPIError> {
+    let user = authenticated_user(&token, &state).await?;
+    if !user.requires_2fa() {
+        return Err(AuthAPIError::TwoFANotEnabled);
+    }
+
+    let reauthentication = reauthenticate(
+        &user,
+        request.password,
+        request.login_attempt_id,
+        request.two_fa_code,
+        ip,
+        &state,
+    )
+    .await?;
+    if let Reauthentication::SecondFactorRequired(login_attempt_id) = reauthentication {
+        let response = TwoFactorAuthResponse {
+            message: "2FA required".to_owned(),
+            login_attempt_id: login_attempt_id.as_ref().to_owned(),
+        };
+        return Ok((StatusCode::PARTIAL_CONTENT, Json(response)).into_response());
+    }
+
+    let mut user_store = state.user_store.write().await;
+    let recovery_codes = issue_recovery_codes(&user.id, &mut *user_store).await?;
+
+    Ok((StatusCode::OK, Json(RecoveryCodesResponse { recovery_codes })).into_response())
+}
+
+// Store a new set of recovery codes for the user, returning the plaintext
+// codes. This is the only time they are available, only hashes are stored.
+pub(crate) async fn issue_recovery_codes(
+    user_id: &UserId,
+    user_store: &mut (dyn UserStore + Send + Sync),
+) -> Result<Vec<String>, AuthAPIError> {
+    let recovery_codes = RecoveryCode::generate_set();
+    let hashes = recovery_codes.iter().map(RecoveryCode::hash).collect();
+
+    if user_store.set_recovery_codes(user_id, hashes).await.is_err() {
+        return Err(AuthAPIError::UnexpectedError);
+    }
+
+    Ok(recovery_codes
+        .into_iter()
+        .map(|code| code.as_ref().to_owned())
+        .collect())
+}
+
+#[derive(Deserialize)]
+pub struct RegenerateRecoveryCodesRequest {
+    pub password: String,
+    // Only in the second request, see `reauthenticate`
+    #[serde(rename = "loginAttemptId")]
+    pub login_attempt_id: Option<String>,
+    #[serde(rename = "2FACode")]
+    pub two_fa_code: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RecoveryCodesResponse {
+    #[serde(rename = "recoveryCodes")]
+    pub recovery_codes: Vec<String>,
+}
@@ -0,0 +1,88 @@
+use axum::{extract::State, http::StatusCode, response::IntoResponse};
+use axum_extra::extract::CookieJar;
+
+use crate::{
+    app_state::AppState,
+    domain::{AuthAPIError, RefreshToken, RefreshTokenStoreError, UserStoreError},
+    utils::{
+        auth::{generate_auth_cookie, generate_refresh_cookie, GenerateTokenError},
+        constants::{JWT_COOKIE_NAME, REFRESH_COOKIE_NAME},
+    },
+};
+
+// Exchange the refresh cookie for a new auth cookie. The refresh token is
+// rotated on every use: the presented token stops working and a new one of
+// the same family is returned.
+pub async fn refresh(
+    State(state): State<AppState>,
+    jar: CookieJar,
+) -> (CookieJar, Result<impl IntoResponse, AuthAPIError>) {
+    let token = match jar.get(REFRESH_COOKIE_NAME) {
+        Some(cookie) => cookie.value().to_owned(),
+        None => return (jar, Err(AuthAPIError::MissingToken)),
+    };
+
+    let token = match RefreshToken::parse(token) {
+        Ok(token) => token,
+        Err(_) => return (jar.remove(REFRESH_COOKIE_NAME), Err(AuthAPIError::InvalidToken)),
+    };
+
+    let session = {
+        let mut refresh_token_store = state.refresh_token_store.write().await;
+        match refresh_token_store.use_token(&token.hash()).await {
+            Ok(session) => session,
+            // A token that was already rotated is presented again, so either
+            // the client or an attacker holds a stolen copy. There is no telling
+            // which one, so the whole family is revoked and both have to log in.
+            Err(RefreshTokenStoreError::TokenReused(family_id)) => {
+                println!("Refresh token reuse detected, revoking token family {}", family_id.as_ref());
+                if refresh_token_store.revoke_family(&family_id).await.is_err() {
+                    return (jar, Err(AuthAPIError::UnexpectedError));
+                }
+                return (remove_session_cookies(jar), Err(AuthAPIError::InvalidToken));
+            }
+            Err(RefreshTokenStoreError::TokenNotFound) => {
+                return (jar.remove(REFRESH_COOKIE_NAME), Err(AuthAPIError::InvalidToken))
+            }
+            Err(_) => return (jar, Err(AuthAPIError::UnexpectedError)),
+        }
+    }; // Write lock is dropped here
+
+    // The refresh token may outlive the user it was issued to
+    let user = {
+        let user_store = state.user_store.read().await;
+        match user_store.get_user(&session.user_id).await {
+            Ok(user) => user,
+            Err(UserStoreError::UserNotFound) => {
+                return (remove_session_cookies(jar), Err(AuthAPIError::InvalidToken))
+            }
+            Err(_) => return (jar, Err(AuthAPIError::UnexpectedError)),
+        }
+    };
+
+    let auth_cookie = match generate_auth_cookie(&user) {
+        Ok(cookie) => cookie,
+        Err(_) => return (jar, Err(AuthAPIError::UnexpectedError)),
+    };
+
+    let refresh_cookie = match generate_refresh_cookie(
+        &session.user_id,
+        session.family_id,
+        &state.refresh_token_store,
+    )
+    .await
+    {
+        Ok(cookie) => cookie,
+        // The session was ended while its token was being refreshed
+        Err(GenerateTokenError::SessionRevoked) => {
+            return (remove_session_cookies(jar), Err(AuthAPIError::InvalidToken))
+        }
+        Err(_) => return (jar, Err(AuthAPIError::UnexpectedError)),
+    };
+
+    (jar.add(auth_cookie).add(refresh_cookie), Ok(StatusCode::OK))
+}
+
+fn remove_session_cookies(jar: CookieJar) -> CookieJar {
+    jar.remove(JWT_COOKIE_NAME).remove(REFRESH_COOKIE_NAME)
+}
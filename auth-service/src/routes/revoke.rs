@@ -0,0 +1,45 @@
+use axum::{extract::State, http::{HeaderMap, StatusCode}, response::IntoResponse, Form};
+use serde::Deserialize;
+
+use crate::{
+    app_state::AppState,
+    domain::{AuthAPIError, RefreshToken},
+    utils::{
+        auth::{revoke_refresh_token, validate_token},
+        client_auth::authenticate_client,
+    },
+};
+
+// OAuth 2.0 token revocation (RFC 7009) for clients which don't use cookies.
+// Auth tokens are banned until they expire, refresh tokens have their whole
+// family revoked. Unknown and invalid tokens are accepted as well, since there
+// is nothing left to revoke.
+pub async fn revoke(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Form(request): Form<RevokeRequest>,
+) -> Result<impl IntoResponse, AuthAPIError> {
+    authenticate_client(&headers)?;
+
+    // Auth tokens are JWTs and refresh tokens are not, so `token_type_hint`
+    // is not needed to tell them apart
+    if let Ok(claims) = validate_token(&request.token, None).await {
+        let mut banned_store = state.banned_token_store.write().await;
+        banned_store
+            .store_tokens(claims.jti, claims.exp)
+            .await
+            .map_err(|_| AuthAPIError::UnexpectedError)?;
+    } else if let Ok(token) = RefreshToken::parse(request.token) {
+        revoke_refresh_token(&token, &state.refresh_token_store)
+            .await
+            .map_err(|_| AuthAPIError::UnexpectedError)?;
+    }
+
+    Ok(StatusCode::OK)
+}
+
+#[derive(Deserialize)]
+pub struct RevokeRequest {
+    pub token: String,
+    pub token_type_hint: Option<String>,
+}
@@ -1,6 +1,7 @@
 use axum::{extract::State, http::StatusCode, response::IntoResponse, Json};
 use serde::{Deserialize, Serialize};
-use crate::{app_state::AppState, domain::{AuthAPIError, User, Email, Password},};
+use super::{recovery_codes::issue_recovery_codes, verify_email::send_verification_email};
+use crate::{app_state::AppState, domain::{AuthAPIError, User, Email, Password, PasswordHash},};
 
 pub async fn signup(
     State(state): State<AppState>,
@@ -16,19 +17,40 @@ pub async fn signup(
         Err(_) => return Err(AuthAPIError::InvalidCredentials),
     };
 
-    let user = User::new(email.clone(), password, request.requires_2fa);
+    // Hash before taking the store lock, hashing is intentionally slow
+    let password_hash = match PasswordHash::from_password(password).await {
+        Ok(password_hash) => password_hash,
+        Err(_) => return Err(AuthAPIError::UnexpectedError),
+    };
+
+    let user = User::new(email.clone(), password_hash, request.requires_2fa);
     let mut user_store = state.user_store.write().await;
 
-    if user_store.get_user(&email).await.is_ok() {
+    if user_store.get_user_by_email(&email).await.is_ok() {
         return Err(AuthAPIError::UserAlreadyExists);
     }
 
-    if let Err(_) = user_store.add_user(user).await {
+    if user_store.add_user(user.clone()).await.is_err() {
         return Err(AuthAPIError::UnexpectedError);
     }
 
+    // Users with 2FA get recovery codes in case they lose access to their email
+    let recovery_codes = if request.requires_2fa {
+        Some(issue_recovery_codes(&user.id, &mut *user_store).await?)
+    } else {
+        None
+    };
+
+    // Release the lock before talking to the mail server. The account exists
+    // either way, if the email is lost the user can ask for another one.
+    drop(user_store);
+    if let Err(e) = send_verification_email(&user, &state).await {
+        println!("Failed to send verification email to {}: {:?}", email.as_ref(), e);
+    }
+
     let response = Json(SignupResponse {
         message: "User created successfully!".to_string(),
+        recovery_codes,
     });
 
     Ok((StatusCode::CREATED, response))
@@ -45,4 +67,6 @@ pub struct SignupRequest {
 #[derive(Serialize, Deserialize, PartialEq, Debug)]
 pub struct SignupResponse {
     pub message: String,
+    #[serde(rename = "recoveryCodes", default, skip_serializing_if = "Option::is_none")]
+    pub recovery_codes: Option<Vec<String>>,
 }
@@ -0,0 +1,182 @@
+use axum::{
+    extract::State,
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    Json,
+};
+use serde::{Deserialize, Serialize};
+
+use super::{
+    login::TwoFactorAuthResponse,
+    recovery_codes::{issue_recovery_codes, RecoveryCodesResponse},
+    verify_2fa::{reauthenticate, Reauthentication},
+};
+use crate::{
+    app_state::AppState,
+    domain::{
+        data_stores::{LoginAttemptId, TwoFACode},
+        AuthAPIError, TotpSecret, TwoFAMethod, User, UserStore, UserStoreError,
+    },
+    utils::{
+        auth::{authenticated_user, AuthToken},
+        client_ip::ClientIp,
+    },
+};
+
+// Start enrolling an authenticator app for the logged in user. The returned
+// secret only becomes active once a code generated from it is confirmed.
+// The user confirms with their password, and with a second factor if 2FA is
+// already on, see `reauthenticate`.
+pub async fn enroll_totp(
+    State(state): State<AppState>,
+    ClientIp(ip): ClientIp,
+    token: AuthToken,
+    Json(request): Json<EnrollTotpRequest>,
+) -> Result<Response, AuthAPIError> {
+    let user = authenticated_user(&token, &state).await?;
+    if user.two_fa_method() == TwoFAMethod::Totp {
+        return Err(AuthAPIError::TotpAlreadyEnabled);
+    }
+
+    let reauthentication = reauthenticate(
+        &user,
+        request.password,
+        request.login_attempt_id,
+        request.two_fa_code,
+        ip,
+        &state,
+    )
+    .await?;
+    if let Reauthentication::SecondFactorRequired(login_attempt_id) = reauthentication {
+        return Ok(second_factor_required(login_attempt_id));
+    }
+
+    let secret = TotpSecret::generate();
+    let response = EnrollTotpResponse {
+        otpauth_uri: secret.provisioning_uri(&user.email).map_err(|_| AuthAPIError::UnexpectedError)?,
+        qr_code_png: secret.qr_code_png(&user.email).map_err(|_| AuthAPIError::UnexpectedError)?,
+        qr_code_svg: secret.qr_code_svg(&user.email).map_err(|_| AuthAPIError::UnexpectedError)?,
+        secret: secret.as_ref().to_owned(),
+    };
+
+    // Enrolling again replaces a secret which has not been confirmed yet
+    let mut user_store = state.user_store.write().await;
+    if user_store.set_totp_secret(&user.id, secret).await.is_err() {
+        return Err(AuthAPIError::UnexpectedError);
+    }
+
+    Ok((StatusCode::OK, Json(response)).into_response())
+}
+
+// Finish enrolling with a first code from the authenticator app, proving it
+// was set up correctly. From then on logins require codes from the app, and
+// a new set of recovery codes is issued. Like enrolling, this needs the
+// password and, if 2FA is already on, a current second factor.
+pub async fn confirm_totp(
+    State(state): State<AppState>,
+    ClientIp(ip): ClientIp,
+    token: AuthToken,
+    Json(request): Json<ConfirmTotpRequest>,
+) -> Result<Response, AuthAPIError> {
+    let user = authenticated_user(&token, &state).await?;
+
+    let code = match TwoFACode::parse(request.code) {
+        Ok(code) => code,
+        Err(_) => return Err(AuthAPIError::InvalidCredentials),
+    };
+
+    if user.two_fa_method() == TwoFAMethod::Totp {
+        return Err(AuthAPIError::TotpAlreadyEnabled);
+    }
+
+    let secret = user.totp_secret().ok_or(AuthAPIError::TotpNotEnrolled)?;
+
+    let reauthentication = reauthenticate(
+        &user,
+        request.password,
+        request.login_attempt_id,
+        request.two_fa_code,
+        ip,
+        &state,
+    )
+    .await?;
+    if let Reauthentication::SecondFactorRequired(login_attempt_id) = reauthentication {
+        return Ok(second_factor_required(login_attempt_id));
+    }
+
+    let mut user_store = state.user_store.write().await;
+    if !use_totp_code(&user, secret, &code, &mut *user_store).await? {
+        return Err(AuthAPIError::IncorrectCredentials);
+    }
+
+    if user_store.enable_totp(&user.id).await.is_err() {
+        return Err(AuthAPIError::UnexpectedError);
+    }
+
+    let recovery_codes = issue_recovery_codes(&user.id, &mut *user_store).await?;
+
+    Ok((StatusCode::OK, Json(RecoveryCodesResponse { recovery_codes })).into_response())
+}
+
+fn second_factor_required(login_attempt_id: LoginAttemptId) -> Response {
+    let response = TwoFactorAuthResponse {
+        message: "2FA required".to_owned(),
+        login_attempt_id: login_attempt_id.as_ref().to_owned(),
+    };
+    (StatusCode::PARTIAL_CONTENT, Json(response)).into_response()
+}
+
+// Check a code from the user's authenticator app and use it up, so that a code
+// seen by someone else cannot be replayed while it is still current
+pub(crate) async fn use_totp_code(
+    user: &User,
+    secret: &TotpSecret,
+    code: &TwoFACode,
+    user_store: &mut (dyn UserStore + Send + Sync),
+) -> Result<bool, AuthAPIError> {
+    let step = match secret.verify(code, &user.email) {
+        Ok(Some(step)) => step,
+        Ok(None) => return Ok(false),
+        Err(_) => return Err(AuthAPIError::UnexpectedError),
+    };
+
+    match user_store.accept_totp_step(&user.id, step).await {
+        Ok(()) => Ok(true),
+        Err(UserStoreError::InvalidCredentials) => Ok(false),
+        Err(_) => Err(AuthAPIError::UnexpectedError),
+    }
+}
+
+#[derive(Deserialize)]
+pub struct EnrollTotpRequest {
+    pub password: String,
+    // Only in the second request of users with 2FA, see `reauthenticate`
+    #[serde(rename = "loginAttemptId")]
+    pub login_attempt_id: Option<String>,
+    #[serde(rename = "2FACode")]
+    pub two_fa_code: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct EnrollTotpResponse {
+    pub secret: String,
+    #[serde(rename = "otpauthUri")]
+    pub otpauth_uri: String,
+    // Base64 encoded PNG image
+    #[serde(rename = "qrCodePng")]
+    pub qr_code_png: String,
+    #[serde(rename = "qrCodeSvg")]
+    pub qr_code_svg: String,
+}
+
+#[derive(Deserialize)]
+pub struct ConfirmTotpRequest {
+    // From the authenticator app being enrolled
+    pub code: String,
+    pub password: String,
+    // Only in the second request of users with 2FA, see `reauthenticate`
+    #[serde(rename = "loginAttemptId")]
+    pub login_attempt_id: Option<String>,
+    #[serde(rename = "2FACode")]
+    pub two_fa_code: Option<String>,
+}
@@ -2,7 +2,26 @@ use axum::{extract::State, http::StatusCode, response::IntoResponse, Json};
 use axum_extra::extract::cookie::CookieJar;
 use serde::Deserialize;
 
-use crate::{app_state::AppState, domain::{AuthAPIError, Email, data_stores::{LoginAttemptId, TwoFACode}}, utils::auth::generate_auth_cookie};
+use std::net::IpAddr;
+
+use super::{
+    delete_account::restore_account,
+    login::{start_2fa, AuthTokenResponse},
+    totp::use_totp_code,
+};
+use crate::{
+    app_state::AppState,
+    domain::{
+        AuthAPIError, Email, Password, RecoveryCode, RefreshTokenFamilyId, TwoFAMethod, User, UserId, UserStore,
+        UserStoreError,
+        data_stores::{LoginAttemptId, TwoFACode, TwoFACodeStore, TwoFACodeStoreError},
+    },
+    utils::{
+        auth::{generate_auth_cookie, generate_refresh_cookie},
+        constants::TWO_FA_MAX_ATTEMPTS,
+        login_throttle,
+    },
+};
 
 pub async fn verify_2fa(
     State(state): State<AppState>, 
@@ -26,42 +45,209 @@ pub async fn verify_2fa(
         Err(_) => return (jar, Err(AuthAPIError::IncorrectCredentials)),
     };
     
-    let two_fa_code = match TwoFACode::parse(request.two_fa_code) {
-        Ok(code) => code,
+    let second_factor = match SecondFactor::parse(request.two_fa_code) {
+        Ok(second_factor) => second_factor,
         Err(_) => return (jar, Err(AuthAPIError::IncorrectCredentials)),
     };
 
-    // Get the stored code for this email
-    let two_fa_store = state.two_fa_code_store.read().await;
-    match two_fa_store.get_code(&email).await {
-        Ok((stored_login_attempt_id, stored_code)) => {
-            // Check if the provided credentials match the stored ones
-            if stored_login_attempt_id == login_attempt_id && stored_code == two_fa_code {
-                // Drop the read lock before acquiring write lock
-                drop(two_fa_store);
-                
-                // Remove the used 2FA code from the store
-                {
-                    let mut two_fa_store = state.two_fa_code_store.write().await;
-                    if let Err(_) = two_fa_store.remove_code(&email).await {
-                        return (jar, Err(AuthAPIError::UnexpectedError));
-                    }
-                } // Write lock is dropped here
-                
-                // Generate JWT token and set auth cookie
-                let auth_cookie = match generate_auth_cookie(&email) {
-                    Ok(cookie) => cookie,
-                    Err(_) => return (jar, Err(AuthAPIError::UnexpectedError)),
+    // Pending logins are stored by user ID
+    let user = {
+        let user_store = state.user_store.read().await;
+        match user_store.get_user_by_email(&email).await {
+            Ok(user) => user,
+            Err(UserStoreError::UserNotFound) => return (jar, Err(AuthAPIError::IncorrectCredentials)),
+            Err(_) => return (jar, Err(AuthAPIError::UnexpectedError)),
+        }
+    };
+
+    if let Err(e) = complete_2fa(&user, &login_attempt_id, &second_factor, &state).await {
+        return (jar, Err(e));
+    }
+
+    // Restore an account that was about to be deleted
+    if let Err(e) = restore_account(&user, &state).await {
+        return (jar, Err(e));
+    }
+
+    // Generate JWT token and set auth cookie
+    let auth_cookie = match generate_auth_cookie(&user) {
+        Ok(cookie) => cookie,
+        Err(_) => return (jar, Err(AuthAPIError::UnexpectedError)),
+    };
+
+    // The completed login starts a new refresh token family
+    let refresh_cookie = match generate_refresh_cookie(
+        &user.id,
+        RefreshTokenFamilyId::default(),
+        &state.refresh_token_store,
+    ).await {
+        Ok(cookie) => cookie,
+        Err(_) => return (jar, Err(AuthAPIError::UnexpectedError)),
+    };
+
+    let response = if request.return_token {
+        (StatusCode::OK, Json(AuthTokenResponse::new(&auth_cookie))).into_response()
+    } else {
+        StatusCode::OK.into_response()
+    };
+
+    // Return response with cookies
+    let updated_jar = jar.add(auth_cookie).add(refresh_cookie);
+    (updated_jar, Ok(response))
+}
+
+// Check the second factor against the user's pending 2FA attempt, which is
+// used up on success. Wrong codes count towards `TWO_FA_MAX_ATTEMPTS`.
+pub(crate) async fn complete_2fa(
+    user: &User,
+    login_attempt_id: &LoginAttemptId,
+    second_factor: &SecondFactor,
+    state: &AppState,
+) -> Result<(), AuthAPIError> {
+    // Emailed codes are compared by the store in the same step that uses
+    // them up, so that concurrent requests cannot both use the same code
+    if let (SecondFactor::Code(code), TwoFAMethod::Email) = (second_factor, user.two_fa_method()) {
+        let mut two_fa_store = state.two_fa_code_store.write().await;
+        return take_code(&user.id, login_attempt_id, Some(code), &mut *two_fa_store).await;
+    }
+
+    // Codes from an authenticator app and recovery codes are used up by
+    // checking them, which is only done for the pending login attempt. Both
+    // locks are held from checking the attempt until it is used up, so that a
+    // concurrent request cannot complete it in between and leave the user
+    // with a one-time code used up for nothing.
+    let mut user_store = state.user_store.write().await;
+    let mut two_fa_store = state.two_fa_code_store.write().await;
+    match two_fa_store.get_code(&user.id).await {
+        Ok((stored_login_attempt_id, _)) if &stored_login_attempt_id == login_attempt_id => {}
+        Ok(_) => return Err(record_failed_attempt(&user.id, &mut *two_fa_store).await),
+        Err(TwoFACodeStoreError::CodeExpired) => return Err(invalidate_login_attempt(&user.id, &mut *two_fa_store).await),
+        Err(_) => return Err(AuthAPIError::IncorrectCredentials),
+    }
+
+    let code_is_valid = match second_factor {
+        SecondFactor::Code(code) => match user.totp_secret() {
+            Some(secret) => use_totp_code(user, secret, code, &mut *user_store).await?,
+            None => false,
+        },
+        SecondFactor::RecoveryCode(recovery_code) => {
+            consume_recovery_code(&user.id, recovery_code, &mut *user_store).await?
+        }
+    };
+
+    if !code_is_valid {
+        return Err(record_failed_attempt(&user.id, &mut *two_fa_store).await);
+    }
+
+    take_code(&user.id, login_attempt_id, None, &mut *two_fa_store).await
+}
+
+// Outcome of `reauthenticate`
+pub(crate) enum Reauthentication {
+    Confirmed,
+    // The user has to send the request again with this login attempt's ID
+    // and a code
+    SecondFactorRequired(LoginAttemptId),
+}
+
+// Have a logged in user confirm a sensitive action with their password, and
+// with a second factor if they use 2FA: the first request starts a 2FA
+// attempt like `/login`, the second one sends its ID together with the code.
+// A session alone must not be enough, it may have been stolen.
+pub(crate) async fn reauthenticate(
+    user: &User,
+    password: String,
+    login_attempt_id: Option<String>,
+    two_fa_code: Option<String>,
+    ip: IpAddr,
+    state: &AppState,
+) -> Result<Reauthentication, AuthAPIError> {
+    let password = Password::parse(password).map_err(|_| AuthAPIError::InvalidCredentials)?;
+    login_throttle::verify_password(user, &password, ip, state).await?;
+
+    if !user.requires_2fa() {
+        return Ok(Reauthentication::Confirmed);
+    }
+
+    match (login_attempt_id, two_fa_code) {
+        (Some(login_attempt_id), Some(two_fa_code)) => {
+            let (login_attempt_id, second_factor) =
+                match (LoginAttemptId::parse(login_attempt_id), SecondFactor::parse(two_fa_code)) {
+                    (Ok(login_attempt_id), Ok(second_factor)) => (login_attempt_id, second_factor),
+                    _ => return Err(AuthAPIError::IncorrectCredentials),
                 };
-                
-                // Return response with cookie
-                let updated_jar = jar.add(auth_cookie);
-                (updated_jar, Ok(StatusCode::OK.into_response()))
-            } else {
-                (jar, Err(AuthAPIError::IncorrectCredentials))
-            }
+            complete_2fa(user, &login_attempt_id, &second_factor, state).await?;
+            Ok(Reauthentication::Confirmed)
         }
-        Err(_) => (jar, Err(AuthAPIError::IncorrectCredentials)),
+        _ => Ok(Reauthentication::SecondFactorRequired(start_2fa(user, state).await?)),
+    }
+}
+
+// What the user sent in the `2FACode` field
+pub(crate) enum SecondFactor {
+    Code(TwoFACode),
+    RecoveryCode(RecoveryCode),
+}
+
+impl SecondFactor {
+    pub(crate) fn parse(code: String) -> Result<Self, String> {
+        match TwoFACode::parse(code.clone()) {
+            Ok(code) => Ok(SecondFactor::Code(code)),
+            Err(_) => RecoveryCode::parse(code).map(SecondFactor::RecoveryCode),
+        }
+    }
+}
+
+// A recovery code works in place of any 2FA code, but only once
+async fn consume_recovery_code(
+    user_id: &UserId,
+    recovery_code: &RecoveryCode,
+    user_store: &mut (dyn UserStore + Send + Sync),
+) -> Result<bool, AuthAPIError> {
+    match user_store.consume_recovery_code(user_id, &recovery_code.hash()).await {
+        Ok(()) => Ok(true),
+        Err(UserStoreError::InvalidCredentials) => Ok(false),
+        Err(_) => Err(AuthAPIError::UnexpectedError),
+    }
+}
+
+// Use up the pending login attempt, see `TwoFACodeStore::take_code`
+async fn take_code(
+    user_id: &UserId,
+    login_attempt_id: &LoginAttemptId,
+    code: Option<&TwoFACode>,
+    two_fa_store: &mut (dyn TwoFACodeStore + Send + Sync),
+) -> Result<(), AuthAPIError> {
+    match two_fa_store.take_code(user_id, login_attempt_id, code, *TWO_FA_MAX_ATTEMPTS).await {
+        Ok(()) => Ok(()),
+        Err(TwoFACodeStoreError::IncorrectCode(failed_attempts)) => Err(failed_attempt_error(failed_attempts)),
+        Err(TwoFACodeStoreError::CodeExpired) => Err(AuthAPIError::LoginAttemptExpired),
+        Err(TwoFACodeStoreError::LoginAttemptIdNotFound) => Err(AuthAPIError::IncorrectCredentials),
+        Err(_) => Err(AuthAPIError::UnexpectedError),
+    }
+}
+
+// Count the wrong code. The store invalidates the login attempt once too many
+// wrong codes have been tried, so the code cannot be brute forced.
+async fn record_failed_attempt(user_id: &UserId, two_fa_store: &mut (dyn TwoFACodeStore + Send + Sync)) -> AuthAPIError {
+    match two_fa_store.record_failed_attempt(user_id, *TWO_FA_MAX_ATTEMPTS).await {
+        Ok(failed_attempts) => failed_attempt_error(failed_attempts),
+        Err(_) => AuthAPIError::IncorrectCredentials,
+    }
+}
+
+fn failed_attempt_error(failed_attempts: u32) -> AuthAPIError {
+    if failed_attempts >= *TWO_FA_MAX_ATTEMPTS {
+        AuthAPIError::LoginAttemptExpired
+    } else {
+        AuthAPIError::IncorrectCredentials
+    }
+}
+
+async fn invalidate_login_attempt(user_id: &UserId, two_fa_store: &mut (dyn TwoFACodeStore + Send + Sync)) -> AuthAPIError {
+    match two_fa_store.remove_code(user_id).await {
+        Ok(()) => AuthAPIError::LoginAttemptExpired,
+        Err(_) => AuthAPIError::UnexpectedError,
     }
 }
 
@@ -72,4 +258,7 @@ pub struct Verify2FARequest {
     login_attempt_id: String,
     #[serde(rename = "2FACode")]
     two_fa_code: String,
+    // See LoginRequest
+    #[serde(rename = "returnToken", default)]
+    return_token: bool,
 }
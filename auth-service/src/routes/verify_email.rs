@@ -0,0 +1,110 @@
+use axum::{
+    extract::{Query, State},
+    http::StatusCode,
+    response::IntoResponse,
+    Json,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    app_state::AppState,
+    domain::{AuthAPIError, Email, User, UserStoreError},
+    utils::{auth, constants::PUBLIC_URL},
+};
+
+// Target of the link emailed at signup
+pub async fn verify_email(
+    State(state): State<AppState>,
+    Query(request): Query<VerifyEmailRequest>,
+) -> Result<impl IntoResponse, AuthAPIError> {
+    let verification = auth::validate_email_verification_token(&request.token)
+        .map_err(|_| AuthAPIError::InvalidToken)?;
+
+    let mut user_store = state.user_store.write().await;
+    let user = user_store.get_user(&verification.user_id).await.map_err(|e| match e {
+        // The link may outlive the account it was sent for
+        UserStoreError::UserNotFound => AuthAPIError::InvalidToken,
+        _ => AuthAPIError::UnexpectedError,
+    })?;
+
+    // A link sent to an address the user has moved away from proves nothing
+    // about the current one
+    if user.email != verification.email {
+        return Err(AuthAPIError::InvalidToken);
+    }
+
+    user_store
+        .verify_email(&user.id)
+        .await
+        .map_err(|_| AuthAPIError::UnexpectedError)?;
+
+    Ok((
+        StatusCode::OK,
+        Json(VerifyEmailResponse {
+            message: "Email address verified".to_owned(),
+        }),
+    ))
+}
+
+// Send another verification link, e.g. because the first one expired. The
+// answer is the same whether or not the account exists or still needs to be
+// verified, so that it does not reveal who signed up.
+pub async fn resend_verification_email(
+    State(state): State<AppState>,
+    Json(request): Json<ResendVerificationEmailRequest>,
+) -> Result<impl IntoResponse, AuthAPIError> {
+    let email = Email::parse(request.email).map_err(|_| AuthAPIError::InvalidCredentials)?;
+
+    let user = {
+        let user_store = state.user_store.read().await;
+        match user_store.get_user_by_email(&email).await {
+            Ok(user) => Some(user).filter(|user| !user.email_verified()),
+            Err(UserStoreError::UserNotFound) => None,
+            Err(_) => return Err(AuthAPIError::UnexpectedError),
+        }
+    };
+
+    if let Some(user) = user {
+        if let Err(e) = send_verification_email(&user, &state).await {
+            println!("Failed to send verification email to {}: {:?}", email.as_ref(), e);
+        }
+    }
+
+    Ok((
+        StatusCode::OK,
+        Json(VerifyEmailResponse {
+            message: "If the account needs to be verified, a new link was sent".to_owned(),
+        }),
+    ))
+}
+
+// Email the user a link to `/verify-email`
+pub(crate) async fn send_verification_email(user: &User, state: &AppState) -> Result<(), AuthAPIError> {
+    let token = auth::generate_email_verification_token(user).map_err(|_| AuthAPIError::UnexpectedError)?;
+    let link = format!("{}/verify-email?token={}", PUBLIC_URL.trim_end_matches('/'), token);
+
+    let email_client = state.email_client.read().await;
+    email_client
+        .send_email(
+            &user.email,
+            "Verify your email address",
+            &format!("Please verify your email address by opening this link: {}", link),
+        )
+        .await
+        .map_err(|_| AuthAPIError::UnexpectedError)
+}
+
+#[derive(Deserialize)]
+pub struct VerifyEmailRequest {
+    pub token: String,
+}
+
+#[derive(Deserialize)]
+pub struct ResendVerificationEmailRequest {
+    pub email: String,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct VerifyEmailResponse {
+    pub message: String,
+}
@@ -1,14 +1,16 @@
-use axum::{extract::State, response::IntoResponse, http::StatusCode, Json};
+use axum::{extract::State, Json};
 use serde::Deserialize;
 
-use crate::{app_state::AppState, domain::AuthAPIError, utils::auth::validate_token};
+use crate::{app_state::AppState, domain::AuthAPIError, utils::auth::{validate_token, Claims}};
 
+// Valid tokens are answered with their claims, so that other services learn
+// who the user is
 pub async fn verify_token(
     State(state): State<AppState>,
     Json(request): Json<VerifyTokenRequest>
-) -> Result<impl IntoResponse, AuthAPIError> {
+) -> Result<Json<Claims>, AuthAPIError> {
     match validate_token(&request.token, Some(&state.banned_token_store)).await {
-        Ok(_) => Ok(StatusCode::OK.into_response()),
+        Ok(claims) => Ok(Json(claims)),
         Err(_) => Err(AuthAPIError::InvalidToken),
     }
 }
@@ -0,0 +1,170 @@
+use std::collections::HashMap;
+use chrono::Utc;
+
+use crate::{
+    domain::data_stores::{FailedLoginKey, FailedLoginStore, FailedLoginStoreError, FailedLogins},
+    utils::constants::LOGIN_LOCKOUT_SECONDS,
+};
+
+pub struct HashmapFailedLoginStore {
+    failures: HashMap<String, FailedLogins>,
+    ttl_seconds: i64,
+}
+
+impl HashmapFailedLoginStore {
+    pub fn new(ttl_seconds: u64) -> Self {
+        Self {
+            failures: HashMap::new(),
+            ttl_seconds: ttl_seconds.try_into().unwrap_or(i64::MAX),
+        }
+    }
+}
+
+impl Default for HashmapFailedLoginStore {
+    fn default() -> Self {
+        Self::new((*LOGIN_LOCKOUT_SECONDS).into())
+    }
+}
+
+#[async_trait::async_trait]
+impl FailedLoginStore for HashmapFailedLoginStore {
+    async fn get_failures(&self, key: &FailedLoginKey) -> Result<Option<FailedLogins>, FailedLoginStoreError> {
+        let now = Utc::now().timestamp();
+        Ok(self
+            .failures
+            .get(&key.as_key())
+            .filter(|failures| !is_expired(failures, self.ttl_seconds, now))
+            .copied())
+    }
+
+    async fn record_attempt(&mut self, key: &FailedLoginKey) -> Result<Option<FailedLogins>, FailedLoginStoreError> {
+        // Expired failures of other keys are left to `prune_expired`
+        let now = Utc::now().timestamp();
+        let previous = self
+            .failures
+            .get(&key.as_key())
+            .filter(|failures| !is_expired(failures, self.ttl_seconds, now))
+            .copied();
+        self.failures.insert(
+            key.as_key(),
+            FailedLogins {
+                count: previous.map_or(0, |failures| failures.count) + 1,
+                last_failed_at: now,
+            },
+        );
+        Ok(previous)
+    }
+
+    async fn forgive_attempt(&mut self, key: &FailedLoginKey) -> Result<(), FailedLoginStoreError> {
+        if let Some(failures) = self.failures.get_mut(&key.as_key()) {
+            failures.count = failures.count.saturating_sub(1);
+        }
+        Ok(())
+    }
+
+    async fn clear_failures(&mut self, key: &FailedLoginKey) -> Result<(), FailedLoginStoreError> {
+        self.failures.remove(&key.as_key());
+        Ok(())
+    }
+
+    async fn prune_expired(&mut self) -> Result<usize, FailedLoginStoreError> {
+        let now = Utc::now().timestamp();
+        let ttl_seconds = self.ttl_seconds;
+        let before = self.failures.len();
+        self.failures.retain(|_, failures| !is_expired(failures, ttl_seconds, now));
+        Ok(before - self.failures.len())
+    }
+}
+
+// Failures are forgotten once none was added for the TTL
+fn is_expired(failures: &FailedLogins, ttl_seconds: i64, now: i64) -> bool {
+    failures.last_failed_at.saturating_add(ttl_seconds) <= now
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::Email;
+
+    fn email_key() -> FailedLoginKey {
+        FailedLoginKey::Email(Email::parse("test@example.com".to_owned()).unwrap())
+    }
+
+    fn ip_key() -> FailedLoginKey {
+        FailedLoginKey::Ip("127.0.0.1".parse().unwrap())
+    }
+
+    async fn count(store: &mut HashmapFailedLoginStore, key: &FailedLoginKey) -> u32 {
+        store.record_attempt(key).await.unwrap().map_or(0, |failures| failures.count)
+    }
+
+    #[tokio::test]
+    async fn test_record_attempt() {
+        let mut store = HashmapFailedLoginStore::new(600);
+
+        assert_eq!(store.record_attempt(&email_key()).await, Ok(None));
+        assert_eq!(count(&mut store, &email_key()).await, 1);
+        assert_eq!(count(&mut store, &ip_key()).await, 0);
+
+        let failures = store.record_attempt(&email_key()).await.unwrap().unwrap();
+        assert_eq!(failures.count, 2);
+        assert!(failures.last_failed_at <= Utc::now().timestamp());
+    }
+
+    #[tokio::test]
+    async fn test_get_failures() {
+        let mut store = HashmapFailedLoginStore::new(600);
+        assert_eq!(store.get_failures(&email_key()).await, Ok(None));
+
+        store.record_attempt(&email_key()).await.unwrap();
+        let failures = store.get_failures(&email_key()).await.unwrap().unwrap();
+        assert_eq!(failures.count, 1);
+
+        // Reading does not count
+        assert_eq!(store.get_failures(&email_key()).await, Ok(Some(failures)));
+        assert_eq!(store.get_failures(&ip_key()).await, Ok(None));
+    }
+
+    #[tokio::test]
+    async fn test_forgive_attempt() {
+        let mut store = HashmapFailedLoginStore::new(600);
+        store.record_attempt(&ip_key()).await.unwrap();
+        store.record_attempt(&ip_key()).await.unwrap();
+
+        store.forgive_attempt(&ip_key()).await.unwrap();
+        assert_eq!(count(&mut store, &ip_key()).await, 1);
+
+        store.forgive_attempt(&email_key()).await.unwrap();
+        assert_eq!(store.record_attempt(&email_key()).await, Ok(None));
+    }
+
+    #[tokio::test]
+    async fn test_clear_failures() {
+        let mut store = HashmapFailedLoginStore::new(600);
+        store.record_attempt(&email_key()).await.unwrap();
+        store.record_attempt(&ip_key()).await.unwrap();
+
+        store.clear_failures(&email_key()).await.unwrap();
+        assert_eq!(store.record_attempt(&email_key()).await, Ok(None));
+        assert_eq!(count(&mut store, &ip_key()).await, 1);
+    }
+
+    #[tokio::test]
+    async fn test_prune_expired() {
+        let mut store = HashmapFailedLoginStore::new(0);
+        store.record_attempt(&email_key()).await.unwrap();
+        store.record_attempt(&ip_key()).await.unwrap();
+
+        assert_eq!(store.prune_expired().await, Ok(2));
+        assert!(store.failures.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_failures_expire() {
+        let mut store = HashmapFailedLoginStore::new(0);
+        store.record_attempt(&email_key()).await.unwrap();
+
+        assert_eq!(store.get_failures(&email_key()).await, Ok(None));
+        assert_eq!(store.record_attempt(&email_key()).await, Ok(None));
+    }
+}
@@ -0,0 +1,216 @@
+use std::collections::{HashMap, HashSet};
+
+use chrono::Utc;
+
+use crate::domain::{
+    data_stores::{RefreshTokenStore, RefreshTokenStoreError},
+    RefreshSession, UserId, RefreshTokenFamilyId, RefreshTokenHash,
+};
+
+#[derive(Default)]
+pub struct HashmapRefreshTokenStore {
+    tokens: HashMap<RefreshTokenHash, StoredToken>,
+    // Revoked families and until when they are remembered
+    revoked_families: HashMap<RefreshTokenFamilyId, i64>,
+}
+
+// Used tokens are kept until they expire, to recognize them if they are
+// presented again
+struct StoredToken {
+    session: RefreshSession,
+    used: bool,
+}
+
+#[async_trait::async_trait]
+impl RefreshTokenStore for HashmapRefreshTokenStore {
+    async fn add_token(
+        &mut self,
+        token: RefreshTokenHash,
+        session: RefreshSession,
+    ) -> Result<(), RefreshTokenStoreError> {
+        // Drop expired tokens here, there is no other point where they would go away
+        let now = Utc::now().timestamp();
+        self.tokens.retain(|_, stored| stored.session.expires_at > now);
+        self.revoked_families.retain(|_, expires_at| *expires_at > now);
+
+        if self.revoked_families.contains_key(&session.family_id) {
+            return Err(RefreshTokenStoreError::FamilyRevoked);
+        }
+
+        self.tokens.insert(token, StoredToken { session, used: false });
+        Ok(())
+    }
+
+    async fn use_token(
+        &mut self,
+        token: &RefreshTokenHash,
+    ) -> Result<RefreshSession, RefreshTokenStoreError> {
+        let stored = self
+            .tokens
+            .get_mut(token)
+            .filter(|stored| stored.session.expires_at > Utc::now().timestamp())
+            .ok_or(RefreshTokenStoreError::TokenNotFound)?;
+
+        if stored.used {
+            return Err(RefreshTokenStoreError::TokenReused(stored.session.family_id.clone()));
+        }
+
+        stored.used = true;
+        Ok(stored.session.clone())
+    }
+
+    async fn revoke_family(
+        &mut self,
+        family_id: &RefreshTokenFamilyId,
+    ) -> Result<(), RefreshTokenStoreError> {
+        let expires_at = self
+            .tokens
+            .values()
+            .filter(|stored| &stored.session.family_id == family_id)
+            .map(|stored| stored.session.expires_at)
+            .max();
+
+        if let Some(expires_at) = expires_at {
+            let revoked_until = self.revoked_families.entry(family_id.clone()).or_insert(expires_at);
+            *revoked_until = (*revoked_until).max(expires_at);
+        }
+
+        self.tokens.retain(|_, stored| &stored.session.family_id != family_id);
+        Ok(())
+    }
+
+    async fn revoke_user(&mut self, user_id: &UserId) -> Result<(), RefreshTokenStoreError> {
+        let family_ids: HashSet<RefreshTokenFamilyId> = self
+            .tokens
+            .values()
+            .filter(|stored| &stored.session.user_id == user_id)
+            .map(|stored| stored.session.family_id.clone())
+            .collect();
+
+        for family_id in family_ids {
+            self.revoke_family(&family_id).await?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::RefreshToken;
+
+    // All sessions belong to the same user unless overridden
+    fn user_id() -> UserId {
+        UserId::parse("5f3f3c4e-8a4b-4d9e-9a57-0c7d6f1e2b3a".to_string()).unwrap()
+    }
+
+    fn session(family_id: &RefreshTokenFamilyId, expires_in: i64) -> RefreshSession {
+        RefreshSession {
+            user_id: user_id(),
+            family_id: family_id.clone(),
+            expires_at: Utc::now().timestamp() + expires_in,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_use_token() {
+        let mut store = HashmapRefreshTokenStore::default();
+        let family_id = RefreshTokenFamilyId::default();
+        let token = RefreshToken::generate().hash();
+
+        store.add_token(token.clone(), session(&family_id, 60)).await.unwrap();
+
+        let result = store.use_token(&token).await.unwrap();
+        assert_eq!(result.family_id, family_id);
+
+        // A token can only be used once
+        let result = store.use_token(&token).await;
+        assert_eq!(result, Err(RefreshTokenStoreError::TokenReused(family_id)));
+    }
+
+    #[tokio::test]
+    async fn test_use_unknown_token() {
+        let mut store = HashmapRefreshTokenStore::default();
+        let result = store.use_token(&RefreshToken::generate().hash()).await;
+        assert_eq!(result, Err(RefreshTokenStoreError::TokenNotFound));
+    }
+
+    #[tokio::test]
+    async fn test_use_expired_token() {
+        let mut store = HashmapRefreshTokenStore::default();
+        let token = RefreshToken::generate().hash();
+
+        store
+            .add_token(token.clone(), session(&RefreshTokenFamilyId::default(), 0))
+            .await
+            .unwrap();
+
+        let result = store.use_token(&token).await;
+        assert_eq!(result, Err(RefreshTokenStoreError::TokenNotFound));
+    }
+
+    #[tokio::test]
+    async fn test_revoke_family() {
+        let mut store = HashmapRefreshTokenStore::default();
+        let family_id = RefreshTokenFamilyId::default();
+        let other_family_id = RefreshTokenFamilyId::default();
+        let first = RefreshToken::generate().hash();
+        let second = RefreshToken::generate().hash();
+        let other = RefreshToken::generate().hash();
+
+        store.add_token(first.clone(), session(&family_id, 60)).await.unwrap();
+        store.add_token(second.clone(), session(&family_id, 60)).await.unwrap();
+        store.add_token(other.clone(), session(&other_family_id, 60)).await.unwrap();
+
+        store.revoke_family(&family_id).await.unwrap();
+
+        assert_eq!(store.use_token(&first).await, Err(RefreshTokenStoreError::TokenNotFound));
+        assert_eq!(store.use_token(&second).await, Err(RefreshTokenStoreError::TokenNotFound));
+        assert!(store.use_token(&other).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_add_token_to_revoked_family() {
+        let mut store = HashmapRefreshTokenStore::default();
+        let family_id = RefreshTokenFamilyId::default();
+        let token = RefreshToken::generate().hash();
+
+        store.add_token(token.clone(), session(&family_id, 60)).await.unwrap();
+        // The token is being refreshed while its family is revoked
+        store.use_token(&token).await.unwrap();
+        store.revoke_user(&user_id()).await.unwrap();
+
+        let result = store
+            .add_token(RefreshToken::generate().hash(), session(&family_id, 60))
+            .await;
+        assert_eq!(result, Err(RefreshTokenStoreError::FamilyRevoked));
+
+        // New logins start a new family
+        let result = store
+            .add_token(RefreshToken::generate().hash(), session(&RefreshTokenFamilyId::default(), 60))
+            .await;
+        assert!(result.is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_revoke_user() {
+        let mut store = HashmapRefreshTokenStore::default();
+        let first = RefreshToken::generate().hash();
+        let second = RefreshToken::generate().hash();
+        let other = RefreshToken::generate().hash();
+
+        store.add_token(first.clone(), session(&RefreshTokenFamilyId::default(), 60)).await.unwrap();
+        store.add_token(second.clone(), session(&RefreshTokenFamilyId::default(), 60)).await.unwrap();
+        let other_session = RefreshSession {
+            user_id: UserId::default(),
+            ..session(&RefreshTokenFamilyId::default(), 60)
+        };
+        store.add_token(other.clone(), other_session).await.unwrap();
+
+        store.revoke_user(&user_id()).await.unwrap();
+
+        assert_eq!(store.use_token(&first).await, Err(RefreshTokenStoreError::TokenNotFound));
+        assert_eq!(store.use_token(&second).await, Err(RefreshTokenStoreError::TokenNotFound));
+        assert!(store.use_token(&other).await.is_ok());
+    }
+}
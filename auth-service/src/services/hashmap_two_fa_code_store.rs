@@ -1,40 +1,127 @@
 use std::collections::HashMap;
+use chrono::Utc;
 
-use crate::domain::{
-    data_stores::{LoginAttemptId, TwoFACode, TwoFACodeStore, TwoFACodeStoreError},
-    email::Email,
+use crate::{
+    domain::{
+        data_stores::{LoginAttemptId, TwoFACode, TwoFACodeStore, TwoFACodeStoreError},
+        UserId,
+    },
+    utils::constants::TWO_FA_CODE_TTL_SECONDS,
 };
 
-#[derive(Default)]
 pub struct HashmapTwoFACodeStore {
-    codes: HashMap<Email, (LoginAttemptId, TwoFACode)>,
+    codes: HashMap<UserId, PendingCode>,
+    ttl_seconds: i64,
+}
+
+// A code waiting to be verified, together with when it was created and how
+// many wrong codes have been tried for it
+struct PendingCode {
+    login_attempt_id: LoginAttemptId,
+    code: TwoFACode,
+    created_at: i64,
+    failed_attempts: u32,
+}
+
+impl HashmapTwoFACodeStore {
+    pub fn new(ttl_seconds: u64) -> Self {
+        Self {
+            codes: HashMap::new(),
+            ttl_seconds: ttl_seconds.try_into().unwrap_or(i64::MAX),
+        }
+    }
+}
+
+impl Default for HashmapTwoFACodeStore {
+    fn default() -> Self {
+        Self::new((*TWO_FA_CODE_TTL_SECONDS).into())
+    }
 }
 
 #[async_trait::async_trait]
 impl TwoFACodeStore for HashmapTwoFACodeStore {
     async fn add_code(
         &mut self,
-        email: Email,
+        user_id: UserId,
         login_attempt_id: LoginAttemptId,
         code: TwoFACode,
     ) -> Result<(), TwoFACodeStoreError> {
-        self.codes.insert(email, (login_attempt_id, code));
+        let pending_code = PendingCode {
+            login_attempt_id,
+            code,
+            created_at: Utc::now().timestamp(),
+            failed_attempts: 0,
+        };
+        self.codes.insert(user_id, pending_code);
         Ok(())
     }
 
-    async fn remove_code(&mut self, email: &Email) -> Result<(), TwoFACodeStoreError> {
-        self.codes.remove(email);
+    async fn remove_code(&mut self, user_id: &UserId) -> Result<(), TwoFACodeStoreError> {
+        self.codes.remove(user_id);
         Ok(())
     }
 
     async fn get_code(
         &self,
-        email: &Email,
+        user_id: &UserId,
     ) -> Result<(LoginAttemptId, TwoFACode), TwoFACodeStoreError> {
-        self.codes
-            .get(email)
-            .cloned()
-            .ok_or(TwoFACodeStoreError::LoginAttemptIdNotFound)
+        let pending_code = self
+            .codes
+            .get(user_id)
+            .ok_or(TwoFACodeStoreError::LoginAttemptIdNotFound)?;
+
+        if Utc::now().timestamp() - pending_code.created_at >= self.ttl_seconds {
+            return Err(TwoFACodeStoreError::CodeExpired);
+        }
+
+        Ok((pending_code.login_attempt_id.clone(), pending_code.code.clone()))
+    }
+
+    async fn take_code(
+        &mut self,
+        user_id: &UserId,
+        login_attempt_id: &LoginAttemptId,
+        code: Option<&TwoFACode>,
+        max_attempts: u32,
+    ) -> Result<(), TwoFACodeStoreError> {
+        let pending_code = self
+            .codes
+            .get(user_id)
+            .ok_or(TwoFACodeStoreError::LoginAttemptIdNotFound)?;
+
+        if Utc::now().timestamp() - pending_code.created_at >= self.ttl_seconds {
+            self.codes.remove(user_id);
+            return Err(TwoFACodeStoreError::CodeExpired);
+        }
+
+        if &pending_code.login_attempt_id != login_attempt_id
+            || code.is_some_and(|code| code != &pending_code.code)
+        {
+            let failed_attempts = self.record_failed_attempt(user_id, max_attempts).await?;
+            return Err(TwoFACodeStoreError::IncorrectCode(failed_attempts));
+        }
+
+        self.codes.remove(user_id);
+        Ok(())
+    }
+
+    async fn record_failed_attempt(&mut self, user_id: &UserId, max_attempts: u32) -> Result<u32, TwoFACodeStoreError> {
+        let pending_code = self
+            .codes
+            .get_mut(user_id)
+            .ok_or(TwoFACodeStoreError::LoginAttemptIdNotFound)?;
+
+        if Utc::now().timestamp() - pending_code.created_at >= self.ttl_seconds {
+            self.codes.remove(user_id);
+            return Err(TwoFACodeStoreError::CodeExpired);
+        }
+
+        pending_code.failed_attempts += 1;
+        let failed_attempts = pending_code.failed_attempts;
+        if failed_attempts >= max_attempts {
+            self.codes.remove(user_id);
+        }
+        Ok(failed_attempts)
     }
 }
 
@@ -46,16 +133,16 @@ mod tests {
     #[tokio::test]
     async fn test_add_and_get_code() {
         let mut store = HashmapTwoFACodeStore::default();
-        let email = Email::parse("test@example.com".to_string()).unwrap();
+        let user_id = UserId::default();
         let login_attempt_id = LoginAttemptId::default();
         let code = TwoFACode::parse("123456".to_string()).unwrap();
 
         let result = store
-            .add_code(email.clone(), login_attempt_id.clone(), code.clone())
+            .add_code(user_id.clone(), login_attempt_id.clone(), code.clone())
             .await;
         assert!(result.is_ok());
 
-        let retrieved = store.get_code(&email).await;
+        let retrieved = store.get_code(&user_id).await;
         assert!(retrieved.is_ok());
         let (retrieved_id, retrieved_code) = retrieved.unwrap();
         assert_eq!(retrieved_id, login_attempt_id);
@@ -65,89 +152,201 @@ mod tests {
     #[tokio::test]
     async fn test_get_code_not_found() {
         let store = HashmapTwoFACodeStore::default();
-        let email = Email::parse("nonexistent@example.com".to_string()).unwrap();
+        let user_id = UserId::default();
 
-        let result = store.get_code(&email).await;
+        let result = store.get_code(&user_id).await;
         assert_eq!(result, Err(TwoFACodeStoreError::LoginAttemptIdNotFound));
     }
 
     #[tokio::test]
     async fn test_remove_code() {
         let mut store = HashmapTwoFACodeStore::default();
-        let email = Email::parse("test@example.com".to_string()).unwrap();
+        let user_id = UserId::default();
         let login_attempt_id = LoginAttemptId::default();
         let code = TwoFACode::parse("123456".to_string()).unwrap();
 
         store
-            .add_code(email.clone(), login_attempt_id, code)
+            .add_code(user_id.clone(), login_attempt_id, code)
             .await
             .unwrap();
 
-        let result = store.remove_code(&email).await;
+        let result = store.remove_code(&user_id).await;
         assert!(result.is_ok());
 
-        let get_result = store.get_code(&email).await;
+        let get_result = store.get_code(&user_id).await;
         assert_eq!(get_result, Err(TwoFACodeStoreError::LoginAttemptIdNotFound));
     }
 
     #[tokio::test]
     async fn test_remove_nonexistent_code() {
         let mut store = HashmapTwoFACodeStore::default();
-        let email = Email::parse("nonexistent@example.com".to_string()).unwrap();
+        let user_id = UserId::default();
 
-        let result = store.remove_code(&email).await;
+        let result = store.remove_code(&user_id).await;
         assert!(result.is_ok());
     }
 
     #[tokio::test]
     async fn test_overwrite_existing_code() {
         let mut store = HashmapTwoFACodeStore::default();
-        let email = Email::parse("test@example.com".to_string()).unwrap();
+        let user_id = UserId::default();
         let login_attempt_id1 = LoginAttemptId::default();
         let code1 = TwoFACode::parse("123456".to_string()).unwrap();
         let login_attempt_id2 = LoginAttemptId::default();
         let code2 = TwoFACode::parse("654321".to_string()).unwrap();
 
         store
-            .add_code(email.clone(), login_attempt_id1, code1)
+            .add_code(user_id.clone(), login_attempt_id1, code1)
             .await
             .unwrap();
 
         store
-            .add_code(email.clone(), login_attempt_id2.clone(), code2.clone())
+            .add_code(user_id.clone(), login_attempt_id2.clone(), code2.clone())
             .await
             .unwrap();
 
-        let retrieved = store.get_code(&email).await.unwrap();
+        let retrieved = store.get_code(&user_id).await.unwrap();
         assert_eq!(retrieved.0, login_attempt_id2);
         assert_eq!(retrieved.1, code2);
     }
 
     #[tokio::test]
-    async fn test_multiple_emails() {
+    async fn test_multiple_users() {
         let mut store = HashmapTwoFACodeStore::default();
-        let email1 = Email::parse("user1@example.com".to_string()).unwrap();
-        let email2 = Email::parse("user2@example.com".to_string()).unwrap();
+        let user_id1 = UserId::default();
+        let user_id2 = UserId::default();
         let login_attempt_id1 = LoginAttemptId::default();
         let login_attempt_id2 = LoginAttemptId::default();
         let code1 = TwoFACode::parse("111111".to_string()).unwrap();
         let code2 = TwoFACode::parse("222222".to_string()).unwrap();
 
         store
-            .add_code(email1.clone(), login_attempt_id1.clone(), code1.clone())
+            .add_code(user_id1.clone(), login_attempt_id1.clone(), code1.clone())
             .await
             .unwrap();
         store
-            .add_code(email2.clone(), login_attempt_id2.clone(), code2.clone())
+            .add_code(user_id2.clone(), login_attempt_id2.clone(), code2.clone())
             .await
             .unwrap();
 
-        let retrieved1 = store.get_code(&email1).await.unwrap();
-        let retrieved2 = store.get_code(&email2).await.unwrap();
+        let retrieved1 = store.get_code(&user_id1).await.unwrap();
+        let retrieved2 = store.get_code(&user_id2).await.unwrap();
 
         assert_eq!(retrieved1.0, login_attempt_id1);
         assert_eq!(retrieved1.1, code1);
         assert_eq!(retrieved2.0, login_attempt_id2);
         assert_eq!(retrieved2.1, code2);
     }
-}
\ No newline at end of file
+
+    #[tokio::test]
+    async fn test_get_expired_code() {
+        let mut store = HashmapTwoFACodeStore::new(0);
+        let user_id = UserId::default();
+        let code = TwoFACode::parse("123456".to_string()).unwrap();
+
+        store
+            .add_code(user_id.clone(), LoginAttemptId::default(), code)
+            .await
+            .unwrap();
+
+        let result = store.get_code(&user_id).await;
+        assert_eq!(result, Err(TwoFACodeStoreError::CodeExpired));
+    }
+
+    #[tokio::test]
+    async fn test_record_failed_attempt() {
+        let mut store = HashmapTwoFACodeStore::default();
+        let user_id = UserId::default();
+        let code = TwoFACode::parse("123456".to_string()).unwrap();
+
+        // No pending login attempt
+        let result = store.record_failed_attempt(&user_id, 3).await;
+        assert_eq!(result, Err(TwoFACodeStoreError::LoginAttemptIdNotFound));
+
+        store
+            .add_code(user_id.clone(), LoginAttemptId::default(), code.clone())
+            .await
+            .unwrap();
+        assert_eq!(store.record_failed_attempt(&user_id, 3).await, Ok(1));
+        assert_eq!(store.record_failed_attempt(&user_id, 3).await, Ok(2));
+
+        // A new login attempt starts counting from scratch
+        store
+            .add_code(user_id.clone(), LoginAttemptId::default(), code)
+            .await
+            .unwrap();
+        assert_eq!(store.record_failed_attempt(&user_id, 3).await, Ok(1));
+        assert_eq!(store.record_failed_attempt(&user_id, 3).await, Ok(2));
+
+        // The last allowed failure removes the login attempt
+        assert_eq!(store.record_failed_attempt(&user_id, 3).await, Ok(3));
+        let result = store.get_code(&user_id).await;
+        assert_eq!(result, Err(TwoFACodeStoreError::LoginAttemptIdNotFound));
+    }
+
+    #[tokio::test]
+    async fn test_take_code() {
+        let mut store = HashmapTwoFACodeStore::default();
+        let user_id = UserId::default();
+        let login_attempt_id = LoginAttemptId::default();
+        let code = TwoFACode::parse("123456".to_string()).unwrap();
+        let wrong_code = TwoFACode::parse("654321".to_string()).unwrap();
+
+        store
+            .add_code(user_id.clone(), login_attempt_id.clone(), code.clone())
+            .await
+            .unwrap();
+
+        let result = store.take_code(&user_id, &login_attempt_id, Some(&wrong_code), 5).await;
+        assert_eq!(result, Err(TwoFACodeStoreError::IncorrectCode(1)));
+        let result = store.take_code(&user_id, &LoginAttemptId::default(), Some(&code), 5).await;
+        assert_eq!(result, Err(TwoFACodeStoreError::IncorrectCode(2)));
+
+        // The code can only be taken once
+        assert_eq!(store.take_code(&user_id, &login_attempt_id, Some(&code), 5).await, Ok(()));
+        let result = store.take_code(&user_id, &login_attempt_id, Some(&code), 5).await;
+        assert_eq!(result, Err(TwoFACodeStoreError::LoginAttemptIdNotFound));
+
+        // Without a code only the login attempt is checked
+        store
+            .add_code(user_id.clone(), login_attempt_id.clone(), code)
+            .await
+            .unwrap();
+        assert_eq!(store.take_code(&user_id, &login_attempt_id, None, 5).await, Ok(()));
+    }
+
+    #[tokio::test]
+    async fn test_take_expired_code() {
+        let mut store = HashmapTwoFACodeStore::new(0);
+        let user_id = UserId::default();
+        let login_attempt_id = LoginAttemptId::default();
+        let code = TwoFACode::parse("123456".to_string()).unwrap();
+
+        store
+            .add_code(user_id.clone(), login_attempt_id.clone(), code.clone())
+            .await
+            .unwrap();
+
+        let result = store.take_code(&user_id, &login_attempt_id, Some(&code), 5).await;
+        assert_eq!(result, Err(TwoFACodeStoreError::CodeExpired));
+        let result = store.get_code(&user_id).await;
+        assert_eq!(result, Err(TwoFACodeStoreError::LoginAttemptIdNotFound));
+    }
+
+    #[tokio::test]
+    async fn test_record_failed_attempt_for_expired_code() {
+        let mut store = HashmapTwoFACodeStore::new(0);
+        let user_id = UserId::default();
+        let code = TwoFACode::parse("123456".to_string()).unwrap();
+
+        store
+            .add_code(user_id.clone(), LoginAttemptId::default(), code)
+            .await
+            .unwrap();
+
+        let result = store.record_failed_attempt(&user_id, 5).await;
+        assert_eq!(result, Err(TwoFACodeStoreError::CodeExpired));
+        let result = store.get_code(&user_id).await;
+        assert_eq!(result, Err(TwoFACodeStoreError::LoginAttemptIdNotFound));
+    }
+}
@@ -1,50 +1,176 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 
-use crate::domain::{User, UserStoreError, Email, Password};
+use crate::domain::{User, UserId, UserStoreError, Email, Password, PasswordHash, PasswordHashError, RecoveryCodeHash, TotpSecret, TwoFAMethod};
 
 #[derive(Default)]
 pub struct HashmapUserStore {
-    users: HashMap<Email, User>,
+    users: HashMap<UserId, User>,
+    // Index of the users' current email addresses
+    emails: HashMap<Email, UserId>,
+    recovery_codes: HashMap<UserId, HashSet<RecoveryCodeHash>>,
+    // Time step of each user's last accepted authenticator app code
+    totp_last_steps: HashMap<UserId, u64>,
 }
 
 impl HashmapUserStore {
     pub fn add_user(&mut self, user: User) -> Result<(), UserStoreError> {
-        if self.users.contains_key(&user.email) {
+        if self.users.contains_key(&user.id) || self.emails.contains_key(&user.email) {
             Err(UserStoreError::UserAlreadyExists)
         } else {
-            self.users.insert(user.email.clone(), user);
+            self.emails.insert(user.email.clone(), user.id.clone());
+            self.users.insert(user.id.clone(), user);
             Ok(())
         }
     }
 
-    pub fn get_user(&self, email: &Email) -> Result<User, UserStoreError> {
+    pub fn get_user(&self, id: &UserId) -> Result<User, UserStoreError> {
         self.users
-            .get(email)
+            .get(id)
             .cloned()
             .ok_or(UserStoreError::UserNotFound)
     }
 
-    pub fn validate_user(&self, email: &Email, password: &Password) -> Result<(), UserStoreError> {
-        let user = self.get_user(email)?;
-        
-        if user.password() == password {
+    pub fn get_user_by_email(&self, email: &Email) -> Result<User, UserStoreError> {
+        let id = self.emails.get(email).ok_or(UserStoreError::UserNotFound)?;
+        self.get_user(id)
+    }
+
+    pub async fn validate_user(&self, id: &UserId, password: &Password) -> Result<(), UserStoreError> {
+        let user = self.get_user(id)?;
+
+        match user.password_hash().verify(password).await {
+            Ok(()) => Ok(()),
+            Err(PasswordHashError::PasswordMismatch) => Err(UserStoreError::InvalidCredentials),
+            Err(_) => Err(UserStoreError::UnexpectedError),
+        }
+    }
+
+    pub fn update_password_hash(&mut self, id: &UserId, password_hash: PasswordHash) -> Result<(), UserStoreError> {
+        let user = self.users.get_mut(id).ok_or(UserStoreError::UserNotFound)?;
+        user.set_password_hash(password_hash);
+        Ok(())
+    }
+
+    pub fn replace_password_hash(
+        &mut self,
+        id: &UserId,
+        current_password_hash: &PasswordHash,
+        new_password_hash: PasswordHash,
+    ) -> Result<(), UserStoreError> {
+        let user = self.users.get_mut(id).ok_or(UserStoreError::UserNotFound)?;
+        if user.password_hash() != current_password_hash {
+            return Err(UserStoreError::InvalidCredentials);
+        }
+        user.set_password_hash(new_password_hash);
+        Ok(())
+    }
+
+    pub fn set_totp_secret(&mut self, id: &UserId, totp_secret: TotpSecret) -> Result<(), UserStoreError> {
+        let user = self.users.get_mut(id).ok_or(UserStoreError::UserNotFound)?;
+        user.set_totp_secret(Some(totp_secret));
+        Ok(())
+    }
+
+    pub fn enable_totp(&mut self, id: &UserId) -> Result<(), UserStoreError> {
+        let user = self.users.get_mut(id).ok_or(UserStoreError::UserNotFound)?;
+        user.requires_2fa = true;
+        user.set_two_fa_method(TwoFAMethod::Totp);
+        Ok(())
+    }
+
+    pub fn accept_totp_step(&mut self, id: &UserId, step: u64) -> Result<(), UserStoreError> {
+        if !self.users.contains_key(id) {
+            return Err(UserStoreError::UserNotFound);
+        }
+
+        if self.totp_last_steps.get(id).is_some_and(|&last_step| last_step >= step) {
+            return Err(UserStoreError::InvalidCredentials);
+        }
+        self.totp_last_steps.insert(id.clone(), step);
+        Ok(())
+    }
+
+    pub fn set_recovery_codes(&mut self, id: &UserId, recovery_codes: Vec<RecoveryCodeHash>) -> Result<(), UserStoreError> {
+        if !self.users.contains_key(id) {
+            return Err(UserStoreError::UserNotFound);
+        }
+        self.recovery_codes.insert(id.clone(), recovery_codes.into_iter().collect());
+        Ok(())
+    }
+
+    pub fn consume_recovery_code(&mut self, id: &UserId, recovery_code: &RecoveryCodeHash) -> Result<(), UserStoreError> {
+        let recovery_codes = self.recovery_codes.get_mut(id).ok_or(UserStoreError::InvalidCredentials)?;
+        if recovery_codes.remove(recovery_code) {
             Ok(())
         } else {
             Err(UserStoreError::InvalidCredentials)
         }
     }
+
+    pub fn verify_email(&mut self, id: &UserId) -> Result<(), UserStoreError> {
+        let user = self.users.get_mut(id).ok_or(UserStoreError::UserNotFound)?;
+        user.set_email_verified(true);
+        Ok(())
+    }
+
+    pub fn change_email(&mut self, id: &UserId, new_email: Email) -> Result<(), UserStoreError> {
+        let user = self.users.get_mut(id).ok_or(UserStoreError::UserNotFound)?;
+        match self.emails.get(&new_email) {
+            Some(owner) if owner != id => return Err(UserStoreError::UserAlreadyExists),
+            _ => {}
+        }
+
+        self.emails.remove(&user.email);
+        self.emails.insert(new_email.clone(), id.clone());
+        user.email = new_email;
+        user.set_email_verified(true);
+        Ok(())
+    }
+
+    pub fn schedule_deletion(&mut self, id: &UserId, due_at: i64) -> Result<(), UserStoreError> {
+        let user = self.users.get_mut(id).ok_or(UserStoreError::UserNotFound)?;
+        user.set_deletion_due_at(Some(due_at));
+        Ok(())
+    }
+
+    pub fn cancel_deletion(&mut self, id: &UserId) -> Result<(), UserStoreError> {
+        let user = self.users.get_mut(id).ok_or(UserStoreError::UserNotFound)?;
+        user.set_deletion_due_at(None);
+        Ok(())
+    }
+
+    pub fn users_due_for_deletion(&self, now: i64) -> Vec<UserId> {
+        self.users
+            .values()
+            .filter(|user| user.deletion_due_at().is_some_and(|due_at| due_at <= now))
+            .map(|user| user.id.clone())
+            .collect()
+    }
+
+    pub fn delete_user(&mut self, id: &UserId) -> Result<(), UserStoreError> {
+        let user = self.users.remove(id).ok_or(UserStoreError::UserNotFound)?;
+        self.emails.remove(&user.email);
+        self.recovery_codes.remove(id);
+        self.totp_last_steps.remove(id);
+        Ok(())
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::domain::RecoveryCode;
+
+    async fn hash(password: &Password) -> PasswordHash {
+        PasswordHash::from_password(password.clone()).await.unwrap()
+    }
 
     #[tokio::test]
     async fn test_add_user() {
         let mut store = HashmapUserStore::default();
         let email = Email::parse("test@example.com".to_string()).unwrap();
         let password = Password::parse("Password123!".to_string()).unwrap();
-        let user = User::new(email, password, false);
+        let user = User::new(email.clone(), hash(&password).await, false);
         
         let result = store.add_user(user.clone());
         assert!(result.is_ok());
@@ -52,6 +178,10 @@ mod tests {
         // Test adding duplicate user
         let duplicate_result = store.add_user(user);
         assert_eq!(duplicate_result, Err(UserStoreError::UserAlreadyExists));
+
+        // Test adding another user with the same email
+        let other_user = User::new(email, hash(&password).await, false);
+        assert_eq!(store.add_user(other_user), Err(UserStoreError::UserAlreadyExists));
     }
 
     #[tokio::test]
@@ -59,18 +189,17 @@ mod tests {
         let mut store = HashmapUserStore::default();
         let email = Email::parse("test@example.com".to_string()).unwrap();
         let password = Password::parse("Password123!".to_string()).unwrap();
-        let user = User::new(email.clone(), password, false);
+        let user = User::new(email.clone(), hash(&password).await, false);
         
         // Test getting non-existent user
         let nonexistent_email = Email::parse("nonexistent@example.com".to_string()).unwrap();
-        let result = store.get_user(&nonexistent_email);
-        assert_eq!(result, Err(UserStoreError::UserNotFound));
+        assert_eq!(store.get_user(&UserId::default()), Err(UserStoreError::UserNotFound));
+        assert_eq!(store.get_user_by_email(&nonexistent_email), Err(UserStoreError::UserNotFound));
         
         // Add user and test getting existing user
         store.add_user(user.clone()).unwrap();
-        let result = store.get_user(&email);
-        assert!(result.is_ok());
-        assert_eq!(result.unwrap().email, email);
+        assert_eq!(store.get_user(&user.id), Ok(user.clone()));
+        assert_eq!(store.get_user_by_email(&email), Ok(user));
     }
 
     #[tokio::test]
@@ -78,21 +207,228 @@ mod tests {
         let mut store = HashmapUserStore::default();
         let email = Email::parse("test@example.com".to_string()).unwrap();
         let password = Password::parse("Password123!".to_string()).unwrap();
-        let user = User::new(email.clone(), password.clone(), false);
+        let user = User::new(email, hash(&password).await, false);
+        let id = user.id.clone();
         store.add_user(user).unwrap();
         
         // Test valid credentials
-        let result = store.validate_user(&email, &password);
+        let result = store.validate_user(&id, &password).await;
         assert!(result.is_ok());
         
         // Test invalid password
         let wrong_password = Password::parse("WrongPassword123!".to_string()).unwrap();
-        let result = store.validate_user(&email, &wrong_password);
+        let result = store.validate_user(&id, &wrong_password).await;
         assert_eq!(result, Err(UserStoreError::InvalidCredentials));
         
         // Test non-existent user
-        let nonexistent_email = Email::parse("nonexistent@example.com".to_string()).unwrap();
-        let result = store.validate_user(&nonexistent_email, &password);
+        let result = store.validate_user(&UserId::default(), &password).await;
+        assert_eq!(result, Err(UserStoreError::UserNotFound));
+    }
+
+    #[tokio::test]
+    async fn test_update_password_hash() {
+        let mut store = HashmapUserStore::default();
+        let email = Email::parse("test@example.com".to_string()).unwrap();
+        let password = Password::parse("Password123!".to_string()).unwrap();
+        let user = User::new(email, hash(&password).await, false);
+        let id = user.id.clone();
+        store.add_user(user).unwrap();
+
+        let new_password = Password::parse("NewPassword123!".to_string()).unwrap();
+        let new_hash = hash(&new_password).await;
+        let result = store.update_password_hash(&id, new_hash.clone());
+        assert!(result.is_ok());
+        assert_eq!(store.get_user(&id).unwrap().password_hash(), &new_hash);
+        assert!(store.validate_user(&id, &new_password).await.is_ok());
+
+        // Test non-existent user
+        let result = store.update_password_hash(&UserId::default(), new_hash);
+        assert_eq!(result, Err(UserStoreError::UserNotFound));
+    }
+
+    #[tokio::test]
+    async fn test_replace_password_hash() {
+        let mut store = HashmapUserStore::default();
+        let email = Email::parse("test@example.com".to_string()).unwrap();
+        let password = Password::parse("Password123!".to_string()).unwrap();
+        let old_hash = hash(&password).await;
+        let user = User::new(email, old_hash.clone(), false);
+        let id = user.id.clone();
+        store.add_user(user).unwrap();
+
+        let new_hash = hash(&Password::parse("NewPassword123!".to_string()).unwrap()).await;
+        assert!(store.replace_password_hash(&id, &old_hash, new_hash.clone()).is_ok());
+        assert_eq!(store.get_user(&id).unwrap().password_hash(), &new_hash);
+
+        // The hash was replaced in the meantime
+        let result = store.replace_password_hash(&id, &old_hash, old_hash.clone());
+        assert_eq!(result, Err(UserStoreError::InvalidCredentials));
+        assert_eq!(store.get_user(&id).unwrap().password_hash(), &new_hash);
+
+        let result = store.replace_password_hash(&UserId::default(), &new_hash, old_hash);
         assert_eq!(result, Err(UserStoreError::UserNotFound));
     }
-}
\ No newline at end of file
+
+    #[tokio::test]
+    async fn test_enable_totp() {
+        let mut store = HashmapUserStore::default();
+        let email = Email::parse("test@example.com".to_string()).unwrap();
+        let password = Password::parse("Password123!".to_string()).unwrap();
+        let user = User::new(email, hash(&password).await, false);
+        let id = user.id.clone();
+        store.add_user(user).unwrap();
+
+        // Storing the secret alone does not change how the user logs in
+        let secret = TotpSecret::generate();
+        store.set_totp_secret(&id, secret.clone()).unwrap();
+        let user = store.get_user(&id).unwrap();
+        assert_eq!(user.totp_secret(), Some(&secret));
+        assert_eq!(user.two_fa_method(), TwoFAMethod::Email);
+        assert!(!user.requires_2fa());
+
+        store.enable_totp(&id).unwrap();
+        let user = store.get_user(&id).unwrap();
+        assert_eq!(user.two_fa_method(), TwoFAMethod::Totp);
+        assert!(user.requires_2fa());
+
+        // Test non-existent user
+        let nonexistent_id = UserId::default();
+        assert_eq!(store.set_totp_secret(&nonexistent_id, secret), Err(UserStoreError::UserNotFound));
+        assert_eq!(store.enable_totp(&nonexistent_id), Err(UserStoreError::UserNotFound));
+    }
+
+    #[tokio::test]
+    async fn test_accept_totp_step() {
+        let mut store = HashmapUserStore::default();
+        let email = Email::parse("test@example.com".to_string()).unwrap();
+        let password = Password::parse("Password123!".to_string()).unwrap();
+        let user = User::new(email, hash(&password).await, false);
+        let id = user.id.clone();
+        store.add_user(user).unwrap();
+
+        assert!(store.accept_totp_step(&id, 100).is_ok());
+        // Codes of the same or an earlier step cannot be used anymore
+        assert_eq!(store.accept_totp_step(&id, 100), Err(UserStoreError::InvalidCredentials));
+        assert_eq!(store.accept_totp_step(&id, 99), Err(UserStoreError::InvalidCredentials));
+        assert!(store.accept_totp_step(&id, 101).is_ok());
+
+        assert_eq!(store.accept_totp_step(&UserId::default(), 102), Err(UserStoreError::UserNotFound));
+    }
+
+    #[tokio::test]
+    async fn test_recovery_codes() {
+        let mut store = HashmapUserStore::default();
+        let email = Email::parse("test@example.com".to_string()).unwrap();
+        let password = Password::parse("Password123!".to_string()).unwrap();
+        let user = User::new(email, hash(&password).await, true);
+        let id = user.id.clone();
+        store.add_user(user).unwrap();
+
+        let first_set: Vec<_> = RecoveryCode::generate_set().iter().map(RecoveryCode::hash).collect();
+        store.set_recovery_codes(&id, first_set.clone()).unwrap();
+
+        // Codes can only be used once
+        assert!(store.consume_recovery_code(&id, &first_set[0]).is_ok());
+        assert_eq!(store.consume_recovery_code(&id, &first_set[0]), Err(UserStoreError::InvalidCredentials));
+
+        // A new set replaces the old one
+        let second_set: Vec<_> = RecoveryCode::generate_set().iter().map(RecoveryCode::hash).collect();
+        store.set_recovery_codes(&id, second_set.clone()).unwrap();
+        assert_eq!(store.consume_recovery_code(&id, &first_set[1]), Err(UserStoreError::InvalidCredentials));
+        assert!(store.consume_recovery_code(&id, &second_set[1]).is_ok());
+
+        // Test non-existent user
+        assert_eq!(store.set_recovery_codes(&UserId::default(), second_set), Err(UserStoreError::UserNotFound));
+    }
+
+    #[tokio::test]
+    async fn test_verify_email() {
+        let mut store = HashmapUserStore::default();
+        let email = Email::parse("test@example.com".to_string()).unwrap();
+        let password = Password::parse("Password123!".to_string()).unwrap();
+        let user = User::new(email, hash(&password).await, false);
+        let id = user.id.clone();
+        store.add_user(user).unwrap();
+        assert!(!store.get_user(&id).unwrap().email_verified());
+
+        store.verify_email(&id).unwrap();
+        assert!(store.get_user(&id).unwrap().email_verified());
+
+        // Test non-existent user
+        assert_eq!(store.verify_email(&UserId::default()), Err(UserStoreError::UserNotFound));
+    }
+
+    #[tokio::test]
+    async fn test_change_email() {
+        let mut store = HashmapUserStore::default();
+        let email = Email::parse("test@example.com".to_string()).unwrap();
+        let new_email = Email::parse("new@example.com".to_string()).unwrap();
+        let taken_email = Email::parse("taken@example.com".to_string()).unwrap();
+        let password = Password::parse("Password123!".to_string()).unwrap();
+        let user = User::new(email.clone(), hash(&password).await, true);
+        let id = user.id.clone();
+        store.add_user(user).unwrap();
+        store.add_user(User::new(taken_email.clone(), hash(&password).await, false)).unwrap();
+        let recovery_codes: Vec<_> = RecoveryCode::generate_set().iter().map(RecoveryCode::hash).collect();
+        store.set_recovery_codes(&id, recovery_codes.clone()).unwrap();
+
+        assert_eq!(store.change_email(&id, taken_email), Err(UserStoreError::UserAlreadyExists));
+        assert_eq!(store.get_user(&id).unwrap().email, email);
+
+        store.change_email(&id, new_email.clone()).unwrap();
+        assert_eq!(store.get_user_by_email(&email), Err(UserStoreError::UserNotFound));
+        let user = store.get_user_by_email(&new_email).unwrap();
+        assert_eq!(user.id, id);
+        assert_eq!(user.email, new_email);
+        assert!(user.email_verified());
+        assert!(user.requires_2fa());
+
+        // Everything else stays with the user
+        assert!(store.validate_user(&id, &password).await.is_ok());
+        assert!(store.consume_recovery_code(&id, &recovery_codes[0]).is_ok());
+
+        // The old address is free again
+        let other_user = User::new(email, hash(&password).await, false);
+        assert!(store.add_user(other_user).is_ok());
+
+        // Test non-existent user
+        let other_email = Email::parse("other@example.com".to_string()).unwrap();
+        assert_eq!(store.change_email(&UserId::default(), other_email), Err(UserStoreError::UserNotFound));
+    }
+
+    #[tokio::test]
+    async fn test_deletion() {
+        let mut store = HashmapUserStore::default();
+        let email = Email::parse("test@example.com".to_string()).unwrap();
+        let password = Password::parse("Password123!".to_string()).unwrap();
+        let user = User::new(email.clone(), hash(&password).await, true);
+        let id = user.id.clone();
+        store.add_user(user).unwrap();
+        let recovery_codes: Vec<_> = RecoveryCode::generate_set().iter().map(RecoveryCode::hash).collect();
+        store.set_recovery_codes(&id, recovery_codes.clone()).unwrap();
+
+        // Only users whose deletion is due are listed
+        store.schedule_deletion(&id, 1000).unwrap();
+        assert_eq!(store.get_user(&id).unwrap().deletion_due_at(), Some(1000));
+        assert!(store.users_due_for_deletion(999).is_empty());
+        assert_eq!(store.users_due_for_deletion(1000), vec![id.clone()]);
+
+        store.cancel_deletion(&id).unwrap();
+        assert_eq!(store.get_user(&id).unwrap().deletion_due_at(), None);
+        assert!(store.users_due_for_deletion(1000).is_empty());
+
+        store.delete_user(&id).unwrap();
+        assert_eq!(store.get_user(&id), Err(UserStoreError::UserNotFound));
+        assert_eq!(store.get_user_by_email(&email), Err(UserStoreError::UserNotFound));
+        assert_eq!(store.delete_user(&id), Err(UserStoreError::UserNotFound));
+
+        // Nothing of the user is left behind under their ID or address
+        assert!(!store.recovery_codes.contains_key(&id));
+        let other_user = User::new(email, hash(&password).await, false);
+        assert!(store.add_user(other_user).is_ok());
+
+        // Test non-existent user
+        assert_eq!(store.schedule_deletion(&id, 1000), Err(UserStoreError::UserNotFound));
+        assert_eq!(store.cancel_deletion(&id), Err(UserStoreError::UserNotFound));
+    }
+}
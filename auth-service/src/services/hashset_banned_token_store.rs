@@ -1,20 +1,71 @@
-use std::collections::HashSet;
+use std::collections::HashMap;
+use chrono::Utc;
 use crate::domain::{BannedTokenStoreError};
 
+// Banned tokens keyed by `jti` for O(1) lookups, together with the token's
+// expiration so entries can be pruned once the token has expired anyway.
 #[derive(Default)]
 pub struct HashsetBannedTokenStore {
-    banned_tokens: HashSet<(String, usize)>,
+    banned_tokens: HashMap<String, usize>,
+    banned_subjects: HashMap<String, SubjectBan>,
+}
+
+struct SubjectBan {
+    issued_until: usize,
+    exempt_jti: Option<String>,
+    exp: usize,
 }
 
 impl HashsetBannedTokenStore {
-    pub async fn store_tokens(&mut self, token: String, exp: usize) -> Result<(), BannedTokenStoreError> {
-        self.banned_tokens.insert((token, exp));
+    pub async fn store_tokens(&mut self, jti: String, exp: usize) -> Result<(), BannedTokenStoreError> {
+        // Keep the latest expiration if the same token is banned twice
+        let banned_exp = self.banned_tokens.entry(jti).or_insert(exp);
+        *banned_exp = (*banned_exp).max(exp);
+        Ok(())
+    }
+
+    pub async fn is_token_exists(&self, jti: &str) -> Result<bool, BannedTokenStoreError> {
+        Ok(self.banned_tokens.contains_key(jti))
+    }
+
+    pub async fn ban_subject(
+        &mut self,
+        sub: String,
+        issued_until: usize,
+        exempt_jti: Option<String>,
+        exp: usize,
+    ) -> Result<(), BannedTokenStoreError> {
+        // A later ban covers everything an earlier one did, including the
+        // token the earlier one exempted
+        let ban = self.banned_subjects.entry(sub).or_insert(SubjectBan {
+            issued_until,
+            exempt_jti: exempt_jti.clone(),
+            exp,
+        });
+        if issued_until >= ban.issued_until {
+            ban.issued_until = issued_until;
+            ban.exempt_jti = exempt_jti;
+        }
+        ban.exp = ban.exp.max(exp);
         Ok(())
     }
 
-    pub async fn is_token_exists(&self, token: &str) -> Result<bool, BannedTokenStoreError> {
-        let exists = self.banned_tokens.iter().any(|(banned_token, _)| banned_token == token);
-        Ok(exists)
+    pub async fn is_subject_banned(&self, sub: &str, iat: usize, jti: &str) -> Result<bool, BannedTokenStoreError> {
+        Ok(self.banned_subjects.get(sub).is_some_and(|ban| {
+            iat <= ban.issued_until && ban.exempt_jti.as_deref() != Some(jti)
+        }))
+    }
+
+    pub async fn prune_expired(&mut self) -> Result<usize, BannedTokenStoreError> {
+        let now = Utc::now().timestamp();
+        let size_before = self.banned_tokens.len() + self.banned_subjects.len();
+        self.banned_tokens.retain(|_, exp| *exp as i64 > now);
+        self.banned_subjects.retain(|_, ban| ban.exp as i64 > now);
+        Ok(size_before - self.banned_tokens.len() - self.banned_subjects.len())
+    }
+
+    pub async fn size(&self) -> Result<usize, BannedTokenStoreError> {
+        Ok(self.banned_tokens.len())
     }
 }
 
@@ -32,7 +83,7 @@ mod tests {
         assert!(result.is_ok());
         
         // Verify token was stored
-        assert!(store.banned_tokens.contains(&(token, exp)));
+        assert_eq!(store.banned_tokens.get(&token), Some(&exp));
     }
 
     #[tokio::test]
@@ -44,19 +95,19 @@ mod tests {
         // Test non-existent token
         let result = store.is_token_exists(&token).await;
         assert!(result.is_ok());
-        assert_eq!(result.unwrap(), false);
+        assert!(!result.unwrap());
         
         // Add token and test existing token
         store.store_tokens(token.clone(), exp).await.unwrap();
         let result = store.is_token_exists(&token).await;
         assert!(result.is_ok());
-        assert_eq!(result.unwrap(), true);
+        assert!(result.unwrap());
         
         // Test different token
         let different_token = "different.jwt.token";
         let result = store.is_token_exists(different_token).await;
         assert!(result.is_ok());
-        assert_eq!(result.unwrap(), false);
+        assert!(!result.unwrap());
     }
 
     #[tokio::test]
@@ -78,7 +129,7 @@ mod tests {
         for (token, _) in &tokens {
             let result = store.is_token_exists(token).await;
             assert!(result.is_ok());
-            assert_eq!(result.unwrap(), true);
+            assert!(result.unwrap());
         }
         
         // Verify store contains correct number of tokens
@@ -95,9 +146,9 @@ mod tests {
         store.store_tokens(token.clone(), exp).await.unwrap();
         store.store_tokens(token.clone(), exp).await.unwrap();
         
-        // HashSet should only contain one copy
+        // Store should only contain one copy
         assert_eq!(store.banned_tokens.len(), 1);
-        assert!(store.banned_tokens.contains(&(token, exp)));
+        assert_eq!(store.banned_tokens.get(&token), Some(&exp));
     }
 
     #[tokio::test]
@@ -111,14 +162,64 @@ mod tests {
         store.store_tokens(token.clone(), exp1).await.unwrap();
         store.store_tokens(token.clone(), exp2).await.unwrap();
         
-        // Both entries should exist (different tuples)
-        assert_eq!(store.banned_tokens.len(), 2);
-        assert!(store.banned_tokens.contains(&(token.clone(), exp1)));
-        assert!(store.banned_tokens.contains(&(token.clone(), exp2)));
+        // A single entry with the latest expiration is kept
+        assert_eq!(store.banned_tokens.len(), 1);
+        assert_eq!(store.banned_tokens.get(&token), Some(&exp2));
+        
+        // Storing an earlier expiration afterwards does not shorten the ban
+        store.store_tokens(token.clone(), exp1).await.unwrap();
+        assert_eq!(store.banned_tokens.get(&token), Some(&exp2));
         
-        // is_token_exists should return true (finds either)
         let result = store.is_token_exists(&token).await;
         assert!(result.is_ok());
-        assert_eq!(result.unwrap(), true);
+        assert!(result.unwrap());
     }
-}
\ No newline at end of file
+
+    #[tokio::test]
+    async fn test_prune_expired() {
+        let mut store = HashsetBannedTokenStore::default();
+        let now = Utc::now().timestamp() as usize;
+
+        store.store_tokens("expired.jwt.token".to_string(), now - 1).await.unwrap();
+        store.store_tokens("valid.jwt.token".to_string(), now + 600).await.unwrap();
+        assert_eq!(store.size().await, Ok(2));
+
+        let result = store.prune_expired().await;
+        assert_eq!(result, Ok(1));
+        assert_eq!(store.size().await, Ok(1));
+        assert!(!store.is_token_exists("expired.jwt.token").await.unwrap());
+        assert!(store.is_token_exists("valid.jwt.token").await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_ban_subject() {
+        let mut store = HashsetBannedTokenStore::default();
+        let now = Utc::now().timestamp() as usize;
+
+        store
+            .ban_subject("test@example.com".to_string(), now, Some("exempt".to_string()), now + 600)
+            .await
+            .unwrap();
+
+        // Only tokens issued up to the second of the ban are affected, except
+        // the exempt one
+        assert_eq!(store.is_subject_banned("test@example.com", now - 1, "jti").await, Ok(true));
+        assert_eq!(store.is_subject_banned("test@example.com", now, "jti").await, Ok(true));
+        assert_eq!(store.is_subject_banned("test@example.com", now, "exempt").await, Ok(false));
+        assert_eq!(store.is_subject_banned("test@example.com", now + 1, "jti").await, Ok(false));
+        assert_eq!(store.is_subject_banned("other@example.com", now - 1, "jti").await, Ok(false));
+
+        // An earlier ban does not undo a later one
+        store.ban_subject("test@example.com".to_string(), now - 60, None, now + 540).await.unwrap();
+        assert_eq!(store.is_subject_banned("test@example.com", now, "jti").await, Ok(true));
+        assert_eq!(store.is_subject_banned("test@example.com", now, "exempt").await, Ok(false));
+
+        // A new ban in the same second replaces the exemption
+        store.ban_subject("test@example.com".to_string(), now, None, now + 600).await.unwrap();
+        assert_eq!(store.is_subject_banned("test@example.com", now, "exempt").await, Ok(true));
+
+        store.ban_subject("expired@example.com".to_string(), now - 600, None, now - 1).await.unwrap();
+        assert_eq!(store.prune_expired().await, Ok(1));
+        assert_eq!(store.is_subject_banned("expired@example.com", now - 601, "jti").await, Ok(false));
+    }
+}
@@ -1,9 +1,43 @@
 pub mod hashmap_user_store;
 pub mod hashset_banned_token_store;
 pub mod hashmap_two_fa_code_store;
+pub mod hashmap_refresh_token_store;
+pub mod hashmap_failed_login_store;
 pub mod mock_email_client;
+pub mod postgres_user_store;
+pub mod redis_banned_token_store;
+pub mod redis_two_fa_code_store;
+pub mod redis_refresh_token_store;
+pub mod redis_failed_login_store;
+#[cfg(feature = "sqlite")]
+pub mod sqlite_user_store;
+#[cfg(feature = "sqlite")]
+pub mod sqlite_banned_token_store;
+#[cfg(feature = "sqlite")]
+pub mod sqlite_two_fa_code_store;
+#[cfg(feature = "sqlite")]
+pub mod sqlite_refresh_token_store;
+#[cfg(feature = "sqlite")]
+pub mod sqlite_failed_login_store;
 
 pub use hashmap_user_store::HashmapUserStore;
 pub use hashset_banned_token_store::HashsetBannedTokenStore;
 pub use hashmap_two_fa_code_store::HashmapTwoFACodeStore;
+pub use hashmap_refresh_token_store::HashmapRefreshTokenStore;
+pub use hashmap_failed_login_store::HashmapFailedLoginStore;
 pub use mock_email_client::MockEmailClient;
+pub use postgres_user_store::PostgresUserStore;
+pub use redis_banned_token_store::RedisBannedTokenStore;
+pub use redis_two_fa_code_store::RedisTwoFACodeStore;
+pub use redis_refresh_token_store::RedisRefreshTokenStore;
+pub use redis_failed_login_store::RedisFailedLoginStore;
+#[cfg(feature = "sqlite")]
+pub use sqlite_user_store::SqliteUserStore;
+#[cfg(feature = "sqlite")]
+pub use sqlite_banned_token_store::SqliteBannedTokenStore;
+#[cfg(feature = "sqlite")]
+pub use sqlite_two_fa_code_store::SqliteTwoFACodeStore;
+#[cfg(feature = "sqlite")]
+pub use sqlite_refresh_token_store::SqliteRefreshTokenStore;
+#[cfg(feature = "sqlite")]
+pub use sqlite_failed_login_store::SqliteFailedLoginStore;
@@ -0,0 +1,325 @@
+use sqlx::{PgPool, Row};
+
+use crate::domain::{
+    Email, Password, PasswordHash, PasswordHashError, RecoveryCodeHash, TotpSecret, TwoFAMethod,
+    User, UserId, UserStore, UserStoreError,
+};
+
+pub struct PostgresUserStore {
+    pool: PgPool,
+}
+
+impl PostgresUserStore {
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+
+    // Load the user whose `key` column, `id` or `email`, is `value`
+    async fn find_user(&self, key: &str, value: &str) -> Result<User, UserStoreError> {
+        let query = format!(
+            "SELECT id, email, password_hash, requires_2fa, two_fa_method, totp_secret, email_verified, \
+             deletion_due_at FROM users WHERE {} = $1",
+            key
+        );
+        let row = sqlx::query(&query)
+            .bind(value)
+            .fetch_optional(&self.pool)
+            .await
+            .map_err(|_| UserStoreError::UnexpectedError)?
+            .ok_or(UserStoreError::UserNotFound)?;
+
+        let id = UserId::parse(row.get("id")).map_err(|_| UserStoreError::UnexpectedError)?;
+        let email = Email::parse(row.get("email")).map_err(|_| UserStoreError::UnexpectedError)?;
+        let password_hash = PasswordHash::parse(row.get("password_hash"))
+            .map_err(|_| UserStoreError::UnexpectedError)?;
+
+        let two_fa_method = TwoFAMethod::parse(row.get("two_fa_method"))
+            .map_err(|_| UserStoreError::UnexpectedError)?;
+        let totp_secret = row
+            .get::<Option<String>, _>("totp_secret")
+            .map(TotpSecret::parse)
+            .transpose()
+            .map_err(|_| UserStoreError::UnexpectedError)?;
+
+        let mut user = User::new(email, password_hash, row.get("requires_2fa"));
+        user.id = id;
+        user.set_two_fa_method(two_fa_method);
+        user.set_totp_secret(totp_secret);
+        user.set_email_verified(row.get("email_verified"));
+        user.set_deletion_due_at(row.get("deletion_due_at"));
+        Ok(user)
+    }
+}
+
+#[async_trait::async_trait]
+impl UserStore for PostgresUserStore {
+    async fn add_user(&mut self, user: User) -> Result<(), UserStoreError> {
+        sqlx::query(
+            "INSERT INTO users (id, email, password_hash, requires_2fa, two_fa_method, totp_secret, email_verified, \
+             deletion_due_at) VALUES ($1, $2, $3, $4, $5, $6, $7, $8)",
+        )
+        .bind(user.id.as_ref())
+        .bind(user.email.as_ref())
+        .bind(user.password_hash().as_ref())
+        .bind(user.requires_2fa())
+        .bind(user.two_fa_method().as_ref())
+        .bind(user.totp_secret().map(|secret| secret.as_ref()))
+        .bind(user.email_verified())
+        .bind(user.deletion_due_at())
+        .execute(&self.pool)
+        .await
+        .map_err(|e| match e {
+            sqlx::Error::Database(e) if e.is_unique_violation() => {
+                UserStoreError::UserAlreadyExists
+            }
+            _ => UserStoreError::UnexpectedError,
+        })?;
+
+        Ok(())
+    }
+
+    async fn get_user(&self, id: &UserId) -> Result<User, UserStoreError> {
+        self.find_user("id", id.as_ref()).await
+    }
+
+    async fn get_user_by_email(&self, email: &Email) -> Result<User, UserStoreError> {
+        self.find_user("email", email.as_ref()).await
+    }
+
+    async fn validate_user(&self, id: &UserId, password: &Password) -> Result<(), UserStoreError> {
+        let user = self.get_user(id).await?;
+
+        match user.password_hash().verify(password).await {
+            Ok(()) => Ok(()),
+            Err(PasswordHashError::PasswordMismatch) => Err(UserStoreError::InvalidCredentials),
+            Err(_) => Err(UserStoreError::UnexpectedError),
+        }
+    }
+
+    async fn update_password_hash(&mut self, id: &UserId, password_hash: PasswordHash) -> Result<(), UserStoreError> {
+        let result = sqlx::query("UPDATE users SET password_hash = $1 WHERE id = $2")
+            .bind(password_hash.as_ref())
+            .bind(id.as_ref())
+            .execute(&self.pool)
+            .await
+            .map_err(|_| UserStoreError::UnexpectedError)?;
+
+        if result.rows_affected() == 0 {
+            return Err(UserStoreError::UserNotFound);
+        }
+
+        Ok(())
+    }
+
+    async fn replace_password_hash(
+        &mut self,
+        id: &UserId,
+        current_password_hash: &PasswordHash,
+        new_password_hash: PasswordHash,
+    ) -> Result<(), UserStoreError> {
+        let result = sqlx::query("UPDATE users SET password_hash = $1 WHERE id = $2 AND password_hash = $3")
+            .bind(new_password_hash.as_ref())
+            .bind(id.as_ref())
+            .bind(current_password_hash.as_ref())
+            .execute(&self.pool)
+            .await
+            .map_err(|_| UserStoreError::UnexpectedError)?;
+
+        if result.rows_affected() == 0 {
+            // Tell a missing user apart from a hash that was replaced
+            self.get_user(id).await?;
+            return Err(UserStoreError::InvalidCredentials);
+        }
+
+        Ok(())
+    }
+
+    async fn set_totp_secret(&mut self, id: &UserId, totp_secret: TotpSecret) -> Result<(), UserStoreError> {
+        let result = sqlx::query("UPDATE users SET totp_secret = $1 WHERE id = $2")
+            .bind(totp_secret.as_ref())
+            .bind(id.as_ref())
+            .execute(&self.pool)
+            .await
+            .map_err(|_| UserStoreError::UnexpectedError)?;
+
+        if result.rows_affected() == 0 {
+            return Err(UserStoreError::UserNotFound);
+        }
+
+        Ok(())
+    }
+
+    async fn enable_totp(&mut self, id: &UserId) -> Result<(), UserStoreError> {
+        let result = sqlx::query("UPDATE users SET requires_2fa = TRUE, two_fa_method = $1 WHERE id = $2")
+            .bind(TwoFAMethod::Totp.as_ref())
+            .bind(id.as_ref())
+            .execute(&self.pool)
+            .await
+            .map_err(|_| UserStoreError::UnexpectedError)?;
+
+        if result.rows_affected() == 0 {
+            return Err(UserStoreError::UserNotFound);
+        }
+
+        Ok(())
+    }
+
+    async fn accept_totp_step(&mut self, id: &UserId, step: u64) -> Result<(), UserStoreError> {
+        let step = i64::try_from(step).map_err(|_| UserStoreError::UnexpectedError)?;
+
+        // Comparing and updating in one statement makes sure two concurrent
+        // requests cannot both use the same code
+        let result = sqlx::query(
+            "UPDATE users SET totp_last_step = $1 \
+             WHERE id = $2 AND (totp_last_step IS NULL OR totp_last_step < $1)",
+        )
+        .bind(step)
+        .bind(id.as_ref())
+        .execute(&self.pool)
+        .await
+        .map_err(|_| UserStoreError::UnexpectedError)?;
+
+        if result.rows_affected() == 0 {
+            // Tell a missing user apart from a code that was used before
+            self.get_user(id).await?;
+            return Err(UserStoreError::InvalidCredentials);
+        }
+
+        Ok(())
+    }
+
+    async fn set_recovery_codes(&mut self, id: &UserId, recovery_codes: Vec<RecoveryCodeHash>) -> Result<(), UserStoreError> {
+        let mut transaction = self.pool.begin().await.map_err(|_| UserStoreError::UnexpectedError)?;
+
+        sqlx::query("DELETE FROM recovery_codes WHERE user_id = $1")
+            .bind(id.as_ref())
+            .execute(&mut *transaction)
+            .await
+            .map_err(|_| UserStoreError::UnexpectedError)?;
+
+        sqlx::query(
+            "INSERT INTO recovery_codes (user_id, code_hash) SELECT $1, UNNEST($2::TEXT[])",
+        )
+        .bind(id.as_ref())
+        .bind(recovery_codes.iter().map(|code| code.as_ref()).collect::<Vec<_>>())
+        .execute(&mut *transaction)
+        .await
+        .map_err(map_recovery_code_error)?;
+
+        transaction.commit().await.map_err(|_| UserStoreError::UnexpectedError)
+    }
+
+    async fn consume_recovery_code(&mut self, id: &UserId, recovery_code: &RecoveryCodeHash) -> Result<(), UserStoreError> {
+        // Deleting the row both checks and consumes the code, so concurrent
+        // requests cannot use it twice
+        let result = sqlx::query("DELETE FROM recovery_codes WHERE user_id = $1 AND code_hash = $2")
+            .bind(id.as_ref())
+            .bind(recovery_code.as_ref())
+            .execute(&self.pool)
+            .await
+            .map_err(|_| UserStoreError::UnexpectedError)?;
+
+        if result.rows_affected() == 0 {
+            return Err(UserStoreError::InvalidCredentials);
+        }
+
+        Ok(())
+    }
+
+    async fn verify_email(&mut self, id: &UserId) -> Result<(), UserStoreError> {
+        let result = sqlx::query("UPDATE users SET email_verified = TRUE WHERE id = $1")
+            .bind(id.as_ref())
+            .execute(&self.pool)
+            .await
+            .map_err(|_| UserStoreError::UnexpectedError)?;
+
+        if result.rows_affected() == 0 {
+            return Err(UserStoreError::UserNotFound);
+        }
+
+        Ok(())
+    }
+
+    async fn change_email(&mut self, id: &UserId, new_email: Email) -> Result<(), UserStoreError> {
+        let result = sqlx::query("UPDATE users SET email = $1, email_verified = TRUE WHERE id = $2")
+            .bind(new_email.as_ref())
+            .bind(id.as_ref())
+            .execute(&self.pool)
+            .await
+            .map_err(|e| match e {
+                sqlx::Error::Database(e) if e.is_unique_violation() => UserStoreError::UserAlreadyExists,
+                _ => UserStoreError::UnexpectedError,
+            })?;
+
+        if result.rows_affected() == 0 {
+            return Err(UserStoreError::UserNotFound);
+        }
+
+        Ok(())
+    }
+
+    async fn schedule_deletion(&mut self, id: &UserId, due_at: i64) -> Result<(), UserStoreError> {
+        let result = sqlx::query("UPDATE users SET deletion_due_at = $1 WHERE id = $2")
+            .bind(due_at)
+            .bind(id.as_ref())
+            .execute(&self.pool)
+            .await
+            .map_err(|_| UserStoreError::UnexpectedError)?;
+
+        if result.rows_affected() == 0 {
+            return Err(UserStoreError::UserNotFound);
+        }
+
+        Ok(())
+    }
+
+    async fn cancel_deletion(&mut self, id: &UserId) -> Result<(), UserStoreError> {
+        let result = sqlx::query("UPDATE users SET deletion_due_at = NULL WHERE id = $1")
+            .bind(id.as_ref())
+            .execute(&self.pool)
+            .await
+            .map_err(|_| UserStoreError::UnexpectedError)?;
+
+        if result.rows_affected() == 0 {
+            return Err(UserStoreError::UserNotFound);
+        }
+
+        Ok(())
+    }
+
+    async fn users_due_for_deletion(&self, now: i64) -> Result<Vec<UserId>, UserStoreError> {
+        let rows = sqlx::query("SELECT id FROM users WHERE deletion_due_at <= $1")
+            .bind(now)
+            .fetch_all(&self.pool)
+            .await
+            .map_err(|_| UserStoreError::UnexpectedError)?;
+
+        rows.into_iter()
+            .map(|row| UserId::parse(row.get("id")).map_err(|_| UserStoreError::UnexpectedError))
+            .collect()
+    }
+
+    async fn delete_user(&mut self, id: &UserId) -> Result<(), UserStoreError> {
+        // Recovery codes go with the user through the foreign key
+        let result = sqlx::query("DELETE FROM users WHERE id = $1")
+            .bind(id.as_ref())
+            .execute(&self.pool)
+            .await
+            .map_err(|_| UserStoreError::UnexpectedError)?;
+
+        if result.rows_affected() == 0 {
+            return Err(UserStoreError::UserNotFound);
+        }
+
+        Ok(())
+    }
+}
+
+// Recovery codes reference their user, so inserting them for an unknown user
+// violates the foreign key
+fn map_recovery_code_error(e: sqlx::Error) -> UserStoreError {
+    match e {
+        sqlx::Error::Database(e) if e.is_foreign_key_violation() => UserStoreError::UserNotFound,
+        _ => UserStoreError::UnexpectedError,
+    }
+}
@@ -0,0 +1,128 @@
+use chrono::Utc;
+use redis::{aio::ConnectionManager, AsyncCommands};
+
+use crate::domain::{BannedTokenStore, BannedTokenStoreError};
+
+// Banned tokens are shared by all auth-service replicas through Redis. Each
+// entry expires together with the token itself, after which the JWT `exp`
+// check rejects the token anyway. A sorted set of the banned tokens by expiry
+// keeps the store's size countable without scanning the keyspace.
+pub struct RedisBannedTokenStore {
+    conn: ConnectionManager,
+}
+
+impl RedisBannedTokenStore {
+    pub fn new(conn: ConnectionManager) -> Self {
+        Self { conn }
+    }
+}
+
+#[async_trait::async_trait]
+impl BannedTokenStore for RedisBannedTokenStore {
+    async fn store_tokens(&mut self, jti: String, exp: usize) -> Result<(), BannedTokenStoreError> {
+        let exp = i64::try_from(exp).map_err(|_| BannedTokenStoreError::UnexpectedError)?;
+        let ttl = exp - Utc::now().timestamp();
+
+        // Already expired tokens never pass validation, nothing to store
+        if ttl <= 0 {
+            return Ok(());
+        }
+
+        redis::pipe()
+            .atomic()
+            .set_ex(get_key(&jti), true, ttl as u64)
+            .ignore()
+            .zadd(BANNED_TOKEN_INDEX_KEY, &jti, exp)
+            .ignore()
+            .query_async::<()>(&mut self.conn)
+            .await
+            .map_err(|_| BannedTokenStoreError::UnexpectedError)
+    }
+
+    async fn is_token_exists(&self, jti: &str) -> Result<bool, BannedTokenStoreError> {
+        // ConnectionManager is a cheap handle to a shared multiplexed connection
+        self.conn
+            .clone()
+            .exists(get_key(jti))
+            .await
+            .map_err(|_| BannedTokenStoreError::UnexpectedError)
+    }
+
+    async fn ban_subject(
+        &mut self,
+        sub: String,
+        issued_until: usize,
+        exempt_jti: Option<String>,
+        exp: usize,
+    ) -> Result<(), BannedTokenStoreError> {
+        let exp = i64::try_from(exp).map_err(|_| BannedTokenStoreError::UnexpectedError)?;
+        let ttl = exp - Utc::now().timestamp();
+
+        if ttl <= 0 {
+            return Ok(());
+        }
+
+        // Bans are made with the current time, so a new one always covers
+        // everything an earlier one did and can replace it. The value is the
+        // last second covered, followed by the exempt token if there is one.
+        let value = match exempt_jti {
+            Some(jti) => format!("{} {}", issued_until, jti),
+            None => issued_until.to_string(),
+        };
+        self.conn
+            .set_ex::<_, _, ()>(get_subject_key(&sub), value, ttl as u64)
+            .await
+            .map_err(|_| BannedTokenStoreError::UnexpectedError)
+    }
+
+    async fn is_subject_banned(&self, sub: &str, iat: usize, jti: &str) -> Result<bool, BannedTokenStoreError> {
+        let value: Option<String> = self
+            .conn
+            .clone()
+            .get(get_subject_key(sub))
+            .await
+            .map_err(|_| BannedTokenStoreError::UnexpectedError)?;
+
+        let Some(value) = value else {
+            return Ok(false);
+        };
+        let (issued_until, exempt_jti) = match value.split_once(' ') {
+            Some((issued_until, exempt_jti)) => (issued_until, Some(exempt_jti)),
+            None => (value.as_str(), None),
+        };
+        let issued_until: usize = issued_until.parse().map_err(|_| BannedTokenStoreError::UnexpectedError)?;
+
+        Ok(iat <= issued_until && exempt_jti != Some(jti))
+    }
+
+    // Redis expires the token keys itself, only the index needs pruning
+    async fn prune_expired(&mut self) -> Result<usize, BannedTokenStoreError> {
+        self.conn
+            .zrembyscore(BANNED_TOKEN_INDEX_KEY, "-inf", Utc::now().timestamp())
+            .await
+            .map_err(|_| BannedTokenStoreError::UnexpectedError)
+    }
+
+    // Counts tokens expired since the last prune too
+    async fn size(&self) -> Result<usize, BannedTokenStoreError> {
+        self.conn
+            .clone()
+            .zcard(BANNED_TOKEN_INDEX_KEY)
+            .await
+            .map_err(|_| BannedTokenStoreError::UnexpectedError)
+    }
+}
+
+// We are using a key prefix to prevent collisions and organize data!
+const BANNED_TOKEN_KEY_PREFIX: &str = "banned_token:";
+const BANNED_SUBJECT_KEY_PREFIX: &str = "banned_subject:";
+// Sorted set of banned token IDs, scored by their expiry
+const BANNED_TOKEN_INDEX_KEY: &str = "banned_tokens";
+
+fn get_key(jti: &str) -> String {
+    format!("{}{}", BANNED_TOKEN_KEY_PREFIX, jti)
+}
+
+fn get_subject_key(sub: &str) -> String {
+    format!("{}{}", BANNED_SUBJECT_KEY_PREFIX, sub)
+}
@@ -0,0 +1,101 @@
+use std::collections::HashMap;
+
+use chrono::Utc;
+use redis::{aio::ConnectionManager, AsyncCommands};
+
+use crate::domain::data_stores::{FailedLoginKey, FailedLoginStore, FailedLoginStoreError, FailedLogins};
+
+// Failed logins are shared by all auth-service replicas through Redis, so that
+// spreading guesses over replicas does not help. Each key is a hash of the
+// count and the time of the latest failure, which expires once no failure was
+// added for the TTL.
+pub struct RedisFailedLoginStore {
+    conn: ConnectionManager,
+    ttl_seconds: u64,
+}
+
+impl RedisFailedLoginStore {
+    pub fn new(conn: ConnectionManager, ttl_seconds: u64) -> Self {
+        Self { conn, ttl_seconds }
+    }
+}
+
+#[async_trait::async_trait]
+impl FailedLoginStore for RedisFailedLoginStore {
+    async fn get_failures(&self, key: &FailedLoginKey) -> Result<Option<FailedLogins>, FailedLoginStoreError> {
+        let failures: HashMap<String, i64> = self
+            .conn
+            .clone()
+            .hgetall(get_key(key))
+            .await
+            .map_err(|_| FailedLoginStoreError::UnexpectedError)?;
+        parse_failures(&failures)
+    }
+
+    async fn record_attempt(&mut self, key: &FailedLoginKey) -> Result<Option<FailedLogins>, FailedLoginStoreError> {
+        // Reading the previous failures in the same transaction as counting
+        // this one lets concurrent attempts of several replicas see each other
+        let (previous,): (HashMap<String, i64>,) = redis::pipe()
+            .atomic()
+            .hgetall(get_key(key))
+            .hincr(get_key(key), COUNT_FIELD, 1)
+            .ignore()
+            .hset(get_key(key), LAST_FAILED_AT_FIELD, Utc::now().timestamp())
+            .ignore()
+            .expire(get_key(key), self.ttl_seconds.try_into().unwrap_or(i64::MAX))
+            .ignore()
+            .query_async(&mut self.conn)
+            .await
+            .map_err(|_| FailedLoginStoreError::UnexpectedError)?;
+
+        parse_failures(&previous)
+    }
+
+    async fn forgive_attempt(&mut self, key: &FailedLoginKey) -> Result<(), FailedLoginStoreError> {
+        // HINCRBY on its own would recreate an expired key without a TTL
+        redis::cmd("EVAL")
+            .arg(FORGIVE_ATTEMPT_SCRIPT)
+            .arg(1)
+            .arg(get_key(key))
+            .arg(COUNT_FIELD)
+            .query_async::<()>(&mut self.conn)
+            .await
+            .map_err(|_| FailedLoginStoreError::UnexpectedError)
+    }
+
+    async fn clear_failures(&mut self, key: &FailedLoginKey) -> Result<(), FailedLoginStoreError> {
+        self.conn
+            .del::<_, ()>(get_key(key))
+            .await
+            .map_err(|_| FailedLoginStoreError::UnexpectedError)
+    }
+
+    // Redis expires the keys itself
+    async fn prune_expired(&mut self) -> Result<usize, FailedLoginStoreError> {
+        Ok(0)
+    }
+}
+
+const FAILED_LOGIN_PREFIX: &str = "failed_login:";
+const COUNT_FIELD: &str = "count";
+const LAST_FAILED_AT_FIELD: &str = "last_failed_at";
+
+// Decrement the count of an existing key, never below zero
+const FORGIVE_ATTEMPT_SCRIPT: &str = "\
+local count = tonumber(redis.call('HGET', KEYS[1], ARGV[1])) \
+if count and count > 0 then redis.call('HINCRBY', KEYS[1], ARGV[1], -1) end";
+
+// The failures stored in a key's hash, which is empty if the key does not exist
+fn parse_failures(fields: &HashMap<String, i64>) -> Result<Option<FailedLogins>, FailedLoginStoreError> {
+    match (fields.get(COUNT_FIELD), fields.get(LAST_FAILED_AT_FIELD)) {
+        (Some(&count), Some(&last_failed_at)) => Ok(Some(FailedLogins {
+            count: count.try_into().map_err(|_| FailedLoginStoreError::UnexpectedError)?,
+            last_failed_at,
+        })),
+        _ => Ok(None),
+    }
+}
+
+fn get_key(key: &FailedLoginKey) -> String {
+    format!("{}{}", FAILED_LOGIN_PREFIX, key.as_key())
+}
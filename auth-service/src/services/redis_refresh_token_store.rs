@@ -0,0 +1,209 @@
+use chrono::Utc;
+use redis::{aio::ConnectionManager, AsyncCommands};
+use serde::{Deserialize, Serialize};
+
+use crate::domain::{
+    data_stores::{RefreshTokenStore, RefreshTokenStoreError},
+    RefreshSession, UserId, RefreshTokenFamilyId, RefreshTokenHash,
+};
+
+// Refresh tokens are shared by all auth-service replicas through Redis and
+// expire on their own. Using a token sets a separate marker with `SET NX`, so
+// only one of two concurrent requests presenting the same token succeeds.
+// Each family keeps a set of its token hashes to revoke them together, and
+// each user a set of their families. A revoked family leaves a marker that
+// expires with the family's tokens, and which keeps new tokens out of it.
+pub struct RedisRefreshTokenStore {
+    conn: ConnectionManager,
+}
+
+impl RedisRefreshTokenStore {
+    pub fn new(conn: ConnectionManager) -> Self {
+        Self { conn }
+    }
+}
+
+#[async_trait::async_trait]
+impl RefreshTokenStore for RedisRefreshTokenStore {
+    async fn add_token(
+        &mut self,
+        token: RefreshTokenHash,
+        session: RefreshSession,
+    ) -> Result<(), RefreshTokenStoreError> {
+        let ttl = session.expires_at - Utc::now().timestamp();
+
+        // Already expired tokens are never accepted, nothing to store
+        if ttl <= 0 {
+            return Ok(());
+        }
+
+        let stored = StoredSession {
+            user_id: session.user_id.as_ref().to_owned(),
+            family_id: session.family_id.as_ref().to_owned(),
+            expires_at: session.expires_at,
+        };
+        let value = serde_json::to_string(&stored).map_err(|_| RefreshTokenStoreError::UnexpectedError)?;
+
+        // Rotated tokens get the same lifetime, so the family set lives as
+        // long as its newest token. The script checks for a revocation and
+        // stores the token in one step.
+        let added: bool = redis::cmd("EVAL")
+            .arg(ADD_TOKEN_SCRIPT)
+            .arg(4)
+            .arg(get_revoked_family_key(&session.family_id))
+            .arg(get_key(&token))
+            .arg(get_family_key(&session.family_id))
+            .arg(get_user_key(&session.user_id))
+            .arg(value)
+            .arg(ttl)
+            .arg(token.as_ref())
+            .arg(session.family_id.as_ref())
+            .query_async(&mut self.conn)
+            .await
+            .map_err(|_| RefreshTokenStoreError::UnexpectedError)?;
+
+        if !added {
+            return Err(RefreshTokenStoreError::FamilyRevoked);
+        }
+
+        Ok(())
+    }
+
+    async fn use_token(
+        &mut self,
+        token: &RefreshTokenHash,
+    ) -> Result<RefreshSession, RefreshTokenStoreError> {
+        let value: Option<String> = self
+            .conn
+            .get(get_key(token))
+            .await
+            .map_err(|_| RefreshTokenStoreError::UnexpectedError)?;
+
+        let value = value.ok_or(RefreshTokenStoreError::TokenNotFound)?;
+        // Sessions stored before users had IDs are keyed by email, and can no
+        // longer be refreshed
+        let stored: StoredSession =
+            serde_json::from_str(&value).map_err(|_| RefreshTokenStoreError::TokenNotFound)?;
+
+        let session = RefreshSession {
+            user_id: UserId::parse(stored.user_id).map_err(|_| RefreshTokenStoreError::UnexpectedError)?,
+            family_id: RefreshTokenFamilyId::parse(stored.family_id)
+                .map_err(|_| RefreshTokenStoreError::UnexpectedError)?,
+            expires_at: stored.expires_at,
+        };
+
+        let ttl = (session.expires_at - Utc::now().timestamp()).max(1) as u64;
+        let options = redis::SetOptions::default()
+            .conditional_set(redis::ExistenceCheck::NX)
+            .with_expiration(redis::SetExpiry::EX(ttl));
+        let first_use: Option<String> = self
+            .conn
+            .set_options(get_used_key(token), true, options)
+            .await
+            .map_err(|_| RefreshTokenStoreError::UnexpectedError)?;
+
+        match first_use {
+            Some(_) => Ok(session),
+            None => Err(RefreshTokenStoreError::TokenReused(session.family_id)),
+        }
+    }
+
+    async fn revoke_family(
+        &mut self,
+        family_id: &RefreshTokenFamilyId,
+    ) -> Result<(), RefreshTokenStoreError> {
+        // The marker is set before the tokens are read, so that a token added
+        // in between is either refused or deleted with the others
+        let ttl: i64 = self
+            .conn
+            .pttl(get_family_key(family_id))
+            .await
+            .map_err(|_| RefreshTokenStoreError::UnexpectedError)?;
+
+        if ttl > 0 {
+            self.conn
+                .pset_ex::<_, _, ()>(get_revoked_family_key(family_id), true, ttl as u64)
+                .await
+                .map_err(|_| RefreshTokenStoreError::UnexpectedError)?;
+        }
+
+        let tokens: Vec<String> = self
+            .conn
+            .smembers(get_family_key(family_id))
+            .await
+            .map_err(|_| RefreshTokenStoreError::UnexpectedError)?;
+
+        let mut keys = vec![get_family_key(family_id)];
+        for token in tokens {
+            keys.push(format!("{}{}", REFRESH_TOKEN_PREFIX, token));
+            keys.push(format!("{}{}", REFRESH_TOKEN_USED_PREFIX, token));
+        }
+
+        self.conn
+            .del::<_, ()>(keys)
+            .await
+            .map_err(|_| RefreshTokenStoreError::UnexpectedError)
+    }
+
+    async fn revoke_user(&mut self, user_id: &UserId) -> Result<(), RefreshTokenStoreError> {
+        let families: Vec<String> = self
+            .conn
+            .smembers(get_user_key(user_id))
+            .await
+            .map_err(|_| RefreshTokenStoreError::UnexpectedError)?;
+
+        for family_id in families {
+            let family_id = RefreshTokenFamilyId::parse(family_id)
+                .map_err(|_| RefreshTokenStoreError::UnexpectedError)?;
+            self.revoke_family(&family_id).await?;
+        }
+
+        self.conn
+            .del::<_, ()>(get_user_key(user_id))
+            .await
+            .map_err(|_| RefreshTokenStoreError::UnexpectedError)
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct StoredSession {
+    user_id: String,
+    family_id: String,
+    expires_at: i64,
+}
+
+const REFRESH_TOKEN_PREFIX: &str = "refresh_token:";
+const REFRESH_TOKEN_USED_PREFIX: &str = "refresh_token_used:";
+const REFRESH_TOKEN_FAMILY_PREFIX: &str = "refresh_token_family:";
+const REFRESH_TOKEN_USER_PREFIX: &str = "refresh_token_user:";
+const REFRESH_TOKEN_REVOKED_FAMILY_PREFIX: &str = "refresh_token_revoked_family:";
+
+// Store a token unless its family was revoked, returning whether it was stored
+const ADD_TOKEN_SCRIPT: &str = "\
+if redis.call('EXISTS', KEYS[1]) == 1 then return 0 end \
+redis.call('SET', KEYS[2], ARGV[1], 'EX', ARGV[2]) \
+redis.call('SADD', KEYS[3], ARGV[3]) \
+redis.call('EXPIRE', KEYS[3], ARGV[2]) \
+redis.call('SADD', KEYS[4], ARGV[4]) \
+redis.call('EXPIRE', KEYS[4], ARGV[2]) \
+return 1";
+
+fn get_key(token: &RefreshTokenHash) -> String {
+    format!("{}{}", REFRESH_TOKEN_PREFIX, token.as_ref())
+}
+
+fn get_used_key(token: &RefreshTokenHash) -> String {
+    format!("{}{}", REFRESH_TOKEN_USED_PREFIX, token.as_ref())
+}
+
+fn get_family_key(family_id: &RefreshTokenFamilyId) -> String {
+    format!("{}{}", REFRESH_TOKEN_FAMILY_PREFIX, family_id.as_ref())
+}
+
+fn get_user_key(user_id: &UserId) -> String {
+    format!("{}{}", REFRESH_TOKEN_USER_PREFIX, user_id.as_ref())
+}
+
+fn get_revoked_family_key(family_id: &RefreshTokenFamilyId) -> String {
+    format!("{}{}", REFRESH_TOKEN_REVOKED_FAMILY_PREFIX, family_id.as_ref())
+}
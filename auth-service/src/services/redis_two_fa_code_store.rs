@@ -0,0 +1,202 @@
+use chrono::Utc;
+use redis::{aio::ConnectionManager, AsyncCommands};
+use serde::{Deserialize, Serialize};
+
+use crate::domain::{
+    data_stores::{LoginAttemptId, TwoFACode, TwoFACodeStore, TwoFACodeStoreError},
+    UserId,
+};
+
+// Pending 2FA codes are shared by all auth-service replicas through Redis.
+// Each code is stored with its creation time and checked against the TTL
+// like in the other stores, while the key itself lives for
+// `EXPIRED_CODE_GRACE_SECONDS` longer, so that an expired code is still
+// reported as `CodeExpired` rather than `LoginAttemptIdNotFound`.
+pub struct RedisTwoFACodeStore {
+    conn: ConnectionManager,
+    ttl_seconds: u64,
+}
+
+impl RedisTwoFACodeStore {
+    pub fn new(conn: ConnectionManager, ttl_seconds: u64) -> Self {
+        Self { conn, ttl_seconds }
+    }
+
+    fn key_ttl_seconds(&self) -> u64 {
+        self.ttl_seconds.saturating_add(EXPIRED_CODE_GRACE_SECONDS)
+    }
+
+    // Codes created at or before this timestamp have expired
+    fn expired_before(&self) -> i64 {
+        Utc::now().timestamp() - i64::try_from(self.ttl_seconds).unwrap_or(i64::MAX)
+    }
+}
+
+#[async_trait::async_trait]
+impl TwoFACodeStore for RedisTwoFACodeStore {
+    async fn add_code(
+        &mut self,
+        user_id: UserId,
+        login_attempt_id: LoginAttemptId,
+        code: TwoFACode,
+    ) -> Result<(), TwoFACodeStoreError> {
+        let tuple = TwoFATuple(
+            login_attempt_id.as_ref().to_owned(),
+            code.as_ref().to_owned(),
+            Utc::now().timestamp(),
+        );
+        let value = serde_json::to_string(&tuple).map_err(|_| TwoFACodeStoreError::UnexpectedError)?;
+
+        // SET replaces any pending code for the same user and resets the expiry,
+        // the failed attempts of the previous login attempt are discarded
+        redis::pipe()
+            .atomic()
+            .set_ex(get_key(&user_id), value, self.key_ttl_seconds())
+            .ignore()
+            .del(get_attempts_key(&user_id))
+            .ignore()
+            .query_async::<()>(&mut self.conn)
+            .await
+            .map_err(|_| TwoFACodeStoreError::UnexpectedError)
+    }
+
+    async fn remove_code(&mut self, user_id: &UserId) -> Result<(), TwoFACodeStoreError> {
+        self.conn
+            .del::<_, ()>(&[get_key(user_id), get_attempts_key(user_id)])
+            .await
+            .map_err(|_| TwoFACodeStoreError::UnexpectedError)
+    }
+
+    async fn get_code(
+        &self,
+        user_id: &UserId,
+    ) -> Result<(LoginAttemptId, TwoFACode), TwoFACodeStoreError> {
+        let value: Option<String> = self
+            .conn
+            .clone()
+            .get(get_key(user_id))
+            .await
+            .map_err(|_| TwoFACodeStoreError::UnexpectedError)?;
+
+        let value = value.ok_or(TwoFACodeStoreError::LoginAttemptIdNotFound)?;
+        let TwoFATuple(login_attempt_id, code, created_at) =
+            serde_json::from_str(&value).map_err(|_| TwoFACodeStoreError::UnexpectedError)?;
+
+        if created_at <= self.expired_before() {
+            return Err(TwoFACodeStoreError::CodeExpired);
+        }
+
+        let login_attempt_id = LoginAttemptId::parse(login_attempt_id)
+            .map_err(|_| TwoFACodeStoreError::UnexpectedError)?;
+        let code = TwoFACode::parse(code).map_err(|_| TwoFACodeStoreError::UnexpectedError)?;
+
+        Ok((login_attempt_id, code))
+    }
+
+    async fn take_code(
+        &mut self,
+        user_id: &UserId,
+        login_attempt_id: &LoginAttemptId,
+        code: Option<&TwoFACode>,
+        max_attempts: u32,
+    ) -> Result<(), TwoFACodeStoreError> {
+        let result: i64 = redis::cmd("EVAL")
+            .arg(TAKE_CODE_SCRIPT)
+            .arg(2)
+            .arg(get_key(user_id))
+            .arg(get_attempts_key(user_id))
+            .arg(self.key_ttl_seconds())
+            .arg(max_attempts)
+            .arg(self.expired_before())
+            .arg(login_attempt_id.as_ref())
+            .arg(code.map_or("", |code| code.as_ref()))
+            .query_async(&mut self.conn)
+            .await
+            .map_err(|_| TwoFACodeStoreError::UnexpectedError)?;
+
+        match result {
+            0 => Ok(()),
+            -1 => Err(TwoFACodeStoreError::LoginAttemptIdNotFound),
+            -2 => Err(TwoFACodeStoreError::CodeExpired),
+            failed_attempts => Err(TwoFACodeStoreError::IncorrectCode(
+                failed_attempts.try_into().map_err(|_| TwoFACodeStoreError::UnexpectedError)?,
+            )),
+        }
+    }
+
+    async fn record_failed_attempt(&mut self, user_id: &UserId, max_attempts: u32) -> Result<u32, TwoFACodeStoreError> {
+        let result: i64 = redis::cmd("EVAL")
+            .arg(RECORD_FAILED_ATTEMPT_SCRIPT)
+            .arg(2)
+            .arg(get_key(user_id))
+            .arg(get_attempts_key(user_id))
+            .arg(self.key_ttl_seconds())
+            .arg(max_attempts)
+            .arg(self.expired_before())
+            .query_async(&mut self.conn)
+            .await
+            .map_err(|_| TwoFACodeStoreError::UnexpectedError)?;
+
+        match result {
+            -1 => Err(TwoFACodeStoreError::LoginAttemptIdNotFound),
+            -2 => Err(TwoFACodeStoreError::CodeExpired),
+            failed_attempts => failed_attempts.try_into().map_err(|_| TwoFACodeStoreError::UnexpectedError),
+        }
+    }
+}
+
+// Login attempt ID, code and creation timestamp
+#[derive(Serialize, Deserialize)]
+struct TwoFATuple(pub String, pub String, pub i64);
+
+// How long an expired code is kept to tell it apart from one which never
+// existed, after that it is reported as not found
+const EXPIRED_CODE_GRACE_SECONDS: u64 = 3600;
+
+const TWO_FA_CODE_PREFIX: &str = "two_fa_code:";
+const TWO_FA_ATTEMPTS_PREFIX: &str = "two_fa_attempts:";
+
+// Count a wrong code, the counter lives no longer than the code it belongs to.
+// The pending code is removed once `ARGV[2]` wrong codes were counted.
+// Returns the count, -1 if there is no pending code, or -2 if it was created
+// at or before `ARGV[3]` and has expired, removing it.
+const RECORD_FAILED_ATTEMPT_SCRIPT: &str = "\
+local value = redis.call('GET', KEYS[1]) \
+if not value then return -1 end \
+if cjson.decode(value)[3] <= tonumber(ARGV[3]) then \
+    redis.call('DEL', KEYS[1], KEYS[2]) \
+    return -2 \
+end \
+local failed_attempts = redis.call('INCR', KEYS[2]) \
+redis.call('EXPIRE', KEYS[2], ARGV[1]) \
+if failed_attempts >= tonumber(ARGV[2]) then redis.call('DEL', KEYS[1], KEYS[2]) end \
+return failed_attempts";
+
+// Remove the pending code if its login attempt ID is `ARGV[4]` and, unless
+// `ARGV[5]` is empty, its code is `ARGV[5]`, returning 0. Otherwise count a
+// wrong code like `RECORD_FAILED_ATTEMPT_SCRIPT`, which also describes the
+// other arguments and return values.
+const TAKE_CODE_SCRIPT: &str = "\
+local value = redis.call('GET', KEYS[1]) \
+if not value then return -1 end \
+local pending = cjson.decode(value) \
+if pending[3] <= tonumber(ARGV[3]) then \
+    redis.call('DEL', KEYS[1], KEYS[2]) \
+    return -2 \
+end \
+if pending[1] == ARGV[4] and (ARGV[5] == '' or pending[2] == ARGV[5]) then \
+    redis.call('DEL', KEYS[1], KEYS[2]) \
+    return 0 \
+end \
+local failed_attempts = redis.call('INCR', KEYS[2]) \
+redis.call('EXPIRE', KEYS[2], ARGV[1]) \
+if failed_attempts >= tonumber(ARGV[2]) then redis.call('DEL', KEYS[1], KEYS[2]) end \
+return failed_attempts";
+
+fn get_key(user_id: &UserId) -> String {
+    format!("{}{}", TWO_FA_CODE_PREFIX, user_id.as_ref())
+}
+
+fn get_attempts_key(user_id: &UserId) -> String {
+    format!("{}{}", TWO_FA_ATTEMPTS_PREFIX, user_id.as_ref())
+}
@@ -0,0 +1,202 @@
+use chrono::Utc;
+use sqlx::SqlitePool;
+
+use crate::domain::{BannedTokenStore, BannedTokenStoreError};
+
+pub struct SqliteBannedTokenStore {
+    pool: SqlitePool,
+}
+
+impl SqliteBannedTokenStore {
+    pub fn new(pool: SqlitePool) -> Self {
+        Self { pool }
+    }
+}
+
+#[async_trait::async_trait]
+impl BannedTokenStore for SqliteBannedTokenStore {
+    async fn store_tokens(&mut self, jti: String, exp: usize) -> Result<(), BannedTokenStoreError> {
+        let exp = i64::try_from(exp).map_err(|_| BannedTokenStoreError::UnexpectedError)?;
+
+        // Keep the latest expiration if the same token is banned twice
+        sqlx::query(
+            "INSERT INTO banned_tokens (jti, exp) VALUES ($1, $2)
+             ON CONFLICT (jti) DO UPDATE SET exp = MAX(exp, excluded.exp)",
+        )
+        .bind(jti)
+        .bind(exp)
+        .execute(&self.pool)
+        .await
+        .map_err(|_| BannedTokenStoreError::UnexpectedError)?;
+
+        Ok(())
+    }
+
+    async fn is_token_exists(&self, jti: &str) -> Result<bool, BannedTokenStoreError> {
+        let row = sqlx::query("SELECT 1 FROM banned_tokens WHERE jti = $1")
+            .bind(jti)
+            .fetch_optional(&self.pool)
+            .await
+            .map_err(|_| BannedTokenStoreError::UnexpectedError)?;
+
+        Ok(row.is_some())
+    }
+
+    async fn ban_subject(
+        &mut self,
+        sub: String,
+        issued_until: usize,
+        exempt_jti: Option<String>,
+        exp: usize,
+    ) -> Result<(), BannedTokenStoreError> {
+        let issued_until = i64::try_from(issued_until).map_err(|_| BannedTokenStoreError::UnexpectedError)?;
+        let exp = i64::try_from(exp).map_err(|_| BannedTokenStoreError::UnexpectedError)?;
+
+        // A later ban covers everything an earlier one did, including the
+        // token the earlier one exempted.
+        sqlx::query(
+            "INSERT INTO banned_subjects (sub, issued_until, exempt_jti, exp) VALUES ($1, $2, $3, $4)
+             ON CONFLICT (sub) DO UPDATE SET
+                 issued_until = MAX(issued_until, excluded.issued_until),
+                 exempt_jti = CASE WHEN excluded.issued_until >= issued_until
+                     THEN excluded.exempt_jti ELSE exempt_jti END,
+                 exp = MAX(exp, excluded.exp)",
+        )
+        .bind(sub)
+        .bind(issued_until)
+        .bind(exempt_jti)
+        .bind(exp)
+        .execute(&self.pool)
+        .await
+        .map_err(|_| BannedTokenStoreError::UnexpectedError)?;
+
+        Ok(())
+    }
+
+    async fn is_subject_banned(&self, sub: &str, iat: usize, jti: &str) -> Result<bool, BannedTokenStoreError> {
+        let iat = i64::try_from(iat).map_err(|_| BannedTokenStoreError::UnexpectedError)?;
+
+        let row = sqlx::query(
+            "SELECT 1 FROM banned_subjects WHERE sub = $1 AND issued_until >= $2
+             AND (exempt_jti IS NULL OR exempt_jti != $3)",
+        )
+        .bind(sub)
+        .bind(iat)
+        .bind(jti)
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(|_| BannedTokenStoreError::UnexpectedError)?;
+
+        Ok(row.is_some())
+    }
+
+    async fn prune_expired(&mut self) -> Result<usize, BannedTokenStoreError> {
+        let now = Utc::now().timestamp();
+
+        let tokens = sqlx::query("DELETE FROM banned_tokens WHERE exp <= $1")
+            .bind(now)
+            .execute(&self.pool)
+            .await
+            .map_err(|_| BannedTokenStoreError::UnexpectedError)?;
+        let subjects = sqlx::query("DELETE FROM banned_subjects WHERE exp <= $1")
+            .bind(now)
+            .execute(&self.pool)
+            .await
+            .map_err(|_| BannedTokenStoreError::UnexpectedError)?;
+
+        Ok((tokens.rows_affected() + subjects.rows_affected()) as usize)
+    }
+
+    async fn size(&self) -> Result<usize, BannedTokenStoreError> {
+        let size: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM banned_tokens")
+            .fetch_one(&self.pool)
+            .await
+            .map_err(|_| BannedTokenStoreError::UnexpectedError)?;
+
+        Ok(size as usize)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{get_sqlite_pool, SQLITE_MIGRATOR};
+
+    async fn store() -> SqliteBannedTokenStore {
+        let pool = get_sqlite_pool("sqlite::memory:").await.unwrap();
+        SQLITE_MIGRATOR.run(&pool).await.unwrap();
+        SqliteBannedTokenStore::new(pool)
+    }
+
+    #[tokio::test]
+    async fn test_is_token_exists() {
+        let mut store = store().await;
+        let token = "test.jwt.token".to_string();
+
+        let result = store.is_token_exists(&token).await;
+        assert_eq!(result, Ok(false));
+
+        store.store_tokens(token.clone(), 1234567890).await.unwrap();
+        let result = store.is_token_exists(&token).await;
+        assert_eq!(result, Ok(true));
+
+        let result = store.is_token_exists("different.jwt.token").await;
+        assert_eq!(result, Ok(false));
+    }
+
+    #[tokio::test]
+    async fn test_store_same_token_twice() {
+        let mut store = store().await;
+        let token = "same.jwt.token".to_string();
+
+        assert!(store.store_tokens(token.clone(), 1111111111).await.is_ok());
+        assert!(store.store_tokens(token.clone(), 2222222222).await.is_ok());
+
+        let result = store.is_token_exists(&token).await;
+        assert_eq!(result, Ok(true));
+    }
+
+    #[tokio::test]
+    async fn test_prune_expired() {
+        let mut store = store().await;
+        let now = Utc::now().timestamp() as usize;
+
+        store.store_tokens("expired.jwt.token".to_string(), now - 1).await.unwrap();
+        store.store_tokens("valid.jwt.token".to_string(), now + 600).await.unwrap();
+        assert_eq!(store.size().await, Ok(2));
+
+        let result = store.prune_expired().await;
+        assert_eq!(result, Ok(1));
+        assert_eq!(store.size().await, Ok(1));
+        assert_eq!(store.is_token_exists("expired.jwt.token").await, Ok(false));
+        assert_eq!(store.is_token_exists("valid.jwt.token").await, Ok(true));
+    }
+
+    #[tokio::test]
+    async fn test_ban_subject() {
+        let mut store = store().await;
+        let now = Utc::now().timestamp() as usize;
+
+        store
+            .ban_subject("test@example.com".to_string(), now, Some("exempt".to_string()), now + 600)
+            .await
+            .unwrap();
+
+        assert_eq!(store.is_subject_banned("test@example.com", now - 1, "jti").await, Ok(true));
+        assert_eq!(store.is_subject_banned("test@example.com", now, "jti").await, Ok(true));
+        assert_eq!(store.is_subject_banned("test@example.com", now, "exempt").await, Ok(false));
+        assert_eq!(store.is_subject_banned("test@example.com", now + 1, "jti").await, Ok(false));
+        assert_eq!(store.is_subject_banned("other@example.com", now - 1, "jti").await, Ok(false));
+
+        store.ban_subject("test@example.com".to_string(), now - 60, None, now + 540).await.unwrap();
+        assert_eq!(store.is_subject_banned("test@example.com", now, "jti").await, Ok(true));
+        assert_eq!(store.is_subject_banned("test@example.com", now, "exempt").await, Ok(false));
+
+        store.ban_subject("test@example.com".to_string(), now, None, now + 600).await.unwrap();
+        assert_eq!(store.is_subject_banned("test@example.com", now, "exempt").await, Ok(true));
+
+        store.ban_subject("expired@example.com".to_string(), now - 600, None, now - 1).await.unwrap();
+        assert_eq!(store.prune_expired().await, Ok(1));
+        assert_eq!(store.is_subject_banned("expired@example.com", now - 601, "jti").await, Ok(false));
+    }
+}
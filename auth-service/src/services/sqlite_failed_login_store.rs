@@ -0,0 +1,201 @@
+use chrono::Utc;
+use sqlx::{Row, SqlitePool};
+
+use crate::domain::data_stores::{FailedLoginKey, FailedLoginStore, FailedLoginStoreError, FailedLogins};
+
+pub struct SqliteFailedLoginStore {
+    pool: SqlitePool,
+    ttl_seconds: i64,
+}
+
+impl SqliteFailedLoginStore {
+    pub fn new(pool: SqlitePool, ttl_seconds: u64) -> Self {
+        Self {
+            pool,
+            ttl_seconds: ttl_seconds.try_into().unwrap_or(i64::MAX),
+        }
+    }
+
+    // Failures before this are forgotten
+    fn expired_before(&self, now: i64) -> i64 {
+        now.saturating_sub(self.ttl_seconds)
+    }
+}
+
+#[async_trait::async_trait]
+impl FailedLoginStore for SqliteFailedLoginStore {
+    async fn get_failures(&self, key: &FailedLoginKey) -> Result<Option<FailedLogins>, FailedLoginStoreError> {
+        let row = sqlx::query("SELECT count, last_failed_at FROM failed_logins WHERE key = $1 AND last_failed_at > $2")
+            .bind(key.as_key())
+            .bind(self.expired_before(Utc::now().timestamp()))
+            .fetch_optional(&self.pool)
+            .await
+            .map_err(|_| FailedLoginStoreError::UnexpectedError)?;
+
+        row.map(|row| {
+            Ok(FailedLogins {
+                count: row
+                    .get::<i64, _>("count")
+                    .try_into()
+                    .map_err(|_| FailedLoginStoreError::UnexpectedError)?,
+                last_failed_at: row.get("last_failed_at"),
+            })
+        })
+        .transpose()
+    }
+
+    async fn record_attempt(&mut self, key: &FailedLoginKey) -> Result<Option<FailedLogins>, FailedLoginStoreError> {
+        let now = Utc::now().timestamp();
+
+        // The SET expressions see the row as it was, so a single statement
+        // both counts the attempt and returns the failures before it. Expired
+        // failures that were not pruned yet start over.
+        let row = sqlx::query(
+            "INSERT INTO failed_logins (key, count, last_failed_at) VALUES ($1, 1, $2) \
+             ON CONFLICT (key) DO UPDATE SET \
+             count = CASE WHEN last_failed_at <= $3 THEN 1 ELSE count + 1 END, \
+             previous_failed_at = CASE WHEN last_failed_at <= $3 THEN NULL ELSE last_failed_at END, \
+             last_failed_at = excluded.last_failed_at \
+             RETURNING count, previous_failed_at",
+        )
+        .bind(key.as_key())
+        .bind(now)
+        .bind(self.expired_before(now))
+        .fetch_one(&self.pool)
+        .await
+        .map_err(|_| FailedLoginStoreError::UnexpectedError)?;
+
+        let count: i64 = row.get("count");
+        match row.get::<Option<i64>, _>("previous_failed_at") {
+            Some(previous_failed_at) if count > 1 => Ok(Some(FailedLogins {
+                count: (count - 1).try_into().map_err(|_| FailedLoginStoreError::UnexpectedError)?,
+                last_failed_at: previous_failed_at,
+            })),
+            _ => Ok(None),
+        }
+    }
+
+    async fn forgive_attempt(&mut self, key: &FailedLoginKey) -> Result<(), FailedLoginStoreError> {
+        sqlx::query("UPDATE failed_logins SET count = count - 1 WHERE key = $1 AND count > 0")
+            .bind(key.as_key())
+            .execute(&self.pool)
+            .await
+            .map_err(|_| FailedLoginStoreError::UnexpectedError)?;
+
+        Ok(())
+    }
+
+    async fn clear_failures(&mut self, key: &FailedLoginKey) -> Result<(), FailedLoginStoreError> {
+        sqlx::query("DELETE FROM failed_logins WHERE key = $1")
+            .bind(key.as_key())
+            .execute(&self.pool)
+            .await
+            .map_err(|_| FailedLoginStoreError::UnexpectedError)?;
+
+        Ok(())
+    }
+
+    async fn prune_expired(&mut self) -> Result<usize, FailedLoginStoreError> {
+        let result = sqlx::query("DELETE FROM failed_logins WHERE last_failed_at <= $1")
+            .bind(self.expired_before(Utc::now().timestamp()))
+            .execute(&self.pool)
+            .await
+            .map_err(|_| FailedLoginStoreError::UnexpectedError)?;
+
+        Ok(result.rows_affected() as usize)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{domain::Email, get_sqlite_pool, SQLITE_MIGRATOR};
+
+    async fn store_with_ttl(ttl_seconds: u64) -> SqliteFailedLoginStore {
+        let pool = get_sqlite_pool("sqlite::memory:").await.unwrap();
+        SQLITE_MIGRATOR.run(&pool).await.unwrap();
+        SqliteFailedLoginStore::new(pool, ttl_seconds)
+    }
+
+    fn email_key() -> FailedLoginKey {
+        FailedLoginKey::Email(Email::parse("test@example.com".to_owned()).unwrap())
+    }
+
+    fn ip_key() -> FailedLoginKey {
+        FailedLoginKey::Ip("127.0.0.1".parse().unwrap())
+    }
+
+    async fn count(store: &mut SqliteFailedLoginStore, key: &FailedLoginKey) -> u32 {
+        store.record_attempt(key).await.unwrap().map_or(0, |failures| failures.count)
+    }
+
+    #[tokio::test]
+    async fn test_record_attempt() {
+        let mut store = store_with_ttl(600).await;
+
+        assert_eq!(store.record_attempt(&email_key()).await, Ok(None));
+        assert_eq!(count(&mut store, &email_key()).await, 1);
+        assert_eq!(count(&mut store, &ip_key()).await, 0);
+
+        let failures = store.record_attempt(&email_key()).await.unwrap().unwrap();
+        assert_eq!(failures.count, 2);
+        assert!(failures.last_failed_at <= Utc::now().timestamp());
+    }
+
+    #[tokio::test]
+    async fn test_get_failures() {
+        let mut store = store_with_ttl(600).await;
+        assert_eq!(store.get_failures(&email_key()).await, Ok(None));
+
+        store.record_attempt(&email_key()).await.unwrap();
+        let failures = store.get_failures(&email_key()).await.unwrap().unwrap();
+        assert_eq!(failures.count, 1);
+
+        // Reading does not count
+        assert_eq!(store.get_failures(&email_key()).await, Ok(Some(failures)));
+        assert_eq!(store.get_failures(&ip_key()).await, Ok(None));
+    }
+
+    #[tokio::test]
+    async fn test_forgive_attempt() {
+        let mut store = store_with_ttl(600).await;
+        store.record_attempt(&ip_key()).await.unwrap();
+        store.record_attempt(&ip_key()).await.unwrap();
+
+        store.forgive_attempt(&ip_key()).await.unwrap();
+        assert_eq!(count(&mut store, &ip_key()).await, 1);
+
+        store.forgive_attempt(&email_key()).await.unwrap();
+        assert_eq!(store.record_attempt(&email_key()).await, Ok(None));
+    }
+
+    #[tokio::test]
+    async fn test_clear_failures() {
+        let mut store = store_with_ttl(600).await;
+        store.record_attempt(&email_key()).await.unwrap();
+        store.record_attempt(&ip_key()).await.unwrap();
+
+        store.clear_failures(&email_key()).await.unwrap();
+        assert_eq!(store.record_attempt(&email_key()).await, Ok(None));
+        assert_eq!(count(&mut store, &ip_key()).await, 1);
+    }
+
+    #[tokio::test]
+    async fn test_prune_expired() {
+        let mut store = store_with_ttl(0).await;
+        store.record_attempt(&email_key()).await.unwrap();
+        store.record_attempt(&ip_key()).await.unwrap();
+
+        assert_eq!(store.prune_expired().await, Ok(2));
+        assert_eq!(store.prune_expired().await, Ok(0));
+    }
+
+    #[tokio::test]
+    async fn test_failures_expire() {
+        let mut store = store_with_ttl(0).await;
+        store.record_attempt(&email_key()).await.unwrap();
+
+        assert_eq!(store.get_failures(&email_key()).await, Ok(None));
+        assert_eq!(store.record_attempt(&email_key()).await, Ok(None));
+    }
+}
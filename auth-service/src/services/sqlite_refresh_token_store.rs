@@ -0,0 +1,276 @@
+use chrono::Utc;
+use sqlx::{sqlite::SqliteRow, Row, SqlitePool};
+
+use crate::domain::{
+    data_stores::{RefreshTokenStore, RefreshTokenStoreError},
+    RefreshSession, UserId, RefreshTokenFamilyId, RefreshTokenHash,
+};
+
+pub struct SqliteRefreshTokenStore {
+    pool: SqlitePool,
+}
+
+impl SqliteRefreshTokenStore {
+    pub fn new(pool: SqlitePool) -> Self {
+        Self { pool }
+    }
+
+    // Revoke the families of all tokens whose `column` is `value`, remembering
+    // each until its newest token would have expired
+    async fn revoke_families(&self, column: &str, value: &str) -> Result<(), RefreshTokenStoreError> {
+        let mut transaction = self.pool.begin().await.map_err(|_| RefreshTokenStoreError::UnexpectedError)?;
+
+        sqlx::query(&format!(
+            "INSERT INTO revoked_refresh_token_families (family_id, expires_at) \
+             SELECT family_id, MAX(expires_at) FROM refresh_tokens WHERE {column} = $1 GROUP BY family_id \
+             ON CONFLICT (family_id) DO UPDATE SET expires_at = MAX(expires_at, excluded.expires_at)"
+        ))
+        .bind(value)
+        .execute(&mut *transaction)
+        .await
+        .map_err(|_| RefreshTokenStoreError::UnexpectedError)?;
+
+        sqlx::query(&format!("DELETE FROM refresh_tokens WHERE {column} = $1"))
+            .bind(value)
+            .execute(&mut *transaction)
+            .await
+            .map_err(|_| RefreshTokenStoreError::UnexpectedError)?;
+
+        transaction.commit().await.map_err(|_| RefreshTokenStoreError::UnexpectedError)
+    }
+}
+
+#[async_trait::async_trait]
+impl RefreshTokenStore for SqliteRefreshTokenStore {
+    async fn add_token(
+        &mut self,
+        token: RefreshTokenHash,
+        session: RefreshSession,
+    ) -> Result<(), RefreshTokenStoreError> {
+        let now = Utc::now().timestamp();
+
+        // Drop expired tokens and revocations here, there is no other point
+        // where they would go away
+        sqlx::query("DELETE FROM refresh_tokens WHERE expires_at <= $1")
+            .bind(now)
+            .execute(&self.pool)
+            .await
+            .map_err(|_| RefreshTokenStoreError::UnexpectedError)?;
+        sqlx::query("DELETE FROM revoked_refresh_token_families WHERE expires_at <= $1")
+            .bind(now)
+            .execute(&self.pool)
+            .await
+            .map_err(|_| RefreshTokenStoreError::UnexpectedError)?;
+
+        // Checking for a revocation in the same statement that inserts the
+        // token makes sure it cannot slip in while its family is revoked
+        let result = sqlx::query(
+            "INSERT INTO refresh_tokens (token_hash, user_id, family_id, expires_at) \
+             SELECT $1, $2, $3, $4 \
+             WHERE NOT EXISTS (SELECT 1 FROM revoked_refresh_token_families WHERE family_id = $3)",
+        )
+        .bind(token.as_ref())
+        .bind(session.user_id.as_ref())
+        .bind(session.family_id.as_ref())
+        .bind(session.expires_at)
+        .execute(&self.pool)
+        .await
+        .map_err(|_| RefreshTokenStoreError::UnexpectedError)?;
+
+        if result.rows_affected() == 0 {
+            return Err(RefreshTokenStoreError::FamilyRevoked);
+        }
+
+        Ok(())
+    }
+
+    async fn use_token(
+        &mut self,
+        token: &RefreshTokenHash,
+    ) -> Result<RefreshSession, RefreshTokenStoreError> {
+        let now = Utc::now().timestamp();
+
+        // Marking the token as used in the same statement that reads it makes
+        // sure two concurrent requests cannot both use it
+        let row = sqlx::query(
+            "UPDATE refresh_tokens SET used = TRUE \
+             WHERE token_hash = $1 AND used = FALSE AND expires_at > $2 \
+             RETURNING user_id, family_id, expires_at",
+        )
+        .bind(token.as_ref())
+        .bind(now)
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(|_| RefreshTokenStoreError::UnexpectedError)?;
+
+        if let Some(row) = row {
+            return session_from_row(&row);
+        }
+
+        let family_id: Option<String> = sqlx::query_scalar(
+            "SELECT family_id FROM refresh_tokens \
+             WHERE token_hash = $1 AND used = TRUE AND expires_at > $2",
+        )
+        .bind(token.as_ref())
+        .bind(now)
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(|_| RefreshTokenStoreError::UnexpectedError)?;
+
+        match family_id {
+            Some(family_id) => Err(RefreshTokenStoreError::TokenReused(
+                RefreshTokenFamilyId::parse(family_id)
+                    .map_err(|_| RefreshTokenStoreError::UnexpectedError)?,
+            )),
+            None => Err(RefreshTokenStoreError::TokenNotFound),
+        }
+    }
+
+    async fn revoke_family(
+        &mut self,
+        family_id: &RefreshTokenFamilyId,
+    ) -> Result<(), RefreshTokenStoreError> {
+        self.revoke_families("family_id", family_id.as_ref()).await
+    }
+
+    async fn revoke_user(&mut self, user_id: &UserId) -> Result<(), RefreshTokenStoreError> {
+        self.revoke_families("user_id", user_id.as_ref()).await
+    }
+}
+
+fn session_from_row(row: &SqliteRow) -> Result<RefreshSession, RefreshTokenStoreError> {
+    Ok(RefreshSession {
+        user_id: UserId::parse(row.get("user_id")).map_err(|_| RefreshTokenStoreError::UnexpectedError)?,
+        family_id: RefreshTokenFamilyId::parse(row.get("family_id"))
+            .map_err(|_| RefreshTokenStoreError::UnexpectedError)?,
+        expires_at: row.get("expires_at"),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{domain::RefreshToken, get_sqlite_pool, SQLITE_MIGRATOR};
+
+    async fn store() -> SqliteRefreshTokenStore {
+        let pool = get_sqlite_pool("sqlite::memory:").await.unwrap();
+        SQLITE_MIGRATOR.run(&pool).await.unwrap();
+        SqliteRefreshTokenStore::new(pool)
+    }
+
+    // All sessions belong to the same user unless overridden
+    fn user_id() -> UserId {
+        UserId::parse("5f3f3c4e-8a4b-4d9e-9a57-0c7d6f1e2b3a".to_string()).unwrap()
+    }
+
+    fn session(family_id: &RefreshTokenFamilyId, expires_in: i64) -> RefreshSession {
+        RefreshSession {
+            user_id: user_id(),
+            family_id: family_id.clone(),
+            expires_at: Utc::now().timestamp() + expires_in,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_use_token() {
+        let mut store = store().await;
+        let family_id = RefreshTokenFamilyId::default();
+        let token = RefreshToken::generate().hash();
+
+        store.add_token(token.clone(), session(&family_id, 60)).await.unwrap();
+
+        let result = store.use_token(&token).await.unwrap();
+        assert_eq!(result.family_id, family_id);
+    }
+
+    #[tokio::test]
+    async fn test_use_token_twice_is_detected() {
+        let mut store = store().await;
+        let family_id = RefreshTokenFamilyId::default();
+        let token = RefreshToken::generate().hash();
+
+        store.add_token(token.clone(), session(&family_id, 60)).await.unwrap();
+        store.use_token(&token).await.unwrap();
+
+        let result = store.use_token(&token).await;
+        assert_eq!(result, Err(RefreshTokenStoreError::TokenReused(family_id)));
+    }
+
+    #[tokio::test]
+    async fn test_use_unknown_or_expired_token() {
+        let mut store = store().await;
+        let token = RefreshToken::generate().hash();
+
+        let result = store.use_token(&token).await;
+        assert_eq!(result, Err(RefreshTokenStoreError::TokenNotFound));
+
+        store
+            .add_token(token.clone(), session(&RefreshTokenFamilyId::default(), 0))
+            .await
+            .unwrap();
+
+        let result = store.use_token(&token).await;
+        assert_eq!(result, Err(RefreshTokenStoreError::TokenNotFound));
+    }
+
+    #[tokio::test]
+    async fn test_revoke_family() {
+        let mut store = store().await;
+        let family_id = RefreshTokenFamilyId::default();
+        let token = RefreshToken::generate().hash();
+        let other_token = RefreshToken::generate().hash();
+
+        store.add_token(token.clone(), session(&family_id, 60)).await.unwrap();
+        store
+            .add_token(other_token.clone(), session(&RefreshTokenFamilyId::default(), 60))
+            .await
+            .unwrap();
+
+        store.revoke_family(&family_id).await.unwrap();
+
+        assert_eq!(store.use_token(&token).await, Err(RefreshTokenStoreError::TokenNotFound));
+        assert!(store.use_token(&other_token).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_revoke_user() {
+        let mut store = store().await;
+        let token = RefreshToken::generate().hash();
+        let other_token = RefreshToken::generate().hash();
+
+        store.add_token(token.clone(), session(&RefreshTokenFamilyId::default(), 60)).await.unwrap();
+        let other_session = RefreshSession {
+            user_id: UserId::default(),
+            ..session(&RefreshTokenFamilyId::default(), 60)
+        };
+        store.add_token(other_token.clone(), other_session).await.unwrap();
+
+        store.revoke_user(&user_id()).await.unwrap();
+
+        assert_eq!(store.use_token(&token).await, Err(RefreshTokenStoreError::TokenNotFound));
+        assert!(store.use_token(&other_token).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_add_token_to_revoked_family() {
+        let mut store = store().await;
+        let family_id = RefreshTokenFamilyId::default();
+        let token = RefreshToken::generate().hash();
+
+        store.add_token(token.clone(), session(&family_id, 60)).await.unwrap();
+        // The token is being refreshed while its family is revoked
+        store.use_token(&token).await.unwrap();
+        store.revoke_user(&user_id()).await.unwrap();
+
+        let result = store
+            .add_token(RefreshToken::generate().hash(), session(&family_id, 60))
+            .await;
+        assert_eq!(result, Err(RefreshTokenStoreError::FamilyRevoked));
+
+        // New logins start a new family
+        let result = store
+            .add_token(RefreshToken::generate().hash(), session(&RefreshTokenFamilyId::default(), 60))
+            .await;
+        assert!(result.is_ok());
+    }
+}
@@ -0,0 +1,341 @@
+use chrono::Utc;
+use sqlx::{Row, SqlitePool};
+
+use crate::domain::{
+    data_stores::{LoginAttemptId, TwoFACode, TwoFACodeStore, TwoFACodeStoreError},
+    UserId,
+};
+
+pub struct SqliteTwoFACodeStore {
+    pool: SqlitePool,
+    ttl_seconds: i64,
+}
+
+impl SqliteTwoFACodeStore {
+    pub fn new(pool: SqlitePool, ttl_seconds: u64) -> Self {
+        Self {
+            pool,
+            ttl_seconds: ttl_seconds.try_into().unwrap_or(i64::MAX),
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl TwoFACodeStore for SqliteTwoFACodeStore {
+    async fn add_code(
+        &mut self,
+        user_id: UserId,
+        login_attempt_id: LoginAttemptId,
+        code: TwoFACode,
+    ) -> Result<(), TwoFACodeStoreError> {
+        // A new login attempt replaces any pending one for the same user,
+        // including its failed attempts
+        sqlx::query(
+            "INSERT OR REPLACE INTO two_fa_codes (user_id, login_attempt_id, code, created_at, failed_attempts) \
+             VALUES ($1, $2, $3, $4, 0)",
+        )
+        .bind(user_id.as_ref())
+        .bind(login_attempt_id.as_ref())
+        .bind(code.as_ref())
+        .bind(Utc::now().timestamp())
+        .execute(&self.pool)
+        .await
+        .map_err(|_| TwoFACodeStoreError::UnexpectedError)?;
+
+        Ok(())
+    }
+
+    async fn remove_code(&mut self, user_id: &UserId) -> Result<(), TwoFACodeStoreError> {
+        sqlx::query("DELETE FROM two_fa_codes WHERE user_id = $1")
+            .bind(user_id.as_ref())
+            .execute(&self.pool)
+            .await
+            .map_err(|_| TwoFACodeStoreError::UnexpectedError)?;
+
+        Ok(())
+    }
+
+    async fn get_code(
+        &self,
+        user_id: &UserId,
+    ) -> Result<(LoginAttemptId, TwoFACode), TwoFACodeStoreError> {
+        let row = sqlx::query(
+            "SELECT login_attempt_id, code, created_at FROM two_fa_codes WHERE user_id = $1",
+        )
+        .bind(user_id.as_ref())
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(|_| TwoFACodeStoreError::UnexpectedError)?
+        .ok_or(TwoFACodeStoreError::LoginAttemptIdNotFound)?;
+
+        let created_at: i64 = row.get("created_at");
+        if Utc::now().timestamp() - created_at >= self.ttl_seconds {
+            return Err(TwoFACodeStoreError::CodeExpired);
+        }
+
+        let login_attempt_id = LoginAttemptId::parse(row.get("login_attempt_id"))
+            .map_err(|_| TwoFACodeStoreError::UnexpectedError)?;
+        let code = TwoFACode::parse(row.get("code"))
+            .map_err(|_| TwoFACodeStoreError::UnexpectedError)?;
+
+        Ok((login_attempt_id, code))
+    }
+
+    async fn take_code(
+        &mut self,
+        user_id: &UserId,
+        login_attempt_id: &LoginAttemptId,
+        code: Option<&TwoFACode>,
+        max_attempts: u32,
+    ) -> Result<(), TwoFACodeStoreError> {
+        let created_after = Utc::now().timestamp().saturating_sub(self.ttl_seconds);
+
+        // Deleting the row both checks and uses up the code, so concurrent
+        // requests cannot both use it
+        let taken = sqlx::query(
+            "DELETE FROM two_fa_codes \
+             WHERE user_id = $1 AND login_attempt_id = $2 AND ($3 IS NULL OR code = $3) AND created_at > $4 \
+             RETURNING user_id",
+        )
+        .bind(user_id.as_ref())
+        .bind(login_attempt_id.as_ref())
+        .bind(code.map(|code| code.as_ref()))
+        .bind(created_after)
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(|_| TwoFACodeStoreError::UnexpectedError)?;
+
+        if taken.is_some() {
+            return Ok(());
+        }
+
+        let failed_attempts = self.record_failed_attempt(user_id, max_attempts).await?;
+        Err(TwoFACodeStoreError::IncorrectCode(failed_attempts))
+    }
+
+    async fn record_failed_attempt(&mut self, user_id: &UserId, max_attempts: u32) -> Result<u32, TwoFACodeStoreError> {
+        let created_after = Utc::now().timestamp().saturating_sub(self.ttl_seconds);
+
+        let expired = sqlx::query("DELETE FROM two_fa_codes WHERE user_id = $1 AND created_at <= $2 RETURNING user_id")
+            .bind(user_id.as_ref())
+            .bind(created_after)
+            .fetch_optional(&self.pool)
+            .await
+            .map_err(|_| TwoFACodeStoreError::UnexpectedError)?;
+
+        if expired.is_some() {
+            return Err(TwoFACodeStoreError::CodeExpired);
+        }
+
+        let failed_attempts: i64 = sqlx::query_scalar(
+            "UPDATE two_fa_codes SET failed_attempts = failed_attempts + 1 WHERE user_id = $1 \
+             RETURNING failed_attempts",
+        )
+        .bind(user_id.as_ref())
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(|_| TwoFACodeStoreError::UnexpectedError)?
+        .ok_or(TwoFACodeStoreError::LoginAttemptIdNotFound)?;
+
+        // Only removes the attempt that was counted, not one started since
+        if failed_attempts >= i64::from(max_attempts) {
+            sqlx::query("DELETE FROM two_fa_codes WHERE user_id = $1 AND failed_attempts >= $2")
+                .bind(user_id.as_ref())
+                .bind(i64::from(max_attempts))
+                .execute(&self.pool)
+                .await
+                .map_err(|_| TwoFACodeStoreError::UnexpectedError)?;
+        }
+
+        failed_attempts
+            .try_into()
+            .map_err(|_| TwoFACodeStoreError::UnexpectedError)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{get_sqlite_pool, SQLITE_MIGRATOR};
+
+    async fn store_with_ttl(ttl_seconds: u64) -> SqliteTwoFACodeStore {
+        let pool = get_sqlite_pool("sqlite::memory:").await.unwrap();
+        SQLITE_MIGRATOR.run(&pool).await.unwrap();
+        SqliteTwoFACodeStore::new(pool, ttl_seconds)
+    }
+
+    async fn store() -> SqliteTwoFACodeStore {
+        store_with_ttl(600).await
+    }
+
+    #[tokio::test]
+    async fn test_add_and_get_code() {
+        let mut store = store().await;
+        let user_id = UserId::default();
+        let login_attempt_id = LoginAttemptId::default();
+        let code = TwoFACode::parse("123456".to_string()).unwrap();
+
+        let result = store
+            .add_code(user_id.clone(), login_attempt_id.clone(), code.clone())
+            .await;
+        assert!(result.is_ok());
+
+        let retrieved = store.get_code(&user_id).await;
+        assert_eq!(retrieved, Ok((login_attempt_id, code)));
+    }
+
+    #[tokio::test]
+    async fn test_get_code_not_found() {
+        let store = store().await;
+        let user_id = UserId::default();
+
+        let result = store.get_code(&user_id).await;
+        assert_eq!(result, Err(TwoFACodeStoreError::LoginAttemptIdNotFound));
+    }
+
+    #[tokio::test]
+    async fn test_remove_code() {
+        let mut store = store().await;
+        let user_id = UserId::default();
+        let code = TwoFACode::parse("123456".to_string()).unwrap();
+
+        store
+            .add_code(user_id.clone(), LoginAttemptId::default(), code)
+            .await
+            .unwrap();
+
+        let result = store.remove_code(&user_id).await;
+        assert!(result.is_ok());
+
+        let get_result = store.get_code(&user_id).await;
+        assert_eq!(get_result, Err(TwoFACodeStoreError::LoginAttemptIdNotFound));
+    }
+
+    #[tokio::test]
+    async fn test_overwrite_existing_code() {
+        let mut store = store().await;
+        let user_id = UserId::default();
+        let login_attempt_id2 = LoginAttemptId::default();
+        let code2 = TwoFACode::parse("654321".to_string()).unwrap();
+
+        store
+            .add_code(user_id.clone(), LoginAttemptId::default(), TwoFACode::parse("123456".to_string()).unwrap())
+            .await
+            .unwrap();
+        store
+            .add_code(user_id.clone(), login_attempt_id2.clone(), code2.clone())
+            .await
+            .unwrap();
+
+        let retrieved = store.get_code(&user_id).await;
+        assert_eq!(retrieved, Ok((login_attempt_id2, code2)));
+    }
+
+    #[tokio::test]
+    async fn test_get_expired_code() {
+        let mut store = store_with_ttl(0).await;
+        let user_id = UserId::default();
+        let code = TwoFACode::parse("123456".to_string()).unwrap();
+
+        store
+            .add_code(user_id.clone(), LoginAttemptId::default(), code)
+            .await
+            .unwrap();
+
+        let result = store.get_code(&user_id).await;
+        assert_eq!(result, Err(TwoFACodeStoreError::CodeExpired));
+    }
+
+    #[tokio::test]
+    async fn test_record_failed_attempt() {
+        let mut store = store().await;
+        let user_id = UserId::default();
+        let code = TwoFACode::parse("123456".to_string()).unwrap();
+
+        let result = store.record_failed_attempt(&user_id, 3).await;
+        assert_eq!(result, Err(TwoFACodeStoreError::LoginAttemptIdNotFound));
+
+        store
+            .add_code(user_id.clone(), LoginAttemptId::default(), code.clone())
+            .await
+            .unwrap();
+        assert_eq!(store.record_failed_attempt(&user_id, 3).await, Ok(1));
+        assert_eq!(store.record_failed_attempt(&user_id, 3).await, Ok(2));
+
+        store
+            .add_code(user_id.clone(), LoginAttemptId::default(), code)
+            .await
+            .unwrap();
+        assert_eq!(store.record_failed_attempt(&user_id, 3).await, Ok(1));
+        assert_eq!(store.record_failed_attempt(&user_id, 3).await, Ok(2));
+
+        // The last allowed failure removes the login attempt
+        assert_eq!(store.record_failed_attempt(&user_id, 3).await, Ok(3));
+        let result = store.get_code(&user_id).await;
+        assert_eq!(result, Err(TwoFACodeStoreError::LoginAttemptIdNotFound));
+    }
+
+    #[tokio::test]
+    async fn test_take_code() {
+        let mut store = store().await;
+        let user_id = UserId::default();
+        let login_attempt_id = LoginAttemptId::default();
+        let code = TwoFACode::parse("123456".to_string()).unwrap();
+        let wrong_code = TwoFACode::parse("654321".to_string()).unwrap();
+
+        store
+            .add_code(user_id.clone(), login_attempt_id.clone(), code.clone())
+            .await
+            .unwrap();
+
+        let result = store.take_code(&user_id, &login_attempt_id, Some(&wrong_code), 5).await;
+        assert_eq!(result, Err(TwoFACodeStoreError::IncorrectCode(1)));
+        let result = store.take_code(&user_id, &LoginAttemptId::default(), Some(&code), 5).await;
+        assert_eq!(result, Err(TwoFACodeStoreError::IncorrectCode(2)));
+
+        assert_eq!(store.take_code(&user_id, &login_attempt_id, Some(&code), 5).await, Ok(()));
+        let result = store.take_code(&user_id, &login_attempt_id, Some(&code), 5).await;
+        assert_eq!(result, Err(TwoFACodeStoreError::LoginAttemptIdNotFound));
+
+        store
+            .add_code(user_id.clone(), login_attempt_id.clone(), code)
+            .await
+            .unwrap();
+        assert_eq!(store.take_code(&user_id, &login_attempt_id, None, 5).await, Ok(()));
+    }
+
+    #[tokio::test]
+    async fn test_take_expired_code() {
+        let mut store = store_with_ttl(0).await;
+        let user_id = UserId::default();
+        let login_attempt_id = LoginAttemptId::default();
+        let code = TwoFACode::parse("123456".to_string()).unwrap();
+
+        store
+            .add_code(user_id.clone(), login_attempt_id.clone(), code.clone())
+            .await
+            .unwrap();
+
+        let result = store.take_code(&user_id, &login_attempt_id, Some(&code), 5).await;
+        assert_eq!(result, Err(TwoFACodeStoreError::CodeExpired));
+        let result = store.get_code(&user_id).await;
+        assert_eq!(result, Err(TwoFACodeStoreError::LoginAttemptIdNotFound));
+    }
+
+    #[tokio::test]
+    async fn test_record_failed_attempt_for_expired_code() {
+        let mut store = store_with_ttl(0).await;
+        let user_id = UserId::default();
+        let code = TwoFACode::parse("123456".to_string()).unwrap();
+
+        store
+            .add_code(user_id.clone(), LoginAttemptId::default(), code)
+            .await
+            .unwrap();
+
+        let result = store.record_failed_attempt(&user_id, 5).await;
+        assert_eq!(result, Err(TwoFACodeStoreError::CodeExpired));
+        let result = store.get_code(&user_id).await;
+        assert_eq!(result, Err(TwoFACodeStoreError::LoginAttemptIdNotFound));
+    }
+}
@@ -0,0 +1,643 @@
+use sqlx::{Row, SqlitePool};
+
+use crate::domain::{
+    Email, Password, PasswordHash, PasswordHashError, RecoveryCodeHash, TotpSecret, TwoFAMethod,
+    User, UserId, UserStore, UserStoreError,
+};
+
+pub struct SqliteUserStore {
+    pool: SqlitePool,
+}
+
+impl SqliteUserStore {
+    pub fn new(pool: SqlitePool) -> Self {
+        Self { pool }
+    }
+
+    // Load the user whose `key` column, `id` or `email`, is `value`
+    async fn find_user(&self, key: &str, value: &str) -> Result<User, UserStoreError> {
+        let query = format!(
+            "SELECT id, email, password_hash, requires_2fa, two_fa_method, totp_secret, email_verified, \
+             deletion_due_at FROM users WHERE {} = $1",
+            key
+        );
+        let row = sqlx::query(&query)
+            .bind(value)
+            .fetch_optional(&self.pool)
+            .await
+            .map_err(|_| UserStoreError::UnexpectedError)?
+            .ok_or(UserStoreError::UserNotFound)?;
+
+        let id = UserId::parse(row.get("id")).map_err(|_| UserStoreError::UnexpectedError)?;
+        let email = Email::parse(row.get("email")).map_err(|_| UserStoreError::UnexpectedError)?;
+        let password_hash = PasswordHash::parse(row.get("password_hash"))
+            .map_err(|_| UserStoreError::UnexpectedError)?;
+
+        let two_fa_method = TwoFAMethod::parse(row.get("two_fa_method"))
+            .map_err(|_| UserStoreError::UnexpectedError)?;
+        let totp_secret = row
+            .get::<Option<String>, _>("totp_secret")
+            .map(TotpSecret::parse)
+            .transpose()
+            .map_err(|_| UserStoreError::UnexpectedError)?;
+
+        let mut user = User::new(email, password_hash, row.get("requires_2fa"));
+        user.id = id;
+        user.set_two_fa_method(two_fa_method);
+        user.set_totp_secret(totp_secret);
+        user.set_email_verified(row.get("email_verified"));
+        user.set_deletion_due_at(row.get("deletion_due_at"));
+        Ok(user)
+    }
+}
+
+#[async_trait::async_trait]
+impl UserStore for SqliteUserStore {
+    async fn add_user(&mut self, user: User) -> Result<(), UserStoreError> {
+        sqlx::query(
+            "INSERT INTO users (id, email, password_hash, requires_2fa, two_fa_method, totp_secret, email_verified, \
+             deletion_due_at) VALUES ($1, $2, $3, $4, $5, $6, $7, $8)",
+        )
+        .bind(user.id.as_ref())
+        .bind(user.email.as_ref())
+        .bind(user.password_hash().as_ref())
+        .bind(user.requires_2fa())
+        .bind(user.two_fa_method().as_ref())
+        .bind(user.totp_secret().map(|secret| secret.as_ref()))
+        .bind(user.email_verified())
+        .bind(user.deletion_due_at())
+        .execute(&self.pool)
+        .await
+        .map_err(|e| match e {
+            sqlx::Error::Database(e) if e.is_unique_violation() => {
+                UserStoreError::UserAlreadyExists
+            }
+            _ => UserStoreError::UnexpectedError,
+        })?;
+
+        Ok(())
+    }
+
+    async fn get_user(&self, id: &UserId) -> Result<User, UserStoreError> {
+        self.find_user("id", id.as_ref()).await
+    }
+
+    async fn get_user_by_email(&self, email: &Email) -> Result<User, UserStoreError> {
+        self.find_user("email", email.as_ref()).await
+    }
+
+    async fn validate_user(&self, id: &UserId, password: &Password) -> Result<(), UserStoreError> {
+        let user = self.get_user(id).await?;
+
+        match user.password_hash().verify(password).await {
+            Ok(()) => Ok(()),
+            Err(PasswordHashError::PasswordMismatch) => Err(UserStoreError::InvalidCredentials),
+            Err(_) => Err(UserStoreError::UnexpectedError),
+        }
+    }
+
+    async fn update_password_hash(&mut self, id: &UserId, password_hash: PasswordHash) -> Result<(), UserStoreError> {
+        let result = sqlx::query("UPDATE users SET password_hash = $1 WHERE id = $2")
+            .bind(password_hash.as_ref())
+            .bind(id.as_ref())
+            .execute(&self.pool)
+            .await
+            .map_err(|_| UserStoreError::UnexpectedError)?;
+
+        if result.rows_affected() == 0 {
+            return Err(UserStoreError::UserNotFound);
+        }
+
+        Ok(())
+    }
+
+    async fn replace_password_hash(
+        &mut self,
+        id: &UserId,
+        current_password_hash: &PasswordHash,
+        new_password_hash: PasswordHash,
+    ) -> Result<(), UserStoreError> {
+        let result = sqlx::query("UPDATE users SET password_hash = $1 WHERE id = $2 AND password_hash = $3")
+            .bind(new_password_hash.as_ref())
+            .bind(id.as_ref())
+            .bind(current_password_hash.as_ref())
+            .execute(&self.pool)
+            .await
+            .map_err(|_| UserStoreError::UnexpectedError)?;
+
+        if result.rows_affected() == 0 {
+            // Tell a missing user apart from a hash that was replaced
+            self.get_user(id).await?;
+            return Err(UserStoreError::InvalidCredentials);
+        }
+
+        Ok(())
+    }
+
+    async fn set_totp_secret(&mut self, id: &UserId, totp_secret: TotpSecret) -> Result<(), UserStoreError> {
+        let result = sqlx::query("UPDATE users SET totp_secret = $1 WHERE id = $2")
+            .bind(totp_secret.as_ref())
+            .bind(id.as_ref())
+            .execute(&self.pool)
+            .await
+            .map_err(|_| UserStoreError::UnexpectedError)?;
+
+        if result.rows_affected() == 0 {
+            return Err(UserStoreError::UserNotFound);
+        }
+
+        Ok(())
+    }
+
+    async fn enable_totp(&mut self, id: &UserId) -> Result<(), UserStoreError> {
+        let result = sqlx::query("UPDATE users SET requires_2fa = TRUE, two_fa_method = $1 WHERE id = $2")
+            .bind(TwoFAMethod::Totp.as_ref())
+            .bind(id.as_ref())
+            .execute(&self.pool)
+            .await
+            .map_err(|_| UserStoreError::UnexpectedError)?;
+
+        if result.rows_affected() == 0 {
+            return Err(UserStoreError::UserNotFound);
+        }
+
+        Ok(())
+    }
+
+    async fn accept_totp_step(&mut self, id: &UserId, step: u64) -> Result<(), UserStoreError> {
+        let step = i64::try_from(step).map_err(|_| UserStoreError::UnexpectedError)?;
+
+        // Comparing and updating in one statement makes sure two concurrent
+        // requests cannot both use the same code
+        let result = sqlx::query(
+            "UPDATE users SET totp_last_step = $1 \
+             WHERE id = $2 AND (totp_last_step IS NULL OR totp_last_step < $1)",
+        )
+        .bind(step)
+        .bind(id.as_ref())
+        .execute(&self.pool)
+        .await
+        .map_err(|_| UserStoreError::UnexpectedError)?;
+
+        if result.rows_affected() == 0 {
+            // Tell a missing user apart from a code that was used before
+            self.get_user(id).await?;
+            return Err(UserStoreError::InvalidCredentials);
+        }
+
+        Ok(())
+    }
+
+    async fn set_recovery_codes(&mut self, id: &UserId, recovery_codes: Vec<RecoveryCodeHash>) -> Result<(), UserStoreError> {
+        let mut transaction = self.pool.begin().await.map_err(|_| UserStoreError::UnexpectedError)?;
+
+        sqlx::query("DELETE FROM recovery_codes WHERE user_id = $1")
+            .bind(id.as_ref())
+            .execute(&mut *transaction)
+            .await
+            .map_err(|_| UserStoreError::UnexpectedError)?;
+
+        for recovery_code in &recovery_codes {
+            sqlx::query("INSERT INTO recovery_codes (user_id, code_hash) VALUES ($1, $2)")
+                .bind(id.as_ref())
+                .bind(recovery_code.as_ref())
+                .execute(&mut *transaction)
+                .await
+                .map_err(map_recovery_code_error)?;
+        }
+
+        transaction.commit().await.map_err(|_| UserStoreError::UnexpectedError)
+    }
+
+    async fn consume_recovery_code(&mut self, id: &UserId, recovery_code: &RecoveryCodeHash) -> Result<(), UserStoreError> {
+        // Deleting the row both checks and consumes the code, so concurrent
+        // requests cannot use it twice
+        let result = sqlx::query("DELETE FROM recovery_codes WHERE user_id = $1 AND code_hash = $2")
+            .bind(id.as_ref())
+            .bind(recovery_code.as_ref())
+            .execute(&self.pool)
+            .await
+            .map_err(|_| UserStoreError::UnexpectedError)?;
+
+        if result.rows_affected() == 0 {
+            return Err(UserStoreError::InvalidCredentials);
+        }
+
+        Ok(())
+    }
+
+    async fn verify_email(&mut self, id: &UserId) -> Result<(), UserStoreError> {
+        let result = sqlx::query("UPDATE users SET email_verified = TRUE WHERE id = $1")
+            .bind(id.as_ref())
+            .execute(&self.pool)
+            .await
+            .map_err(|_| UserStoreError::UnexpectedError)?;
+
+        if result.rows_affected() == 0 {
+            return Err(UserStoreError::UserNotFound);
+        }
+
+        Ok(())
+    }
+
+    async fn change_email(&mut self, id: &UserId, new_email: Email) -> Result<(), UserStoreError> {
+        let result = sqlx::query("UPDATE users SET email = $1, email_verified = TRUE WHERE id = $2")
+            .bind(new_email.as_ref())
+            .bind(id.as_ref())
+            .execute(&self.pool)
+            .await
+            .map_err(|e| match e {
+                sqlx::Error::Database(e) if e.is_unique_violation() => UserStoreError::UserAlreadyExists,
+                _ => UserStoreError::UnexpectedError,
+            })?;
+
+        if result.rows_affected() == 0 {
+            return Err(UserStoreError::UserNotFound);
+        }
+
+        Ok(())
+    }
+
+    async fn schedule_deletion(&mut self, id: &UserId, due_at: i64) -> Result<(), UserStoreError> {
+        let result = sqlx::query("UPDATE users SET deletion_due_at = $1 WHERE id = $2")
+            .bind(due_at)
+            .bind(id.as_ref())
+            .execute(&self.pool)
+            .await
+            .map_err(|_| UserStoreError::UnexpectedError)?;
+
+        if result.rows_affected() == 0 {
+            return Err(UserStoreError::UserNotFound);
+        }
+
+        Ok(())
+    }
+
+    async fn cancel_deletion(&mut self, id: &UserId) -> Result<(), UserStoreError> {
+        let result = sqlx::query("UPDATE users SET deletion_due_at = NULL WHERE id = $1")
+            .bind(id.as_ref())
+            .execute(&self.pool)
+            .await
+            .map_err(|_| UserStoreError::UnexpectedError)?;
+
+        if result.rows_affected() == 0 {
+            return Err(UserStoreError::UserNotFound);
+        }
+
+        Ok(())
+    }
+
+    async fn users_due_for_deletion(&self, now: i64) -> Result<Vec<UserId>, UserStoreError> {
+        let rows = sqlx::query("SELECT id FROM users WHERE deletion_due_at <= $1")
+            .bind(now)
+            .fetch_all(&self.pool)
+            .await
+            .map_err(|_| UserStoreError::UnexpectedError)?;
+
+        rows.into_iter()
+            .map(|row| UserId::parse(row.get("id")).map_err(|_| UserStoreError::UnexpectedError))
+            .collect()
+    }
+
+    async fn delete_user(&mut self, id: &UserId) -> Result<(), UserStoreError> {
+        // Recovery codes go with the user through the foreign key
+        let result = sqlx::query("DELETE FROM users WHERE id = $1")
+            .bind(id.as_ref())
+            .execute(&self.pool)
+            .await
+            .map_err(|_| UserStoreError::UnexpectedError)?;
+
+        if result.rows_affected() == 0 {
+            return Err(UserStoreError::UserNotFound);
+        }
+
+        Ok(())
+    }
+}
+
+// Recovery codes reference their user, so inserting them for an unknown user
+// violates the foreign key
+fn map_recovery_code_error(e: sqlx::Error) -> UserStoreError {
+    match e {
+        sqlx::Error::Database(e) if e.is_foreign_key_violation() => UserStoreError::UserNotFound,
+        _ => UserStoreError::UnexpectedError,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::RecoveryCode;
+    use crate::{get_sqlite_pool, SQLITE_MIGRATOR};
+
+    async fn store() -> SqliteUserStore {
+        let pool = get_sqlite_pool("sqlite::memory:").await.unwrap();
+        SQLITE_MIGRATOR.run(&pool).await.unwrap();
+        SqliteUserStore::new(pool)
+    }
+
+    async fn user(email: &Email, password: &Password) -> User {
+        let password_hash = PasswordHash::from_password(password.clone()).await.unwrap();
+        User::new(email.clone(), password_hash, false)
+    }
+
+    // Add a new user and return their ID
+    async fn add_user(store: &mut SqliteUserStore, email: &Email, password: &Password) -> UserId {
+        let user = user(email, password).await;
+        let id = user.id.clone();
+        store.add_user(user).await.unwrap();
+        id
+    }
+
+    #[tokio::test]
+    async fn test_add_user() {
+        let mut store = store().await;
+        let email = Email::parse("test@example.com".to_string()).unwrap();
+        let password = Password::parse("Password123!".to_string()).unwrap();
+        let other_user = user(&email, &password).await;
+        let user = user(&email, &password).await;
+
+        let result = store.add_user(user.clone()).await;
+        assert!(result.is_ok());
+
+        // Test adding duplicate user
+        let duplicate_result = store.add_user(user).await;
+        assert_eq!(duplicate_result, Err(UserStoreError::UserAlreadyExists));
+
+        // Test adding another user with the same email
+        let result = store.add_user(other_user).await;
+        assert_eq!(result, Err(UserStoreError::UserAlreadyExists));
+    }
+
+    #[tokio::test]
+    async fn test_get_user() {
+        let mut store = store().await;
+        let email = Email::parse("test@example.com".to_string()).unwrap();
+        let password = Password::parse("Password123!".to_string()).unwrap();
+        let user = user(&email, &password).await;
+
+        let nonexistent_email = Email::parse("nonexistent@example.com".to_string()).unwrap();
+        assert_eq!(store.get_user(&UserId::default()).await, Err(UserStoreError::UserNotFound));
+        assert_eq!(store.get_user_by_email(&nonexistent_email).await, Err(UserStoreError::UserNotFound));
+
+        store.add_user(user.clone()).await.unwrap();
+        assert_eq!(store.get_user(&user.id).await, Ok(user.clone()));
+        assert_eq!(store.get_user_by_email(&email).await, Ok(user));
+    }
+
+    #[tokio::test]
+    async fn test_validate_user() {
+        let mut store = store().await;
+        let email = Email::parse("test@example.com".to_string()).unwrap();
+        let password = Password::parse("Password123!".to_string()).unwrap();
+        let id = add_user(&mut store, &email, &password).await;
+
+        let result = store.validate_user(&id, &password).await;
+        assert!(result.is_ok());
+
+        let wrong_password = Password::parse("WrongPassword123!".to_string()).unwrap();
+        let result = store.validate_user(&id, &wrong_password).await;
+        assert_eq!(result, Err(UserStoreError::InvalidCredentials));
+
+        let result = store.validate_user(&UserId::default(), &password).await;
+        assert_eq!(result, Err(UserStoreError::UserNotFound));
+    }
+
+    #[tokio::test]
+    async fn test_update_password_hash() {
+        let mut store = store().await;
+        let email = Email::parse("test@example.com".to_string()).unwrap();
+        let password = Password::parse("Password123!".to_string()).unwrap();
+        let id = add_user(&mut store, &email, &password).await;
+
+        let new_password = Password::parse("NewPassword123!".to_string()).unwrap();
+        let new_hash = PasswordHash::from_password(new_password.clone()).await.unwrap();
+        let result = store.update_password_hash(&id, new_hash).await;
+        assert!(result.is_ok());
+        assert!(store.validate_user(&id, &new_password).await.is_ok());
+
+        let new_hash = PasswordHash::from_password(new_password).await.unwrap();
+        let result = store.update_password_hash(&UserId::default(), new_hash).await;
+        assert_eq!(result, Err(UserStoreError::UserNotFound));
+    }
+
+    #[tokio::test]
+    async fn test_replace_password_hash() {
+        let mut store = store().await;
+        let email = Email::parse("test@example.com".to_string()).unwrap();
+        let password = Password::parse("Password123!".to_string()).unwrap();
+        let id = add_user(&mut store, &email, &password).await;
+        let old_hash = store.get_user(&id).await.unwrap().password_hash().clone();
+
+        let new_password = Password::parse("NewPassword123!".to_string()).unwrap();
+        let new_hash = PasswordHash::from_password(new_password.clone()).await.unwrap();
+        assert!(store.replace_password_hash(&id, &old_hash, new_hash.clone()).await.is_ok());
+        assert!(store.validate_user(&id, &new_password).await.is_ok());
+
+        // The hash was replaced in the meantime
+        let result = store.replace_password_hash(&id, &old_hash, old_hash.clone()).await;
+        assert_eq!(result, Err(UserStoreError::InvalidCredentials));
+        assert!(store.validate_user(&id, &new_password).await.is_ok());
+
+        let result = store.replace_password_hash(&UserId::default(), &new_hash, old_hash).await;
+        assert_eq!(result, Err(UserStoreError::UserNotFound));
+    }
+
+    #[tokio::test]
+    async fn test_enable_totp() {
+        let mut store = store().await;
+        let email = Email::parse("test@example.com".to_string()).unwrap();
+        let password = Password::parse("Password123!".to_string()).unwrap();
+        let id = add_user(&mut store, &email, &password).await;
+
+        let secret = TotpSecret::generate();
+        store.set_totp_secret(&id, secret.clone()).await.unwrap();
+        let user = store.get_user(&id).await.unwrap();
+        assert_eq!(user.totp_secret(), Some(&secret));
+        assert_eq!(user.two_fa_method(), TwoFAMethod::Email);
+        assert!(!user.requires_2fa());
+
+        store.enable_totp(&id).await.unwrap();
+        let user = store.get_user(&id).await.unwrap();
+        assert_eq!(user.two_fa_method(), TwoFAMethod::Totp);
+        assert!(user.requires_2fa());
+
+        let nonexistent_id = UserId::default();
+        let result = store.set_totp_secret(&nonexistent_id, secret).await;
+        assert_eq!(result, Err(UserStoreError::UserNotFound));
+        let result = store.enable_totp(&nonexistent_id).await;
+        assert_eq!(result, Err(UserStoreError::UserNotFound));
+    }
+
+    #[tokio::test]
+    async fn test_accept_totp_step() {
+        let mut store = store().await;
+        let email = Email::parse("test@example.com".to_string()).unwrap();
+        let password = Password::parse("Password123!".to_string()).unwrap();
+        let id = add_user(&mut store, &email, &password).await;
+
+        assert!(store.accept_totp_step(&id, 100).await.is_ok());
+        // Codes of the same or an earlier step cannot be used anymore
+        assert_eq!(store.accept_totp_step(&id, 100).await, Err(UserStoreError::InvalidCredentials));
+        assert_eq!(store.accept_totp_step(&id, 99).await, Err(UserStoreError::InvalidCredentials));
+        assert!(store.accept_totp_step(&id, 101).await.is_ok());
+
+        let result = store.accept_totp_step(&UserId::default(), 102).await;
+        assert_eq!(result, Err(UserStoreError::UserNotFound));
+    }
+
+    #[tokio::test]
+    async fn test_recovery_codes() {
+        let mut store = store().await;
+        let email = Email::parse("test@example.com".to_string()).unwrap();
+        let password = Password::parse("Password123!".to_string()).unwrap();
+        let id = add_user(&mut store, &email, &password).await;
+
+        let first_set: Vec<_> = RecoveryCode::generate_set().iter().map(RecoveryCode::hash).collect();
+        store.set_recovery_codes(&id, first_set.clone()).await.unwrap();
+
+        assert!(store.consume_recovery_code(&id, &first_set[0]).await.is_ok());
+        let result = store.consume_recovery_code(&id, &first_set[0]).await;
+        assert_eq!(result, Err(UserStoreError::InvalidCredentials));
+
+        let second_set: Vec<_> = RecoveryCode::generate_set().iter().map(RecoveryCode::hash).collect();
+        store.set_recovery_codes(&id, second_set.clone()).await.unwrap();
+        let result = store.consume_recovery_code(&id, &first_set[1]).await;
+        assert_eq!(result, Err(UserStoreError::InvalidCredentials));
+        assert!(store.consume_recovery_code(&id, &second_set[1]).await.is_ok());
+
+        let result = store.set_recovery_codes(&UserId::default(), second_set).await;
+        assert_eq!(result, Err(UserStoreError::UserNotFound));
+    }
+
+    #[tokio::test]
+    async fn test_verify_email() {
+        let mut store = store().await;
+        let email = Email::parse("test@example.com".to_string()).unwrap();
+        let password = Password::parse("Password123!".to_string()).unwrap();
+        let id = add_user(&mut store, &email, &password).await;
+        assert!(!store.get_user(&id).await.unwrap().email_verified());
+
+        store.verify_email(&id).await.unwrap();
+        assert!(store.get_user(&id).await.unwrap().email_verified());
+
+        let result = store.verify_email(&UserId::default()).await;
+        assert_eq!(result, Err(UserStoreError::UserNotFound));
+    }
+
+    #[tokio::test]
+    async fn test_change_email() {
+        let mut store = store().await;
+        let email = Email::parse("test@example.com".to_string()).unwrap();
+        let new_email = Email::parse("new@example.com".to_string()).unwrap();
+        let taken_email = Email::parse("taken@example.com".to_string()).unwrap();
+        let password = Password::parse("Password123!".to_string()).unwrap();
+        let id = add_user(&mut store, &email, &password).await;
+        add_user(&mut store, &taken_email, &password).await;
+        let recovery_codes: Vec<_> = RecoveryCode::generate_set().iter().map(RecoveryCode::hash).collect();
+        store.set_recovery_codes(&id, recovery_codes.clone()).await.unwrap();
+
+        let result = store.change_email(&id, taken_email).await;
+        assert_eq!(result, Err(UserStoreError::UserAlreadyExists));
+        assert_eq!(store.get_user(&id).await.unwrap().email, email);
+
+        store.change_email(&id, new_email.clone()).await.unwrap();
+        assert_eq!(store.get_user_by_email(&email).await, Err(UserStoreError::UserNotFound));
+        let user = store.get_user_by_email(&new_email).await.unwrap();
+        assert_eq!(user.id, id);
+        assert!(user.email_verified());
+        assert!(store.validate_user(&id, &password).await.is_ok());
+        assert!(store.consume_recovery_code(&id, &recovery_codes[0]).await.is_ok());
+
+        let other_email = Email::parse("other@example.com".to_string()).unwrap();
+        let result = store.change_email(&UserId::default(), other_email).await;
+        assert_eq!(result, Err(UserStoreError::UserNotFound));
+    }
+
+    #[tokio::test]
+    async fn test_deletion() {
+        let mut store = store().await;
+        let email = Email::parse("test@example.com".to_string()).unwrap();
+        let password = Password::parse("Password123!".to_string()).unwrap();
+        let id = add_user(&mut store, &email, &password).await;
+        let recovery_codes: Vec<_> = RecoveryCode::generate_set().iter().map(RecoveryCode::hash).collect();
+        store.set_recovery_codes(&id, recovery_codes).await.unwrap();
+
+        store.schedule_deletion(&id, 1000).await.unwrap();
+        assert_eq!(store.get_user(&id).await.unwrap().deletion_due_at(), Some(1000));
+        assert!(store.users_due_for_deletion(999).await.unwrap().is_empty());
+        assert_eq!(store.users_due_for_deletion(1000).await.unwrap(), vec![id.clone()]);
+
+        store.cancel_deletion(&id).await.unwrap();
+        assert_eq!(store.get_user(&id).await.unwrap().deletion_due_at(), None);
+        assert!(store.users_due_for_deletion(1000).await.unwrap().is_empty());
+
+        store.delete_user(&id).await.unwrap();
+        assert_eq!(store.get_user(&id).await, Err(UserStoreError::UserNotFound));
+        assert_eq!(store.delete_user(&id).await, Err(UserStoreError::UserNotFound));
+
+        let recovery_codes: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM recovery_codes WHERE user_id = $1")
+            .bind(id.as_ref())
+            .fetch_one(&store.pool)
+            .await
+            .unwrap();
+        assert_eq!(recovery_codes, 0);
+
+        let result = store.schedule_deletion(&id, 1000).await;
+        assert_eq!(result, Err(UserStoreError::UserNotFound));
+        let result = store.cancel_deletion(&id).await;
+        assert_eq!(result, Err(UserStoreError::UserNotFound));
+    }
+
+    // Users from before user IDs get one, and keep their recovery codes and sessions
+    #[tokio::test]
+    async fn test_migrate_to_user_ids() {
+        let pool = get_sqlite_pool("sqlite::memory:").await.unwrap();
+        let mut migrator = sqlx::migrate::Migrator::new(std::path::Path::new("./migrations/sqlite")).await.unwrap();
+        migrator.migrations = std::borrow::Cow::Owned(
+            SQLITE_MIGRATOR
+                .iter()
+                .filter(|migration| migration.version < 20261018000008)
+                .cloned()
+                .collect(),
+        );
+        migrator.run(&pool).await.unwrap();
+
+        let email = Email::parse("test@example.com".to_string()).unwrap();
+        let password = Password::parse("Password123!".to_string()).unwrap();
+        let password_hash = PasswordHash::from_password(password.clone()).await.unwrap();
+        sqlx::query("INSERT INTO users (email, password_hash, requires_2fa) VALUES ($1, $2, TRUE)")
+            .bind(email.as_ref())
+            .bind(password_hash.as_ref())
+            .execute(&pool)
+            .await
+            .unwrap();
+        let recovery_code = RecoveryCode::generate_set()[0].hash();
+        sqlx::query("INSERT INTO recovery_codes (email, code_hash) VALUES ($1, $2)")
+            .bind(email.as_ref())
+            .bind(recovery_code.as_ref())
+            .execute(&pool)
+            .await
+            .unwrap();
+        sqlx::query("INSERT INTO refresh_tokens (token_hash, email, family_id, expires_at) VALUES ('hash', $1, 'family', 0)")
+            .bind(email.as_ref())
+            .execute(&pool)
+            .await
+            .unwrap();
+
+        SQLITE_MIGRATOR.run(&pool).await.unwrap();
+        let mut store = SqliteUserStore::new(pool.clone());
+
+        let user = store.get_user_by_email(&email).await.unwrap();
+        assert!(user.requires_2fa());
+        assert_eq!(store.get_user(&user.id).await, Ok(user.clone()));
+        assert!(store.validate_user(&user.id, &password).await.is_ok());
+        assert!(store.consume_recovery_code(&user.id, &recovery_code).await.is_ok());
+
+        let user_id: String = sqlx::query_scalar("SELECT user_id FROM refresh_tokens WHERE token_hash = 'hash'")
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+        assert_eq!(user_id, user.id.as_ref());
+    }
+}
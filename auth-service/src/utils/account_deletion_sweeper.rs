@@ -0,0 +1,155 @@
+use chrono::Utc;
+use std::time::Duration;
+use tokio::task::JoinHandle;
+
+use crate::{
+    app_state::AppState,
+    domain::{AuthAPIError, UserStoreError},
+};
+
+use super::auth::revoke_all_sessions;
+
+// Periodically purge the accounts whose deletion grace period has passed
+pub fn spawn_account_deletion_sweeper(state: AppState, interval: Duration) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(interval);
+        loop {
+            ticker.tick().await;
+            if let Err(e) = purge_deleted_accounts(&state).await {
+                println!("Failed to purge deleted accounts: {:?}", e);
+            }
+        }
+    })
+}
+
+// Remove every user whose deletion is due, together with their pending 2FA
+// attempt and their sessions, returning how many were purged
+pub async fn purge_deleted_accounts(state: &AppState) -> Result<usize, AuthAPIError> {
+    let now = Utc::now().timestamp();
+    let due = state
+        .user_store
+        .read()
+        .await
+        .users_due_for_deletion(now)
+        .await
+        .map_err(|_| AuthAPIError::UnexpectedError)?;
+
+    let mut purged = 0;
+    for id in due {
+        // Holding the lock until the user is gone keeps a login from restoring
+        // the account halfway through. The user is removed last, so that
+        // whatever fails before is retried on the next run.
+        let mut user_store = state.user_store.write().await;
+        match user_store.get_user(&id).await {
+            Ok(user) if user.deletion_due_at().is_some_and(|due_at| due_at <= now) => {}
+            Ok(_) | Err(UserStoreError::UserNotFound) => continue,
+            Err(_) => return Err(AuthAPIError::UnexpectedError),
+        }
+
+        state
+            .two_fa_code_store
+            .write()
+            .await
+            .remove_code(&id)
+            .await
+            .map_err(|_| AuthAPIError::UnexpectedError)?;
+        revoke_all_sessions(&id, None, state).await?;
+
+        user_store
+            .delete_user(&id)
+            .await
+            .map_err(|_| AuthAPIError::UnexpectedError)?;
+        purged += 1;
+    }
+
+    Ok(purged)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        domain::{
+            data_stores::{LoginAttemptId, TwoFACode, TwoFACodeStoreError},
+            Email, Password, PasswordHash, RefreshSession, RefreshToken, RefreshTokenFamilyId,
+            RefreshTokenStoreError, User,
+        },
+        services::{
+            HashmapFailedLoginStore, HashmapRefreshTokenStore, HashmapTwoFACodeStore, HashmapUserStore,
+            HashsetBannedTokenStore, MockEmailClient,
+        },
+    };
+    use std::sync::Arc;
+    use tokio::sync::RwLock;
+
+    async fn add_user(state: &AppState, email: &str) -> User {
+        let password = Password::parse("Password123!".to_owned()).unwrap();
+        let user = User::new(
+            Email::parse(email.to_owned()).unwrap(),
+            PasswordHash::from_password(password).await.unwrap(),
+            true,
+        );
+        state.user_store.write().await.add_user(user.clone()).await.unwrap();
+
+        let mut two_fa_code_store = state.two_fa_code_store.write().await;
+        two_fa_code_store
+            .add_code(user.id.clone(), LoginAttemptId::default(), TwoFACode::default())
+            .await
+            .unwrap();
+        user
+    }
+
+    async fn add_session(state: &AppState, user: &User) -> RefreshToken {
+        let token = RefreshToken::generate();
+        let session = RefreshSession {
+            user_id: user.id.clone(),
+            family_id: RefreshTokenFamilyId::default(),
+            expires_at: Utc::now().timestamp() + 60,
+        };
+        state.refresh_token_store.write().await.add_token(token.hash(), session).await.unwrap();
+        token
+    }
+
+    #[tokio::test]
+    async fn test_purge_deleted_accounts() {
+        let state = AppState::new(
+            Arc::new(RwLock::new(HashmapUserStore::default())),
+            Arc::new(RwLock::new(HashsetBannedTokenStore::default())),
+            Arc::new(RwLock::new(HashmapTwoFACodeStore::default())),
+            Arc::new(RwLock::new(HashmapRefreshTokenStore::default())),
+            Arc::new(RwLock::new(HashmapFailedLoginStore::default())),
+            Arc::new(RwLock::new(MockEmailClient)),
+        );
+        let now = Utc::now().timestamp();
+
+        let due = add_user(&state, "due@example.com").await;
+        let due_session = add_session(&state, &due).await;
+        let pending = add_user(&state, "pending@example.com").await;
+        let pending_session = add_session(&state, &pending).await;
+        {
+            let mut user_store = state.user_store.write().await;
+            user_store.schedule_deletion(&due.id, now - 1).await.unwrap();
+            user_store.schedule_deletion(&pending.id, now + 600).await.unwrap();
+        }
+
+        assert_eq!(purge_deleted_accounts(&state).await.unwrap(), 1);
+
+        let user_store = state.user_store.read().await;
+        assert_eq!(user_store.get_user(&due.id).await, Err(UserStoreError::UserNotFound));
+        assert!(user_store.get_user(&pending.id).await.is_ok());
+
+        let two_fa_code_store = state.two_fa_code_store.read().await;
+        assert_eq!(
+            two_fa_code_store.get_code(&due.id).await,
+            Err(TwoFACodeStoreError::LoginAttemptIdNotFound)
+        );
+        assert!(two_fa_code_store.get_code(&pending.id).await.is_ok());
+
+        let mut refresh_token_store = state.refresh_token_store.write().await;
+        assert_eq!(
+            refresh_token_store.use_token(&due_session.hash()).await,
+            Err(RefreshTokenStoreError::TokenNotFound)
+        );
+        assert!(refresh_token_store.use_token(&pending_session.hash()).await.is_ok());
+    }
+}
@@ -0,0 +1,57 @@
+use std::{sync::atomic::Ordering, time::Duration};
+use tokio::task::JoinHandle;
+
+use crate::{app_state::BannedTokenStoreType, domain::BannedTokenStoreError};
+
+use super::metrics::BANNED_TOKEN_STORE_SIZE;
+
+// Periodically drop banned tokens that have expired, so long-running instances
+// don't keep every token that was ever banned
+pub fn spawn_banned_token_sweeper(store: BannedTokenStoreType, interval: Duration) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(interval);
+        loop {
+            ticker.tick().await;
+            if let Err(e) = sweep_banned_tokens(&store).await {
+                println!("Failed to prune banned tokens: {:?}", e);
+            }
+        }
+    })
+}
+
+// Prune expired tokens once and record the remaining store size
+pub async fn sweep_banned_tokens(store: &BannedTokenStoreType) -> Result<usize, BannedTokenStoreError> {
+    store.write().await.prune_expired().await?;
+
+    let size = store.read().await.size().await?;
+    BANNED_TOKEN_STORE_SIZE.store(size, Ordering::Relaxed);
+
+    Ok(size)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::services::HashsetBannedTokenStore;
+    use chrono::Utc;
+    use std::sync::Arc;
+    use tokio::sync::RwLock;
+
+    #[tokio::test]
+    async fn test_sweep_banned_tokens() {
+        let store: BannedTokenStoreType = Arc::new(RwLock::new(HashsetBannedTokenStore::default()));
+        let now = Utc::now().timestamp() as usize;
+        {
+            let mut store = store.write().await;
+            store.store_tokens("expired.jwt.token".to_string(), now - 1).await.unwrap();
+            store.store_tokens("valid.jwt.token".to_string(), now + 600).await.unwrap();
+        }
+
+        let result = sweep_banned_tokens(&store).await;
+        assert_eq!(result, Ok(1));
+
+        let store = store.read().await;
+        assert!(!store.is_token_exists("expired.jwt.token").await.unwrap());
+        assert!(store.is_token_exists("valid.jwt.token").await.unwrap());
+    }
+}
@@ -0,0 +1,78 @@
+use axum::http::{header, HeaderMap};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use sha2::{Digest, Sha256};
+
+use crate::domain::AuthAPIError;
+
+use super::constants::OAUTH_CLIENTS;
+
+// ID of the OAuth client which authenticated with HTTP Basic credentials
+// (`client_secret_basic`), for endpoints called by other services rather
+// than by users
+pub fn authenticate_client(headers: &HeaderMap) -> Result<String, AuthAPIError> {
+    let (client_id, client_secret) = basic_credentials(headers).ok_or(AuthAPIError::InvalidClient)?;
+
+    if is_registered_client(&OAUTH_CLIENTS, &client_id, &client_secret) {
+        Ok(client_id)
+    } else {
+        Err(AuthAPIError::InvalidClient)
+    }
+}
+
+fn basic_credentials(headers: &HeaderMap) -> Option<(String, String)> {
+    let value = headers.get(header::AUTHORIZATION)?.to_str().ok()?;
+    let (scheme, credentials) = value.split_once(' ')?;
+    if !scheme.eq_ignore_ascii_case("basic") {
+        return None;
+    }
+
+    let credentials = String::from_utf8(STANDARD.decode(credentials.trim()).ok()?).ok()?;
+    let (client_id, client_secret) = credentials.split_once(':')?;
+    Some((client_id.to_owned(), client_secret.to_owned()))
+}
+
+// Clients are configured as `client_id:client_secret` entries. Secrets are
+// compared by their hashes, so the comparison leaks nothing about them.
+fn is_registered_client(clients: &[String], client_id: &str, client_secret: &str) -> bool {
+    let secret_hash = Sha256::digest(client_secret.as_bytes());
+
+    clients.iter().any(|client| match client.split_once(':') {
+        Some((id, secret)) => id == client_id && Sha256::digest(secret.as_bytes()) == secret_hash,
+        None => false,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn basic(credentials: &str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        let value = format!("Basic {}", STANDARD.encode(credentials));
+        headers.insert(header::AUTHORIZATION, value.parse().unwrap());
+        headers
+    }
+
+    #[test]
+    fn test_basic_credentials() {
+        let credentials = basic_credentials(&basic("gateway:s3cr:et"));
+        assert_eq!(credentials, Some(("gateway".to_owned(), "s3cr:et".to_owned())));
+
+        assert_eq!(basic_credentials(&HeaderMap::new()), None);
+
+        let mut headers = HeaderMap::new();
+        headers.insert(header::AUTHORIZATION, "Bearer token".parse().unwrap());
+        assert_eq!(basic_credentials(&headers), None);
+    }
+
+    #[test]
+    fn test_is_registered_client() {
+        let clients = vec!["gateway:secret".to_owned(), "proxy:other".to_owned()];
+
+        assert!(is_registered_client(&clients, "gateway", "secret"));
+        assert!(is_registered_client(&clients, "proxy", "other"));
+        assert!(!is_registered_client(&clients, "gateway", "other"));
+        assert!(!is_registered_client(&clients, "unknown", "secret"));
+        assert!(!is_registered_client(&[], "gateway", "secret"));
+    }
+}
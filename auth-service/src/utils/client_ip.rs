@@ -0,0 +1,75 @@
+use std::net::{IpAddr, SocketAddr};
+
+use axum::{
+    async_trait,
+    extract::{ConnectInfo, FromRequestParts},
+    http::{request::Parts, HeaderMap},
+};
+
+use crate::domain::AuthAPIError;
+
+use super::constants::TRUST_X_FORWARDED_FOR;
+
+// Address of the client making the request. Behind a load balancer every
+// request comes from the balancer, so with `TRUST_X_FORWARDED_FOR` set the
+// address it appended to `X-Forwarded-For` is used instead. Only set it when
+// clients cannot reach auth-service directly, otherwise they pick their own.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ClientIp(pub IpAddr);
+
+#[async_trait]
+impl<S: Send + Sync> FromRequestParts<S> for ClientIp {
+    type Rejection = AuthAPIError;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        if *TRUST_X_FORWARDED_FOR {
+            if let Some(ip) = forwarded_ip(&parts.headers) {
+                return Ok(ClientIp(ip));
+            }
+        }
+
+        // Only there when the app is served with connect info, see `Application::build`
+        parts
+            .extensions
+            .get::<ConnectInfo<SocketAddr>>()
+            .map(|ConnectInfo(address)| ClientIp(address.ip()))
+            .ok_or(AuthAPIError::UnexpectedError)
+    }
+}
+
+// The last address in `X-Forwarded-For`, the one added by the closest proxy.
+// Earlier ones are whatever the client sent.
+fn forwarded_ip(headers: &HeaderMap) -> Option<IpAddr> {
+    headers
+        .get_all("x-forwarded-for")
+        .iter()
+        .next_back()?
+        .to_str()
+        .ok()?
+        .rsplit(',')
+        .next()?
+        .trim()
+        .parse()
+        .ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::http::HeaderValue;
+
+    #[test]
+    fn test_forwarded_ip() {
+        let mut headers = HeaderMap::new();
+        assert_eq!(forwarded_ip(&headers), None);
+
+        headers.insert("x-forwarded-for", HeaderValue::from_static("10.0.0.1, 192.0.2.7"));
+        assert_eq!(forwarded_ip(&headers), Some("192.0.2.7".parse().unwrap()));
+
+        headers.append("x-forwarded-for", HeaderValue::from_static("2001:db8::1"));
+        assert_eq!(forwarded_ip(&headers), Some("2001:db8::1".parse().unwrap()));
+
+        headers.insert("x-forwarded-for", HeaderValue::from_static("not-an-ip"));
+        assert_eq!(forwarded_ip(&headers), None);
+    }
+}
@@ -5,6 +5,39 @@ use std::env as std_env;
 // Define a lazily evaluated static. lazy_static is needed because std_env::var is not a const function.
 lazy_static! {
     pub static ref JWT_SECRET: String = set_token();
+    pub static ref JWT_ALGORITHM: String = set_string(env::JWT_ALGORITHM_ENV_VAR, "HS256");
+    pub static ref JWT_PRIVATE_KEY_PATH: Option<String> = set_optional(env::JWT_PRIVATE_KEY_PATH_ENV_VAR);
+    pub static ref JWT_KEY_ID: Option<String> = set_optional(env::JWT_KEY_ID_ENV_VAR);
+    pub static ref JWT_KEYS_DIR: Option<String> = set_optional(env::JWT_KEYS_DIR_ENV_VAR);
+    pub static ref JWT_ISSUER: String = set_string(env::JWT_ISSUER_ENV_VAR, "auth-service");
+    pub static ref JWT_AUDIENCES: Vec<String> = set_list(env::JWT_AUDIENCES_ENV_VAR, &["auth-service"]);
+    pub static ref JWT_INCLUDE_EMAIL: bool = set_bool(env::JWT_INCLUDE_EMAIL_ENV_VAR, false);
+    pub static ref ARGON2_MEMORY_KIB: u32 = set_u32(env::ARGON2_MEMORY_KIB_ENV_VAR, 19_456);
+    pub static ref ARGON2_ITERATIONS: u32 = set_u32(env::ARGON2_ITERATIONS_ENV_VAR, 2);
+    pub static ref ARGON2_PARALLELISM: u32 = set_u32(env::ARGON2_PARALLELISM_ENV_VAR, 1);
+    pub static ref DATABASE_URL: Option<String> = set_optional(env::DATABASE_URL_ENV_VAR);
+    pub static ref REDIS_URL: Option<String> = set_optional(env::REDIS_URL_ENV_VAR);
+    pub static ref TWO_FA_CODE_TTL_SECONDS: u32 = set_u32(env::TWO_FA_CODE_TTL_SECONDS_ENV_VAR, 600);
+    pub static ref TWO_FA_MAX_ATTEMPTS: u32 = set_u32(env::TWO_FA_MAX_ATTEMPTS_ENV_VAR, 5);
+    pub static ref TOTP_ISSUER: String = set_string(env::TOTP_ISSUER_ENV_VAR, "auth-service");
+    pub static ref TOTP_DRIFT_STEPS: u32 = set_u32(env::TOTP_DRIFT_STEPS_ENV_VAR, 1);
+    pub static ref BANNED_TOKEN_SWEEP_INTERVAL_SECONDS: u32 = set_u32(env::BANNED_TOKEN_SWEEP_INTERVAL_SECONDS_ENV_VAR, 60);
+    pub static ref REFRESH_TOKEN_TTL_SECONDS: u32 = set_u32(env::REFRESH_TOKEN_TTL_SECONDS_ENV_VAR, 2_592_000);
+    pub static ref OAUTH_CLIENTS: Vec<String> = set_list(env::OAUTH_CLIENTS_ENV_VAR, &[]);
+    pub static ref PUBLIC_URL: String = set_string(env::PUBLIC_URL_ENV_VAR, "http://localhost:3000");
+    pub static ref EMAIL_VERIFICATION_TTL_SECONDS: u32 = set_u32(env::EMAIL_VERIFICATION_TTL_SECONDS_ENV_VAR, 86_400);
+    pub static ref PASSWORD_RESET_TTL_SECONDS: u32 = set_u32(env::PASSWORD_RESET_TTL_SECONDS_ENV_VAR, 3_600);
+    pub static ref ALLOW_UNVERIFIED_LOGIN: bool = set_bool(env::ALLOW_UNVERIFIED_LOGIN_ENV_VAR, true);
+    pub static ref ACCOUNT_DELETION_GRACE_PERIOD_SECONDS: u32 = set_u32(env::ACCOUNT_DELETION_GRACE_PERIOD_SECONDS_ENV_VAR, 2_592_000);
+    pub static ref ACCOUNT_DELETION_SWEEP_INTERVAL_SECONDS: u32 = set_u32(env::ACCOUNT_DELETION_SWEEP_INTERVAL_SECONDS_ENV_VAR, 3_600);
+    pub static ref LOGIN_BACKOFF_THRESHOLD: u32 = set_u32(env::LOGIN_BACKOFF_THRESHOLD_ENV_VAR, 3);
+    pub static ref LOGIN_BACKOFF_BASE_SECONDS: u32 = set_u32(env::LOGIN_BACKOFF_BASE_SECONDS_ENV_VAR, 1);
+    pub static ref LOGIN_LOCKOUT_THRESHOLD: u32 = set_u32(env::LOGIN_LOCKOUT_THRESHOLD_ENV_VAR, 10);
+    pub static ref LOGIN_LOCKOUT_SECONDS: u32 = set_u32(env::LOGIN_LOCKOUT_SECONDS_ENV_VAR, 900);
+    pub static ref LOGIN_IP_BACKOFF_THRESHOLD: u32 = set_u32(env::LOGIN_IP_BACKOFF_THRESHOLD_ENV_VAR, 10);
+    pub static ref LOGIN_IP_LOCKOUT_THRESHOLD: u32 = set_u32(env::LOGIN_IP_LOCKOUT_THRESHOLD_ENV_VAR, 50);
+    pub static ref FAILED_LOGIN_SWEEP_INTERVAL_SECONDS: u32 = set_u32(env::FAILED_LOGIN_SWEEP_INTERVAL_SECONDS_ENV_VAR, 60);
+    pub static ref TRUST_X_FORWARDED_FOR: bool = set_bool(env::TRUST_X_FORWARDED_FOR_ENV_VAR, false);
 }
 
 
@@ -24,11 +57,98 @@ fn set_token() -> String {
     secret
 }
 
+// Read an optional numeric setting, falling back to the default when unset
+fn set_u32(name: &str, default: u32) -> u32 {
+    dotenv().ok();
+    match std_env::var(name) {
+        Ok(value) => value
+            .parse()
+            .unwrap_or_else(|_| panic!("{} must be a positive integer", name)),
+        Err(_) => default,
+    }
+}
+
+// Read an optional true/false setting, falling back to the default when unset
+fn set_bool(name: &str, default: bool) -> bool {
+    dotenv().ok();
+    match std_env::var(name) {
+        Ok(value) => value
+            .parse()
+            .unwrap_or_else(|_| panic!("{} must be true or false", name)),
+        Err(_) => default,
+    }
+}
+
+// Read an optional text setting, falling back to the default when unset or empty
+fn set_string(name: &str, default: &str) -> String {
+    set_optional(name).unwrap_or_else(|| default.to_owned())
+}
+
+// Read an optional comma separated list, falling back to the default when unset or empty
+fn set_list(name: &str, default: &[&str]) -> Vec<String> {
+    let list: Vec<String> = set_optional(name)
+        .map(|value| {
+            value
+                .split(',')
+                .map(str::trim)
+                .filter(|item| !item.is_empty())
+                .map(str::to_owned)
+                .collect()
+        })
+        .unwrap_or_default();
+
+    if list.is_empty() {
+        default.iter().map(|&item| item.to_owned()).collect()
+    } else {
+        list
+    }
+}
+
+// Read an optional setting, treating an empty value as unset
+fn set_optional(name: &str) -> Option<String> {
+    dotenv().ok();
+    std_env::var(name).ok().filter(|value| !value.is_empty())
+}
+
 pub mod env {
     pub const JWT_SECRET_ENV_VAR: &str = "JWT_SECRET";
+    pub const JWT_ALGORITHM_ENV_VAR: &str = "JWT_ALGORITHM";
+    pub const JWT_PRIVATE_KEY_PATH_ENV_VAR: &str = "JWT_PRIVATE_KEY_PATH";
+    pub const JWT_KEY_ID_ENV_VAR: &str = "JWT_KEY_ID";
+    pub const JWT_KEYS_DIR_ENV_VAR: &str = "JWT_KEYS_DIR";
+    pub const JWT_ISSUER_ENV_VAR: &str = "JWT_ISSUER";
+    pub const JWT_AUDIENCES_ENV_VAR: &str = "JWT_AUDIENCES";
+    pub const JWT_INCLUDE_EMAIL_ENV_VAR: &str = "JWT_INCLUDE_EMAIL";
+    pub const ARGON2_MEMORY_KIB_ENV_VAR: &str = "ARGON2_MEMORY_KIB";
+    pub const ARGON2_ITERATIONS_ENV_VAR: &str = "ARGON2_ITERATIONS";
+    pub const ARGON2_PARALLELISM_ENV_VAR: &str = "ARGON2_PARALLELISM";
+    pub const DATABASE_URL_ENV_VAR: &str = "DATABASE_URL";
+    pub const REDIS_URL_ENV_VAR: &str = "REDIS_URL";
+    pub const TWO_FA_CODE_TTL_SECONDS_ENV_VAR: &str = "TWO_FA_CODE_TTL_SECONDS";
+    pub const TWO_FA_MAX_ATTEMPTS_ENV_VAR: &str = "TWO_FA_MAX_ATTEMPTS";
+    pub const TOTP_ISSUER_ENV_VAR: &str = "TOTP_ISSUER";
+    pub const TOTP_DRIFT_STEPS_ENV_VAR: &str = "TOTP_DRIFT_STEPS";
+    pub const BANNED_TOKEN_SWEEP_INTERVAL_SECONDS_ENV_VAR: &str = "BANNED_TOKEN_SWEEP_INTERVAL_SECONDS";
+    pub const REFRESH_TOKEN_TTL_SECONDS_ENV_VAR: &str = "REFRESH_TOKEN_TTL_SECONDS";
+    pub const OAUTH_CLIENTS_ENV_VAR: &str = "OAUTH_CLIENTS";
+    pub const PUBLIC_URL_ENV_VAR: &str = "PUBLIC_URL";
+    pub const EMAIL_VERIFICATION_TTL_SECONDS_ENV_VAR: &str = "EMAIL_VERIFICATION_TTL_SECONDS";
+    pub const PASSWORD_RESET_TTL_SECONDS_ENV_VAR: &str = "PASSWORD_RESET_TTL_SECONDS";
+    pub const ALLOW_UNVERIFIED_LOGIN_ENV_VAR: &str = "ALLOW_UNVERIFIED_LOGIN";
+    pub const ACCOUNT_DELETION_GRACE_PERIOD_SECONDS_ENV_VAR: &str = "ACCOUNT_DELETION_GRACE_PERIOD_SECONDS";
+    pub const ACCOUNT_DELETION_SWEEP_INTERVAL_SECONDS_ENV_VAR: &str = "ACCOUNT_DELETION_SWEEP_INTERVAL_SECONDS";
+    pub const LOGIN_BACKOFF_THRESHOLD_ENV_VAR: &str = "LOGIN_BACKOFF_THRESHOLD";
+    pub const LOGIN_BACKOFF_BASE_SECONDS_ENV_VAR: &str = "LOGIN_BACKOFF_BASE_SECONDS";
+    pub const LOGIN_LOCKOUT_THRESHOLD_ENV_VAR: &str = "LOGIN_LOCKOUT_THRESHOLD";
+    pub const LOGIN_LOCKOUT_SECONDS_ENV_VAR: &str = "LOGIN_LOCKOUT_SECONDS";
+    pub const LOGIN_IP_BACKOFF_THRESHOLD_ENV_VAR: &str = "LOGIN_IP_BACKOFF_THRESHOLD";
+    pub const LOGIN_IP_LOCKOUT_THRESHOLD_ENV_VAR: &str = "LOGIN_IP_LOCKOUT_THRESHOLD";
+    pub const FAILED_LOGIN_SWEEP_INTERVAL_SECONDS_ENV_VAR: &str = "FAILED_LOGIN_SWEEP_INTERVAL_SECONDS";
+    pub const TRUST_X_FORWARDED_FOR_ENV_VAR: &str = "TRUST_X_FORWARDED_FOR";
 }
 
 pub const JWT_COOKIE_NAME: &str = "jwt";
+pub const REFRESH_COOKIE_NAME: &str = "refresh_token";
 
 pub mod prod {
     pub const APP_ADDRESS: &str = "0.0.0.0:3000";
@@ -36,4 +156,11 @@ pub mod prod {
 
 pub mod test {
     pub const APP_ADDRESS: &str = "127.0.0.1:0";
+    // Postgres server used by the integration tests, each test gets its own database
+    pub const DATABASE_URL_ENV_VAR: &str = "TEST_DATABASE_URL";
+    // Redis server used by the integration tests for banned tokens and 2FA codes
+    pub const REDIS_URL_ENV_VAR: &str = "TEST_REDIS_URL";
+    // OAuth client the integration tests authenticate as
+    pub const OAUTH_CLIENT_ID: &str = "test-client";
+    pub const OAUTH_CLIENT_SECRET: &str = "test-client-secret";
 }
\ No newline at end of file
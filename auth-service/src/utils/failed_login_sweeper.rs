@@ -0,0 +1,42 @@
+use std::time::Duration;
+use tokio::task::JoinHandle;
+
+use crate::{app_state::FailedLoginStoreType, domain::data_stores::FailedLoginStoreError};
+
+// Periodically drop the failed logins that expired. Logins only look at the
+// keys they count, so without this every address and IP that ever failed
+// would be kept.
+pub fn spawn_failed_login_sweeper(store: FailedLoginStoreType, interval: Duration) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(interval);
+        loop {
+            ticker.tick().await;
+            if let Err(e) = sweep_failed_logins(&store).await {
+                println!("Failed to prune failed logins: {:?}", e);
+            }
+        }
+    })
+}
+
+// Prune expired failures once, returning how many keys were removed
+pub async fn sweep_failed_logins(store: &FailedLoginStoreType) -> Result<usize, FailedLoginStoreError> {
+    store.write().await.prune_expired().await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{domain::data_stores::FailedLoginKey, services::HashmapFailedLoginStore};
+    use std::sync::Arc;
+    use tokio::sync::RwLock;
+
+    #[tokio::test]
+    async fn test_sweep_failed_logins() {
+        let store: FailedLoginStoreType = Arc::new(RwLock::new(HashmapFailedLoginStore::new(0)));
+        let key = FailedLoginKey::Ip("127.0.0.1".parse().unwrap());
+        store.write().await.record_attempt(&key).await.unwrap();
+
+        assert_eq!(sweep_failed_logins(&store).await, Ok(1));
+        assert_eq!(sweep_failed_logins(&store).await, Ok(0));
+    }
+}
@@ -0,0 +1,26 @@
+use tokio::{
+    signal::unix::{signal, SignalKind},
+    task::JoinHandle,
+};
+
+use super::keys::reload_keyring;
+
+// Reload the signing keys on SIGHUP, so keys can be rotated without a restart
+pub fn spawn_keyring_reloader() -> JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut hangups = match signal(SignalKind::hangup()) {
+            Ok(hangups) => hangups,
+            Err(e) => {
+                println!("Failed to listen for SIGHUP, signing keys will not be reloaded: {:?}", e);
+                return;
+            }
+        };
+
+        while hangups.recv().await.is_some() {
+            match reload_keyring() {
+                Ok(()) => println!("Reloaded signing keys"),
+                Err(e) => println!("Failed to reload signing keys, keeping the current ones: {:?}", e),
+            }
+        }
+    })
+}
@@ -0,0 +1,510 @@
+use std::{
+    collections::HashSet,
+    path::Path,
+    sync::{Arc, RwLock},
+};
+
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use chrono::{DateTime, Utc};
+use jsonwebtoken::{
+    jwk::{
+        AlgorithmParameters, CommonParameters, EllipticCurve, Jwk, JwkSet, KeyAlgorithm,
+        OctetKeyPairParameters, OctetKeyPairType, PublicKeyUse, RSAKeyParameters, RSAKeyType,
+    },
+    Algorithm, DecodingKey, EncodingKey,
+};
+use lazy_static::lazy_static;
+use ring::signature::{Ed25519KeyPair, KeyPair};
+use rsa::{pkcs1::DecodeRsaPrivateKey, pkcs8::DecodePrivateKey, traits::PublicKeyParts, RsaPrivateKey};
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+
+use super::constants::{JWT_ALGORITHM, JWT_KEYS_DIR, JWT_KEY_ID, JWT_PRIVATE_KEY_PATH, JWT_SECRET};
+
+// Name of the file in JWT_KEYS_DIR that lists the keys
+pub const KEYRING_MANIFEST_FILE: &str = "keyring.json";
+
+lazy_static! {
+    // main loads the keyring on startup, so that a missing or broken key file
+    // stops the service right away instead of failing the first login. Readers
+    // clone the Arc, so a reload never blocks on tokens being signed or verified.
+    static ref KEYRING: RwLock<Arc<Keyring>> = RwLock::new(Arc::new(
+        Keyring::from_env().unwrap_or_else(|e| panic!("Failed to load the JWT signing keys: {:?}", e))
+    ));
+}
+
+// The keys auth tokens are currently signed and verified with
+pub fn keyring() -> Arc<Keyring> {
+    KEYRING.read().unwrap_or_else(|e| e.into_inner()).clone()
+}
+
+// Load the keys again, e.g. after a new key was added to JWT_KEYS_DIR. The
+// current keys stay in use if the new ones cannot be loaded.
+pub fn reload_keyring() -> Result<(), KeyError> {
+    let keyring = Keyring::from_env()?;
+    *KEYRING.write().unwrap_or_else(|e| e.into_inner()) = Arc::new(keyring);
+    Ok(())
+}
+
+// All keys tokens may be signed with, identified by their `kid`. One of them
+// signs new tokens, the others are still accepted for tokens issued before
+// the active key changed, until their retirement date.
+//
+// Without JWT_KEYS_DIR the keyring holds the single key configured by
+// JWT_ALGORITHM, see `SigningKey`. With JWT_KEYS_DIR the keys are listed in
+// its `keyring.json`:
+//
+//     {
+//       "active": "2026-10",
+//       "keys": [
+//         { "kid": "2026-10", "algorithm": "EdDSA", "file": "2026-10.pem" },
+//         { "kid": "2026-04", "algorithm": "RS256", "file": "2026-04.pem",
+//           "retire_at": "2026-10-18T12:00:00Z" }
+//       ]
+//     }
+//
+// Key files are resolved relative to the directory. HS256 key files hold the
+// shared secret instead of a PEM key.
+pub struct Keyring {
+    active: usize,
+    keys: Vec<KeyringEntry>,
+}
+
+struct KeyringEntry {
+    key: SigningKey,
+    retire_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Deserialize)]
+struct KeyringManifest {
+    active: String,
+    keys: Vec<ManifestKey>,
+}
+
+#[derive(Deserialize)]
+struct ManifestKey {
+    kid: String,
+    algorithm: String,
+    file: String,
+    retire_at: Option<String>,
+}
+
+impl Keyring {
+    fn from_env() -> Result<Self, KeyError> {
+        match JWT_KEYS_DIR.as_deref() {
+            Some(dir) => Self::from_dir(Path::new(dir)),
+            None => Ok(Self::single(SigningKey::from_env()?)),
+        }
+    }
+
+    pub fn single(key: SigningKey) -> Self {
+        Self {
+            active: 0,
+            keys: vec![KeyringEntry { key, retire_at: None }],
+        }
+    }
+
+    pub fn from_dir(dir: &Path) -> Result<Self, KeyError> {
+        let manifest = std::fs::read_to_string(dir.join(KEYRING_MANIFEST_FILE))
+            .map_err(KeyError::ReadFailed)?;
+        Self::from_manifest(&manifest, dir)
+    }
+
+    fn from_manifest(manifest: &str, dir: &Path) -> Result<Self, KeyError> {
+        let manifest: KeyringManifest =
+            serde_json::from_str(manifest).map_err(|e| KeyError::InvalidKeyring(e.to_string()))?;
+
+        let mut kids = HashSet::new();
+        let mut keys = Vec::with_capacity(manifest.keys.len());
+        for entry in manifest.keys {
+            if !kids.insert(entry.kid.clone()) {
+                return Err(KeyError::InvalidKeyring(format!("Duplicate kid {}", entry.kid)));
+            }
+
+            let retire_at = entry
+                .retire_at
+                .map(|retire_at| {
+                    DateTime::parse_from_rfc3339(&retire_at)
+                        .map(|retire_at| retire_at.with_timezone(&Utc))
+                        .map_err(|e| KeyError::InvalidKeyring(format!("Invalid retire_at of {}: {}", entry.kid, e)))
+                })
+                .transpose()?;
+
+            let algorithm = parse_algorithm(&entry.algorithm)?;
+            let contents = std::fs::read_to_string(dir.join(&entry.file)).map_err(KeyError::ReadFailed)?;
+            let key = match algorithm {
+                Algorithm::HS256 => SigningKey::hmac(contents.trim().as_bytes(), Some(entry.kid)),
+                _ => SigningKey::from_pem(algorithm, &contents, Some(entry.kid))?,
+            };
+
+            keys.push(KeyringEntry { key, retire_at });
+        }
+
+        let active = keys
+            .iter()
+            .position(|entry| entry.key.kid() == Some(manifest.active.as_str()))
+            .ok_or_else(|| KeyError::InvalidKeyring(format!("Active key {} is not listed", manifest.active)))?;
+        if keys[active].retire_at.is_some() {
+            return Err(KeyError::InvalidKeyring("The active key must not be retired".to_owned()));
+        }
+
+        Ok(Self { active, keys })
+    }
+
+    // The key new tokens are signed with
+    pub fn active(&self) -> &SigningKey {
+        &self.keys[self.active].key
+    }
+
+    // The key a token names in its `kid` header, unless it has been retired
+    pub fn find(&self, kid: Option<&str>) -> Option<&SigningKey> {
+        self.current_keys().find(|key| key.kid() == kid)
+    }
+
+    // The public keys tokens can be verified with. Keys which are not active
+    // yet are published too, so that clients caching the set already know
+    // them once they start signing.
+    pub fn jwks(&self) -> JwkSet {
+        JwkSet {
+            keys: self.current_keys().filter_map(SigningKey::jwk).cloned().collect(),
+        }
+    }
+
+    fn current_keys(&self) -> impl Iterator<Item = &SigningKey> {
+        let now = Utc::now();
+        self.keys
+            .iter()
+            .filter(move |entry| entry.retire_at.is_none_or(|retire_at| retire_at > now))
+            .map(|entry| &entry.key)
+    }
+}
+
+// A single key, configured by JWT_ALGORITHM when JWT_KEYS_DIR is not set:
+// - HS256 (default) uses JWT_SECRET, only holders of the secret can verify tokens
+// - RS256 and EdDSA use the private key in the PEM file at JWT_PRIVATE_KEY_PATH.
+//   The public key is served at `/.well-known/jwks.json`, so other services can
+//   verify tokens without being able to issue them.
+pub struct SigningKey {
+    algorithm: Algorithm,
+    kid: Option<String>,
+    encoding_key: EncodingKey,
+    decoding_key: DecodingKey,
+    // The public key as published in the JWKS, there is none for HS256
+    jwk: Option<Jwk>,
+}
+
+#[derive(Debug)]
+pub enum KeyError {
+    UnsupportedAlgorithm(String),
+    MissingPrivateKeyPath,
+    ReadFailed(std::io::Error),
+    InvalidKey(String),
+    InvalidKeyring(String),
+}
+
+impl SigningKey {
+    fn from_env() -> Result<Self, KeyError> {
+        if JWT_ALGORITHM.as_str() == "HS256" {
+            return Ok(Self::hmac(JWT_SECRET.as_bytes(), JWT_KEY_ID.clone()));
+        }
+
+        let algorithm = parse_algorithm(&JWT_ALGORITHM)?;
+
+        let path = JWT_PRIVATE_KEY_PATH
+            .as_deref()
+            .ok_or(KeyError::MissingPrivateKeyPath)?;
+        let pem = std::fs::read_to_string(path).map_err(KeyError::ReadFailed)?;
+
+        Self::from_pem(algorithm, &pem, JWT_KEY_ID.clone())
+    }
+
+    // A shared secret only identifies itself with a `kid` when one is configured,
+    // deriving it from the secret would leak a hash of the secret
+    pub fn hmac(secret: &[u8], kid: Option<String>) -> Self {
+        Self {
+            algorithm: Algorithm::HS256,
+            kid,
+            encoding_key: EncodingKey::from_secret(secret),
+            decoding_key: DecodingKey::from_secret(secret),
+            jwk: None,
+        }
+    }
+
+    // Load an RS256 (PKCS#1 or PKCS#8) or EdDSA (Ed25519, PKCS#8) private key.
+    // Without a configured `kid` the RFC 7638 thumbprint of the public key is
+    // used, which is the same on every replica sharing the key.
+    pub fn from_pem(algorithm: Algorithm, pem: &str, kid: Option<String>) -> Result<Self, KeyError> {
+        let (encoding_key, parameters, key_algorithm) = match algorithm {
+            Algorithm::RS256 => (
+                EncodingKey::from_rsa_pem(pem.as_bytes()).map_err(invalid_key)?,
+                rsa_public_key(pem)?,
+                KeyAlgorithm::RS256,
+            ),
+            Algorithm::EdDSA => (
+                EncodingKey::from_ed_pem(pem.as_bytes()).map_err(invalid_key)?,
+                ed25519_public_key(pem)?,
+                KeyAlgorithm::EdDSA,
+            ),
+            other => return Err(KeyError::UnsupportedAlgorithm(format!("{:?}", other))),
+        };
+
+        let kid = kid.unwrap_or_else(|| thumbprint(&parameters));
+        let jwk = Jwk {
+            common: CommonParameters {
+                public_key_use: Some(PublicKeyUse::Signature),
+                key_algorithm: Some(key_algorithm),
+                key_id: Some(kid.clone()),
+                ..Default::default()
+            },
+            algorithm: parameters,
+        };
+
+        Ok(Self {
+            algorithm,
+            kid: Some(kid),
+            encoding_key,
+            decoding_key: DecodingKey::from_jwk(&jwk).map_err(invalid_key)?,
+            jwk: Some(jwk),
+        })
+    }
+
+    pub fn algorithm(&self) -> Algorithm {
+        self.algorithm
+    }
+
+    pub fn kid(&self) -> Option<&str> {
+        self.kid.as_deref()
+    }
+
+    pub fn encoding_key(&self) -> &EncodingKey {
+        &self.encoding_key
+    }
+
+    pub fn decoding_key(&self) -> &DecodingKey {
+        &self.decoding_key
+    }
+
+    pub fn jwk(&self) -> Option<&Jwk> {
+        self.jwk.as_ref()
+    }
+}
+
+fn parse_algorithm(algorithm: &str) -> Result<Algorithm, KeyError> {
+    match algorithm {
+        "HS256" => Ok(Algorithm::HS256),
+        "RS256" => Ok(Algorithm::RS256),
+        "EdDSA" => Ok(Algorithm::EdDSA),
+        other => Err(KeyError::UnsupportedAlgorithm(other.to_owned())),
+    }
+}
+
+fn rsa_public_key(pem: &str) -> Result<AlgorithmParameters, KeyError> {
+    let key = if pem.contains("BEGIN RSA PRIVATE KEY") {
+        RsaPrivateKey::from_pkcs1_pem(pem).map_err(invalid_key)?
+    } else {
+        RsaPrivateKey::from_pkcs8_pem(pem).map_err(invalid_key)?
+    };
+
+    Ok(AlgorithmParameters::RSA(RSAKeyParameters {
+        key_type: RSAKeyType::RSA,
+        n: URL_SAFE_NO_PAD.encode(key.n().to_bytes_be()),
+        e: URL_SAFE_NO_PAD.encode(key.e().to_bytes_be()),
+    }))
+}
+
+fn ed25519_public_key(pem: &str) -> Result<AlgorithmParameters, KeyError> {
+    let der = pem::parse(pem).map_err(invalid_key)?;
+    // OpenSSL writes PKCS#8 v1 keys, which do not include the public key
+    let key_pair =
+        Ed25519KeyPair::from_pkcs8_maybe_unchecked(der.contents()).map_err(invalid_key)?;
+
+    Ok(AlgorithmParameters::OctetKeyPair(OctetKeyPairParameters {
+        key_type: OctetKeyPairType::OctetKeyPair,
+        curve: EllipticCurve::Ed25519,
+        x: URL_SAFE_NO_PAD.encode(key_pair.public_key().as_ref()),
+    }))
+}
+
+// SHA-256 over the required members of the key in lexicographic order, see RFC 7638
+fn thumbprint(parameters: &AlgorithmParameters) -> String {
+    let canonical = match parameters {
+        AlgorithmParameters::RSA(rsa) => {
+            format!(r#"{{"e":"{}","kty":"RSA","n":"{}"}}"#, rsa.e, rsa.n)
+        }
+        AlgorithmParameters::OctetKeyPair(okp) => {
+            format!(r#"{{"crv":"Ed25519","kty":"OKP","x":"{}"}}"#, okp.x)
+        }
+        // Only RSA and Ed25519 keys are loaded
+        _ => unreachable!("Unsupported key type"),
+    };
+
+    URL_SAFE_NO_PAD.encode(Sha256::digest(canonical.as_bytes()))
+}
+
+fn invalid_key(e: impl std::fmt::Display) -> KeyError {
+    KeyError::InvalidKey(e.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use jsonwebtoken::{decode, encode, Header, Validation};
+    use serde::{Deserialize, Serialize};
+
+    const RS256_PEM: &str = include_str!("../../tests/fixtures/keys/rs256.pem");
+    const ED25519_PEM: &str = include_str!("../../tests/fixtures/keys/ed25519.pem");
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct TestClaims {
+        sub: String,
+        exp: usize,
+    }
+
+    fn round_trip(key: &SigningKey) -> TestClaims {
+        let claims = TestClaims {
+            sub: "test@example.com".to_owned(),
+            exp: 4_102_444_800,
+        };
+        let mut header = Header::new(key.algorithm());
+        header.kid = key.kid().map(str::to_owned);
+
+        let token = encode(&header, &claims, key.encoding_key()).unwrap();
+        decode::<TestClaims>(&token, key.decoding_key(), &Validation::new(key.algorithm()))
+            .unwrap()
+            .claims
+    }
+
+    #[test]
+    fn test_rs256_key() {
+        let key = SigningKey::from_pem(Algorithm::RS256, RS256_PEM, None).unwrap();
+        assert_eq!(round_trip(&key).sub, "test@example.com");
+
+        let jwk = key.jwk().unwrap();
+        assert_eq!(jwk.common.key_algorithm, Some(KeyAlgorithm::RS256));
+        assert_eq!(jwk.common.key_id.as_deref(), key.kid());
+        assert!(matches!(&jwk.algorithm, AlgorithmParameters::RSA(rsa) if rsa.e == "AQAB"));
+    }
+
+    #[test]
+    fn test_ed25519_key() {
+        let key = SigningKey::from_pem(Algorithm::EdDSA, ED25519_PEM, None).unwrap();
+        assert_eq!(round_trip(&key).sub, "test@example.com");
+
+        let jwk = key.jwk().unwrap();
+        assert_eq!(jwk.common.key_algorithm, Some(KeyAlgorithm::EdDSA));
+        assert!(matches!(&jwk.algorithm, AlgorithmParameters::OctetKeyPair(okp) if okp.x.len() == 43));
+    }
+
+    #[test]
+    fn test_kid_defaults_to_stable_thumbprint() {
+        let key = SigningKey::from_pem(Algorithm::RS256, RS256_PEM, None).unwrap();
+        let same_key = SigningKey::from_pem(Algorithm::RS256, RS256_PEM, None).unwrap();
+        let other_key = SigningKey::from_pem(Algorithm::EdDSA, ED25519_PEM, None).unwrap();
+        assert_eq!(key.kid(), same_key.kid());
+        assert_ne!(key.kid(), other_key.kid());
+
+        let key = SigningKey::from_pem(Algorithm::RS256, RS256_PEM, Some("key-1".to_owned())).unwrap();
+        assert_eq!(key.kid(), Some("key-1"));
+    }
+
+    #[test]
+    fn test_hmac_key_is_not_published() {
+        let key = SigningKey::hmac(b"secret", None);
+        assert_eq!(round_trip(&key).sub, "test@example.com");
+        assert!(key.jwk().is_none());
+        assert!(key.kid().is_none());
+    }
+
+    fn keyring(manifest: serde_json::Value) -> Result<Keyring, KeyError> {
+        Keyring::from_manifest(&manifest.to_string(), Path::new("tests/fixtures/keys"))
+    }
+
+    #[test]
+    fn test_keyring_from_dir() {
+        let keyring = Keyring::from_dir(Path::new("tests/fixtures/keys")).unwrap();
+        assert_eq!(keyring.active().kid(), Some("ed25519"));
+        assert!(keyring.find(Some("rs256")).is_some());
+        // The HS256 key has been retired
+        assert!(keyring.find(Some("hs256")).is_none());
+        assert_eq!(keyring.jwks().keys.len(), 2);
+    }
+
+    #[test]
+    fn test_keyring_accepts_keys_until_retired() {
+        let keyring = keyring(serde_json::json!({
+            "active": "new",
+            "keys": [
+                { "kid": "new", "algorithm": "EdDSA", "file": "ed25519.pem" },
+                { "kid": "old", "algorithm": "RS256", "file": "rs256.pem", "retire_at": "2999-01-01T00:00:00Z" },
+                { "kid": "retired", "algorithm": "HS256", "file": "hs256.key", "retire_at": "2000-01-01T00:00:00+02:00" },
+            ]
+        }))
+        .unwrap();
+
+        assert_eq!(keyring.active().algorithm(), Algorithm::EdDSA);
+        assert_eq!(keyring.find(Some("old")).map(SigningKey::algorithm), Some(Algorithm::RS256));
+        assert!(keyring.find(Some("retired")).is_none());
+        assert!(keyring.find(Some("unknown")).is_none());
+        assert!(keyring.find(None).is_none());
+
+        // A token of the old key still verifies after the active key changed
+        assert_eq!(round_trip(keyring.find(Some("old")).unwrap()).sub, "test@example.com");
+
+        let kids: Vec<_> = keyring.jwks().keys.into_iter().filter_map(|jwk| jwk.common.key_id).collect();
+        assert_eq!(kids, ["new", "old"]);
+    }
+
+    #[test]
+    fn test_invalid_keyrings() {
+        let manifests = [
+            // Active key not listed
+            serde_json::json!({
+                "active": "missing",
+                "keys": [{ "kid": "a", "algorithm": "EdDSA", "file": "ed25519.pem" }]
+            }),
+            // Duplicate kid
+            serde_json::json!({
+                "active": "a",
+                "keys": [
+                    { "kid": "a", "algorithm": "EdDSA", "file": "ed25519.pem" },
+                    { "kid": "a", "algorithm": "RS256", "file": "rs256.pem" },
+                ]
+            }),
+            // Active key retired
+            serde_json::json!({
+                "active": "a",
+                "keys": [{ "kid": "a", "algorithm": "EdDSA", "file": "ed25519.pem", "retire_at": "2999-01-01T00:00:00Z" }]
+            }),
+            // Invalid retirement date
+            serde_json::json!({
+                "active": "a",
+                "keys": [
+                    { "kid": "a", "algorithm": "EdDSA", "file": "ed25519.pem" },
+                    { "kid": "b", "algorithm": "RS256", "file": "rs256.pem", "retire_at": "tomorrow" },
+                ]
+            }),
+            // Missing key file
+            serde_json::json!({
+                "active": "a",
+                "keys": [{ "kid": "a", "algorithm": "EdDSA", "file": "missing.pem" }]
+            }),
+            // Unsupported algorithm
+            serde_json::json!({
+                "active": "a",
+                "keys": [{ "kid": "a", "algorithm": "ES256", "file": "ed25519.pem" }]
+            }),
+        ];
+
+        for manifest in manifests {
+            assert!(keyring(manifest.clone()).is_err(), "{}", manifest);
+        }
+    }
+
+    #[test]
+    fn test_invalid_keys() {
+        assert!(SigningKey::from_pem(Algorithm::RS256, ED25519_PEM, None).is_err());
+        assert!(SigningKey::from_pem(Algorithm::EdDSA, RS256_PEM, None).is_err());
+        assert!(SigningKey::from_pem(Algorithm::RS256, "not a key", None).is_err());
+        assert!(SigningKey::from_pem(Algorithm::HS256, RS256_PEM, None).is_err());
+    }
+}
@@ -0,0 +1,289 @@
+use std::net::IpAddr;
+
+use chrono::Utc;
+
+use crate::{
+    app_state::AppState,
+    domain::{
+        data_stores::{FailedLoginKey, FailedLogins},
+        AuthAPIError, Email, Password, User, UserStoreError,
+    },
+};
+
+use super::constants::{
+    LOGIN_BACKOFF_BASE_SECONDS, LOGIN_BACKOFF_THRESHOLD, LOGIN_IP_BACKOFF_THRESHOLD, LOGIN_IP_LOCKOUT_THRESHOLD,
+    LOGIN_LOCKOUT_SECONDS, LOGIN_LOCKOUT_THRESHOLD,
+};
+
+// Limits password guessing. Failed logins are counted per email address and
+// per client IP. From the backoff threshold on, every further attempt waits
+// twice as long as the one before, starting at the base. From the lockout
+// threshold on, attempts are refused for the lockout duration. A client IP is
+// allowed more failures, since it may be shared by many users.
+#[derive(Debug, Clone)]
+pub struct LoginThrottle {
+    pub backoff_threshold: u32,
+    pub lockout_threshold: u32,
+    pub ip_backoff_threshold: u32,
+    pub ip_lockout_threshold: u32,
+    pub backoff_base_seconds: u32,
+    pub lockout_seconds: u32,
+}
+
+impl Default for LoginThrottle {
+    fn default() -> Self {
+        Self {
+            backoff_threshold: *LOGIN_BACKOFF_THRESHOLD,
+            lockout_threshold: *LOGIN_LOCKOUT_THRESHOLD,
+            ip_backoff_threshold: *LOGIN_IP_BACKOFF_THRESHOLD,
+            ip_lockout_threshold: *LOGIN_IP_LOCKOUT_THRESHOLD,
+            backoff_base_seconds: *LOGIN_BACKOFF_BASE_SECONDS,
+            lockout_seconds: *LOGIN_LOCKOUT_SECONDS,
+        }
+    }
+}
+
+// How long a key has to wait before its next attempt
+#[derive(Debug, PartialEq)]
+struct Wait {
+    seconds: u64,
+    locked: bool,
+}
+
+impl LoginThrottle {
+    fn wait(&self, failures: &FailedLogins, backoff_threshold: u32, lockout_threshold: u32, now: i64) -> Option<Wait> {
+        let locked = failures.count >= lockout_threshold;
+        let delay = if locked {
+            u64::from(self.lockout_seconds)
+        } else if failures.count >= backoff_threshold {
+            let doublings = failures.count - backoff_threshold;
+            u64::from(self.backoff_base_seconds)
+                .saturating_mul(1u64.checked_shl(doublings).unwrap_or(u64::MAX))
+                .min(u64::from(self.lockout_seconds))
+        } else {
+            return None;
+        };
+
+        let allowed_at = failures.last_failed_at.saturating_add(delay.try_into().unwrap_or(i64::MAX));
+        let seconds = u64::try_from(allowed_at.saturating_sub(now)).ok().filter(|&seconds| seconds > 0)?;
+        Some(Wait { seconds, locked })
+    }
+}
+
+// A login attempt, counted as failed for the email address and the client
+// until its password turns out to be right
+pub struct CountedAttempt {
+    email: Email,
+    ip: IpAddr,
+    // Failures of the email address including this attempt
+    email_failures: u32,
+}
+
+// Count a login attempt before its password is checked, and refuse it while
+// the email address or the client is backing off or locked out. Counting
+// first means that concurrent guesses see each other. Attempts refused during
+// the wait are not counted, or they would keep pushing the end of the wait
+// out and keep the owner of the account locked out for good.
+pub async fn count_login_attempt(email: &Email, ip: IpAddr, state: &AppState) -> Result<CountedAttempt, AuthAPIError> {
+    let email_key = FailedLoginKey::Email(email.clone());
+    let ip_key = FailedLoginKey::Ip(ip);
+    let mut failed_login_store = state.failed_login_store.write().await;
+
+    let email_failures = failed_login_store
+        .get_failures(&email_key)
+        .await
+        .map_err(|_| AuthAPIError::UnexpectedError)?;
+    let ip_failures = failed_login_store
+        .get_failures(&ip_key)
+        .await
+        .map_err(|_| AuthAPIError::UnexpectedError)?;
+    check_wait(email_failures, ip_failures, state)?;
+
+    let email_failures = failed_login_store
+        .record_attempt(&email_key)
+        .await
+        .map_err(|_| AuthAPIError::UnexpectedError)?;
+    let ip_failures = failed_login_store
+        .record_attempt(&ip_key)
+        .await
+        .map_err(|_| AuthAPIError::UnexpectedError)?;
+    drop(failed_login_store);
+
+    let attempt = CountedAttempt {
+        email: email.clone(),
+        ip,
+        email_failures: email_failures.map_or(0, |failures| failures.count) + 1,
+    };
+
+    // Another replica may have counted attempts since the check above; those
+    // that raced past it stay counted, like guesses whose password was wrong
+    if let Err(e) = check_wait(email_failures, ip_failures, state) {
+        attempt.failed(state).await;
+        return Err(e);
+    }
+
+    Ok(attempt)
+}
+
+// Refuse an attempt while the email address or the client has to wait
+fn check_wait(
+    email_failures: Option<FailedLogins>,
+    ip_failures: Option<FailedLogins>,
+    state: &AppState,
+) -> Result<(), AuthAPIError> {
+    let throttle = &state.login_throttle;
+    let now = Utc::now().timestamp();
+
+    let email_wait = email_failures
+        .and_then(|failures| throttle.wait(&failures, throttle.backoff_threshold, throttle.lockout_threshold, now));
+    let ip_wait = ip_failures
+        .and_then(|failures| throttle.wait(&failures, throttle.ip_backoff_threshold, throttle.ip_lockout_threshold, now));
+
+    let seconds = match email_wait.iter().chain(ip_wait.iter()).map(|wait| wait.seconds).max() {
+        Some(seconds) => seconds,
+        None => return Ok(()),
+    };
+
+    // Only the account's own lockout is reported as such, a client that is
+    // locked out is just told to slow down
+    if email_wait.is_some_and(|wait| wait.locked) {
+        Err(AuthAPIError::AccountLocked(seconds))
+    } else {
+        Err(AuthAPIError::TooManyLoginAttempts(seconds))
+    }
+}
+
+impl CountedAttempt {
+    // The attempt stays counted. The owner of the account is emailed when it
+    // locked the account; failures past the threshold lock it again, but only
+    // the first lockout is worth an email. Unknown addresses are counted all
+    // the same, so that the answers do not tell which accounts exist.
+    pub async fn failed(self, state: &AppState) {
+        if self.email_failures == state.login_throttle.lockout_threshold {
+            if let Err(e) = send_lockout_email(&self.email, state).await {
+                println!("Failed to send lockout email to {}: {:?}", self.email.as_ref(), e);
+            }
+        }
+    }
+
+    // Forget the failed logins of the email address now that its password was
+    // entered correctly. The client only gets this attempt back, or an
+    // attacker could reset its count by logging in to their own account
+    // between guesses.
+    pub async fn succeeded(self, state: &AppState) -> Result<(), AuthAPIError> {
+        let mut failed_login_store = state.failed_login_store.write().await;
+        failed_login_store
+            .clear_failures(&FailedLoginKey::Email(self.email))
+            .await
+            .map_err(|_| AuthAPIError::UnexpectedError)?;
+        failed_login_store
+            .forgive_attempt(&FailedLoginKey::Ip(self.ip))
+            .await
+            .map_err(|_| AuthAPIError::UnexpectedError)
+    }
+}
+
+// Check the password of a logged in user, e.g. before changing it. The check
+// is throttled and counted like a login, or a stolen session would allow
+// guessing the password without limit.
+pub async fn verify_password(user: &User, password: &Password, ip: IpAddr, state: &AppState) -> Result<(), AuthAPIError> {
+    let attempt = count_login_attempt(&user.email, ip, state).await?;
+
+    let result = state.user_store.read().await.validate_user(&user.id, password).await;
+    match result {
+        Ok(()) => attempt.succeeded(state).await,
+        Err(e) => {
+            attempt.failed(state).await;
+            Err(match e {
+                UserStoreError::InvalidCredentials => AuthAPIError::IncorrectCredentials,
+                // The token may outlive the user it was issued to
+                UserStoreError::UserNotFound => AuthAPIError::InvalidToken,
+                _ => AuthAPIError::UnexpectedError,
+            })
+        }
+    }
+}
+
+async fn send_lockout_email(email: &Email, state: &AppState) -> Result<(), AuthAPIError> {
+    let user_store = state.user_store.read().await;
+    match user_store.get_user_by_email(email).await {
+        Ok(_) => {}
+        Err(UserStoreError::UserNotFound) => return Ok(()),
+        Err(_) => return Err(AuthAPIError::UnexpectedError),
+    }
+    drop(user_store);
+
+    let minutes = state.login_throttle.lockout_seconds.div_ceil(60);
+    let email_client = state.email_client.read().await;
+    email_client
+        .send_email(
+            email,
+            "Your account was locked",
+            &format!(
+                "After {} failed logins, logging in to your account is blocked for {} minutes. If it was not you, someone may be guessing your password; consider changing it once you can log in again.",
+                state.login_throttle.lockout_threshold, minutes
+            ),
+        )
+        .await
+        .map_err(|_| AuthAPIError::UnexpectedError)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn throttle() -> LoginThrottle {
+        LoginThrottle {
+            backoff_threshold: 3,
+            lockout_threshold: 10,
+            ip_backoff_threshold: 10,
+            ip_lockout_threshold: 50,
+            backoff_base_seconds: 1,
+            lockout_seconds: 900,
+        }
+    }
+
+    fn wait(count: u32, seconds_ago: i64) -> Option<Wait> {
+        let now = 1_000_000;
+        let failures = FailedLogins {
+            count,
+            last_failed_at: now - seconds_ago,
+        };
+        throttle().wait(&failures, 3, 10, now)
+    }
+
+    #[test]
+    fn test_no_wait_below_backoff_threshold() {
+        assert_eq!(wait(2, 0), None);
+    }
+
+    #[test]
+    fn test_backoff_doubles() {
+        assert_eq!(wait(3, 0), Some(Wait { seconds: 1, locked: false }));
+        assert_eq!(wait(4, 0), Some(Wait { seconds: 2, locked: false }));
+        assert_eq!(wait(9, 0), Some(Wait { seconds: 64, locked: false }));
+        assert_eq!(wait(9, 60), Some(Wait { seconds: 4, locked: false }));
+        assert_eq!(wait(9, 64), None);
+    }
+
+    #[test]
+    fn test_lockout() {
+        assert_eq!(wait(10, 0), Some(Wait { seconds: 900, locked: true }));
+        assert_eq!(wait(11, 100), Some(Wait { seconds: 800, locked: true }));
+        assert_eq!(wait(10, 900), None);
+    }
+
+    #[test]
+    fn test_backoff_capped_at_lockout() {
+        let mut throttle = throttle();
+        throttle.lockout_threshold = 100;
+        let failures = FailedLogins {
+            count: 90,
+            last_failed_at: 0,
+        };
+        assert_eq!(
+            throttle.wait(&failures, 3, 100, 0),
+            Some(Wait { seconds: 900, locked: false })
+        );
+    }
+}
@@ -0,0 +1,14 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+// Number of tokens held in the banned token store, as of the last sweep
+pub static BANNED_TOKEN_STORE_SIZE: AtomicUsize = AtomicUsize::new(0);
+
+// Render all metrics in the Prometheus text exposition format
+pub fn render() -> String {
+    format!(
+        "# HELP auth_service_banned_tokens Number of tokens held in the banned token store.\n\
+         # TYPE auth_service_banned_tokens gauge\n\
+         auth_service_banned_tokens {}\n",
+        BANNED_TOKEN_STORE_SIZE.load(Ordering::Relaxed)
+    )
+}
@@ -1,2 +1,11 @@
 pub mod constants;
-pub mod auth;
\ No newline at end of file
+pub mod account_deletion_sweeper;
+pub mod auth;
+pub mod banned_token_sweeper;
+pub mod client_auth;
+pub mod client_ip;
+pub mod failed_login_sweeper;
+pub mod keyring_reloader;
+pub mod keys;
+pub mod login_throttle;
+pub mod metrics;
\ No newline at end of file
@@ -0,0 +1,218 @@
+use crate::helpers::{get_random_email, TestApp};
+use auth_service::utils::constants::JWT_COOKIE_NAME;
+
+// Sign up and log in with the app's cookie jar, returning the email and the auth token
+async fn signup_and_login(app: &TestApp) -> (String, String) {
+    let email = get_random_email();
+    let response = app
+        .post_signup(&serde_json::json!({
+            "email": email,
+            "password": "Password123!",
+            "requires2FA": false
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 201);
+
+    let response = login(app, &email, "Password123!").await;
+    assert_eq!(response.status().as_u16(), 200);
+    let auth_token = response
+        .cookies()
+        .find(|cookie| cookie.name() == JWT_COOKIE_NAME)
+        .unwrap()
+        .value()
+        .to_owned();
+    (email, auth_token)
+}
+
+async fn login(app: &TestApp, email: &str, password: &str) -> reqwest::Response {
+    app.post_login(&serde_json::json!({
+        "email": email,
+        "password": password
+    }))
+    .await
+}
+
+// The token of the link in the latest email with `subject` to `recipient`
+fn emailed_token(app: &TestApp, recipient: &str, subject: &str) -> String {
+    let sent = app.email_client.sent_to(recipient);
+    let email = sent
+        .iter()
+        .rev()
+        .find(|sent| sent.subject == subject)
+        .unwrap_or_else(|| panic!("No email \"{}\" sent to {}", subject, recipient));
+    email.content.rsplit("token=").next().unwrap().to_owned()
+}
+
+// Request a change of the logged in user's email, returning the confirmation
+// and the cancel token
+async fn request_change(app: &TestApp, email: &str, new_email: &str) -> (String, String) {
+    let response = app
+        .post_change_email(&serde_json::json!({
+            "newEmail": new_email,
+            "password": "Password123!"
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    (
+        emailed_token(app, new_email, "Confirm your new email address"),
+        emailed_token(app, email, "Your email address is being changed"),
+    )
+}
+
+#[tokio::test]
+async fn should_return_400_if_token_missing() {
+    let app = TestApp::new().await;
+    let response = app
+        .post_change_email(&serde_json::json!({
+            "newEmail": get_random_email(),
+            "password": "Password123!"
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 400);
+}
+
+#[tokio::test]
+async fn should_return_400_if_new_email_invalid_or_unchanged() {
+    let app = TestApp::new().await;
+    let (email, _) = signup_and_login(&app).await;
+
+    for new_email in ["invalid-email", email.as_str()] {
+        let response = app
+            .post_change_email(&serde_json::json!({
+                "newEmail": new_email,
+                "password": "Password123!"
+            }))
+            .await;
+        assert_eq!(response.status().as_u16(), 400, "Failed for new email: {}", new_email);
+    }
+}
+
+#[tokio::test]
+async fn should_return_401_if_password_incorrect() {
+    let app = TestApp::new().await;
+    signup_and_login(&app).await;
+    let new_email = get_random_email();
+
+    let response = app
+        .post_change_email(&serde_json::json!({
+            "newEmail": new_email,
+            "password": "WrongPassword123!"
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 401);
+    assert!(app.email_client.sent_to(&new_email).is_empty());
+}
+
+#[tokio::test]
+async fn should_return_409_if_new_email_taken() {
+    let app = TestApp::new().await;
+    let taken_email = get_random_email();
+    let response = app
+        .post_signup(&serde_json::json!({
+            "email": taken_email,
+            "password": "Password123!",
+            "requires2FA": false
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 201);
+    signup_and_login(&app).await;
+
+    let response = app
+        .post_change_email(&serde_json::json!({
+            "newEmail": taken_email,
+            "password": "Password123!"
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 409);
+}
+
+#[tokio::test]
+async fn should_change_email_once_confirmed() {
+    let app = TestApp::new().await;
+    let (email, auth_token) = signup_and_login(&app).await;
+    let new_email = get_random_email();
+
+    let user_id = app.get_user(&email).await.id;
+    let (confirm_token, cancel_token) = request_change(&app, &email, &new_email).await;
+
+    // Nothing changes until the new address is confirmed
+    assert_eq!(login(&app, &email, "Password123!").await.status().as_u16(), 200);
+    let response = app.post_verify_token(&serde_json::json!({ "token": auth_token })).await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let response = app.post_confirm_email_change(&confirm_token).await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    assert_eq!(login(&app, &email, "Password123!").await.status().as_u16(), 401);
+    assert_eq!(login(&app, &new_email, "Password123!").await.status().as_u16(), 200);
+    // The user keeps their ID, which is the subject of their tokens
+    assert_eq!(app.get_user(&new_email).await.id, user_id);
+
+    // Tokens issued up to the change are banned
+    let response = app.post_verify_token(&serde_json::json!({ "token": auth_token })).await;
+    assert_eq!(response.status().as_u16(), 401);
+
+    // Both links are used up
+    let response = app.post_confirm_email_change(&confirm_token).await;
+    assert_eq!(response.status().as_u16(), 401);
+    let response = app.post_cancel_email_change(&cancel_token).await;
+    assert_eq!(response.status().as_u16(), 401);
+}
+
+#[tokio::test]
+async fn should_not_change_email_once_cancelled() {
+    let app = TestApp::new().await;
+    let (email, _) = signup_and_login(&app).await;
+    let new_email = get_random_email();
+    let (confirm_token, cancel_token) = request_change(&app, &email, &new_email).await;
+
+    let response = app.post_cancel_email_change(&cancel_token).await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let response = app.post_confirm_email_change(&confirm_token).await;
+    assert_eq!(response.status().as_u16(), 401);
+    assert_eq!(login(&app, &email, "Password123!").await.status().as_u16(), 200);
+    assert_eq!(login(&app, &new_email, "Password123!").await.status().as_u16(), 401);
+}
+
+#[tokio::test]
+async fn should_return_401_if_links_swapped() {
+    let app = TestApp::new().await;
+    let (email, _) = signup_and_login(&app).await;
+    let new_email = get_random_email();
+    let (confirm_token, cancel_token) = request_change(&app, &email, &new_email).await;
+
+    let response = app.post_confirm_email_change(&cancel_token).await;
+    assert_eq!(response.status().as_u16(), 401);
+    let response = app.post_cancel_email_change(&confirm_token).await;
+    assert_eq!(response.status().as_u16(), 401);
+}
+
+#[tokio::test]
+async fn opening_the_links_should_not_change_anything() {
+    let app = TestApp::new().await;
+    let (email, _) = signup_and_login(&app).await;
+    let new_email = get_random_email();
+    let (confirm_token, cancel_token) = request_change(&app, &email, &new_email).await;
+
+    // Mail scanners open links too, the pages only offer to submit the token
+    let response = app.get_confirm_email_change(&confirm_token).await;
+    assert_eq!(response.status().as_u16(), 200);
+    let page = response.text().await.unwrap();
+    assert!(page.contains(r#"action="/change-email/confirm""#));
+    assert!(page.contains(&confirm_token));
+
+    let response = app.get_cancel_email_change(&cancel_token).await;
+    assert_eq!(response.status().as_u16(), 200);
+    let page = response.text().await.unwrap();
+    assert!(page.contains(r#"action="/change-email/cancel""#));
+    assert!(page.contains(&cancel_token));
+
+    assert_eq!(login(&app, &email, "Password123!").await.status().as_u16(), 200);
+
+    // Both links still work
+    let response = app.post_confirm_email_change(&confirm_token).await;
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(login(&app, &new_email, "Password123!").await.status().as_u16(), 200);
+}
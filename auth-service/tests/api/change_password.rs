@@ -0,0 +1,155 @@
+use crate::helpers::{get_random_email, TestApp};
+use auth_service::{
+    routes::AuthTokenResponse,
+    utils::{constants::JWT_COOKIE_NAME, login_throttle::LoginThrottle},
+};
+
+// Sign up and log in with the app's cookie jar
+async fn signup_and_login(app: &TestApp) -> String {
+    let email = get_random_email();
+    let response = app
+        .post_signup(&serde_json::json!({
+            "email": email,
+            "password": "Password123!",
+            "requires2FA": false
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 201);
+
+    let response = login(app, &email, "Password123!").await;
+    assert_eq!(response.status().as_u16(), 200);
+    email
+}
+
+async fn login(app: &TestApp, email: &str, password: &str) -> reqwest::Response {
+    app.post_login(&serde_json::json!({
+        "email": email,
+        "password": password
+    }))
+    .await
+}
+
+#[tokio::test]
+async fn should_return_400_if_token_missing() {
+    let app = TestApp::new().await;
+    let response = app
+        .post_change_password(&serde_json::json!({
+            "currentPassword": "Password123!",
+            "newPassword": "NewPassword123!"
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 400);
+}
+
+#[tokio::test]
+async fn should_return_401_if_current_password_incorrect() {
+    let app = TestApp::new().await;
+    let email = signup_and_login(&app).await;
+
+    let response = app
+        .post_change_password(&serde_json::json!({
+            "currentPassword": "WrongPassword123!",
+            "newPassword": "NewPassword123!"
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 401);
+
+    assert_eq!(login(&app, &email, "Password123!").await.status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn should_lock_account_after_guessing_current_password() {
+    let app = TestApp::with_app_state(|app_state| {
+        app_state.with_login_throttle(LoginThrottle {
+            backoff_threshold: 100,
+            lockout_threshold: 3,
+            ip_backoff_threshold: 100,
+            ip_lockout_threshold: 100,
+            backoff_base_seconds: 60,
+            lockout_seconds: 900,
+        })
+    })
+    .await;
+    signup_and_login(&app).await;
+
+    // A stolen session must not allow guessing the password without limit
+    let wrong_body = serde_json::json!({
+        "currentPassword": "WrongPassword123!",
+        "newPassword": "NewPassword123!"
+    });
+    for _ in 0..3 {
+        assert_eq!(app.post_change_password(&wrong_body).await.status().as_u16(), 401);
+    }
+    let response = app
+        .post_change_password(&serde_json::json!({
+            "currentPassword": "Password123!",
+            "newPassword": "NewPassword123!"
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 423);
+}
+
+#[tokio::test]
+async fn should_return_400_if_new_password_invalid() {
+    let app = TestApp::new().await;
+    let email = signup_and_login(&app).await;
+
+    let response = app
+        .post_change_password(&serde_json::json!({
+            "currentPassword": "Password123!",
+            "newPassword": "short"
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 400);
+
+    assert_eq!(login(&app, &email, "Password123!").await.status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn should_change_password_and_end_other_sessions() {
+    let app = TestApp::new().await;
+    let email = signup_and_login(&app).await;
+
+    // Another session, e.g. on a different device, without the app's cookie jar
+    let response = reqwest::Client::new()
+        .post(format!("{}/login", &app.address))
+        .json(&serde_json::json!({
+            "email": email,
+            "password": "Password123!",
+            "returnToken": true
+        }))
+        .send()
+        .await
+        .unwrap();
+    let other_token = response.json::<AuthTokenResponse>().await.unwrap().token;
+
+    let response = app
+        .post_change_password(&serde_json::json!({
+            "currentPassword": "Password123!",
+            "newPassword": "NewPassword123!",
+            "returnToken": true
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+    let new_cookie = response
+        .cookies()
+        .find(|cookie| cookie.name() == JWT_COOKIE_NAME)
+        .expect("No auth cookie found")
+        .value()
+        .to_owned();
+    let body = response.json::<AuthTokenResponse>().await.unwrap();
+    assert_eq!(body.token, new_cookie);
+
+    let response = app.post_verify_token(&serde_json::json!({ "token": other_token })).await;
+    assert_eq!(response.status().as_u16(), 401);
+
+    // The current session carries on with its new tokens, although they
+    // were issued in the same second as the ban
+    let response = app.post_verify_token(&serde_json::json!({ "token": new_cookie })).await;
+    assert_eq!(response.status().as_u16(), 200);
+    let response = app.post_refresh().await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    assert_eq!(login(&app, &email, "Password123!").await.status().as_u16(), 401);
+    assert_eq!(login(&app, &email, "NewPassword123!").await.status().as_u16(), 200);
+}
@@ -0,0 +1,169 @@
+use crate::helpers::{get_random_email, TestApp};
+use auth_service::{
+    routes::{AccountDeletionScheduledResponse, TwoFactorAuthResponse},
+    utils::constants::JWT_COOKIE_NAME,
+};
+use chrono::Utc;
+
+// Sign up and log in with the app's cookie jar, returning the session's token
+async fn signup_and_login(app: &TestApp, email: &str, requires_2fa: bool) -> String {
+    let response = app
+        .post_signup(&serde_json::json!({
+            "email": email,
+            "password": "Password123!",
+            "requires2FA": requires_2fa
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 201);
+
+    let response = login(app, email).await;
+    if requires_2fa {
+        assert_eq!(response.status().as_u16(), 206);
+        let login_attempt_id = response.json::<TwoFactorAuthResponse>().await.unwrap().login_attempt_id;
+        let code = stored_code(app, email).await;
+        let response = app
+            .post_verify_2fa(&serde_json::json!({
+                "email": email,
+                "loginAttemptId": login_attempt_id,
+                "2FACode": code
+            }))
+            .await;
+        assert_eq!(response.status().as_u16(), 200);
+        return auth_cookie(&response);
+    }
+
+    assert_eq!(response.status().as_u16(), 200);
+    auth_cookie(&response)
+}
+
+async fn login(app: &TestApp, email: &str) -> reqwest::Response {
+    app.post_login(&serde_json::json!({
+        "email": email,
+        "password": "Password123!"
+    }))
+    .await
+}
+
+fn auth_cookie(response: &reqwest::Response) -> String {
+    response
+        .cookies()
+        .find(|cookie| cookie.name() == JWT_COOKIE_NAME)
+        .expect("No auth cookie found")
+        .value()
+        .to_owned()
+}
+
+async fn stored_code(app: &TestApp, email: &str) -> String {
+    let user_id = app.get_user(email).await.id;
+    let (_, code) = app.two_fa_code_store.read().await.get_code(&user_id).await.unwrap();
+    code.as_ref().to_owned()
+}
+
+#[tokio::test]
+async fn should_return_400_if_token_missing() {
+    let app = TestApp::new().await;
+    let response = app.delete_account(&serde_json::json!({ "password": "Password123!" })).await;
+    assert_eq!(response.status().as_u16(), 400);
+}
+
+#[tokio::test]
+async fn should_return_401_if_password_incorrect() {
+    let app = TestApp::new().await;
+    let email = get_random_email();
+    let token = signup_and_login(&app, &email, false).await;
+
+    let response = app.delete_account(&serde_json::json!({ "password": "WrongPassword123!" })).await;
+    assert_eq!(response.status().as_u16(), 401);
+
+    assert_eq!(app.get_user(&email).await.deletion_due_at(), None);
+    let response = app.post_verify_token(&serde_json::json!({ "token": token })).await;
+    assert_eq!(response.status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn should_schedule_deletion_and_end_sessions() {
+    let app = TestApp::new().await;
+    let email = get_random_email();
+    let token = signup_and_login(&app, &email, false).await;
+
+    let response = app.delete_account(&serde_json::json!({ "password": "Password123!" })).await;
+    assert_eq!(response.status().as_u16(), 200);
+    let body = response.json::<AccountDeletionScheduledResponse>().await.unwrap();
+    assert!(body.deletion_due_at > Utc::now().timestamp());
+    assert_eq!(app.get_user(&email).await.deletion_due_at(), Some(body.deletion_due_at));
+
+    let response = app.post_verify_token(&serde_json::json!({ "token": token })).await;
+    assert_eq!(response.status().as_u16(), 401);
+    // The response removed the session's cookies
+    let response = app.post_refresh().await;
+    assert_eq!(response.status().as_u16(), 400);
+
+    let sent = app.email_client.sent_to(&email);
+    assert!(sent.iter().any(|email| email.subject == "Your account will be deleted"));
+}
+
+#[tokio::test]
+async fn should_restore_account_on_login() {
+    let app = TestApp::new().await;
+    let email = get_random_email();
+    signup_and_login(&app, &email, false).await;
+
+    let response = app.delete_account(&serde_json::json!({ "password": "Password123!" })).await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let response = login(&app, &email).await;
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(app.get_user(&email).await.deletion_due_at(), None);
+}
+
+#[tokio::test]
+async fn should_require_second_factor_for_2fa_users() {
+    let app = TestApp::new().await;
+    let email = get_random_email();
+    signup_and_login(&app, &email, true).await;
+
+    let response = app.delete_account(&serde_json::json!({ "password": "Password123!" })).await;
+    assert_eq!(response.status().as_u16(), 206);
+    let login_attempt_id = response.json::<TwoFactorAuthResponse>().await.unwrap().login_attempt_id;
+    assert_eq!(app.get_user(&email).await.deletion_due_at(), None);
+
+    let response = app
+        .delete_account(&serde_json::json!({
+            "password": "Password123!",
+            "loginAttemptId": login_attempt_id,
+            "2FACode": "000000"
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 401);
+    assert_eq!(app.get_user(&email).await.deletion_due_at(), None);
+
+    let response = app
+        .delete_account(&serde_json::json!({
+            "password": "Password123!",
+            "loginAttemptId": login_attempt_id,
+            "2FACode": stored_code(&app, &email).await
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+    assert!(app.get_user(&email).await.deletion_due_at().is_some());
+}
+
+#[tokio::test]
+async fn should_not_log_in_once_deletion_is_due() {
+    let app = TestApp::new().await;
+    let email = get_random_email();
+    signup_and_login(&app, &email, false).await;
+
+    // The sweeper may not have purged the account yet
+    let user_id = app.get_user(&email).await.id;
+    app.user_store
+        .write()
+        .await
+        .schedule_deletion(&user_id, Utc::now().timestamp() - 1)
+        .await
+        .unwrap();
+
+    let response = login(&app, &email).await;
+    assert_eq!(response.status().as_u16(), 401);
+    assert!(app.get_user(&email).await.deletion_due_at().is_some());
+}
@@ -0,0 +1,93 @@
+use auth_service::{
+    routes::IntrospectResponse,
+    utils::constants::{test, JWT_COOKIE_NAME},
+};
+
+use crate::helpers::{get_random_email, TestApp};
+
+// Sign up and log in a user without 2FA, returning the auth token
+async fn login(app: &TestApp, email: &str) -> String {
+    let signup_body = serde_json::json!({
+        "email": email,
+        "password": "Password123!",
+        "requires2FA": false
+    });
+    let response = app.post_signup(&signup_body).await;
+    assert_eq!(response.status().as_u16(), 201);
+
+    let login_body = serde_json::json!({
+        "email": email,
+        "password": "Password123!",
+    });
+    let response = app.post_login(&login_body).await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let token = response
+        .cookies()
+        .find(|cookie| cookie.name() == JWT_COOKIE_NAME)
+        .map(|cookie| cookie.value().to_owned())
+        .expect("No auth cookie found");
+    token
+}
+
+#[tokio::test]
+async fn should_return_claims_of_active_token() {
+    let app = TestApp::new().await;
+    let email = get_random_email();
+    let token = login(&app, &email).await;
+
+    let body = [("token", token.as_str()), ("token_type_hint", "access_token")];
+    let response = app.post_introspect(&body, test::OAUTH_CLIENT_SECRET).await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let introspection = response
+        .json::<IntrospectResponse>()
+        .await
+        .expect("Could not deserialize response body to IntrospectResponse");
+    assert!(introspection.active);
+    // The subject is the user's ID, the email address is left out by default
+    assert_eq!(introspection.sub, Some(app.get_user(&email).await.id.as_ref().to_owned()));
+    assert_eq!(introspection.username, None);
+    assert!(introspection.exp > introspection.iat);
+    assert!(introspection.scope.is_some());
+    assert!(introspection.client_id.is_some());
+}
+
+#[tokio::test]
+async fn should_return_inactive_for_invalid_token() {
+    let app = TestApp::new().await;
+
+    let body = [("token", "invalid.jwt.token")];
+    let response = app.post_introspect(&body, test::OAUTH_CLIENT_SECRET).await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let introspection = response.json::<serde_json::Value>().await.unwrap();
+    assert_eq!(introspection, serde_json::json!({ "active": false }));
+}
+
+#[tokio::test]
+async fn should_return_inactive_for_banned_token() {
+    let app = TestApp::new().await;
+    let token = login(&app, &get_random_email()).await;
+
+    let response = app.logout().await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let body = [("token", token.as_str())];
+    let response = app.post_introspect(&body, test::OAUTH_CLIENT_SECRET).await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let introspection = response.json::<IntrospectResponse>().await.unwrap();
+    assert!(!introspection.active);
+}
+
+#[tokio::test]
+async fn should_return_401_if_client_not_authenticated() {
+    let app = TestApp::new().await;
+    let token = login(&app, &get_random_email()).await;
+
+    let body = [("token", token.as_str())];
+    let response = app.post_introspect(&body, "wrong-secret").await;
+    assert_eq!(response.status().as_u16(), 401);
+    assert!(response.headers().contains_key("www-authenticate"));
+}
@@ -0,0 +1,36 @@
+use auth_service::utils::keys::keyring;
+use jsonwebtoken::{encode, jwk::JwkSet, Header};
+
+use crate::helpers::TestApp;
+
+#[tokio::test]
+async fn should_return_200_with_public_keys() {
+    let app = TestApp::new().await;
+    let response = app.get_jwks().await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    assert!(response.headers().contains_key("cache-control"));
+
+    // Only public keys are published, an HS256 secret never is
+    let jwks = response.json::<JwkSet>().await.expect("Failed to parse JWKS");
+    assert_eq!(jwks, keyring().jwks());
+    if let Some(jwk) = keyring().active().jwk() {
+        assert!(jwks.keys.contains(jwk));
+    }
+}
+
+#[tokio::test]
+async fn should_reject_token_with_unknown_kid() {
+    let app = TestApp::new().await;
+
+    // Signed with the right key, but claiming to be signed with another one
+    let keyring = keyring();
+    let key = keyring.active();
+    let mut header = Header::new(key.algorithm());
+    header.kid = Some("unknown-key".to_owned());
+    let claims = serde_json::json!({ "sub": "test@example.com", "exp": 4_102_444_800u64 });
+    let token = encode(&header, &claims, key.encoding_key()).unwrap();
+
+    let response = app.post_verify_token(&serde_json::json!({ "token": token })).await;
+    assert_eq!(response.status().as_u16(), 401);
+}
@@ -1,6 +1,16 @@
+use std::{
+    net::{IpAddr, Ipv6Addr},
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
 use crate::helpers::{TestApp, get_random_email};
-use auth_service::{routes::TwoFactorAuthResponse, domain::Email};
-use auth_service::{utils::constants::JWT_COOKIE_NAME};
+use argon2::{password_hash::{rand_core::OsRng, PasswordHasher, SaltString}, Algorithm, Argon2, Params, Version};
+use auth_service::{routes::{AuthTokenResponse, TwoFactorAuthResponse}, domain::{Email, PasswordHash, User}};
+use auth_service::{utils::{constants::{test, JWT_COOKIE_NAME}, login_throttle::LoginThrottle}};
+use auth_service::{get_redis_connection, services::RedisFailedLoginStore};
+use tokio::sync::RwLock;
+use uuid::Uuid;
 
 #[tokio::test]
 async fn should_return_422_if_malformed_credentials() {
@@ -126,6 +136,45 @@ async fn should_return_200_if_valid_credentials_and_2fa_disabled() {
     assert!(!auth_cookie.value().is_empty());
 }
 
+#[tokio::test]
+async fn should_return_auth_token_in_body_if_requested() {
+    let app = TestApp::new().await;
+
+    let random_email = get_random_email();
+
+    let signup_body = serde_json::json!({
+        "email": random_email,
+        "password": "Password123!",
+        "requires2FA": false
+    });
+    let response = app.post_signup(&signup_body).await;
+    assert_eq!(response.status().as_u16(), 201);
+
+    let login_body = serde_json::json!({
+        "email": random_email,
+        "password": "Password123!",
+        "returnToken": true
+    });
+    let response = app.post_login(&login_body).await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let json_body = response
+        .json::<AuthTokenResponse>()
+        .await
+        .expect("Could not deserialize response body to AuthTokenResponse");
+    assert_eq!(json_body.token_type, "Bearer");
+
+    // The token works as a bearer token without any cookies
+    let response = reqwest::Client::new()
+        .post(format!("{}/2fa/totp/enroll", &app.address))
+        .bearer_auth(&json_body.token)
+        .json(&serde_json::json!({ "password": "Password123!" }))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    assert_eq!(response.status().as_u16(), 200);
+}
+
 #[tokio::test]
 async fn should_return_206_if_valid_credentials_and_2fa_enabled() {
     let app = TestApp::new().await;
@@ -153,10 +202,9 @@ async fn should_return_206_if_valid_credentials_and_2fa_enabled() {
         .expect("Could not deserialize response body to TwoFactorAuthResponse");
     assert_eq!(json_body.message, "2FA required".to_owned());
     
-    let email = Email::parse(random_email).expect("Failed to parse
-email");
+    let user_id = app.get_user(&random_email).await.id;
     let two_fa_store = app.two_fa_code_store.read().await;
-    let stored_code = two_fa_store.get_code(&email).await;
+    let stored_code = two_fa_store.get_code(&user_id).await;
     assert!(stored_code.is_ok(), "Login attempt ID should be 
            + stored in 2FA code store");
     
@@ -164,3 +212,364 @@ email");
     assert_eq!(json_body.login_attempt_id, stored_login_attempt_id.as_ref());
     
 }
+
+#[tokio::test]
+async fn should_rehash_bcrypt_password_on_login() {
+    let app = TestApp::new().await;
+    let random_email = get_random_email();
+    let email = Email::parse(random_email.clone()).unwrap();
+
+    // Simulate a user imported from a system that used bcrypt
+    let bcrypt_hash = PasswordHash::parse(bcrypt::hash("Password123!", 4).unwrap()).unwrap();
+    app.user_store
+        .write()
+        .await
+        .add_user(User::new(email.clone(), bcrypt_hash.clone(), false))
+        .await
+        .unwrap();
+
+    let login_body = serde_json::json!({
+        "email": random_email,
+        "password": "Password123!",
+    });
+
+    let response = app.post_login(&login_body).await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let user = app.user_store.read().await.get_user_by_email(&email).await.unwrap();
+    assert_ne!(user.password_hash(), &bcrypt_hash);
+    assert!(user.password_hash().as_ref().starts_with("$argon2id$"));
+    assert!(!user.password_hash().needs_rehash());
+
+    // The upgraded hash keeps working
+    let response = app.post_login(&login_body).await;
+    assert_eq!(response.status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn should_rehash_password_with_outdated_argon2_params_on_login() {
+    let app = TestApp::new().await;
+    let random_email = get_random_email();
+    let email = Email::parse(random_email.clone()).unwrap();
+
+    let salt = SaltString::generate(&mut OsRng);
+    let weak_hash = Argon2::new(Algorithm::Argon2id, Version::V0x13, Params::new(8, 1, 1, None).unwrap())
+        .hash_password(b"Password123!", &salt)
+        .unwrap()
+        .to_string();
+    let weak_hash = PasswordHash::parse(weak_hash).unwrap();
+    app.user_store
+        .write()
+        .await
+        .add_user(User::new(email.clone(), weak_hash.clone(), false))
+        .await
+        .unwrap();
+
+    let login_body = serde_json::json!({
+        "email": random_email,
+        "password": "Password123!",
+    });
+
+    let response = app.post_login(&login_body).await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let user = app.user_store.read().await.get_user_by_email(&email).await.unwrap();
+    assert_ne!(user.password_hash(), &weak_hash);
+    assert!(!user.password_hash().needs_rehash());
+}
+
+#[tokio::test]
+async fn should_not_rehash_password_on_failed_login() {
+    let app = TestApp::new().await;
+    let random_email = get_random_email();
+    let email = Email::parse(random_email.clone()).unwrap();
+
+    let bcrypt_hash = PasswordHash::parse(bcrypt::hash("Password123!", 4).unwrap()).unwrap();
+    app.user_store
+        .write()
+        .await
+        .add_user(User::new(email.clone(), bcrypt_hash.clone(), false))
+        .await
+        .unwrap();
+
+    let login_body = serde_json::json!({
+        "email": random_email,
+        "password": "WrongPassword123!",
+    });
+
+    let response = app.post_login(&login_body).await;
+    assert_eq!(response.status().as_u16(), 401);
+
+    let user = app.user_store.read().await.get_user_by_email(&email).await.unwrap();
+    assert_eq!(user.password_hash(), &bcrypt_hash);
+}
+
+// Throttling tuned so that a test reaches one threshold at a time, the other
+// ones are far out of reach
+fn login_throttle() -> LoginThrottle {
+    LoginThrottle {
+        backoff_threshold: 100,
+        lockout_threshold: 100,
+        ip_backoff_threshold: 100,
+        ip_lockout_threshold: 100,
+        backoff_base_seconds: 60,
+        lockout_seconds: 900,
+    }
+}
+
+async fn signup(app: &TestApp) -> String {
+    let email = get_random_email();
+    let response = app
+        .post_signup(&serde_json::json!({
+            "email": email,
+            "password": "Password123!",
+            "requires2FA": false
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 201);
+    email
+}
+
+async fn login(app: &TestApp, email: &str, password: &str) -> reqwest::Response {
+    app.post_login(&serde_json::json!({
+        "email": email,
+        "password": password
+    }))
+    .await
+}
+
+fn retry_after(response: &reqwest::Response) -> u64 {
+    response
+        .headers()
+        .get("retry-after")
+        .expect("No Retry-After header")
+        .to_str()
+        .unwrap()
+        .parse()
+        .unwrap()
+}
+
+#[tokio::test]
+async fn should_return_429_while_backing_off_after_failed_logins() {
+    let app = TestApp::with_app_state(|app_state| {
+        app_state.with_login_throttle(LoginThrottle {
+            backoff_threshold: 2,
+            ..login_throttle()
+        })
+    })
+    .await;
+    let email = signup(&app).await;
+
+    for _ in 0..2 {
+        assert_eq!(login(&app, &email, "WrongPassword123!").await.status().as_u16(), 401);
+    }
+
+    // Even the right password has to wait
+    let response = login(&app, &email, "Password123!").await;
+    assert_eq!(response.status().as_u16(), 429);
+    let retry_after = retry_after(&response);
+    assert!(retry_after > 0 && retry_after <= 60);
+}
+
+#[tokio::test]
+async fn should_lock_account_and_email_owner_after_failed_logins() {
+    let app = TestApp::with_app_state(|app_state| {
+        app_state.with_login_throttle(LoginThrottle {
+            lockout_threshold: 3,
+            ..login_throttle()
+        })
+    })
+    .await;
+    let email = signup(&app).await;
+
+    for _ in 0..3 {
+        assert_eq!(login(&app, &email, "WrongPassword123!").await.status().as_u16(), 401);
+    }
+
+    let response = login(&app, &email, "Password123!").await;
+    assert_eq!(response.status().as_u16(), 423);
+    let retry_after = retry_after(&response);
+    assert!(retry_after > 0 && retry_after <= 900);
+
+    let sent = app.email_client.sent_to(&email);
+    assert_eq!(sent.iter().filter(|email| email.subject == "Your account was locked").count(), 1);
+
+    // Other accounts are not affected
+    let other_email = signup(&app).await;
+    assert_eq!(login(&app, &other_email, "Password123!").await.status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn should_lock_unknown_email_like_an_account() {
+    let app = TestApp::with_app_state(|app_state| {
+        app_state.with_login_throttle(LoginThrottle {
+            lockout_threshold: 3,
+            ..login_throttle()
+        })
+    })
+    .await;
+    let email = get_random_email();
+
+    for _ in 0..3 {
+        assert_eq!(login(&app, &email, "WrongPassword123!").await.status().as_u16(), 401);
+    }
+
+    assert_eq!(login(&app, &email, "Password123!").await.status().as_u16(), 423);
+    assert!(app.email_client.sent_to(&email).is_empty());
+}
+
+#[tokio::test]
+async fn should_forget_failed_logins_after_successful_login() {
+    let app = TestApp::with_app_state(|app_state| {
+        app_state.with_login_throttle(LoginThrottle {
+            backoff_threshold: 3,
+            ..login_throttle()
+        })
+    })
+    .await;
+    let email = signup(&app).await;
+
+    for _ in 0..2 {
+        assert_eq!(login(&app, &email, "WrongPassword123!").await.status().as_u16(), 401);
+    }
+    assert_eq!(login(&app, &email, "Password123!").await.status().as_u16(), 200);
+
+    for _ in 0..2 {
+        assert_eq!(login(&app, &email, "WrongPassword123!").await.status().as_u16(), 401);
+    }
+    assert_eq!(login(&app, &email, "Password123!").await.status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn should_return_429_for_client_failing_across_accounts() {
+    let app = TestApp::with_app_state(|app_state| {
+        app_state.with_login_throttle(LoginThrottle {
+            ip_backoff_threshold: 3,
+            ..login_throttle()
+        })
+    })
+    .await;
+
+    for _ in 0..3 {
+        let email = get_random_email();
+        assert_eq!(login(&app, &email, "WrongPassword123!").await.status().as_u16(), 401);
+    }
+
+    let email = signup(&app).await;
+    let response = login(&app, &email, "Password123!").await;
+    assert_eq!(response.status().as_u16(), 429);
+    assert!(retry_after(&response) > 0);
+}
+
+#[tokio::test]
+async fn should_count_concurrent_failed_logins() {
+    let app = TestApp::with_app_state(|app_state| {
+        app_state.with_login_throttle(LoginThrottle {
+            lockout_threshold: 3,
+            ..login_throttle()
+        })
+    })
+    .await;
+    let email = signup(&app).await;
+
+    // Guesses sent at once must not all get past the count of the others
+    let responses = tokio::join!(
+        login(&app, &email, "WrongPassword1!"),
+        login(&app, &email, "WrongPassword2!"),
+        login(&app, &email, "WrongPassword3!"),
+        login(&app, &email, "WrongPassword4!"),
+        login(&app, &email, "WrongPassword5!"),
+        login(&app, &email, "WrongPassword6!"),
+    );
+    let statuses = [
+        responses.0.status().as_u16(),
+        responses.1.status().as_u16(),
+        responses.2.status().as_u16(),
+        responses.3.status().as_u16(),
+        responses.4.status().as_u16(),
+        responses.5.status().as_u16(),
+    ];
+    assert_eq!(statuses.iter().filter(|&&status| status == 401).count(), 3);
+    assert_eq!(statuses.iter().filter(|&&status| status == 423).count(), 3);
+
+    assert_eq!(login(&app, &email, "Password123!").await.status().as_u16(), 423);
+}
+
+#[tokio::test]
+async fn should_end_lockout_while_refused_attempts_keep_arriving() {
+    let app = TestApp::with_app_state(|app_state| {
+        app_state.with_login_throttle(LoginThrottle {
+            lockout_threshold: 3,
+            lockout_seconds: 4,
+            ..login_throttle()
+        })
+    })
+    .await;
+    let email = signup(&app).await;
+
+    for _ in 0..2 {
+        assert_eq!(login(&app, &email, "WrongPassword123!").await.status().as_u16(), 401);
+    }
+    // The lockout starts somewhere in between, its end is only known to the second
+    let before_lockout = Instant::now();
+    assert_eq!(login(&app, &email, "WrongPassword123!").await.status().as_u16(), 401);
+    let after_lockout = Instant::now();
+
+    // Guesses refused during the lockout must not extend it
+    loop {
+        tokio::time::sleep(Duration::from_millis(500)).await;
+        if before_lockout.elapsed() >= Duration::from_millis(2500) {
+            break;
+        }
+        assert_eq!(login(&app, &email, "WrongPassword123!").await.status().as_u16(), 423);
+    }
+
+    tokio::time::sleep(Duration::from_millis(4200).saturating_sub(after_lockout.elapsed())).await;
+    assert_eq!(login(&app, &email, "Password123!").await.status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn should_share_failed_logins_between_replicas_through_redis() {
+    let Ok(url) = std::env::var(test::REDIS_URL_ENV_VAR) else {
+        return;
+    };
+    let redis_connection = get_redis_connection(&url).await.expect("Failed to connect to Redis");
+    let replica = || {
+        let redis_connection = redis_connection.clone();
+        TestApp::with_app_state(move |mut app_state| {
+            app_state.failed_login_store = Arc::new(RwLock::new(RedisFailedLoginStore::new(redis_connection, 900)));
+            app_state.with_login_throttle(LoginThrottle {
+                lockout_threshold: 3,
+                ip_backoff_threshold: 3,
+                ..login_throttle()
+            })
+        })
+    };
+    let (first, second) = (replica().await, replica().await);
+
+    // Addresses of their own keep the counts apart from other tests sharing the Redis
+    let email = get_random_email();
+    let login_body = serde_json::json!({
+        "email": email,
+        "password": "WrongPassword123!"
+    });
+    let ips: Vec<IpAddr> = (0..3).map(|_| Ipv6Addr::from(Uuid::new_v4().as_u128()).into()).collect();
+    assert_eq!(first.post_login_from(&login_body, ips[0]).await.status().as_u16(), 401);
+    assert_eq!(second.post_login_from(&login_body, ips[1]).await.status().as_u16(), 401);
+    assert_eq!(first.post_login_from(&login_body, ips[2]).await.status().as_u16(), 401);
+    assert_eq!(second.post_login_from(&login_body, ips[0]).await.status().as_u16(), 423);
+
+    // A client failing across accounts is slowed down on every replica
+    for _ in 0..2 {
+        let login_body = serde_json::json!({
+            "email": get_random_email(),
+            "password": "WrongPassword123!"
+        });
+        assert_eq!(first.post_login_from(&login_body, ips[1]).await.status().as_u16(), 401);
+    }
+    let login_body = serde_json::json!({
+        "email": get_random_email(),
+        "password": "WrongPassword123!"
+    });
+    assert_eq!(second.post_login_from(&login_body, ips[1]).await.status().as_u16(), 429);
+}
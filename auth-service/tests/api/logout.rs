@@ -1,4 +1,4 @@
-use auth_service::{utils::constants::JWT_COOKIE_NAME};
+use auth_service::{routes::AuthTokenResponse, utils::{auth::validate_token, constants::JWT_COOKIE_NAME}};
 use reqwest::{Url, cookie::CookieStore};
 
 use crate::helpers::{TestApp, get_random_email};
@@ -63,6 +63,7 @@ async fn should_return_200_if_valid_jwt_cookie() {
         .split(';')
         .next()
         .unwrap();
+    let jti = validate_token(token_value, None).await.unwrap().jti;
 
     // First logout should succeed
     let response = app.logout().await;
@@ -70,7 +71,7 @@ async fn should_return_200_if_valid_jwt_cookie() {
 
     // Verify token was added to banned store
     let banned_store = app.banned_token_store.read().await;
-    assert!(banned_store.is_token_exists(token_value).await.unwrap());
+    assert!(banned_store.is_token_exists(&jti).await.unwrap());
 }
 
 #[tokio::test]
@@ -106,3 +107,39 @@ async fn should_return_400_if_logout_called_twice_in_a_row() {
     let response = app.logout().await;
     assert_eq!(response.status(), 400);
 }
+
+#[tokio::test]
+async fn should_return_200_if_valid_bearer_token() {
+    let app = TestApp::new().await;
+
+    let random_email = get_random_email();
+
+    let signup_body = serde_json::json!({
+        "email": random_email,
+        "password": "Password123!",
+        "requires2FA": false
+    });
+    let response = app.post_signup(&signup_body).await;
+    assert_eq!(response.status().as_u16(), 201);
+
+    let login_body = serde_json::json!({
+        "email": random_email,
+        "password": "Password123!",
+        "returnToken": true
+    });
+    let response = app.post_login(&login_body).await;
+    assert_eq!(response.status().as_u16(), 200);
+    let token = response.json::<AuthTokenResponse>().await.unwrap().token;
+
+    // Log out without any cookies
+    let response = reqwest::Client::new()
+        .post(format!("{}/logout", &app.address))
+        .bearer_auth(&token)
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    assert_eq!(response.status(), 200);
+
+    let response = app.post_verify_token(&serde_json::json!({ "token": token })).await;
+    assert_eq!(response.status().as_u16(), 401);
+}
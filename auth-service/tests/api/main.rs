@@ -1,7 +1,19 @@
+mod change_email;
+mod change_password;
+mod delete_account;
 mod helpers;
+mod introspect;
+mod jwks;
 mod login;
 mod logout;
+mod metrics;
+mod password_reset;
+mod recovery_codes;
+mod refresh;
+mod revoke;
 mod root;
 mod signup;
+mod totp;
 mod verify_2fa;
+mod verify_email;
 mod verify_token;
\ No newline at end of file
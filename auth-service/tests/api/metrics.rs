@@ -0,0 +1,13 @@
+use crate::helpers::TestApp;
+
+#[tokio::test]
+async fn should_return_banned_token_store_size() {
+    let app = TestApp::new().await;
+    let response = app.get_metrics().await;
+
+    assert_eq!(response.status().as_u16(), 200);
+
+    let body = response.text().await.expect("Failed to read response body");
+    assert!(body.contains("# TYPE auth_service_banned_tokens gauge"));
+    assert!(body.lines().any(|line| line.starts_with("auth_service_banned_tokens ")));
+}
@@ -0,0 +1,181 @@
+use crate::helpers::{get_random_email, TestApp};
+use auth_service::utils::constants::JWT_COOKIE_NAME;
+
+async fn signup(app: &TestApp, email: &str) {
+    let response = app
+        .post_signup(&serde_json::json!({
+            "email": email,
+            "password": "Password123!",
+            "requires2FA": false
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 201);
+}
+
+async fn login(app: &TestApp, email: &str, password: &str) -> reqwest::Response {
+    app.post_login(&serde_json::json!({
+        "email": email,
+        "password": password
+    }))
+    .await
+}
+
+// The token is the last word of the latest reset email
+async fn request_reset_token(app: &TestApp, email: &str) -> String {
+    let response = app.post_password_reset_request(&serde_json::json!({ "email": email })).await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let reset_email = app.email_client.wait_for(email, "Reset your password").await;
+    reset_email.content.split_whitespace().last().unwrap().to_owned()
+}
+
+#[tokio::test]
+async fn should_answer_request_alike_for_any_account() {
+    let app = TestApp::new().await;
+    let email = get_random_email();
+    signup(&app, &email).await;
+    let unknown_email = get_random_email();
+
+    let response = app.post_password_reset_request(&serde_json::json!({ "email": email })).await;
+    assert_eq!(response.status().as_u16(), 200);
+    let known_body = response.text().await.unwrap();
+
+    let response = app.post_password_reset_request(&serde_json::json!({ "email": unknown_email })).await;
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(response.text().await.unwrap(), known_body);
+
+    app.email_client.wait_for(&email, "Reset your password").await;
+    assert!(app.email_client.sent_to(&unknown_email).is_empty());
+}
+
+#[tokio::test]
+async fn should_return_400_if_request_for_invalid_email() {
+    let app = TestApp::new().await;
+    let response = app
+        .post_password_reset_request(&serde_json::json!({ "email": "invalid-email" }))
+        .await;
+    assert_eq!(response.status().as_u16(), 400);
+}
+
+#[tokio::test]
+async fn should_reset_password_once() {
+    let app = TestApp::new().await;
+    let email = get_random_email();
+    signup(&app, &email).await;
+    let token = request_reset_token(&app, &email).await;
+
+    let confirm = serde_json::json!({
+        "token": token,
+        "password": "NewPassword123!"
+    });
+    let response = app.post_password_reset_confirm(&confirm).await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    assert_eq!(login(&app, &email, "Password123!").await.status().as_u16(), 401);
+    assert_eq!(login(&app, &email, "NewPassword123!").await.status().as_u16(), 200);
+
+    // The token is used up
+    let response = app
+        .post_password_reset_confirm(&serde_json::json!({
+            "token": token,
+            "password": "OtherPassword123!"
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 401);
+    assert_eq!(login(&app, &email, "NewPassword123!").await.status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn should_return_400_and_keep_token_if_invalid_password() {
+    let app = TestApp::new().await;
+    let email = get_random_email();
+    signup(&app, &email).await;
+    let token = request_reset_token(&app, &email).await;
+
+    let response = app
+        .post_password_reset_confirm(&serde_json::json!({
+            "token": token,
+            "password": "short"
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 400);
+
+    let response = app
+        .post_password_reset_confirm(&serde_json::json!({
+            "token": token,
+            "password": "NewPassword123!"
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn should_return_401_if_invalid_token() {
+    let app = TestApp::new().await;
+    let email = get_random_email();
+    signup(&app, &email).await;
+
+    // An auth token is no reset token
+    let response = login(&app, &email, "Password123!").await;
+    let auth_token = response
+        .cookies()
+        .find(|cookie| cookie.name() == JWT_COOKIE_NAME)
+        .unwrap()
+        .value()
+        .to_owned();
+
+    for token in ["invalid", auth_token.as_str()] {
+        let response = app
+            .post_password_reset_confirm(&serde_json::json!({
+                "token": token,
+                "password": "NewPassword123!"
+            }))
+            .await;
+        assert_eq!(response.status().as_u16(), 401, "Failed for token: {}", token);
+    }
+}
+
+#[tokio::test]
+async fn should_revoke_existing_sessions() {
+    let app = TestApp::new().await;
+    let email = get_random_email();
+    signup(&app, &email).await;
+
+    let response = login(&app, &email, "Password123!").await;
+    assert_eq!(response.status().as_u16(), 200);
+    let auth_token = response
+        .cookies()
+        .find(|cookie| cookie.name() == JWT_COOKIE_NAME)
+        .unwrap()
+        .value()
+        .to_owned();
+
+    let token = request_reset_token(&app, &email).await;
+    let response = app
+        .post_password_reset_confirm(&serde_json::json!({
+            "token": token,
+            "password": "NewPassword123!"
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let response = app.post_verify_token(&serde_json::json!({ "token": auth_token })).await;
+    assert_eq!(response.status().as_u16(), 401);
+
+    // The refresh cookie from the login is still in the client's jar
+    let response = app.post_refresh().await;
+    assert_eq!(response.status().as_u16(), 401);
+
+    // Tokens issued up to the second of the reset are banned, new sessions
+    // work from the next second on
+    tokio::time::sleep(std::time::Duration::from_secs(1)).await;
+    let response = login(&app, &email, "NewPassword123!").await;
+    let auth_token = response
+        .cookies()
+        .find(|cookie| cookie.name() == JWT_COOKIE_NAME)
+        .unwrap()
+        .value()
+        .to_owned();
+    let response = app.post_verify_token(&serde_json::json!({ "token": auth_token })).await;
+    assert_eq!(response.status().as_u16(), 200);
+}
@@ -0,0 +1,234 @@
+use auth_service::{
+    domain::recovery_code::RECOVERY_CODE_COUNT,
+    routes::{RecoveryCodesResponse, SignupResponse, TwoFactorAuthResponse},
+    utils::constants::JWT_COOKIE_NAME,
+};
+
+use crate::helpers::{get_random_email, TestApp};
+
+// Sign up a user with 2FA, returning the email and the recovery codes
+async fn signup_with_2fa(app: &TestApp) -> (String, Vec<String>) {
+    let email = get_random_email();
+
+    let signup_body = serde_json::json!({
+        "email": email,
+        "password": "Password123!",
+        "requires2FA": true
+    });
+    let response = app.post_signup(&signup_body).await;
+    assert_eq!(response.status().as_u16(), 201);
+
+    let recovery_codes = response
+        .json::<SignupResponse>()
+        .await
+        .expect("Could not deserialize response body to SignupResponse")
+        .recovery_codes
+        .expect("No recovery codes in signup response");
+
+    (email, recovery_codes)
+}
+
+// Log in with the password, returning the login attempt id
+async fn start_login(app: &TestApp, email: &str) -> String {
+    let login_body = serde_json::json!({
+        "email": email,
+        "password": "Password123!"
+    });
+    let response = app.post_login(&login_body).await;
+    assert_eq!(response.status().as_u16(), 206);
+
+    response
+        .json::<TwoFactorAuthResponse>()
+        .await
+        .expect("Could not deserialize response body to TwoFactorAuthResponse")
+        .login_attempt_id
+}
+
+async fn verify_with_code(app: &TestApp, email: &str, login_attempt_id: &str, code: &str) -> reqwest::Response {
+    let verify_body = serde_json::json!({
+        "email": email,
+        "loginAttemptId": login_attempt_id,
+        "2FACode": code
+    });
+    app.post_verify_2fa(&verify_body).await
+}
+
+#[tokio::test]
+async fn should_log_in_with_recovery_code_only_once() {
+    let app = TestApp::new().await;
+    let (email, recovery_codes) = signup_with_2fa(&app).await;
+    assert_eq!(recovery_codes.len(), RECOVERY_CODE_COUNT);
+
+    let login_attempt_id = start_login(&app, &email).await;
+    let response = verify_with_code(&app, &email, &login_attempt_id, &recovery_codes[0]).await;
+    assert_eq!(response.status().as_u16(), 200);
+    assert!(response.cookies().any(|cookie| cookie.name() == JWT_COOKIE_NAME));
+
+    // The code is consumed
+    let login_attempt_id = start_login(&app, &email).await;
+    let response = verify_with_code(&app, &email, &login_attempt_id, &recovery_codes[0]).await;
+    assert_eq!(response.status().as_u16(), 401);
+
+    // Other codes still work, regardless of case
+    let response =
+        verify_with_code(&app, &email, &login_attempt_id, &recovery_codes[1].to_uppercase()).await;
+    assert_eq!(response.status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn should_not_consume_recovery_code_for_wrong_login_attempt() {
+    let app = TestApp::new().await;
+    let (email, recovery_codes) = signup_with_2fa(&app).await;
+
+    let login_attempt_id = start_login(&app, &email).await;
+    let response =
+        verify_with_code(&app, &email, "00000000-0000-4000-8000-000000000000", &recovery_codes[0]).await;
+    assert_eq!(response.status().as_u16(), 401);
+
+    let response = verify_with_code(&app, &email, &login_attempt_id, &recovery_codes[0]).await;
+    assert_eq!(response.status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn should_not_consume_recovery_code_for_login_attempt_completed_concurrently() {
+    let app = TestApp::new().await;
+    let (email, recovery_codes) = signup_with_2fa(&app).await;
+
+    // Only one request completes the login attempt, the other code is kept
+    let login_attempt_id = start_login(&app, &email).await;
+    let (first, second) = tokio::join!(
+        verify_with_code(&app, &email, &login_attempt_id, &recovery_codes[0]),
+        verify_with_code(&app, &email, &login_attempt_id, &recovery_codes[1]),
+    );
+    let (first, second) = (first.status().as_u16(), second.status().as_u16());
+    assert_eq!((first.min(second), first.max(second)), (200, 401));
+    let unused_code = if first == 200 { &recovery_codes[1] } else { &recovery_codes[0] };
+
+    let login_attempt_id = start_login(&app, &email).await;
+    let response = verify_with_code(&app, &email, &login_attempt_id, unused_code).await;
+    assert_eq!(response.status().as_u16(), 200);
+}
+
+// Regenerate the logged in user's recovery codes, confirming with the
+// password and the emailed 2FA code
+async fn regenerate(app: &TestApp, email: &str) -> reqwest::Response {
+    let response = app
+        .post_regenerate_recovery_codes(&serde_json::json!({ "password": "Password123!" }))
+        .await;
+    assert_eq!(response.status().as_u16(), 206);
+    let login_attempt_id = response
+        .json::<TwoFactorAuthResponse>()
+        .await
+        .expect("Could not deserialize response body to TwoFactorAuthResponse")
+        .login_attempt_id;
+
+    let user_id = app.get_user(email).await.id;
+    let (_, code) = app.two_fa_code_store.read().await.get_code(&user_id).await.unwrap();
+    app.post_regenerate_recovery_codes(&serde_json::json!({
+        "password": "Password123!",
+        "loginAttemptId": login_attempt_id,
+        "2FACode": code.as_ref()
+    }))
+    .await
+}
+
+#[tokio::test]
+async fn should_invalidate_old_recovery_codes_on_regenerate() {
+    let app = TestApp::new().await;
+    let (email, old_recovery_codes) = signup_with_2fa(&app).await;
+
+    let login_attempt_id = start_login(&app, &email).await;
+    let response = verify_with_code(&app, &email, &login_attempt_id, &old_recovery_codes[0]).await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let response = regenerate(&app, &email).await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let new_recovery_codes = response
+        .json::<RecoveryCodesResponse>()
+        .await
+        .expect("Could not deserialize response body to RecoveryCodesResponse")
+        .recovery_codes;
+    assert_eq!(new_recovery_codes.len(), RECOVERY_CODE_COUNT);
+    assert!(new_recovery_codes.iter().all(|code| !old_recovery_codes.contains(code)));
+
+    let login_attempt_id = start_login(&app, &email).await;
+    let response = verify_with_code(&app, &email, &login_attempt_id, &old_recovery_codes[1]).await;
+    assert_eq!(response.status().as_u16(), 401);
+
+    let response = verify_with_code(&app, &email, &login_attempt_id, &new_recovery_codes[0]).await;
+    assert_eq!(response.status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn should_require_password_and_second_factor_to_regenerate() {
+    let app = TestApp::new().await;
+    let (email, recovery_codes) = signup_with_2fa(&app).await;
+    let login_attempt_id = start_login(&app, &email).await;
+    let response = verify_with_code(&app, &email, &login_attempt_id, &recovery_codes[0]).await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let response = app
+        .post_regenerate_recovery_codes(&serde_json::json!({ "password": "WrongPassword123!" }))
+        .await;
+    assert_eq!(response.status().as_u16(), 401);
+
+    // The password alone only starts a 2FA attempt
+    let response = app
+        .post_regenerate_recovery_codes(&serde_json::json!({ "password": "Password123!" }))
+        .await;
+    assert_eq!(response.status().as_u16(), 206);
+    let login_attempt_id = response
+        .json::<TwoFactorAuthResponse>()
+        .await
+        .expect("Could not deserialize response body to TwoFactorAuthResponse")
+        .login_attempt_id;
+
+    let response = app
+        .post_regenerate_recovery_codes(&serde_json::json!({
+            "password": "Password123!",
+            "loginAttemptId": login_attempt_id,
+            "2FACode": "000000"
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 401);
+
+    // A remaining recovery code works as the second factor
+    let response = app
+        .post_regenerate_recovery_codes(&serde_json::json!({
+            "password": "Password123!",
+            "loginAttemptId": login_attempt_id,
+            "2FACode": recovery_codes[1]
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn should_return_400_if_regenerating_without_2fa() {
+    let app = TestApp::new().await;
+    let email = get_random_email();
+    let body = serde_json::json!({ "password": "Password123!" });
+
+    // Without auth cookie
+    let response = app.post_regenerate_recovery_codes(&body).await;
+    assert_eq!(response.status().as_u16(), 400);
+
+    let signup_body = serde_json::json!({
+        "email": email,
+        "password": "Password123!",
+        "requires2FA": false
+    });
+    let response = app.post_signup(&signup_body).await;
+    assert_eq!(response.status().as_u16(), 201);
+
+    let login_body = serde_json::json!({
+        "email": email,
+        "password": "Password123!"
+    });
+    let response = app.post_login(&login_body).await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let response = app.post_regenerate_recovery_codes(&body).await;
+    assert_eq!(response.status().as_u16(), 400);
+}
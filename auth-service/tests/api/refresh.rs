@@ -0,0 +1,136 @@
+use auth_service::{
+    domain::{RefreshToken, RefreshTokenStoreError},
+    utils::constants::{JWT_COOKIE_NAME, REFRESH_COOKIE_NAME},
+};
+use reqwest::{cookie::CookieStore, Url};
+
+use crate::helpers::{get_random_email, TestApp};
+
+// Sign up and log in a user without 2FA, returning the refresh token
+async fn login(app: &TestApp) -> String {
+    let random_email = get_random_email();
+
+    let signup_body = serde_json::json!({
+        "email": random_email,
+        "password": "Password123!",
+        "requires2FA": false
+    });
+    let response = app.post_signup(&signup_body).await;
+    assert_eq!(response.status().as_u16(), 201);
+
+    let login_body = serde_json::json!({
+        "email": random_email,
+        "password": "Password123!",
+    });
+    let response = app.post_login(&login_body).await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    get_cookie(&response, REFRESH_COOKIE_NAME).expect("No refresh cookie found")
+}
+
+fn get_cookie(response: &reqwest::Response, name: &str) -> Option<String> {
+    response
+        .cookies()
+        .find(|cookie| cookie.name() == name)
+        .map(|cookie| cookie.value().to_owned())
+}
+
+// Put a refresh token into the client's cookie jar, replacing the current one
+fn set_refresh_cookie(app: &TestApp, token: &str) {
+    app.cookie_jar.add_cookie_str(
+        &format!("{}={}; HttpOnly; SameSite=Lax; Path=/", REFRESH_COOKIE_NAME, token),
+        &Url::parse("http://127.0.0.1").expect("Failed to parse URL"),
+    );
+}
+
+#[tokio::test]
+async fn should_set_refresh_cookie_on_login() {
+    let app = TestApp::new().await;
+    let refresh_token = login(&app).await;
+    assert!(!refresh_token.is_empty());
+}
+
+#[tokio::test]
+async fn should_return_400_if_refresh_cookie_missing() {
+    let app = TestApp::new().await;
+    let response = app.post_refresh().await;
+    assert_eq!(response.status().as_u16(), 400);
+}
+
+#[tokio::test]
+async fn should_return_401_if_invalid_refresh_token() {
+    let app = TestApp::new().await;
+
+    for token in ["invalid", "Hs5m4yPNr4vMeF4t3Ra4zhgXd8kG3cuJvXKqJbdGmC8"] {
+        set_refresh_cookie(&app, token);
+        let response = app.post_refresh().await;
+        assert_eq!(response.status().as_u16(), 401, "Failed for token: {}", token);
+    }
+}
+
+#[tokio::test]
+async fn should_return_200_and_rotate_refresh_token() {
+    let app = TestApp::new().await;
+    let refresh_token = login(&app).await;
+
+    let response = app.post_refresh().await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let auth_token = get_cookie(&response, JWT_COOKIE_NAME).expect("No auth cookie found");
+    let new_refresh_token =
+        get_cookie(&response, REFRESH_COOKIE_NAME).expect("No refresh cookie found");
+    assert!(!auth_token.is_empty());
+    assert_ne!(new_refresh_token, refresh_token);
+
+    // The new auth token is accepted
+    let response = app.post_verify_token(&serde_json::json!({ "token": auth_token })).await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    // The rotated token can be refreshed again
+    let response = app.post_refresh().await;
+    assert_eq!(response.status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn should_revoke_token_family_if_refresh_token_reused() {
+    let app = TestApp::new().await;
+    let refresh_token = login(&app).await;
+
+    let response = app.post_refresh().await;
+    assert_eq!(response.status().as_u16(), 200);
+    let new_refresh_token =
+        get_cookie(&response, REFRESH_COOKIE_NAME).expect("No refresh cookie found");
+
+    // Present the already rotated token again
+    set_refresh_cookie(&app, &refresh_token);
+    let response = app.post_refresh().await;
+    assert_eq!(response.status().as_u16(), 401);
+
+    // The newest token of the family is revoked as well
+    set_refresh_cookie(&app, &new_refresh_token);
+    let response = app.post_refresh().await;
+    assert_eq!(response.status().as_u16(), 401);
+}
+
+#[tokio::test]
+async fn should_revoke_refresh_token_on_logout() {
+    let app = TestApp::new().await;
+    let refresh_token = login(&app).await;
+
+    let response = app.logout().await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let url = Url::parse("http://127.0.0.1").expect("Failed to parse URL");
+    let cookies = app.cookie_jar.cookies(&url);
+    assert!(cookies.is_none_or(|cookies| {
+        !cookies.to_str().unwrap().contains(REFRESH_COOKIE_NAME)
+    }));
+
+    let token = RefreshToken::parse(refresh_token.clone()).unwrap();
+    let result = app.refresh_token_store.write().await.use_token(&token.hash()).await;
+    assert_eq!(result, Err(RefreshTokenStoreError::TokenNotFound));
+
+    set_refresh_cookie(&app, &refresh_token);
+    let response = app.post_refresh().await;
+    assert_eq!(response.status().as_u16(), 401);
+}
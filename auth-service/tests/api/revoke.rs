@@ -0,0 +1,82 @@
+use auth_service::utils::constants::{test, JWT_COOKIE_NAME, REFRESH_COOKIE_NAME};
+
+use crate::helpers::{get_random_email, TestApp};
+
+// Sign up and log in a user without 2FA, returning the auth and refresh tokens
+async fn login(app: &TestApp) -> (String, String) {
+    let email = get_random_email();
+
+    let signup_body = serde_json::json!({
+        "email": email,
+        "password": "Password123!",
+        "requires2FA": false
+    });
+    let response = app.post_signup(&signup_body).await;
+    assert_eq!(response.status().as_u16(), 201);
+
+    let login_body = serde_json::json!({
+        "email": email,
+        "password": "Password123!",
+    });
+    let response = app.post_login(&login_body).await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let get_cookie = |name: &str| {
+        response
+            .cookies()
+            .find(|cookie| cookie.name() == name)
+            .map(|cookie| cookie.value().to_owned())
+            .expect("No cookie found")
+    };
+    (get_cookie(JWT_COOKIE_NAME), get_cookie(REFRESH_COOKIE_NAME))
+}
+
+#[tokio::test]
+async fn should_ban_revoked_auth_token() {
+    let app = TestApp::new().await;
+    let (auth_token, _) = login(&app).await;
+
+    let body = [("token", auth_token.as_str()), ("token_type_hint", "access_token")];
+    let response = app.post_revoke(&body, test::OAUTH_CLIENT_SECRET).await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let response = app.post_verify_token(&serde_json::json!({ "token": auth_token })).await;
+    assert_eq!(response.status().as_u16(), 401);
+}
+
+#[tokio::test]
+async fn should_revoke_refresh_token_family() {
+    let app = TestApp::new().await;
+    let (_, refresh_token) = login(&app).await;
+
+    let body = [("token", refresh_token.as_str()), ("token_type_hint", "refresh_token")];
+    let response = app.post_revoke(&body, test::OAUTH_CLIENT_SECRET).await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    // The refresh cookie from the login is still in the client's jar
+    let response = app.post_refresh().await;
+    assert_eq!(response.status().as_u16(), 401);
+}
+
+#[tokio::test]
+async fn should_return_200_for_invalid_token() {
+    let app = TestApp::new().await;
+
+    let body = [("token", "invalid.jwt.token")];
+    let response = app.post_revoke(&body, test::OAUTH_CLIENT_SECRET).await;
+    assert_eq!(response.status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn should_return_401_if_client_not_authenticated() {
+    let app = TestApp::new().await;
+    let (auth_token, _) = login(&app).await;
+
+    let body = [("token", auth_token.as_str())];
+    let response = app.post_revoke(&body, "wrong-secret").await;
+    assert_eq!(response.status().as_u16(), 401);
+
+    // The token was not revoked
+    let response = app.post_verify_token(&serde_json::json!({ "token": auth_token })).await;
+    assert_eq!(response.status().as_u16(), 200);
+}
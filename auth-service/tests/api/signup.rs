@@ -1,5 +1,5 @@
 use crate::helpers::{get_random_email, TestApp};
-use auth_service::{routes::SignupResponse, ErrorResponse};
+use auth_service::{domain::recovery_code::RECOVERY_CODE_COUNT, routes::SignupResponse, ErrorResponse};
 
 
 #[tokio::test]
@@ -38,29 +38,36 @@ async fn should_return_422_if_malformed_input() {
 async fn should_return_201_if_valid_input() {
 
     let app = TestApp::new().await;
-    let random_email = get_random_email();
 
     let test_cases = [
         serde_json::json!({
-            "email": random_email,
+            "email": get_random_email(),
             "password": "Password123!",
             "requires2FA": true
         }),
+        serde_json::json!({
+            "email": get_random_email(),
+            "password": "Password123!",
+            "requires2FA": false
+        }),
     ];
     for test_case in test_cases.iter() {
         let response = app.post_signup(test_case).await;
         assert_eq!(response.status().as_u16(), 201);
 
-        let expected_response = SignupResponse {
-            message: "User created successfully!".to_owned(),
-        };
+        let body = response
+            .json::<SignupResponse>()
+            .await
+            .expect("Could not deserialize response body to UserBody");
+        assert_eq!(body.message, "User created successfully!");
 
+        // Only users with 2FA get recovery codes
+        let expected_recovery_codes = test_case["requires2FA"].as_bool().unwrap().then_some(RECOVERY_CODE_COUNT);
         assert_eq!(
-            response
-                .json::<SignupResponse>()
-                .await
-                .expect("Could not deserialize response body to UserBody"),
-            expected_response
+            body.recovery_codes.map(|codes| codes.len()),
+            expected_recovery_codes,
+            "Failed for input: {:?}",
+            test_case
         );
     }
 }
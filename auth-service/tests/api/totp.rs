@@ -0,0 +1,301 @@
+use auth_service::{
+    domain::recovery_code::RECOVERY_CODE_COUNT,
+    routes::{EnrollTotpResponse, RecoveryCodesResponse, TwoFactorAuthResponse},
+};
+use totp_rs::{Algorithm, Secret, TOTP};
+
+use crate::helpers::{get_random_email, TestApp};
+
+// Sign up and log in a user without 2FA, leaving the auth cookie in the app's cookie jar
+async fn login_new_user(app: &TestApp) -> String {
+    let email = get_random_email();
+
+    let signup_body = serde_json::json!({
+        "email": email,
+        "password": "Password123!",
+        "requires2FA": false
+    });
+    let response = app.post_signup(&signup_body).await;
+    assert_eq!(response.status().as_u16(), 201);
+
+    let login_body = serde_json::json!({
+        "email": email,
+        "password": "Password123!"
+    });
+    let response = app.post_login(&login_body).await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    email
+}
+
+// The code an authenticator app provisioned with the secret shows right now
+fn current_code(secret: &str) -> String {
+    totp(secret).generate_current().unwrap()
+}
+
+// The code the app shows next, accepted as well to allow for clock drift
+fn next_code(secret: &str) -> String {
+    let now = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap()
+        .as_secs();
+    totp(secret).generate(now + 30)
+}
+
+fn totp(secret: &str) -> TOTP {
+    let secret = Secret::Encoded(secret.to_owned()).to_bytes().unwrap();
+    TOTP::new(Algorithm::SHA1, 6, 0, 30, secret, None, "test".to_owned()).unwrap()
+}
+
+// A code which is guaranteed not to be accepted right now
+fn wrong_code(secret: &str) -> String {
+    let code = current_code(secret);
+    let digit = (code.as_bytes()[0] - b'0' + 5) % 10;
+    format!("{}{}", digit, &code[1..])
+}
+
+async fn enroll(app: &TestApp) -> EnrollTotpResponse {
+    let response = app.post_totp_enroll(&serde_json::json!({ "password": "Password123!" })).await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    response
+        .json::<EnrollTotpResponse>()
+        .await
+        .expect("Could not deserialize response body to EnrollTotpResponse")
+}
+
+#[tokio::test]
+async fn should_return_400_if_enrolling_without_auth_cookie() {
+    let app = TestApp::new().await;
+
+    let response = app.post_totp_enroll(&serde_json::json!({ "password": "Password123!" })).await;
+    assert_eq!(response.status().as_u16(), 400);
+}
+
+#[tokio::test]
+async fn should_return_provisioning_data_on_enroll() {
+    let app = TestApp::new().await;
+    let email = login_new_user(&app).await;
+
+    let enrollment = enroll(&app).await;
+    assert!(enrollment.otpauth_uri.starts_with("otpauth://totp/"));
+    assert!(enrollment.otpauth_uri.contains(&enrollment.secret));
+    assert!(enrollment.otpauth_uri.contains(&email.replace('@', "%40")));
+    assert!(!enrollment.qr_code_png.is_empty());
+    assert!(enrollment.qr_code_svg.contains("<svg"));
+
+    // The secret is not active until confirmed, login still works without 2FA
+    let login_body = serde_json::json!({
+        "email": email,
+        "password": "Password123!"
+    });
+    let response = app.post_login(&login_body).await;
+    assert_eq!(response.status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn should_return_401_if_enrolling_or_confirming_with_wrong_password() {
+    let app = TestApp::new().await;
+    login_new_user(&app).await;
+
+    let response = app
+        .post_totp_enroll(&serde_json::json!({ "password": "WrongPassword123!" }))
+        .await;
+    assert_eq!(response.status().as_u16(), 401);
+
+    let enrollment = enroll(&app).await;
+    let body = serde_json::json!({
+        "code": current_code(&enrollment.secret),
+        "password": "WrongPassword123!"
+    });
+    let response = app.post_totp_confirm(&body).await;
+    assert_eq!(response.status().as_u16(), 401);
+}
+
+#[tokio::test]
+async fn should_require_second_factor_to_enroll_and_confirm_with_2fa_enabled() {
+    let app = TestApp::new().await;
+    let email = get_random_email();
+
+    let signup_body = serde_json::json!({
+        "email": email,
+        "password": "Password123!",
+        "requires2FA": true
+    });
+    let response = app.post_signup(&signup_body).await;
+    assert_eq!(response.status().as_u16(), 201);
+    let recovery_codes = response
+        .json::<RecoveryCodesResponse>()
+        .await
+        .expect("Could not deserialize response body to RecoveryCodesResponse")
+        .recovery_codes;
+
+    let login_body = serde_json::json!({
+        "email": email,
+        "password": "Password123!"
+    });
+    let response = app.post_login(&login_body).await;
+    assert_eq!(response.status().as_u16(), 206);
+    let login_attempt_id = response
+        .json::<TwoFactorAuthResponse>()
+        .await
+        .expect("Could not deserialize response body to TwoFactorAuthResponse")
+        .login_attempt_id;
+    let verify_body = serde_json::json!({
+        "email": email,
+        "loginAttemptId": login_attempt_id,
+        "2FACode": recovery_codes[0]
+    });
+    let response = app.post_verify_2fa(&verify_body).await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    // The password alone only starts a 2FA attempt
+    let response = app.post_totp_enroll(&serde_json::json!({ "password": "Password123!" })).await;
+    assert_eq!(response.status().as_u16(), 206);
+    let login_attempt_id = response
+        .json::<TwoFactorAuthResponse>()
+        .await
+        .expect("Could not deserialize response body to TwoFactorAuthResponse")
+        .login_attempt_id;
+
+    let user_id = app.get_user(&email).await.id;
+    let (_, code) = app.two_fa_code_store.read().await.get_code(&user_id).await.unwrap();
+    let body = serde_json::json!({
+        "password": "Password123!",
+        "loginAttemptId": login_attempt_id,
+        "2FACode": code.as_ref()
+    });
+    let response = app.post_totp_enroll(&body).await;
+    assert_eq!(response.status().as_u16(), 200);
+    let enrollment = response
+        .json::<EnrollTotpResponse>()
+        .await
+        .expect("Could not deserialize response body to EnrollTotpResponse");
+
+    let body = serde_json::json!({
+        "code": current_code(&enrollment.secret),
+        "password": "Password123!"
+    });
+    let response = app.post_totp_confirm(&body).await;
+    assert_eq!(response.status().as_u16(), 206);
+    let login_attempt_id = response
+        .json::<TwoFactorAuthResponse>()
+        .await
+        .expect("Could not deserialize response body to TwoFactorAuthResponse")
+        .login_attempt_id;
+
+    let body = serde_json::json!({
+        "code": current_code(&enrollment.secret),
+        "password": "Password123!",
+        "loginAttemptId": login_attempt_id,
+        "2FACode": recovery_codes[1]
+    });
+    let response = app.post_totp_confirm(&body).await;
+    assert_eq!(response.status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn should_return_400_if_confirming_without_enrollment() {
+    let app = TestApp::new().await;
+    login_new_user(&app).await;
+
+    let response = app.post_totp_confirm(&serde_json::json!({ "code": "123456", "password": "Password123!" })).await;
+    assert_eq!(response.status().as_u16(), 400);
+}
+
+#[tokio::test]
+async fn should_return_401_if_confirming_with_wrong_code() {
+    let app = TestApp::new().await;
+    login_new_user(&app).await;
+    let enrollment = enroll(&app).await;
+
+    let body = serde_json::json!({ "code": wrong_code(&enrollment.secret), "password": "Password123!" });
+    let response = app.post_totp_confirm(&body).await;
+    assert_eq!(response.status().as_u16(), 401);
+}
+
+#[tokio::test]
+async fn should_require_authenticator_code_after_confirmation() {
+    let app = TestApp::new().await;
+    let email = login_new_user(&app).await;
+    let enrollment = enroll(&app).await;
+
+    let confirmation_code = current_code(&enrollment.secret);
+    let body = serde_json::json!({ "code": confirmation_code, "password": "Password123!" });
+    let response = app.post_totp_confirm(&body).await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let recovery_codes = response
+        .json::<RecoveryCodesResponse>()
+        .await
+        .expect("Could not deserialize response body to RecoveryCodesResponse")
+        .recovery_codes;
+    assert_eq!(recovery_codes.len(), RECOVERY_CODE_COUNT);
+
+    // Enrolling again would silently replace the active secret
+    let response = app.post_totp_enroll(&serde_json::json!({ "password": "Password123!" })).await;
+    assert_eq!(response.status().as_u16(), 409);
+
+    let login_body = serde_json::json!({
+        "email": email,
+        "password": "Password123!"
+    });
+    let response = app.post_login(&login_body).await;
+    assert_eq!(response.status().as_u16(), 206);
+
+    let login_attempt_id = response
+        .json::<TwoFactorAuthResponse>()
+        .await
+        .expect("Could not deserialize response body to TwoFactorAuthResponse")
+        .login_attempt_id;
+
+    // The code the login attempt was created with is not accepted
+    let stored_code = {
+        let user_id = app.get_user(&email).await.id;
+        let two_fa_store = app.two_fa_code_store.read().await;
+        let (_, code) = two_fa_store.get_code(&user_id).await.unwrap();
+        code
+    };
+    if stored_code.as_ref() != current_code(&enrollment.secret) {
+        let verify_body = serde_json::json!({
+            "email": email,
+            "loginAttemptId": login_attempt_id,
+            "2FACode": stored_code.as_ref()
+        });
+        let response = app.post_verify_2fa(&verify_body).await;
+        assert_eq!(response.status().as_u16(), 401);
+    }
+
+    // Every code works only once, the one used for the confirmation is spent
+    let verify_body = serde_json::json!({
+        "email": email,
+        "loginAttemptId": login_attempt_id,
+        "2FACode": confirmation_code
+    });
+    let response = app.post_verify_2fa(&verify_body).await;
+    assert_eq!(response.status().as_u16(), 401);
+
+    let code = next_code(&enrollment.secret);
+    let verify_body = serde_json::json!({
+        "email": email,
+        "loginAttemptId": login_attempt_id,
+        "2FACode": code
+    });
+    let response = app.post_verify_2fa(&verify_body).await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    // Nor can the code be replayed for another login
+    let response = app.post_login(&login_body).await;
+    let login_attempt_id = response
+        .json::<TwoFactorAuthResponse>()
+        .await
+        .expect("Could not deserialize response body to TwoFactorAuthResponse")
+        .login_attempt_id;
+    let verify_body = serde_json::json!({
+        "email": email,
+        "loginAttemptId": login_attempt_id,
+        "2FACode": code
+    });
+    let response = app.post_verify_2fa(&verify_body).await;
+    assert_eq!(response.status().as_u16(), 401);
+}
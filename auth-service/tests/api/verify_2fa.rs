@@ -1,5 +1,15 @@
+use std::{sync::Arc, time::Duration};
+
 use crate::helpers::{get_random_email, TestApp};
-use auth_service::{routes::TwoFactorAuthResponse, utils::constants::JWT_COOKIE_NAME};
+use auth_service::{
+    app_state::TwoFACodeStoreType,
+    domain::data_stores::TwoFACodeStoreError,
+    get_redis_connection,
+    routes::{AuthTokenResponse, TwoFactorAuthResponse},
+    services::{HashmapTwoFACodeStore, RedisTwoFACodeStore},
+    utils::constants::{test, JWT_COOKIE_NAME, REFRESH_COOKIE_NAME, TWO_FA_MAX_ATTEMPTS},
+};
+use tokio::sync::RwLock;
 
 #[tokio::test]
 async fn should_return_422_if_malformed_input() {
@@ -213,10 +223,10 @@ async fn should_return_200_if_correct_code() {
         .expect("Could not deserialize response body to TwoFactorAuthResponse");
     
     // Get the actual 2FA code from the store
-    let email = auth_service::domain::Email::parse(random_email.clone()).unwrap();
+    let user_id = app.get_user(&random_email).await.id;
     let two_fa_code = {
         let two_fa_store = app.two_fa_code_store.read().await;
-        let (_, code) = two_fa_store.get_code(&email).await.unwrap();
+        let (_, code) = two_fa_store.get_code(&user_id).await.unwrap();
         code
     }; // Read lock is dropped here
     
@@ -234,10 +244,61 @@ async fn should_return_200_if_correct_code() {
     let cookies = response.cookies().collect::<Vec<_>>();
     assert!(cookies.iter().any(|cookie| cookie.name() == JWT_COOKIE_NAME), 
             "JWT auth cookie should be set after successful 2FA verification");
+    assert!(cookies.iter().any(|cookie| cookie.name() == REFRESH_COOKIE_NAME),
+            "Refresh cookie should be set after successful 2FA verification");
     
     // Verify that the 2FA code is removed from the store after successful verification
     let two_fa_store = app.two_fa_code_store.read().await;
-    assert!(two_fa_store.get_code(&email).await.is_err(), "2FA code should be removed after successful verification");
+    assert!(two_fa_store.get_code(&user_id).await.is_err(), "2FA code should be removed after successful verification");
+}
+
+#[tokio::test]
+async fn should_return_auth_token_in_body_if_requested() {
+    let app = TestApp::new().await;
+    let random_email = get_random_email();
+
+    let signup_body = serde_json::json!({
+        "email": random_email,
+        "password": "Password123!",
+        "requires2FA": true
+    });
+    let response = app.post_signup(&signup_body).await;
+    assert_eq!(response.status().as_u16(), 201);
+
+    let login_body = serde_json::json!({
+        "email": random_email,
+        "password": "Password123!",
+    });
+    let response = app.post_login(&login_body).await;
+    assert_eq!(response.status().as_u16(), 206);
+
+    let json_body = response
+        .json::<TwoFactorAuthResponse>()
+        .await
+        .expect("Could not deserialize response body to TwoFactorAuthResponse");
+
+    let user_id = app.get_user(&random_email).await.id;
+    let two_fa_code = {
+        let two_fa_store = app.two_fa_code_store.read().await;
+        let (_, code) = two_fa_store.get_code(&user_id).await.unwrap();
+        code
+    }; // Read lock is dropped here
+
+    let verify_body = serde_json::json!({
+        "email": random_email,
+        "loginAttemptId": json_body.login_attempt_id,
+        "2FACode": two_fa_code.as_ref(),
+        "returnToken": true
+    });
+    let response = app.post_verify_2fa(&verify_body).await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let json_body = response
+        .json::<AuthTokenResponse>()
+        .await
+        .expect("Could not deserialize response body to AuthTokenResponse");
+    let response = app.post_verify_token(&serde_json::json!({ "token": json_body.token })).await;
+    assert_eq!(response.status().as_u16(), 200);
 }
 
 #[tokio::test]
@@ -268,10 +329,10 @@ async fn should_return_401_if_same_code_twice() {
         .expect("Could not deserialize response body to TwoFactorAuthResponse");
     
     // Get the actual 2FA code from the store
-    let email = auth_service::domain::Email::parse(random_email.clone()).unwrap();
+    let user_id = app.get_user(&random_email).await.id;
     let two_fa_code = {
         let two_fa_store = app.two_fa_code_store.read().await;
-        let (_, code) = two_fa_store.get_code(&email).await.unwrap();
+        let (_, code) = two_fa_store.get_code(&user_id).await.unwrap();
         code
     }; // Read lock is dropped here
     
@@ -288,4 +349,173 @@ async fn should_return_401_if_same_code_twice() {
     // Try to use the same 2FA code again (second time - should fail with 401)
     let response = app.post_verify_2fa(&verify_body).await;
     assert_eq!(response.status().as_u16(), 401, "Using the same 2FA code twice should return 401");
-}
\ No newline at end of file
+}
+#[tokio::test]
+async fn should_return_410_after_too_many_wrong_codes() {
+    let app = TestApp::new().await;
+    let random_email = get_random_email();
+
+    let signup_body = serde_json::json!({
+        "email": random_email,
+        "password": "Password123!",
+        "requires2FA": true
+    });
+    let response = app.post_signup(&signup_body).await;
+    assert_eq!(response.status().as_u16(), 201);
+
+    let login_body = serde_json::json!({
+        "email": random_email,
+        "password": "Password123!",
+    });
+    let response = app.post_login(&login_body).await;
+    assert_eq!(response.status().as_u16(), 206);
+
+    let json_body = response
+        .json::<TwoFactorAuthResponse>()
+        .await
+        .expect("Could not deserialize response body to TwoFactorAuthResponse");
+
+    // Get the actual 2FA code from the store
+    let user_id = app.get_user(&random_email).await.id;
+    let two_fa_code = {
+        let two_fa_store = app.two_fa_code_store.read().await;
+        let (_, code) = two_fa_store.get_code(&user_id).await.unwrap();
+        code
+    }; // Read lock is dropped here
+
+    // A code which is guaranteed to be wrong
+    let wrong_code = if two_fa_code.as_ref() == "000000" { "111111" } else { "000000" };
+    let wrong_body = serde_json::json!({
+        "email": random_email,
+        "loginAttemptId": json_body.login_attempt_id,
+        "2FACode": wrong_code
+    });
+
+    for _ in 1..*TWO_FA_MAX_ATTEMPTS {
+        let response = app.post_verify_2fa(&wrong_body).await;
+        assert_eq!(response.status().as_u16(), 401);
+    }
+
+    // The last allowed attempt invalidates the login attempt
+    let response = app.post_verify_2fa(&wrong_body).await;
+    assert_eq!(response.status().as_u16(), 410);
+
+    // Even the correct code is rejected now, the user has to log in again
+    let verify_body = serde_json::json!({
+        "email": random_email,
+        "loginAttemptId": json_body.login_attempt_id,
+        "2FACode": two_fa_code.as_ref()
+    });
+    let response = app.post_verify_2fa(&verify_body).await;
+    assert_eq!(response.status().as_u16(), 401);
+    assert!(response.cookies().all(|cookie| cookie.name() != JWT_COOKIE_NAME));
+}
+
+#[tokio::test]
+async fn should_accept_code_only_once_when_sent_concurrently() {
+    let app = TestApp::new().await;
+    let random_email = get_random_email();
+
+    let signup_body = serde_json::json!({
+        "email": random_email,
+        "password": "Password123!",
+        "requires2FA": true
+    });
+    let response = app.post_signup(&signup_body).await;
+    assert_eq!(response.status().as_u16(), 201);
+
+    let login_body = serde_json::json!({
+        "email": random_email,
+        "password": "Password123!",
+    });
+    let response = app.post_login(&login_body).await;
+    assert_eq!(response.status().as_u16(), 206);
+    let json_body = response
+        .json::<TwoFactorAuthResponse>()
+        .await
+        .expect("Could not deserialize response body to TwoFactorAuthResponse");
+
+    let user_id = app.get_user(&random_email).await.id;
+    let (_, two_fa_code) = app.two_fa_code_store.read().await.get_code(&user_id).await.unwrap();
+
+    // Only one of the requests may start a session with the code
+    let verify_body = serde_json::json!({
+        "email": random_email,
+        "loginAttemptId": json_body.login_attempt_id,
+        "2FACode": two_fa_code.as_ref()
+    });
+    let responses = tokio::join!(
+        app.post_verify_2fa(&verify_body),
+        app.post_verify_2fa(&verify_body),
+        app.post_verify_2fa(&verify_body),
+    );
+    let statuses = [
+        responses.0.status().as_u16(),
+        responses.1.status().as_u16(),
+        responses.2.status().as_u16(),
+    ];
+    assert_eq!(statuses.iter().filter(|&&status| status == 200).count(), 1);
+    assert_eq!(statuses.iter().filter(|&&status| status == 401).count(), 2);
+}
+
+#[tokio::test]
+async fn should_return_410_if_code_expired() {
+    // Codes expire after a second, in Redis when TEST_REDIS_URL is set
+    let two_fa_code_store: TwoFACodeStoreType = match std::env::var(test::REDIS_URL_ENV_VAR) {
+        Ok(url) => {
+            let redis_connection = get_redis_connection(&url).await.expect("Failed to connect to Redis");
+            Arc::new(RwLock::new(RedisTwoFACodeStore::new(redis_connection, 1)))
+        }
+        Err(_) => Arc::new(RwLock::new(HashmapTwoFACodeStore::new(1))),
+    };
+    let app = TestApp::with_app_state({
+        let two_fa_code_store = two_fa_code_store.clone();
+        move |mut app_state| {
+            app_state.two_fa_code_store = two_fa_code_store;
+            app_state
+        }
+    })
+    .await;
+    let random_email = get_random_email();
+
+    let signup_body = serde_json::json!({
+        "email": random_email,
+        "password": "Password123!",
+        "requires2FA": true
+    });
+    let response = app.post_signup(&signup_body).await;
+    assert_eq!(response.status().as_u16(), 201);
+
+    let login_body = serde_json::json!({
+        "email": random_email,
+        "password": "Password123!",
+    });
+    let response = app.post_login(&login_body).await;
+    assert_eq!(response.status().as_u16(), 206);
+
+    let json_body = response
+        .json::<TwoFactorAuthResponse>()
+        .await
+        .expect("Could not deserialize response body to TwoFactorAuthResponse");
+
+    let user_id = app.get_user(&random_email).await.id;
+    let (_, two_fa_code) = two_fa_code_store.read().await.get_code(&user_id).await.unwrap();
+
+    tokio::time::sleep(Duration::from_secs(2)).await;
+
+    // The expired code is still told apart from a missing one
+    let result = two_fa_code_store.read().await.get_code(&user_id).await;
+    assert_eq!(result, Err(TwoFACodeStoreError::CodeExpired));
+
+    let verify_body = serde_json::json!({
+        "email": random_email,
+        "loginAttemptId": json_body.login_attempt_id,
+        "2FACode": two_fa_code.as_ref()
+    });
+    let response = app.post_verify_2fa(&verify_body).await;
+    assert_eq!(response.status().as_u16(), 410);
+
+    // The expired login attempt is gone, the user has to log in again
+    let response = app.post_verify_2fa(&verify_body).await;
+    assert_eq!(response.status().as_u16(), 401);
+}
@@ -0,0 +1,149 @@
+use crate::helpers::{get_random_email, TestApp};
+use auth_service::{
+    domain::{Email, User},
+    utils::auth::generate_email_verification_token,
+    ErrorResponse,
+};
+
+async fn signup(app: &TestApp, email: &str) {
+    let response = app
+        .post_signup(&serde_json::json!({
+            "email": email,
+            "password": "Password123!",
+            "requires2FA": false
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 201);
+}
+
+async fn is_verified(app: &TestApp, email: &str) -> bool {
+    app.get_user(email).await.email_verified()
+}
+
+#[tokio::test]
+async fn should_verify_email_with_valid_token() {
+    let app = TestApp::new().await;
+    let email = get_random_email();
+    signup(&app, &email).await;
+    assert!(!is_verified(&app, &email).await);
+
+    let token = generate_email_verification_token(&app.get_user(&email).await).unwrap();
+    let response = app.get_verify_email(&token).await;
+    assert_eq!(response.status().as_u16(), 200);
+    assert!(is_verified(&app, &email).await);
+
+    // Following the link again does no harm
+    let response = app.get_verify_email(&token).await;
+    assert_eq!(response.status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn should_return_401_if_invalid_token() {
+    let app = TestApp::new().await;
+    let email = get_random_email();
+    signup(&app, &email).await;
+
+    // An auth token is no verification token
+    let response = app
+        .post_login(&serde_json::json!({
+            "email": email,
+            "password": "Password123!",
+            "returnToken": true
+        }))
+        .await;
+    let auth_token = response.json::<serde_json::Value>().await.unwrap()["token"]
+        .as_str()
+        .unwrap()
+        .to_owned();
+
+    let user = app.get_user(&email).await;
+
+    // A valid token for an account that no longer exists
+    let deleted_user = User::new(
+        Email::parse(get_random_email()).unwrap(),
+        user.password_hash().clone(),
+        false,
+    );
+    let deleted_account_token = generate_email_verification_token(&deleted_user).unwrap();
+
+    // A valid token for an address the user has moved away from
+    let mut old_address_user = user.clone();
+    old_address_user.email = Email::parse(get_random_email()).unwrap();
+    let old_address_token = generate_email_verification_token(&old_address_user).unwrap();
+
+    for token in [
+        "invalid",
+        auth_token.as_str(),
+        deleted_account_token.as_str(),
+        old_address_token.as_str(),
+    ] {
+        let response = app.get_verify_email(token).await;
+        assert_eq!(response.status().as_u16(), 401, "Failed for token: {}", token);
+    }
+    assert!(!is_verified(&app, &email).await);
+}
+
+#[tokio::test]
+async fn should_deny_login_until_verified_if_configured() {
+    let app = TestApp::with_app_state(|app_state| app_state.with_allow_unverified_login(false)).await;
+    let email = get_random_email();
+    signup(&app, &email).await;
+
+    let login = serde_json::json!({
+        "email": email,
+        "password": "Password123!"
+    });
+
+    let response = app.post_login(&login).await;
+    assert_eq!(response.status().as_u16(), 403);
+    assert_eq!(
+        response.json::<ErrorResponse>().await.unwrap().error,
+        "Email address not verified"
+    );
+
+    // A wrong password is still just a wrong password
+    let response = app
+        .post_login(&serde_json::json!({
+            "email": email,
+            "password": "WrongPassword123!"
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 401);
+
+    let token = generate_email_verification_token(&app.get_user(&email).await).unwrap();
+    app.get_verify_email(&token).await;
+
+    let response = app.post_login(&login).await;
+    assert_eq!(response.status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn should_answer_resend_alike_for_any_account() {
+    let app = TestApp::new().await;
+    let unverified_email = get_random_email();
+    signup(&app, &unverified_email).await;
+
+    let verified_email = get_random_email();
+    signup(&app, &verified_email).await;
+    let token = generate_email_verification_token(&app.get_user(&verified_email).await).unwrap();
+    app.get_verify_email(&token).await;
+
+    let mut bodies = Vec::new();
+    for email in [unverified_email, verified_email, get_random_email()] {
+        let response = app
+            .post_resend_verification_email(&serde_json::json!({ "email": email }))
+            .await;
+        assert_eq!(response.status().as_u16(), 200, "Failed for email: {}", email);
+        bodies.push(response.text().await.unwrap());
+    }
+    assert!(bodies.windows(2).all(|pair| pair[0] == pair[1]));
+}
+
+#[tokio::test]
+async fn should_return_400_if_resend_for_invalid_email() {
+    let app = TestApp::new().await;
+    let response = app
+        .post_resend_verification_email(&serde_json::json!({ "email": "invalid-email" }))
+        .await;
+    assert_eq!(response.status().as_u16(), 400);
+}
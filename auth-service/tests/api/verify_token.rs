@@ -1,3 +1,5 @@
+use auth_service::utils::auth::Claims;
+
 use crate::helpers::TestApp;
 
 #[tokio::test]
@@ -46,6 +48,13 @@ async fn should_return_200_valid_token() {
     
     let response = app.post_verify_token(&verify_body).await;
     assert_eq!(response.status().as_u16(), 200);
+
+    let claims = response
+        .json::<Claims>()
+        .await
+        .expect("Could not deserialize response body to Claims");
+    assert_eq!(claims.sub, app.get_user(&email).await.id.as_ref());
+    assert_eq!(claims.email, None);
 }
 
 #[tokio::test]